@@ -1,5 +1,52 @@
 title = OverCiv
 quit = Verlassen
+menu-server-local = Lokales Spiel
+ .new = Neues Spiel
+ .new-name = Spielstandname:
+ .load = Laden
+ .delete = Löschen
+ .duplicate = Duplizieren
+ .no-saves = Keine Spielstände gefunden
+menu-server-join = Server beitreten
+
+save-info = Runde {$turn}, {$civ}, {$width}x{$height} Karte
+ .not-started = Nicht begonnen, {$civ}, {$width}x{$height} Karte
+ .incompatible = Nicht mit dieser Version kompatibel
+ .played = zuletzt gespielt vor {$days}T {$hours}Std
+
+join-game = Server beitreten
+ .address = Serveradresse:
+ .name = Spielername:
+ .join = Beitreten
+ .back = Zurück
+ .recent = Zuletzt genutzte Server
+ .no-recent = Keine zuletzt genutzten Server
+ .password = Passwort:
+ .lan = Spiele in diesem Netzwerk
+ .no-lan = Keine Spiele in diesem Netzwerk gefunden
+lan-game = {$name} auf {$address}: {$players}/{$max} Spieler
+ .locked = {$name} auf {$address}: {$players}/{$max} Spieler, Passwort erforderlich
+ .incompatible = {$name} auf {$address}: Version {$version} ist nicht kompatibel
+joining = Trete {$address} bei
+ .waited = Warte seit {$seconds}s
+ .resuming = Verbinde erneut mit {$address}
+ .cancel = Abbrechen
+joined = {$address} als {$name} beigetreten
+ .turn = Runde {$turn}
+ .players = Spieler
+ .leave = Verlassen
+ .away = abwesend
+ .end-turn = Zug beenden
+ .your-turn = Du bist am Zug
+ .waiting = Warte auf {$players}
+ .time-left = Noch {$seconds} Sekunden
+join-error =
+ .connection = Verbindung fehlgeschlagen: {$reason}
+ .rejected = Der Server hat den Beitritt abgelehnt: {$reason}
+ .timed-out = Der Server hat nicht rechtzeitig geantwortet
+ .lost = Die Verbindung zum Server wurde unterbrochen: {$reason}
+ .game-ended = Der Server hat das Spiel beendet
+
 settings-title = Optionen
 settings-cancel = Abbrechen
 settings_current_language = Aktuelle Sprache:
@@ -0,0 +1,45 @@
+rules-unit = Einheit
+ .settler = Siedler
+ .worker = Arbeiter
+ .warrior = Krieger
+ .scout = Späher
+ .horseman = Reiter
+ .archer = Bogenschütze
+ .catapult = Katapult
+rules-building = Gebäude
+ .monument = Monument
+ .granary = Kornspeicher
+ .library = Bibliothek
+ .walls = Stadtmauern
+ .barracks = Kaserne
+ .harbor = Hafen
+ .caravansary = Karawanserei
+ .pyramids = Pyramiden
+ .great_library = Große Bibliothek
+ .colossus = Koloss
+rules-tech = Technologie
+ .pottery = Töpferei
+ .animal_husbandry = Tierzucht
+ .mining = Bergbau
+ .sailing = Segeln
+ .archery = Bogenschießen
+ .bronze_working = Bronzeverarbeitung
+ .masonry = Maurerhandwerk
+ .writing = Schrift
+ .mathematics = Mathematik
+ .construction = Bauwesen
+rules-improvement = Geländeverbesserung
+ .farm = Bauernhof
+ .mine = Mine
+ .pasture = Weide
+rules-resource = Ressource
+ .wheat = Weizen
+ .cattle = Rinder
+ .fish = Fisch
+ .horses = Pferde
+ .iron = Eisen
+ .gold_ore = Golderz
+rules-civ = Zivilisation
+ .rome = Rom
+ .egypt = Ägypten
+ .greece = Griechenland
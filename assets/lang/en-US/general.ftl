@@ -2,11 +2,54 @@ title = OverCiv
 quit = Quit
 
 menu-server-local = Local Game
- .test = New Test Server
+ .new = New Game
+ .new-name = Save Name:
+ .load = Load
+ .delete = Delete
+ .duplicate = Duplicate
+ .no-saves = No saves found
 menu-server-join = Join Server
 menu-server-starting = Launching Server
  .cancel = Cancel
 
+save-info = Turn {$turn}, {$civ}, {$width}x{$height} map
+ .not-started = Not started, {$civ}, {$width}x{$height} map
+ .incompatible = Not compatible with this version
+ .played = last played {$days}d {$hours}h ago
+
+join-game = Join Server
+ .address = Server Address:
+ .name = Player Name:
+ .join = Join
+ .back = Back
+ .recent = Recent Servers
+ .no-recent = No recently joined servers
+ .password = Password:
+ .lan = Games on this Network
+ .no-lan = No games found on this network
+lan-game = {$name} at {$address}: {$players}/{$max} players
+ .locked = {$name} at {$address}: {$players}/{$max} players, password required
+ .incompatible = {$name} at {$address}: version {$version} is not compatible
+joining = Joining {$address}
+ .waited = Waiting for {$seconds}s
+ .resuming = Reconnecting to {$address}
+ .cancel = Cancel
+joined = Joined {$address} as {$name}
+ .turn = Turn {$turn}
+ .players = Players
+ .leave = Leave
+ .away = away
+ .end-turn = End Turn
+ .your-turn = Your turn
+ .waiting = Waiting for {$players}
+ .time-left = {$seconds} seconds left
+join-error =
+ .connection = Could not connect: {$reason}
+ .rejected = The server refused to let you join: {$reason}
+ .timed-out = The server did not answer in time
+ .lost = Lost the connection to the server: {$reason}
+ .game-ended = The server ended the game
+
 settings-title = Settings
 settings-cancel = Cancel
 settings_current_language = Current Language:
@@ -17,4 +60,5 @@ local-server-state = Local Server:
  .loading = Loading {$completion}
  .loading_off = Loading { NUMBER($completion, style: "percent") }
  .running = Running
+ .paused = Paused
  .shutting-down = Shutting Down
@@ -0,0 +1,45 @@
+rules-unit = Unit
+ .settler = Settler
+ .worker = Worker
+ .warrior = Warrior
+ .scout = Scout
+ .horseman = Horseman
+ .archer = Archer
+ .catapult = Catapult
+rules-building = Building
+ .monument = Monument
+ .granary = Granary
+ .library = Library
+ .walls = Walls
+ .barracks = Barracks
+ .harbor = Harbor
+ .caravansary = Caravansary
+ .pyramids = Pyramids
+ .great_library = Great Library
+ .colossus = Colossus
+rules-tech = Technology
+ .pottery = Pottery
+ .animal_husbandry = Animal Husbandry
+ .mining = Mining
+ .sailing = Sailing
+ .archery = Archery
+ .bronze_working = Bronze Working
+ .masonry = Masonry
+ .writing = Writing
+ .mathematics = Mathematics
+ .construction = Construction
+rules-improvement = Improvement
+ .farm = Farm
+ .mine = Mine
+ .pasture = Pasture
+rules-resource = Resource
+ .wheat = Wheat
+ .cattle = Cattle
+ .fish = Fish
+ .horses = Horses
+ .iron = Iron
+ .gold_ore = Gold Ore
+rules-civ = Civilization
+ .rome = Rome
+ .egypt = Egypt
+ .greece = Greece
@@ -0,0 +1,226 @@
+use super::JoinedGame;
+use crate::client_tui::tui_plugin::Frame;
+use crate::universal::borders::player_colour;
+use crate::universal::commands::state::{GameState, TileVisibility};
+use crate::universal::commands::{ClientCommand, ServerCommand};
+use crate::universal::connection::{
+	Connection, ConnectionCommand, ConnectionEvent, JoinFailure, ServerMessage,
+};
+use crate::universal::exit::Exiting;
+use crate::universal::hex::Hex;
+use crate::universal::I18n;
+use bevy::ecs::system::SystemParam;
+use bevy::ecs::world::WorldCell;
+use bevy::prelude::*;
+use std::collections::BTreeMap;
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans};
+
+pub fn register_systems(app: &mut AppBuilder) {
+	let state = super::ClientState::Joined;
+	app.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+		.add_system_set(
+			SystemSet::on_update(state.clone())
+				.with_system(on_connection_event.system())
+				.with_system(on_server_message.system())
+				.with_system(on_shutdown.system()),
+		)
+		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
+}
+
+/// Everything needed to leave the joined game.
+#[derive(SystemParam)]
+pub struct Leave<'a> {
+	joined: ResMut<'a, Option<JoinedGame>>,
+	failure: ResMut<'a, Option<JoinFailure>>,
+	state: ResMut<'a, State<super::ClientState>>,
+	connection_cmd: EventWriter<'a, ConnectionCommand>,
+}
+
+impl<'a> Leave<'a> {
+	/// Leave the game, going back to the games found on the LAN.
+	fn leave(&mut self, failure: JoinFailure) {
+		// Already left if another system got to it first this update.
+		if self.joined.take().is_none() {
+			return;
+		}
+		*self.failure = Some(failure);
+		self.connection_cmd.send(ConnectionCommand::Disconnect);
+		self.state
+			.set(super::ClientState::NotConnected)
+			.expect("failed transitioning out of the Joined state");
+	}
+}
+
+fn on_enter(mut connection: ResMut<Option<Connection>>) {
+	trace!("Client Joined State: Enter");
+	if let Some(conn) = &mut *connection {
+		if let Err(e) = conn.send(ClientCommand::RequestState) {
+			warn!("Failed requesting the game state: {}", e);
+		}
+	}
+}
+
+fn on_connection_event(mut events: EventReader<ConnectionEvent>, mut leave: Leave) {
+	for event in events.iter() {
+		if let ConnectionEvent::Disconnected(reason) = event {
+			return leave.leave(JoinFailure::Lost(reason.clone()));
+		}
+	}
+}
+
+fn on_server_message(mut messages: EventReader<ServerMessage>, mut leave: Leave) {
+	for ServerMessage(message) in messages.iter() {
+		if message.command == ServerCommand::GameEnded {
+			return leave.leave(JoinFailure::GameEnded);
+		}
+	}
+}
+
+fn on_exit() {
+	trace!("Client Joined State: Exit");
+}
+
+fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::ClientState>>) {
+	if let Some(_exiting) = exiting {
+		state
+			.overwrite_replace(super::ClientState::Exiting)
+			.expect("Failed to transition Client to exiting state");
+	}
+}
+
+pub fn draw(world: &WorldCell, f: &mut Frame) {
+	use tui::layout::{Constraint, Direction, Layout};
+	use tui::widgets::*;
+	let size = f.size();
+	let (lang, joined, replica) = match (
+		world.get_resource::<I18n>(),
+		world.get_resource::<Option<JoinedGame>>(),
+		world.get_resource::<Option<GameState>>(),
+	) {
+		(Some(lang), Some(joined), Some(replica)) => (lang, joined, replica),
+		_ => return,
+	};
+	let joined = match &*joined {
+		Some(joined) => joined,
+		None => return,
+	};
+	let title = lang
+		.get_with_args_list(
+			"joined",
+			vec![
+				("address", joined.address.to_string()),
+				("name", joined.name.clone()),
+			],
+		)
+		.into_owned();
+	let block = Block::default().title(title).borders(Borders::ALL);
+	let game = match &*replica {
+		Some(game) => game,
+		None => return f.render_widget(block, size),
+	};
+	let mut items = vec![ListItem::new(
+		lang.get_attr_with_args_list("joined", "turn", std::iter::once(("turn", game.turn)))
+			.into_owned(),
+	)];
+	let to_move = &game.turn_status.to_move;
+	if to_move.contains(&joined.player) {
+		items.push(ListItem::new(
+			lang.get_attr("joined", "your-turn").into_owned(),
+		));
+	} else if !to_move.is_empty() {
+		let waiting_for: Vec<&str> = game
+			.players
+			.iter()
+			.filter(|p| to_move.contains(&p.id))
+			.map(|p| p.name.as_str())
+			.collect();
+		items.push(ListItem::new(
+			lang.get_attr_with_args_list(
+				"joined",
+				"waiting",
+				std::iter::once(("players", waiting_for.join(", "))),
+			)
+			.into_owned(),
+		));
+	}
+	items.push(ListItem::new(
+		lang.get_attr("joined", "players").into_owned(),
+	));
+	let l_away = lang.get_attr("joined", "away");
+	items.extend(game.players.iter().map(|player| {
+		ListItem::new(if player.away {
+			format!("  {} ({}) - {}", player.name, player.civ, l_away)
+		} else {
+			format!("  {} ({})", player.name, player.civ)
+		})
+	}));
+	let areas = Layout::default()
+		.direction(Direction::Horizontal)
+		.constraints([Constraint::Length(32), Constraint::Min(0)])
+		.split(size);
+	f.render_widget(List::new(items).block(block), areas[0]);
+	let map = draw_map(
+		game,
+		joined,
+		areas[1].width.saturating_sub(2),
+		areas[1].height.saturating_sub(2),
+	);
+	f.render_widget(
+		Paragraph::new(map).block(Block::default().borders(Borders::ALL)),
+		areas[1],
+	);
+}
+
+/// The explored tiles around the player's first city or unit in `width` by `height` characters, two
+/// to a tile with the odd rows shoved right by one.  The tiles along a border are in the colour of
+/// the player whose territory they are in.
+fn draw_map(game: &GameState, joined: &JoinedGame, width: u16, height: u16) -> Vec<Spans<'static>> {
+	let bordered: BTreeMap<Hex, _> = game
+		.borders()
+		.into_iter()
+		.map(|edge| (edge.hex, edge.owner))
+		.collect();
+	let center = game
+		.cities
+		.iter()
+		.filter(|city| city.owner == joined.player)
+		.map(|city| city.position)
+		.chain(
+			game.units
+				.iter()
+				.filter(|unit| unit.owner == joined.player)
+				.map(|unit| unit.position),
+		)
+		.next()
+		.unwrap_or_default()
+		.to_offset();
+	let (cols, rows) = (i32::from(width.saturating_sub(1) / 2), i32::from(height));
+	// Keep to even rows at the top so that the odd rows stay shoved right
+	let top = (center.1 - rows / 2).clamp(0, (game.map.height() as i32 - rows).max(0)) & !1;
+	let left = center.0 - cols / 2;
+	(top..(top + rows).min(game.map.height() as i32))
+		.map(|row| {
+			let mut spans = vec![Span::raw(if row & 1 == 1 { " " } else { "" })];
+			for col in left..left + cols {
+				let hex = match game.map.normalize(Hex::from_offset(col, row)) {
+					Some(hex) => hex,
+					None => continue,
+				};
+				let glyph = match (game.visibility(hex), game.map.get(hex)) {
+					(TileVisibility::Unexplored, _) | (_, None) => "  ",
+					(_, Some(tile)) if tile.terrain.is_water() => "~ ",
+					_ => ". ",
+				};
+				spans.push(match bordered.get(&hex) {
+					Some(owner) => {
+						let [r, g, b] = player_colour(*owner);
+						Span::styled(glyph, Style::default().fg(Color::Rgb(r, g, b)))
+					}
+					None => Span::raw(glyph),
+				});
+			}
+			Spans::from(spans)
+		})
+		.collect()
+}
@@ -0,0 +1,210 @@
+use super::{JoinRequest, JoinedGame};
+use crate::client_tui::tui_plugin::Frame;
+use crate::universal::commands::{ClientCommand, ServerCommand};
+use crate::universal::connection::{
+	Connection, ConnectionCommand, ConnectionEvent, JoinFailure, ServerMessage,
+};
+use crate::universal::exit::Exiting;
+use crate::universal::I18n;
+use bevy::ecs::system::SystemParam;
+use bevy::ecs::world::WorldCell;
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+/// How long connecting and joining may take before giving up.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn register_systems(app: &mut AppBuilder) {
+	let state = super::ClientState::Joining;
+	app.init_resource::<Option<JoiningState>>()
+		.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+		.add_system_set(
+			SystemSet::on_update(state.clone())
+				.with_system(on_update.system())
+				.with_system(on_connection_event.system())
+				.with_system(on_server_message.system())
+				.with_system(on_shutdown.system()),
+		)
+		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
+}
+
+struct JoiningState {
+	request: JoinRequest,
+	started: Instant,
+	/// The sequence number of the `Join` command once connected.
+	join_seq: Option<u64>,
+}
+
+/// Everything needed to give up joining.
+#[derive(SystemParam)]
+pub struct Fail<'a> {
+	failure: ResMut<'a, Option<JoinFailure>>,
+	state: ResMut<'a, State<super::ClientState>>,
+	connection_cmd: EventWriter<'a, ConnectionCommand>,
+}
+
+impl<'a> Fail<'a> {
+	/// Give up joining, going back to the games found on the LAN.
+	fn fail(&mut self, request: &JoinRequest, reason: JoinFailure) {
+		// Already failed if another system got to it first this update.
+		if self.failure.is_some() {
+			return;
+		}
+		warn!("Failed joining `{}`: {:?}", request.address, reason);
+		self.connection_cmd.send(ConnectionCommand::Disconnect);
+		*self.failure = Some(reason);
+		self.state
+			.set(super::ClientState::NotConnected)
+			.expect("failed transitioning out of the Joining state");
+	}
+}
+
+fn on_enter(
+	mut joining_state: ResMut<Option<JoiningState>>,
+	mut join_request: ResMut<Option<JoinRequest>>,
+	mut state: ResMut<State<super::ClientState>>,
+	mut failure: ResMut<Option<JoinFailure>>,
+	mut connection_cmd: EventWriter<ConnectionCommand>,
+) {
+	trace!("Client Joining State: Enter");
+	match join_request.take() {
+		Some(request) => {
+			*failure = None;
+			connection_cmd.send(ConnectionCommand::Connect {
+				address: request.address.clone(),
+				password: None,
+			});
+			*joining_state = Some(JoiningState {
+				request,
+				started: Instant::now(),
+				join_seq: None,
+			});
+		}
+		None => {
+			error!("Entered the Joining state without a server to join");
+			state
+				.set(super::ClientState::NotConnected)
+				.expect("failed transitioning to the NotConnected state");
+		}
+	}
+}
+
+fn on_update(joining_state: Res<Option<JoiningState>>, mut fail: Fail) {
+	if let Some(joining) = &*joining_state {
+		if joining.started.elapsed() > JOIN_TIMEOUT {
+			fail.fail(&joining.request, JoinFailure::TimedOut);
+		}
+	}
+}
+
+fn on_connection_event(
+	mut joining_state: ResMut<Option<JoiningState>>,
+	mut events: EventReader<ConnectionEvent>,
+	mut connection: ResMut<Option<Connection>>,
+	mut fail: Fail,
+) {
+	let joining = match &mut *joining_state {
+		Some(joining) => joining,
+		None => return,
+	};
+	for event in events.iter() {
+		match event {
+			ConnectionEvent::Connected(address) if *address == joining.request.address => {
+				let join = ClientCommand::Join {
+					name: joining.request.name.clone(),
+					player: None,
+				};
+				match connection.as_mut().map(|conn| conn.send(join)) {
+					Some(Ok(seq)) => joining.join_seq = Some(seq),
+					Some(Err(e)) => {
+						return fail.fail(&joining.request, JoinFailure::Connection(e.to_string()))
+					}
+					None => (),
+				}
+			}
+			ConnectionEvent::Connected(_) => (),
+			ConnectionEvent::Disconnected(reason) => {
+				return fail.fail(&joining.request, JoinFailure::Connection(reason.clone()));
+			}
+		}
+	}
+}
+
+fn on_server_message(
+	joining_state: Res<Option<JoiningState>>,
+	mut messages: EventReader<ServerMessage>,
+	mut joined: ResMut<Option<JoinedGame>>,
+	mut fail: Fail,
+) {
+	let joining = match &*joining_state {
+		Some(joining) => joining,
+		None => return,
+	};
+	for ServerMessage(message) in messages.iter() {
+		match &message.command {
+			ServerCommand::Joined { player, .. } => {
+				info!(
+					"Joined `{}` as {:?} playing {:?}",
+					joining.request.address, joining.request.name, player
+				);
+				*joined = Some(JoinedGame {
+					address: joining.request.address.clone(),
+					name: joining.request.name.clone(),
+					player: *player,
+				});
+				fail.state
+					.set(super::ClientState::Joined)
+					.expect("failed transitioning to the Joined state");
+				return;
+			}
+			ServerCommand::Rejected { seq, reason } if Some(*seq) == joining.join_seq => {
+				return fail.fail(&joining.request, JoinFailure::Rejected(reason.clone()));
+			}
+			command => trace!("Ignoring message while joining: {:?}", command),
+		}
+	}
+}
+
+fn on_exit(mut joining_state: ResMut<Option<JoiningState>>) {
+	trace!("Client Joining State: Exit");
+	*joining_state = None;
+}
+
+fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::ClientState>>) {
+	if let Some(_exiting) = exiting {
+		state
+			.overwrite_replace(super::ClientState::Exiting)
+			.expect("Failed to transition Client to exiting state");
+	}
+}
+
+pub fn draw(world: &WorldCell, f: &mut Frame) {
+	use tui::widgets::*;
+	let size = f.size();
+	let (lang, joining_state) = match (
+		world.get_resource::<I18n>(),
+		world.get_resource::<Option<JoiningState>>(),
+	) {
+		(Some(lang), Some(joining_state)) => (lang, joining_state),
+		_ => return,
+	};
+	let joining = match &*joining_state {
+		Some(joining) => joining,
+		None => return,
+	};
+	let title = lang
+		.get_with_args_list(
+			"joining",
+			std::iter::once(("address", joining.request.address.to_string())),
+		)
+		.into_owned();
+	let waited = lang
+		.get_attr_with_args_list(
+			"joining",
+			"waited",
+			std::iter::once(("seconds", joining.started.elapsed().as_secs())),
+		)
+		.into_owned();
+	let block = Block::default().title(title).borders(Borders::ALL);
+	f.render_widget(Paragraph::new(waited).block(block), size);
+}
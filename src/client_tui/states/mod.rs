@@ -3,9 +3,15 @@
 // mod main_menu;
 
 mod exiting;
+mod joined;
+mod joining;
 mod not_connected;
 
 use crate::client_tui::tui_plugin::Frame;
+use crate::universal::connection::{JoinFailure, ServerAddress};
+use crate::universal::ids::PlayerId;
+use crate::universal::recent_servers::RecentServers;
+use crate::universal::ConfigDirectory;
 use bevy::ecs::world::WorldCell;
 use bevy::prelude::*;
 
@@ -14,8 +20,8 @@ pub enum ClientState {
 	NotConnected,
 	// MainMenu,
 	// JoinGame,
-	// Joining,
-	// Joined,
+	Joining,
+	Joined,
 	// Paused,
 	Exiting,
 }
@@ -26,9 +32,15 @@ pub struct ClientStatePlugin;
 impl Plugin for ClientStatePlugin {
 	fn build(&self, app: &mut AppBuilder) {
 		// Add the Client state into the system.
-		app.add_state(ClientState::NotConnected);
+		app.add_state(ClientState::NotConnected)
+			.init_resource::<Option<JoinRequest>>()
+			.init_resource::<Option<JoinFailure>>()
+			.init_resource::<Option<JoinedGame>>()
+			.add_startup_system(load_recent_servers.system());
 		not_connected::register_systems(app);
 		// main_menu::register_systems(app);
+		joining::register_systems(app);
+		joined::register_systems(app);
 		exiting::register_systems(app);
 	}
 }
@@ -37,7 +49,33 @@ impl ClientState {
 	pub fn draw(&self, world: &WorldCell, f: &mut Frame) {
 		match self {
 			ClientState::NotConnected => not_connected::draw(world, f),
+			ClientState::Joining => joining::draw(world, f),
+			ClientState::Joined => joined::draw(world, f),
 			ClientState::Exiting => exiting::draw(world, f),
 		}
 	}
 }
+
+/// The LAN game to join and the name to join as, set before entering `ClientState::Joining`.
+/// Games that need a password cannot be joined as there is no way to type one in yet.
+#[derive(Debug, Clone)]
+pub struct JoinRequest {
+	pub address: ServerAddress,
+	pub name: String,
+}
+
+/// The game the client joined.
+#[derive(Debug, Clone)]
+pub struct JoinedGame {
+	pub address: ServerAddress,
+	pub name: String,
+	pub player: PlayerId,
+}
+
+fn load_recent_servers(mut commands: Commands, config_dir: Option<Res<ConfigDirectory>>) {
+	let recent = match config_dir {
+		Some(config_dir) => RecentServers::load(&config_dir.0),
+		None => RecentServers::default(),
+	};
+	commands.insert_resource(recent);
+}
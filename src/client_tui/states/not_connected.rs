@@ -1,11 +1,21 @@
+use super::JoinRequest;
 use crate::client_tui::tui_plugin::Frame;
+use crate::universal::connection::{
+	discovered_server_label, join_failure_label, DiscoveryCommand, JoinFailure, ServerAddress,
+};
 use crate::universal::exit::Exiting;
+use crate::universal::recent_servers::RecentServers;
+use crate::universal::transport::lan::DiscoveredServers;
+use crate::universal::I18n;
 use bevy::ecs::world::WorldCell;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ElementState;
 use bevy::prelude::*;
 
 pub fn register_systems(app: &mut AppBuilder) {
 	let state = super::ClientState::NotConnected;
-	app.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+	app.init_resource::<SelectedServer>()
+		.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
 		.add_system_set(
 			SystemSet::on_update(state.clone())
 				.with_system(on_update.system())
@@ -14,16 +24,57 @@ pub fn register_systems(app: &mut AppBuilder) {
 		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
 }
 
-fn on_enter() {
+fn on_enter(mut discovery_cmd: EventWriter<DiscoveryCommand>) {
 	trace!("Client Loading State: Enter");
+	discovery_cmd.send(DiscoveryCommand::Start);
 }
 
-fn on_update() {
-	// trace!("Client Loading State: Update");
+/// The index of the game picked in the list of games found on the LAN.
+#[derive(Default)]
+struct SelectedServer(usize);
+
+fn on_update(
+	mut keys: EventReader<KeyboardInput>,
+	mut selected: ResMut<SelectedServer>,
+	servers: Res<DiscoveredServers>,
+	recent: Option<Res<RecentServers>>,
+	mut join_request: ResMut<Option<JoinRequest>>,
+	mut state: ResMut<State<super::ClientState>>,
+) {
+	for key in keys.iter() {
+		if key.state != ElementState::Pressed {
+			continue;
+		}
+		match key.key_code {
+			Some(KeyCode::Up) => selected.0 = selected.0.saturating_sub(1),
+			Some(KeyCode::Down) => selected.0 += 1,
+			Some(KeyCode::Return) => {
+				let server = match servers.0.get(selected.0) {
+					Some(server) if server.announcement.compatible() => server,
+					_ => continue,
+				};
+				*join_request = Some(JoinRequest {
+					address: ServerAddress::Remote(server.address.clone()),
+					name: recent
+						.as_ref()
+						.map(|recent| recent.player_name.clone())
+						.unwrap_or_else(|| RecentServers::default().player_name),
+				});
+				return state
+					.set(super::ClientState::Joining)
+					.expect("failed transitioning to the Joining state");
+			}
+			_ => (),
+		}
+	}
+	if selected.0 >= servers.0.len() {
+		selected.0 = servers.0.len().saturating_sub(1);
+	}
 }
 
-fn on_exit() {
+fn on_exit(mut discovery_cmd: EventWriter<DiscoveryCommand>) {
 	trace!("Client Loading State: Exit");
+	discovery_cmd.send(DiscoveryCommand::Stop);
 }
 
 fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::ClientState>>) {
@@ -34,9 +85,41 @@ fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::Cli
 	}
 }
 
-pub fn draw(_world: &WorldCell, f: &mut Frame) {
+pub fn draw(world: &WorldCell, f: &mut Frame) {
 	use tui::widgets::*;
 	let size = f.size();
 	let block = Block::default().title("Not Loaded").borders(Borders::ALL);
-	f.render_widget(block, size);
+	// Games found on the LAN, listed live as they are announced
+	let (lang, servers, selected) = match (
+		world.get_resource::<I18n>(),
+		world.get_resource::<DiscoveredServers>(),
+		world.get_resource::<SelectedServer>(),
+	) {
+		(Some(lang), Some(servers), Some(selected)) => (lang, servers, selected),
+		_ => return f.render_widget(block, size),
+	};
+	let mut items = vec![ListItem::new(
+		lang.get_attr("join-game", "lan").into_owned(),
+	)];
+	items.extend(
+		servers
+			.0
+			.iter()
+			.map(|server| ListItem::new(discovered_server_label(&lang, server))),
+	);
+	if servers.0.is_empty() {
+		items.push(ListItem::new(
+			lang.get_attr("join-game", "no-lan").into_owned(),
+		));
+	}
+	// Why the last game was left, below the games to join another
+	if let Some(Some(failure)) = world.get_resource::<Option<JoinFailure>>().as_deref() {
+		items.push(ListItem::new(join_failure_label(&lang, failure)));
+	}
+	let mut list_state = ListState::default();
+	if !servers.0.is_empty() {
+		list_state.select(Some(selected.0 + 1));
+	}
+	let list = List::new(items).block(block).highlight_symbol("> ");
+	f.render_stateful_widget(list, size, &mut list_state);
 }
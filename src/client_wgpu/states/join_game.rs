@@ -0,0 +1,269 @@
+use super::{JoinFailure, JoinRequest};
+use crate::universal::connection::{discovered_server_label, DiscoveryCommand, ServerAddress};
+use crate::universal::exit::Exiting;
+use crate::universal::i18n::{I18nLanguageChangedEvent, MsgCache, MsgKey};
+use crate::universal::recent_servers::RecentServers;
+use crate::universal::transport::lan::DiscoveredServers;
+use crate::universal::I18n;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+pub fn register_systems(app: &mut AppBuilder) {
+	let state = super::ClientState::JoinGame;
+	app.init_resource::<Option<JoinGameState>>()
+		.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+		.add_system_set(
+			SystemSet::on_update(state.clone())
+				.with_system(on_update.system())
+				.with_system(update_language.system())
+				.with_system(update_lan_games.system())
+				.with_system(on_shutdown.system()),
+		)
+		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
+}
+
+struct JoinGameState {
+	l_title: MsgCache,
+	l_address: MsgCache,
+	l_name: MsgCache,
+	l_join: MsgCache,
+	l_back: MsgCache,
+	l_recent: MsgCache,
+	l_no_recent: MsgCache,
+	l_password: MsgCache,
+	l_lan: MsgCache,
+	l_no_lan: MsgCache,
+	error_msg: Option<MsgCache>,
+	address: String,
+	name: String,
+	password: String,
+	recent_labels: Vec<(String, String, String)>,
+	/// Label and address of every compatible game found on the LAN.
+	lan_labels: Vec<(String, Option<String>)>,
+}
+
+impl JoinGameState {
+	fn new(lang: &I18n, recent: &RecentServers, failure: &Option<JoinFailure>) -> Self {
+		let mut state = Self {
+			l_title: MsgCache::new(MsgKey::new("join-game")),
+			l_address: MsgCache::new(MsgKey::new("join-game").with_attr("address")),
+			l_name: MsgCache::new(MsgKey::new("join-game").with_attr("name")),
+			l_join: MsgCache::new(MsgKey::new("join-game").with_attr("join")),
+			l_back: MsgCache::new(MsgKey::new("join-game").with_attr("back")),
+			l_recent: MsgCache::new(MsgKey::new("join-game").with_attr("recent")),
+			l_no_recent: MsgCache::new(MsgKey::new("join-game").with_attr("no-recent")),
+			l_password: MsgCache::new(MsgKey::new("join-game").with_attr("password")),
+			l_lan: MsgCache::new(MsgKey::new("join-game").with_attr("lan")),
+			l_no_lan: MsgCache::new(MsgKey::new("join-game").with_attr("no-lan")),
+			error_msg: failure.as_ref().map(|failure| {
+				MsgCache::new(MsgKey::new("join-error").with_attr(failure.msg_attr()))
+			}),
+			address: recent
+				.servers
+				.first()
+				.map(|server| server.address.clone())
+				.unwrap_or_else(|| "localhost".to_owned()),
+			name: recent.player_name.clone(),
+			password: String::new(),
+			recent_labels: recent
+				.servers
+				.iter()
+				.map(|server| {
+					(
+						format!("{} ({})", server.address, server.player_name),
+						server.address.clone(),
+						server.player_name.clone(),
+					)
+				})
+				.collect(),
+			lan_labels: Vec::new(),
+		};
+		state.update_language(lang, failure);
+		state
+	}
+
+	fn update_language(&mut self, lang: &I18n, failure: &Option<JoinFailure>) {
+		self.l_title.update(lang);
+		self.l_address.update(lang);
+		self.l_name.update(lang);
+		self.l_join.update(lang);
+		self.l_back.update(lang);
+		self.l_recent.update(lang);
+		self.l_no_recent.update(lang);
+		self.l_password.update(lang);
+		self.l_lan.update(lang);
+		self.l_no_lan.update(lang);
+		if let (Some(error_msg), Some(failure)) = (&mut self.error_msg, failure) {
+			error_msg.update_args_iter(lang, std::iter::once(("reason", failure.reason())));
+		}
+	}
+
+	fn update_lan_labels(&mut self, lang: &I18n, servers: &DiscoveredServers) {
+		self.lan_labels = servers
+			.0
+			.iter()
+			.map(|server| {
+				(
+					discovered_server_label(lang, server),
+					Some(server.address.clone()).filter(|_| server.announcement.compatible()),
+				)
+			})
+			.collect();
+	}
+
+	fn render(
+		&mut self,
+		ctx: &egui::CtxRef,
+		state: &mut State<super::ClientState>,
+		join_request: &mut Option<JoinRequest>,
+	) {
+		egui::CentralPanel::default().show(ctx, |ui| {
+			ui.vertical(|ui| {
+				ui.heading(self.l_title.as_str());
+				ui.separator();
+				ui.horizontal(|ui| {
+					ui.label(self.l_address.as_str());
+					ui.text_edit_singleline(&mut self.address);
+				});
+				ui.horizontal(|ui| {
+					ui.label(self.l_name.as_str());
+					ui.text_edit_singleline(&mut self.name);
+				});
+				ui.horizontal(|ui| {
+					ui.label(self.l_password.as_str());
+					ui.add(egui::TextEdit::singleline(&mut self.password).password(true));
+				});
+				if let Some(error_msg) = &self.error_msg {
+					ui.colored_label(egui::Color32::RED, error_msg.as_str());
+				}
+				ui.horizontal(|ui| {
+					let address = self.address.trim();
+					let name = self.name.trim();
+					if ui
+						.add(
+							egui::Button::new(self.l_join.as_str())
+								.enabled(!address.is_empty() && !name.is_empty()),
+						)
+						.clicked()
+					{
+						*join_request = Some(JoinRequest {
+							address: ServerAddress::Remote(address.to_owned()),
+							name: name.to_owned(),
+							password: Some(self.password.clone()).filter(|p| !p.is_empty()),
+							resume: None,
+						});
+						state
+							.set(super::ClientState::Joining)
+							.expect("failed transitioning to the Joining state");
+					}
+					if ui.button(self.l_back.as_str()).clicked() {
+						state
+							.set(super::ClientState::MainMenu)
+							.expect("failed transitioning to the MainMenu state");
+					}
+				});
+				ui.separator();
+				ui.heading(self.l_lan.as_str());
+				if self.lan_labels.is_empty() {
+					ui.label(self.l_no_lan.as_str());
+				}
+				for (label, lan_address) in &self.lan_labels {
+					match lan_address {
+						Some(lan_address) => {
+							let selected = &self.address == lan_address;
+							if ui.selectable_label(selected, label).clicked() {
+								self.address = lan_address.clone();
+							}
+						}
+						None => {
+							ui.add(egui::Label::new(label).weak());
+						}
+					}
+				}
+				ui.separator();
+				ui.heading(self.l_recent.as_str());
+				if self.recent_labels.is_empty() {
+					ui.label(self.l_no_recent.as_str());
+				}
+				let (recent_labels, address, name) =
+					(&self.recent_labels, &mut self.address, &mut self.name);
+				egui::ScrollArea::auto_sized().show(ui, |ui| {
+					for (label, recent_address, recent_name) in recent_labels {
+						let selected = address == recent_address;
+						if ui.selectable_label(selected, label).clicked() {
+							*address = recent_address.clone();
+							*name = recent_name.clone();
+						}
+					}
+				});
+			});
+		});
+	}
+}
+
+fn on_enter(
+	mut join_game_state: ResMut<Option<JoinGameState>>,
+	lang: Res<I18n>,
+	recent: Res<RecentServers>,
+	failure: Res<Option<JoinFailure>>,
+	mut discovery_cmd: EventWriter<DiscoveryCommand>,
+) {
+	trace!("Client JoinGame State: Enter");
+	*join_game_state = Some(JoinGameState::new(&lang, &recent, &failure));
+	discovery_cmd.send(DiscoveryCommand::Start);
+}
+
+fn on_update(
+	mut join_game_state: ResMut<Option<JoinGameState>>,
+	egui_ctx: Res<EguiContext>,
+	mut state: ResMut<State<super::ClientState>>,
+	mut join_request: ResMut<Option<JoinRequest>>,
+) {
+	if let Some(join_game_state) = &mut *join_game_state {
+		join_game_state.render(egui_ctx.ctx(), &mut state, &mut join_request);
+	}
+}
+
+fn update_language(
+	mut join_game_state: ResMut<Option<JoinGameState>>,
+	lang: Res<I18n>,
+	failure: Res<Option<JoinFailure>>,
+	servers: Res<DiscoveredServers>,
+	mut event: EventReader<I18nLanguageChangedEvent>,
+) {
+	if event.iter().next().is_some() {
+		if let Some(join_game_state) = &mut *join_game_state {
+			join_game_state.update_language(&lang, &failure);
+			join_game_state.update_lan_labels(&lang, &servers);
+		}
+	}
+}
+
+fn update_lan_games(
+	mut join_game_state: ResMut<Option<JoinGameState>>,
+	lang: Res<I18n>,
+	servers: Res<DiscoveredServers>,
+) {
+	if servers.is_changed() {
+		if let Some(join_game_state) = &mut *join_game_state {
+			join_game_state.update_lan_labels(&lang, &servers);
+		}
+	}
+}
+
+fn on_exit(
+	mut join_game_state: ResMut<Option<JoinGameState>>,
+	mut discovery_cmd: EventWriter<DiscoveryCommand>,
+) {
+	trace!("Client JoinGame State: Exit");
+	*join_game_state = None;
+	discovery_cmd.send(DiscoveryCommand::Stop);
+}
+
+fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::ClientState>>) {
+	if let Some(_exiting) = exiting {
+		state
+			.overwrite_replace(super::ClientState::Exiting)
+			.expect("Failed to transition Client to exiting state");
+	}
+}
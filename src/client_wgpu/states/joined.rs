@@ -0,0 +1,394 @@
+use super::{JoinFailure, JoinRequest, JoinedGame};
+use crate::universal::borders::{player_colour, BorderEdge};
+use crate::universal::commands::state::{GameState, PlayerInfo, TileVisibility, TurnStatus};
+use crate::universal::commands::{ClientCommand, ServerCommand};
+use crate::universal::connection::{
+	Connection, ConnectionCommand, ConnectionEvent, ServerAddress, ServerMessage,
+};
+use crate::universal::exit::Exiting;
+use crate::universal::i18n::{I18nLanguageChangedEvent, MsgCache, MsgKey};
+use crate::universal::ids::PlayerId;
+use crate::universal::local_server::LocalServerCommand;
+use crate::universal::I18n;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+/// Size of the hexes on the map from their center to a corner, in points.
+const HEX_SIZE: f32 = 12.0;
+
+pub fn register_systems(app: &mut AppBuilder) {
+	let state = super::ClientState::Joined;
+	app.init_resource::<Option<JoinedState>>()
+		.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+		.add_system_set(
+			SystemSet::on_update(state.clone())
+				.with_system(on_update.system())
+				.with_system(update_language.system())
+				.with_system(on_connection_event.system())
+				.with_system(on_server_message.system())
+				.with_system(on_replica_changed.system())
+				.with_system(on_shutdown.system()),
+		)
+		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
+}
+
+struct JoinedState {
+	l_joined: MsgCache,
+	l_turn: MsgCache,
+	l_players: MsgCache,
+	l_leave: MsgCache,
+	l_away: MsgCache,
+	l_end_turn: MsgCache,
+	l_your_turn: MsgCache,
+	l_waiting: MsgCache,
+	l_time_left: MsgCache,
+	turn: u32,
+	turn_status: TurnStatus,
+	own_player: PlayerId,
+	players: Vec<PlayerInfo>,
+	player_labels: Vec<String>,
+	borders: Vec<BorderEdge>,
+}
+
+impl JoinedState {
+	fn new(lang: &I18n, joined: &JoinedGame) -> Self {
+		let mut l_joined = MsgCache::new(MsgKey::new("joined"));
+		l_joined.update_args_iter(
+			lang,
+			vec![
+				("address", joined.address.to_string()),
+				("name", joined.name.clone()),
+			],
+		);
+		let mut state = Self {
+			l_joined,
+			l_turn: MsgCache::new(MsgKey::new("joined").with_attr("turn")),
+			l_players: MsgCache::new(MsgKey::new("joined").with_attr("players")),
+			l_leave: MsgCache::new(MsgKey::new("joined").with_attr("leave")),
+			l_away: MsgCache::new(MsgKey::new("joined").with_attr("away")),
+			l_end_turn: MsgCache::new(MsgKey::new("joined").with_attr("end-turn")),
+			l_your_turn: MsgCache::new(MsgKey::new("joined").with_attr("your-turn")),
+			l_waiting: MsgCache::new(MsgKey::new("joined").with_attr("waiting")),
+			l_time_left: MsgCache::new(MsgKey::new("joined").with_attr("time-left")),
+			turn: 0,
+			turn_status: TurnStatus::default(),
+			own_player: joined.player,
+			players: vec![],
+			player_labels: vec![],
+			borders: vec![],
+		};
+		state.update_language(lang);
+		state
+	}
+
+	fn update_language(&mut self, lang: &I18n) {
+		self.l_players.update(lang);
+		self.l_leave.update(lang);
+		self.l_away.update(lang);
+		self.l_end_turn.update(lang);
+		self.l_your_turn.update(lang);
+		self.update_turn(lang, self.turn);
+		self.update_player_labels();
+		self.update_turn_status(lang, self.turn_status.clone());
+	}
+
+	fn update_turn(&mut self, lang: &I18n, turn: u32) {
+		self.turn = turn;
+		self.l_turn
+			.update_args_iter(lang, std::iter::once(("turn", turn)));
+	}
+
+	fn update_turn_status(&mut self, lang: &I18n, status: TurnStatus) {
+		let waiting_for: Vec<&str> = self
+			.players
+			.iter()
+			.filter(|p| status.to_move.contains(&p.id))
+			.map(|p| p.name.as_str())
+			.collect();
+		self.l_waiting
+			.update_args_iter(lang, std::iter::once(("players", waiting_for.join(", "))));
+		self.l_time_left.update_args_iter(
+			lang,
+			std::iter::once(("seconds", status.seconds_left.unwrap_or_default())),
+		);
+		self.turn_status = status;
+	}
+
+	fn is_own_turn(&self) -> bool {
+		self.turn_status.to_move.contains(&self.own_player)
+	}
+
+	fn update_players(&mut self, players: Vec<PlayerInfo>) {
+		self.players = players;
+		self.update_player_labels();
+	}
+
+	fn set_away(&mut self, player: PlayerId, away: bool) {
+		if let Some(info) = self.players.iter_mut().find(|p| p.id == player) {
+			info.away = away;
+		}
+		self.update_player_labels();
+	}
+
+	fn update_player_labels(&mut self) {
+		let l_away = self.l_away.as_str();
+		self.player_labels = self
+			.players
+			.iter()
+			.map(|player| {
+				if player.away {
+					format!("{} ({}) - {}", player.name, player.civ, l_away)
+				} else {
+					format!("{} ({})", player.name, player.civ)
+				}
+			})
+			.collect();
+	}
+}
+
+/// Everything needed to leave the joined game.
+#[derive(SystemParam)]
+pub struct Leave<'a> {
+	joined: ResMut<'a, Option<JoinedGame>>,
+	failure: ResMut<'a, Option<JoinFailure>>,
+	state: ResMut<'a, State<super::ClientState>>,
+	connection_cmd: EventWriter<'a, ConnectionCommand>,
+	local_server_cmd: EventWriter<'a, LocalServerCommand>,
+	join_request: ResMut<'a, Option<JoinRequest>>,
+}
+
+impl<'a> Leave<'a> {
+	/// Leave the game, going back to where the player came from.
+	fn leave(&mut self, failure: Option<JoinFailure>) {
+		// Already left if another system got to it first this update.
+		let joined = match self.joined.take() {
+			Some(joined) => joined,
+			None => return,
+		};
+		*self.failure = failure;
+		self.connection_cmd.send(ConnectionCommand::Disconnect);
+		let next = match joined.address {
+			ServerAddress::Local => {
+				self.local_server_cmd
+					.send(LocalServerCommand::StopServer { force: false });
+				super::ClientState::MainMenu
+			}
+			ServerAddress::Remote(_) => super::ClientState::JoinGame,
+		};
+		self.state
+			.set(next)
+			.expect("failed transitioning out of the Joined state");
+	}
+
+	/// The connection dropped, reconnect to a remote server and take back the seat it keeps for
+	/// us.  The local server cannot drop its connection so losing it ends the game.
+	fn reconnect(&mut self, reason: &str) {
+		let joined = match &*self.joined {
+			Some(joined) if joined.address != ServerAddress::Local => joined,
+			_ => return self.leave(Some(JoinFailure::Lost(reason.to_owned()))),
+		};
+		info!(
+			"Lost the connection to `{}`, reconnecting: {}",
+			joined.address, reason
+		);
+		*self.join_request = Some(JoinRequest {
+			address: joined.address.clone(),
+			name: joined.name.clone(),
+			password: joined.password.clone(),
+			resume: Some(joined.token),
+		});
+		*self.joined = None;
+		self.state
+			.set(super::ClientState::Joining)
+			.expect("failed transitioning to the Joining state");
+	}
+}
+
+fn on_enter(
+	mut joined_state: ResMut<Option<JoinedState>>,
+	joined: Res<Option<JoinedGame>>,
+	mut connection: ResMut<Option<Connection>>,
+	lang: Res<I18n>,
+) {
+	trace!("Client Joined State: Enter");
+	if let Some(joined) = &*joined {
+		*joined_state = Some(JoinedState::new(&lang, joined));
+	}
+	if let Some(conn) = &mut *connection {
+		if let Err(e) = conn.send(ClientCommand::RequestState) {
+			warn!("Failed requesting the game state: {}", e);
+		}
+	}
+}
+
+fn on_update(
+	joined_state: Res<Option<JoinedState>>,
+	replica: Res<Option<GameState>>,
+	egui_ctx: Res<EguiContext>,
+	mut connection: ResMut<Option<Connection>>,
+	mut leave: Leave,
+) {
+	let joined_state = match &*joined_state {
+		Some(joined_state) => joined_state,
+		None => return,
+	};
+	let mut leaving = false;
+	let mut ending_turn = false;
+	egui::TopPanel::top("joined_title").show(egui_ctx.ctx(), |ui| {
+		ui.horizontal(|ui| {
+			ui.heading(joined_state.l_joined.as_str());
+			ui.label(joined_state.l_turn.as_str());
+			if joined_state.is_own_turn() {
+				ui.label(joined_state.l_your_turn.as_str());
+				ending_turn = ui.button(joined_state.l_end_turn.as_str()).clicked();
+			} else if !joined_state.turn_status.to_move.is_empty() {
+				ui.label(joined_state.l_waiting.as_str());
+			}
+			if joined_state.turn_status.seconds_left.is_some() {
+				ui.label(joined_state.l_time_left.as_str());
+			}
+			leaving = ui.button(joined_state.l_leave.as_str()).clicked();
+		});
+	});
+	if ending_turn {
+		if let Some(conn) = &mut *connection {
+			if let Err(e) = conn.send(ClientCommand::EndTurn) {
+				warn!("Failed ending the turn: {}", e);
+			}
+		}
+	}
+	egui::SidePanel::left("joined_players", 200.0).show(egui_ctx.ctx(), |ui| {
+		ui.heading(joined_state.l_players.as_str());
+		for (player, label) in joined_state.players.iter().zip(&joined_state.player_labels) {
+			ui.add(egui::SelectableLabel::new(
+				player.id == joined_state.own_player,
+				label,
+			));
+		}
+	});
+	egui::CentralPanel::default().show(egui_ctx.ctx(), |ui| {
+		if let Some(game) = &*replica {
+			draw_map(ui, game, &joined_state.borders);
+		}
+	});
+	if leaving {
+		leave.leave(None);
+	}
+}
+
+/// Draw the explored tiles of the map with the borders of the players just inside their territory.
+fn draw_map(ui: &mut egui::Ui, game: &GameState, borders: &[BorderEdge]) {
+	let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::hover());
+	let origin = response.rect.min + egui::vec2(HEX_SIZE, HEX_SIZE);
+	let at = |(x, y): (f32, f32)| origin + egui::vec2(x, y);
+	for (hex, tile) in game.map.iter() {
+		let fill = match game.visibility(hex) {
+			TileVisibility::Unexplored => continue,
+			TileVisibility::Fogged if tile.terrain.is_water() => {
+				egui::Color32::from_rgb(20, 35, 70)
+			}
+			TileVisibility::Fogged => egui::Color32::from_rgb(40, 60, 30),
+			TileVisibility::Visible if tile.terrain.is_water() => {
+				egui::Color32::from_rgb(30, 60, 120)
+			}
+			TileVisibility::Visible => egui::Color32::from_rgb(70, 110, 50),
+		};
+		painter.circle_filled(at(hex.to_pixel(HEX_SIZE)), HEX_SIZE * 0.8, fill);
+	}
+	for edge in borders {
+		let (x, y) = edge.hex.to_pixel(HEX_SIZE);
+		let [(x0, y0), (x1, y1)] = edge.corners(HEX_SIZE * 0.9);
+		let [r, g, b] = player_colour(edge.owner);
+		painter.line_segment(
+			[at((x + x0, y + y0)), at((x + x1, y + y1))],
+			egui::Stroke::new(2.0, egui::Color32::from_rgb(r, g, b)),
+		);
+	}
+}
+
+fn update_language(
+	mut joined_state: ResMut<Option<JoinedState>>,
+	lang: Res<I18n>,
+	mut event: EventReader<I18nLanguageChangedEvent>,
+) {
+	if event.iter().next().is_some() {
+		if let Some(joined_state) = &mut *joined_state {
+			joined_state.update_language(&lang);
+		}
+	}
+}
+
+fn on_connection_event(mut events: EventReader<ConnectionEvent>, mut leave: Leave) {
+	for event in events.iter() {
+		if let ConnectionEvent::Disconnected(reason) = event {
+			return leave.reconnect(reason);
+		}
+	}
+}
+
+fn on_server_message(
+	mut joined_state: ResMut<Option<JoinedState>>,
+	mut messages: EventReader<ServerMessage>,
+	lang: Res<I18n>,
+	mut leave: Leave,
+) {
+	let joined_state = match &mut *joined_state {
+		Some(joined_state) => joined_state,
+		None => return,
+	};
+	for ServerMessage(message) in messages.iter() {
+		match &message.command {
+			ServerCommand::TurnStarted { turn } => joined_state.update_turn(&lang, *turn),
+			ServerCommand::PlayerJoined { player, name } => {
+				info!("{:?} joined as {:?}", name, player);
+				joined_state.set_away(*player, false);
+			}
+			ServerCommand::PlayerAway { player } => {
+				info!("{:?} is away", player);
+				joined_state.set_away(*player, true);
+			}
+			ServerCommand::PlayerLeft { player } => {
+				info!("{:?} left", player);
+				joined_state.set_away(*player, false);
+			}
+			ServerCommand::GameEnded => return leave.leave(Some(JoinFailure::GameEnded)),
+			command => trace!("Unhandled server message: {:?}", command),
+		}
+	}
+}
+
+fn on_replica_changed(
+	mut joined_state: ResMut<Option<JoinedState>>,
+	replica: Res<Option<GameState>>,
+	lang: Res<I18n>,
+) {
+	if !replica.is_changed() {
+		return;
+	}
+	if let (Some(joined_state), Some(game)) = (&mut *joined_state, &*replica) {
+		if joined_state.turn != game.turn {
+			joined_state.update_turn(&lang, game.turn);
+		}
+		if joined_state.players != game.players {
+			joined_state.update_players(game.players.clone());
+		}
+		if joined_state.turn_status != game.turn_status {
+			joined_state.update_turn_status(&lang, game.turn_status.clone());
+		}
+		joined_state.borders = game.borders();
+	}
+}
+
+fn on_exit(mut joined_state: ResMut<Option<JoinedState>>) {
+	trace!("Client Joined State: Exit");
+	*joined_state = None;
+}
+
+fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::ClientState>>) {
+	if let Some(_exiting) = exiting {
+		state
+			.overwrite_replace(super::ClientState::Exiting)
+			.expect("Failed to transition Client to exiting state");
+	}
+}
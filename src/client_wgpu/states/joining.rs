@@ -0,0 +1,299 @@
+use super::{JoinFailure, JoinRequest, JoinedGame};
+use crate::universal::commands::{ClientCommand, ServerCommand};
+use crate::universal::connection::{
+	Connection, ConnectionCommand, ConnectionEvent, ServerAddress, ServerMessage,
+};
+use crate::universal::exit::Exiting;
+use crate::universal::i18n::{MsgCache, MsgKey};
+use crate::universal::local_server::LocalServerCommand;
+use crate::universal::recent_servers::RecentServers;
+use crate::universal::{ConfigDirectory, I18n};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use std::time::{Duration, Instant};
+
+/// How long connecting and joining may take before giving up.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to keep trying to resume after the connection dropped, the server keeps the seat for
+/// the grace period of its save so giving up early only loses the seat for the player.
+const RESUME_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long to wait before connecting again when resuming fails to connect.
+const RESUME_RETRY_INTERVAL: Duration = Duration::from_secs(3);
+
+pub fn register_systems(app: &mut AppBuilder) {
+	let state = super::ClientState::Joining;
+	app.init_resource::<Option<JoiningState>>()
+		.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+		.add_system_set(
+			SystemSet::on_update(state.clone())
+				.with_system(on_update.system())
+				.with_system(on_connection_event.system())
+				.with_system(on_server_message.system())
+				.with_system(on_shutdown.system()),
+		)
+		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
+}
+
+struct JoiningState {
+	request: JoinRequest,
+	started: Instant,
+	/// The sequence number of the `Join` command once connected.
+	join_seq: Option<u64>,
+	/// When to connect again while resuming.
+	retry_at: Option<Instant>,
+	l_joining: MsgCache,
+	l_cancel: MsgCache,
+	l_waited: MsgCache,
+	waited_secs: u64,
+}
+
+impl JoiningState {
+	fn new(lang: &I18n, request: JoinRequest) -> Self {
+		let mut l_joining = match request.resume {
+			Some(_) => MsgCache::new(MsgKey::new("joining").with_attr("resuming")),
+			None => MsgCache::new(MsgKey::new("joining")),
+		};
+		l_joining.update_args_iter(
+			lang,
+			std::iter::once(("address", request.address.to_string())),
+		);
+		let mut l_cancel = MsgCache::new(MsgKey::new("joining").with_attr("cancel"));
+		l_cancel.update(lang);
+		let mut state = Self {
+			request,
+			started: Instant::now(),
+			join_seq: None,
+			retry_at: None,
+			l_joining,
+			l_cancel,
+			l_waited: MsgCache::new(MsgKey::new("joining").with_attr("waited")),
+			waited_secs: 0,
+		};
+		state.update_waited(lang);
+		state
+	}
+
+	fn timeout(&self) -> Duration {
+		match self.request.resume {
+			Some(_) => RESUME_TIMEOUT,
+			None => JOIN_TIMEOUT,
+		}
+	}
+
+	fn connect(&self) -> ConnectionCommand {
+		ConnectionCommand::Connect {
+			address: self.request.address.clone(),
+			password: self.request.password.clone(),
+		}
+	}
+
+	fn update_waited(&mut self, lang: &I18n) {
+		self.l_waited
+			.update_args_iter(lang, std::iter::once(("seconds", self.waited_secs)));
+	}
+}
+
+/// Everything needed to give up joining.
+#[derive(SystemParam)]
+pub struct Fail<'a> {
+	failure: ResMut<'a, Option<JoinFailure>>,
+	state: ResMut<'a, State<super::ClientState>>,
+	connection_cmd: EventWriter<'a, ConnectionCommand>,
+}
+
+impl<'a> Fail<'a> {
+	/// Give up joining, going back to where the player can try again.
+	fn fail(&mut self, request: &JoinRequest, reason: JoinFailure) {
+		// Already failed if another system got to it first this update.
+		if self.failure.is_some() {
+			return;
+		}
+		warn!("Failed joining `{}`: {:?}", request.address, reason);
+		self.connection_cmd.send(ConnectionCommand::Disconnect);
+		*self.failure = Some(reason);
+		let next = match request.address {
+			ServerAddress::Local => super::ClientState::MainMenu,
+			ServerAddress::Remote(_) => super::ClientState::JoinGame,
+		};
+		self.state
+			.set(next)
+			.expect("failed transitioning out of the Joining state");
+	}
+}
+
+fn on_enter(
+	mut joining_state: ResMut<Option<JoiningState>>,
+	mut join_request: ResMut<Option<JoinRequest>>,
+	mut state: ResMut<State<super::ClientState>>,
+	mut failure: ResMut<Option<JoinFailure>>,
+	mut connection_cmd: EventWriter<ConnectionCommand>,
+	lang: Res<I18n>,
+) {
+	trace!("Client Joining State: Enter");
+	match join_request.take() {
+		Some(request) => {
+			*failure = None;
+			let joining = JoiningState::new(&lang, request);
+			connection_cmd.send(joining.connect());
+			*joining_state = Some(joining);
+		}
+		None => {
+			error!("Entered the Joining state without a server to join");
+			state
+				.set(super::ClientState::MainMenu)
+				.expect("failed transitioning to the MainMenu state");
+		}
+	}
+}
+
+fn on_update(
+	mut joining_state: ResMut<Option<JoiningState>>,
+	egui_ctx: Res<EguiContext>,
+	lang: Res<I18n>,
+	mut fail: Fail,
+	mut local_server_cmd: EventWriter<LocalServerCommand>,
+) {
+	let joining = match &mut *joining_state {
+		Some(joining) => joining,
+		None => return,
+	};
+	let waited = joining.started.elapsed();
+	if waited.as_secs() != joining.waited_secs {
+		joining.waited_secs = waited.as_secs();
+		joining.update_waited(&lang);
+	}
+	if waited > joining.timeout() {
+		return fail.fail(&joining.request, JoinFailure::TimedOut);
+	}
+	if matches!(joining.retry_at, Some(retry_at) if retry_at <= Instant::now()) {
+		joining.retry_at = None;
+		fail.connection_cmd.send(joining.connect());
+	}
+	let mut cancelled = false;
+	egui::CentralPanel::default().show(egui_ctx.ctx(), |ui| {
+		ui.vertical_centered(|ui| {
+			ui.heading(joining.l_joining.as_str());
+			ui.label(joining.l_waited.as_str());
+			cancelled = ui.button(joining.l_cancel.as_str()).clicked();
+		});
+	});
+	if cancelled {
+		info!("Cancelled joining `{}`", joining.request.address);
+		fail.connection_cmd.send(ConnectionCommand::Disconnect);
+		let next = match joining.request.address {
+			ServerAddress::Local => {
+				local_server_cmd.send(LocalServerCommand::StopServer { force: false });
+				super::ClientState::MainMenu
+			}
+			ServerAddress::Remote(_) => super::ClientState::JoinGame,
+		};
+		fail.state
+			.set(next)
+			.expect("failed transitioning out of the Joining state");
+	}
+}
+
+fn on_connection_event(
+	mut joining_state: ResMut<Option<JoiningState>>,
+	mut events: EventReader<ConnectionEvent>,
+	mut connection: ResMut<Option<Connection>>,
+	mut fail: Fail,
+) {
+	let joining = match &mut *joining_state {
+		Some(joining) => joining,
+		None => return,
+	};
+	for event in events.iter() {
+		match event {
+			ConnectionEvent::Connected(address) if *address == joining.request.address => {
+				let join = match joining.request.resume {
+					Some(token) => ClientCommand::Resume { token },
+					None => ClientCommand::Join {
+						name: joining.request.name.clone(),
+						player: None,
+					},
+				};
+				match connection.as_mut().map(|conn| conn.send(join)) {
+					Some(Ok(seq)) => joining.join_seq = Some(seq),
+					Some(Err(e)) => {
+						return fail.fail(&joining.request, JoinFailure::Connection(e.to_string()))
+					}
+					None => (),
+				}
+			}
+			ConnectionEvent::Connected(_) => (),
+			ConnectionEvent::Disconnected(reason) if joining.request.resume.is_some() => {
+				debug!("Failed resuming, trying again: {}", reason);
+				joining.join_seq = None;
+				joining.retry_at = Some(Instant::now() + RESUME_RETRY_INTERVAL);
+			}
+			ConnectionEvent::Disconnected(reason) => {
+				return fail.fail(&joining.request, JoinFailure::Connection(reason.clone()));
+			}
+		}
+	}
+}
+
+fn on_server_message(
+	joining_state: Res<Option<JoiningState>>,
+	mut messages: EventReader<ServerMessage>,
+	mut joined: ResMut<Option<JoinedGame>>,
+	mut recent: ResMut<RecentServers>,
+	config_dir: Option<Res<ConfigDirectory>>,
+	mut fail: Fail,
+) {
+	let joining = match &*joining_state {
+		Some(joining) => joining,
+		None => return,
+	};
+	for ServerMessage(message) in messages.iter() {
+		match &message.command {
+			ServerCommand::Joined { player, token } => {
+				info!(
+					"Joined `{}` as {:?} playing {:?}",
+					joining.request.address, joining.request.name, player
+				);
+				match &joining.request.address {
+					ServerAddress::Remote(address) => recent.record(address, &joining.request.name),
+					ServerAddress::Local => recent.player_name = joining.request.name.clone(),
+				}
+				if let Some(config_dir) = &config_dir {
+					if let Err(e) = recent.save(&config_dir.0) {
+						warn!("Failed saving recent servers: {}", e);
+					}
+				}
+				*joined = Some(JoinedGame {
+					address: joining.request.address.clone(),
+					name: joining.request.name.clone(),
+					password: joining.request.password.clone(),
+					player: *player,
+					token: *token,
+				});
+				fail.state
+					.set(super::ClientState::Joined)
+					.expect("failed transitioning to the Joined state");
+				return;
+			}
+			ServerCommand::Rejected { seq, reason } if Some(*seq) == joining.join_seq => {
+				return fail.fail(&joining.request, JoinFailure::Rejected(reason.clone()));
+			}
+			command => trace!("Ignoring message while joining: {:?}", command),
+		}
+	}
+}
+
+fn on_exit(mut joining_state: ResMut<Option<JoiningState>>) {
+	trace!("Client Joining State: Exit");
+	*joining_state = None;
+}
+
+fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::ClientState>>) {
+	if let Some(_exiting) = exiting {
+		state
+			.overwrite_replace(super::ClientState::Exiting)
+			.expect("Failed to transition Client to exiting state");
+	}
+}
@@ -1,13 +1,20 @@
+use super::JoinRequest;
+use crate::universal::connection::ServerAddress;
 use crate::universal::exit::{Exiting, RequestExit};
 use crate::universal::i18n::{
 	scan_languages_on_fs, I18nChangeLanguageTo, I18nLanguageChangedEvent, MsgCache, MsgKey,
 };
-use crate::universal::local_server::{LocalServerCommand, LocalServerPublicState};
+use crate::universal::local_server::{
+	LocalServerCommand, LocalServerPublicState, LocalServerSaveCommand, LocalServerSaveList,
+	SaveInfo, SAVES_DIRECTORY,
+};
+use crate::universal::recent_servers::RecentServers;
 use crate::universal::I18n;
 use bevy::prelude::*;
 use bevy_egui::egui::Ui;
 use bevy_egui::{egui, EguiContext, EguiPlugin, EguiSettings};
 use fluent::types::{FluentNumber, FluentNumberOptions, FluentNumberStyle};
+use fluent::FluentValue;
 use std::path::PathBuf;
 
 pub fn register_systems(app: &mut AppBuilder) {
@@ -21,6 +28,7 @@ pub fn register_systems(app: &mut AppBuilder) {
 				.with_system(on_update.system())
 				.with_system(update_language.system())
 				.with_system(update_local_server_state.system())
+				.with_system(update_local_server_saves.system())
 				.with_system(on_shutdown.system()),
 		)
 		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
@@ -44,10 +52,26 @@ fn update_language(
 	}
 }
 
+fn update_local_server_saves(
+	mut main_menu_state: ResMut<Option<MainMenuState>>,
+	lang: Res<I18n>,
+	mut save_list: EventReader<LocalServerSaveList>,
+) {
+	if let Some(save_list) = save_list.iter().last() {
+		if let Some(main_menu_state) = &mut *main_menu_state {
+			main_menu_state.saves = save_list.0.clone();
+			main_menu_state.update_save_labels(&lang);
+		}
+	}
+}
+
 fn update_local_server_state(
 	mut main_menu_state: ResMut<Option<MainMenuState>>,
 	lang: Res<I18n>,
 	mut state: EventReader<LocalServerPublicState>,
+	mut client_state: ResMut<State<super::ClientState>>,
+	mut join_request: ResMut<Option<JoinRequest>>,
+	recent: Res<RecentServers>,
 ) {
 	if let Some(state) = state.iter().last() {
 		if let Some(main_menu_state) = &mut *main_menu_state {
@@ -77,11 +101,28 @@ fn update_local_server_state(
 						);
 				}
 				LocalServerPublicState::Running => {
+					if main_menu_state.screen == MainMenuScreen::LoadJoinLocalServer {
+						*join_request = Some(JoinRequest {
+							address: ServerAddress::Local,
+							name: recent.player_name.clone(),
+							password: None,
+							resume: None,
+						});
+						client_state
+							.set(super::ClientState::Joining)
+							.expect("failed transitioning to the Joining state");
+					}
 					main_menu_state
 						.local_server_state_msg
 						.attr("running")
 						.update(&*lang);
 				}
+				LocalServerPublicState::Paused => {
+					main_menu_state
+						.local_server_state_msg
+						.attr("paused")
+						.update(&*lang);
+				}
 				LocalServerPublicState::ShuttingDown => {
 					main_menu_state
 						.local_server_state_msg
@@ -98,7 +139,6 @@ enum MainMenuScreen {
 	Empty,
 	LocalServer,
 	LoadJoinLocalServer,
-	JoinServer,
 	Settings,
 }
 
@@ -116,7 +156,12 @@ struct MainMenuState {
 	l_server_local: MsgCache,
 	l_server_local_starting: MsgCache,
 	l_server_local_starting_cancel: MsgCache,
-	l_server_local_test: MsgCache,
+	l_server_local_new: MsgCache,
+	l_server_local_new_name: MsgCache,
+	l_server_local_load: MsgCache,
+	l_server_local_delete: MsgCache,
+	l_server_local_duplicate: MsgCache,
+	l_server_local_no_saves: MsgCache,
 	l_server_join: MsgCache,
 	l_settings_title: MsgCache,
 	l_settings_cancel: MsgCache,
@@ -124,6 +169,10 @@ struct MainMenuState {
 	l_settings_choose_language: MsgCache,
 	screen: MainMenuScreen,
 	local_server_state_msg: MsgCache,
+	saves: Vec<SaveInfo>,
+	save_labels: Vec<String>,
+	selected_save: Option<PathBuf>,
+	new_save_name: String,
 }
 
 impl Default for MainMenuState {
@@ -138,7 +187,20 @@ impl Default for MainMenuState {
 			l_server_local_starting_cancel: MsgCache::new(
 				MsgKey::new("menu-server-starting").with_attr("cancel"),
 			),
-			l_server_local_test: MsgCache::new(MsgKey::new("menu-server-local").with_attr("test")),
+			l_server_local_new: MsgCache::new(MsgKey::new("menu-server-local").with_attr("new")),
+			l_server_local_new_name: MsgCache::new(
+				MsgKey::new("menu-server-local").with_attr("new-name"),
+			),
+			l_server_local_load: MsgCache::new(MsgKey::new("menu-server-local").with_attr("load")),
+			l_server_local_delete: MsgCache::new(
+				MsgKey::new("menu-server-local").with_attr("delete"),
+			),
+			l_server_local_duplicate: MsgCache::new(
+				MsgKey::new("menu-server-local").with_attr("duplicate"),
+			),
+			l_server_local_no_saves: MsgCache::new(
+				MsgKey::new("menu-server-local").with_attr("no-saves"),
+			),
 			l_server_join: MsgCache::new(MsgKey::new("menu-server-join")),
 			l_settings_title: MsgCache::new(MsgKey::new("settings-title")),
 			l_settings_cancel: MsgCache::new(MsgKey::new("settings-cancel")),
@@ -146,6 +208,10 @@ impl Default for MainMenuState {
 			l_settings_choose_language: MsgCache::new(MsgKey::new("settings_choose_language")),
 			screen: Default::default(),
 			local_server_state_msg: MsgCache::new(MsgKey::new("local-server-state")),
+			saves: vec![],
+			save_labels: vec![],
+			selected_save: None,
+			new_save_name: "local".to_string(),
 		}
 	}
 }
@@ -171,12 +237,26 @@ impl MainMenuState {
 		self.l_server_local.update(lang);
 		self.l_server_local_starting.update(lang);
 		self.l_server_local_starting_cancel.update(lang);
-		self.l_server_local_test.update(lang);
+		self.l_server_local_new.update(lang);
+		self.l_server_local_new_name.update(lang);
+		self.l_server_local_load.update(lang);
+		self.l_server_local_delete.update(lang);
+		self.l_server_local_duplicate.update(lang);
+		self.l_server_local_no_saves.update(lang);
 		self.l_server_join.update(lang);
 		self.l_settings_title.update(lang);
 		self.l_settings_cancel.update(lang);
 		self.l_settings_current_language.update(lang);
 		self.l_settings_choose_language.update(lang);
+		self.update_save_labels(lang);
+	}
+
+	fn update_save_labels(&mut self, lang: &I18n) {
+		self.save_labels = self
+			.saves
+			.iter()
+			.map(|save| save_label(lang, save))
+			.collect();
 	}
 
 	fn render(
@@ -187,6 +267,7 @@ impl MainMenuState {
 		change_lang: &mut EventWriter<I18nChangeLanguageTo>,
 		local_server_state: &Option<Res<LocalServerPublicState>>,
 		local_server_cmd: &mut EventWriter<LocalServerCommand>,
+		local_server_save_cmd: &mut EventWriter<LocalServerSaveCommand>,
 		exit: &mut EventWriter<RequestExit>,
 	) {
 		egui::TopPanel::top("top_title").show(e.ctx(), |ui| {
@@ -197,17 +278,23 @@ impl MainMenuState {
 		if self.screen == MainMenuScreen::LoadJoinLocalServer {
 			self.loading_local_server(e.ctx(), local_server_state, local_server_cmd);
 		} else {
+			let was_local_server = self.screen == MainMenuScreen::LocalServer;
 			egui::SidePanel::left("news_panel", 150.0).show(e.ctx(), |ui| {
-				self.render_main_menu(ui, local_server_state, exit);
+				self.render_main_menu(ui, state, local_server_state, exit);
 			});
+			if !was_local_server && self.screen == MainMenuScreen::LocalServer {
+				local_server_save_cmd.send(LocalServerSaveCommand::List);
+			}
 			egui::CentralPanel::default().show(e.ctx(), |ui| {
 				match self.screen {
 					MainMenuScreen::Empty => (),
-					MainMenuScreen::LocalServer => {
-						self.render_server_local(ui, local_server_state, local_server_cmd)
-					}
+					MainMenuScreen::LocalServer => self.render_server_local(
+						ui,
+						local_server_state,
+						local_server_cmd,
+						local_server_save_cmd,
+					),
 					MainMenuScreen::LoadJoinLocalServer => (),
-					MainMenuScreen::JoinServer => self.render_server_join(ui, state),
 					MainMenuScreen::Settings => self.render_settings(ui, state, change_lang),
 				};
 			});
@@ -241,6 +328,7 @@ impl MainMenuState {
 	fn render_main_menu(
 		&mut self,
 		ui: &mut Ui,
+		state: &mut ResMut<State<super::ClientState>>,
 		local_server_state: &Option<Res<LocalServerPublicState>>,
 		exit: &mut EventWriter<RequestExit>,
 	) {
@@ -263,12 +351,11 @@ impl MainMenuState {
 					self.l_server_local.as_str(),
 				);
 			}
-			menu_btn(
-				ui,
-				&mut self.screen,
-				MainMenuScreen::JoinServer,
-				self.l_server_join.as_str(),
-			);
+			if ui.button(self.l_server_join.as_str()).clicked() {
+				state
+					.set(super::ClientState::JoinGame)
+					.expect("failed transitioning to the JoinGame state");
+			}
 			menu_btn(
 				ui,
 				&mut self.screen,
@@ -287,26 +374,97 @@ impl MainMenuState {
 		ui: &mut Ui,
 		local_server_exists: &Option<Res<LocalServerPublicState>>,
 		local_server_cmd: &mut EventWriter<LocalServerCommand>,
+		local_server_save_cmd: &mut EventWriter<LocalServerSaveCommand>,
 	) {
-		ui.centered_and_justified(|ui| {
-			ui.vertical(|ui| {
-				if local_server_exists.is_some() {
-					if ui.button(self.l_server_local_test.as_str()).clicked() {
+		if local_server_exists.is_none() {
+			return;
+		}
+		ui.vertical(|ui| {
+			ui.horizontal(|ui| {
+				ui.label(self.l_server_local_new_name.as_str());
+				ui.text_edit_singleline(&mut self.new_save_name);
+				let name = self.new_save_name.trim();
+				let valid_name = !name.is_empty()
+					&& name != "." && name != ".."
+					&& !name.contains(&['/', '\\'][..]);
+				if ui
+					.add(egui::Button::new(self.l_server_local_new.as_str()).enabled(valid_name))
+					.clicked()
+				{
+					local_server_cmd.send(LocalServerCommand::CreateStartServer {
+						path: PathBuf::from(SAVES_DIRECTORY).join(name),
+						config_only_if_not_existing: false,
+					});
+					self.screen = MainMenuScreen::LoadJoinLocalServer;
+				}
+			});
+			ui.separator();
+			if self.saves.is_empty() {
+				ui.label(self.l_server_local_no_saves.as_str());
+			}
+			let (saves, save_labels) = (&self.saves, &self.save_labels);
+			let selected_save = &mut self.selected_save;
+			egui::ScrollArea::from_max_height(ui.available_size().y - 40.0).show(ui, |ui| {
+				for (save, label) in saves.iter().zip(save_labels) {
+					let selected = selected_save.as_ref() == Some(&save.path);
+					if ui.selectable_label(selected, label).clicked() {
+						*selected_save = Some(save.path.clone());
+					}
+				}
+			});
+			let selected = self
+				.selected_save
+				.as_ref()
+				.and_then(|path| self.saves.iter().find(|save| &save.path == path))
+				.cloned();
+			ui.separator();
+			ui.horizontal(|ui| {
+				if ui
+					.add(
+						egui::Button::new(self.l_server_local_load.as_str())
+							.enabled(matches!(&selected, Some(save) if save.compatible)),
+					)
+					.clicked()
+				{
+					if let Some(save) = &selected {
 						local_server_cmd.send(LocalServerCommand::CreateStartServer {
-							path: PathBuf::new().join("saves").join("local"),
+							path: save.path.clone(),
 							config_only_if_not_existing: false,
 						});
 						self.screen = MainMenuScreen::LoadJoinLocalServer;
 					}
 				}
+				if ui
+					.add(
+						egui::Button::new(self.l_server_local_duplicate.as_str())
+							.enabled(selected.is_some()),
+					)
+					.clicked()
+				{
+					if let Some(save) = &selected {
+						local_server_save_cmd.send(LocalServerSaveCommand::Duplicate {
+							path: save.path.clone(),
+						});
+					}
+				}
+				if ui
+					.add(
+						egui::Button::new(self.l_server_local_delete.as_str())
+							.enabled(selected.is_some()),
+					)
+					.clicked()
+				{
+					if let Some(save) = &selected {
+						local_server_save_cmd.send(LocalServerSaveCommand::Delete {
+							path: save.path.clone(),
+						});
+						self.selected_save = None;
+					}
+				}
 			});
 		});
 	}
 
-	fn render_server_join(&mut self, ui: &mut Ui, _state: &mut ResMut<State<super::ClientState>>) {
-		ui.label("todo");
-	}
-
 	fn render_settings(
 		&mut self,
 		ui: &mut Ui,
@@ -354,6 +512,46 @@ impl MainMenuState {
 	}
 }
 
+/// The line describing a save in the Local Game save list.
+fn save_label(lang: &I18n, save: &SaveInfo) -> String {
+	let details = if save.compatible {
+		let (width, height) = save.map_size.unwrap_or_default();
+		let args = vec![
+			(
+				"civ",
+				FluentValue::from(save.civ.clone().unwrap_or_default()),
+			),
+			("width", FluentValue::from(width)),
+			("height", FluentValue::from(height)),
+		];
+		match save.turn {
+			Some(turn) => lang.get_with_args_list(
+				"save-info",
+				args.into_iter()
+					.chain(std::iter::once(("turn", FluentValue::from(turn)))),
+			),
+			None => lang.get_attr_with_args_list("save-info", "not-started", args),
+		}
+		.into_owned()
+	} else {
+		lang.get_attr("save-info", "incompatible").into_owned()
+	};
+	match save.last_played.and_then(|time| time.elapsed().ok()) {
+		Some(age) => {
+			let played = lang.get_attr_with_args_list(
+				"save-info",
+				"played",
+				vec![
+					("days", FluentValue::from(age.as_secs() / 86400)),
+					("hours", FluentValue::from(age.as_secs() / 3600 % 24)),
+				],
+			);
+			format!("{}: {}, {}", save.name, details, played)
+		}
+		None => format!("{}: {}", save.name, details),
+	}
+}
+
 fn on_enter(mut main_menu_state: ResMut<Option<MainMenuState>>, lang: Res<I18n>) {
 	trace!("Client MainMenu State: Enter");
 	// Make the main menu entity
@@ -368,6 +566,7 @@ fn on_update(
 	mut change_lang: EventWriter<I18nChangeLanguageTo>,
 	local_server_state: Option<Res<LocalServerPublicState>>,
 	mut local_server_cmd: EventWriter<LocalServerCommand>,
+	mut local_server_save_cmd: EventWriter<LocalServerSaveCommand>,
 	mut exit: EventWriter<RequestExit>,
 ) {
 	// trace!("Client MainMenu State: Update");
@@ -379,6 +578,7 @@ fn on_update(
 			&mut change_lang,
 			&local_server_state,
 			&mut local_server_cmd,
+			&mut local_server_save_cmd,
 			&mut exit,
 		);
 	}
@@ -1,16 +1,24 @@
 mod exiting;
+mod join_game;
+mod joined;
+mod joining;
 mod loading;
 mod main_menu;
 
+use crate::universal::commands::ResumeToken;
+use crate::universal::connection::{JoinFailure, ServerAddress};
+use crate::universal::ids::PlayerId;
+use crate::universal::recent_servers::RecentServers;
+use crate::universal::ConfigDirectory;
 use bevy::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ClientState {
 	Loading,
 	MainMenu,
-	// JoinGame,
-	// Joining,
-	// Joined,
+	JoinGame,
+	Joining,
+	Joined,
 	// Paused,
 	Exiting,
 }
@@ -21,9 +29,44 @@ pub struct ClientStatePlugin;
 impl Plugin for ClientStatePlugin {
 	fn build(&self, app: &mut AppBuilder) {
 		// Add the Client state into the system.
-		app.add_state(ClientState::Loading);
+		app.add_state(ClientState::Loading)
+			.init_resource::<Option<JoinRequest>>()
+			.init_resource::<Option<JoinFailure>>()
+			.init_resource::<Option<JoinedGame>>()
+			.add_startup_system(load_recent_servers.system());
 		loading::register_systems(app);
 		main_menu::register_systems(app);
+		join_game::register_systems(app);
+		joining::register_systems(app);
+		joined::register_systems(app);
 		exiting::register_systems(app);
 	}
 }
+
+/// The server to join and the name to join as, set before entering `ClientState::Joining`.
+#[derive(Debug, Clone)]
+pub struct JoinRequest {
+	pub address: ServerAddress,
+	pub name: String,
+	pub password: Option<String>,
+	/// Take back the seat kept after the connection dropped instead of joining anew.
+	pub resume: Option<ResumeToken>,
+}
+
+/// The game the client joined.
+#[derive(Debug, Clone)]
+pub struct JoinedGame {
+	pub address: ServerAddress,
+	pub name: String,
+	pub password: Option<String>,
+	pub player: PlayerId,
+	pub token: ResumeToken,
+}
+
+fn load_recent_servers(mut commands: Commands, config_dir: Option<Res<ConfigDirectory>>) {
+	let recent = match config_dir {
+		Some(config_dir) => RecentServers::load(&config_dir.0),
+		None => RecentServers::default(),
+	};
+	commands.insert_resource(recent);
+}
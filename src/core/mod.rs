@@ -19,6 +19,11 @@ mod logger;
 pub enum EngineError<CustErr: 'static + std::error::Error> {
 	#[error("Logging initialization error")]
 	LoggerError(#[from] logger::Error),
+	#[error("Failed listening on {0}")]
+	ListenError(
+		String,
+		#[source] crate::universal::transport::TransportError,
+	),
 	#[error("Custom Runner Error")]
 	CustomRunnerError(#[source] CustErr),
 }
@@ -64,6 +69,9 @@ pub struct Engine {
 	pub include_server: bool,
 	pub client_type: ClientType,
 	pub game_configuration_path: Option<PathBuf>,
+	/// Run as a dedicated server accepting remote clients instead of running a client.
+	#[cfg(feature = "server")]
+	pub dedicated: Option<crate::server::dedicated::DedicatedConfig>,
 }
 
 /// Central engine entrance point, start by calling `Engine::new()` and call its functions
@@ -79,6 +87,8 @@ impl Engine {
 			client_type: ClientType::Logger,
 			#[cfg(feature = "server")]
 			game_configuration_path: None,
+			#[cfg(feature = "server")]
+			dedicated: None,
 		})
 	}
 
@@ -104,6 +114,7 @@ impl Engine {
 			.unwrap_or_else(|_| "assets".to_owned());
 		info!("Setting base assets directory to: {:?}", &asset_folder);
 		app_builder.insert_resource(AssetServerSettings { asset_folder });
+		app_builder.insert_resource(crate::universal::ConfigDirectory(self.config_dir.clone()));
 
 		app_builder.add_plugins(crate::universal::UniversalPluginGroup::default());
 
@@ -113,18 +124,66 @@ impl Engine {
 			app_builder.add_plugins(crate::server::ServerPluginGroup::default());
 		}
 
-		match self.client_type {
-			ClientType::Logger => {
+		#[cfg(feature = "server")]
+		if let Some(config) = &self.dedicated {
+			let listener =
+				crate::universal::transport::tcp::TcpServerListener::bind(config.listen.as_str())
+					.map_err(|e| EngineError::ListenError(config.listen.clone(), e))?
+					.with_password(config.password.clone());
+			info!("Dedicated server listening on {}", config.listen);
+			if config.announce_on_lan {
+				let port = listener
+					.local_addr()
+					.map_err(|e| EngineError::ListenError(config.listen.clone(), e))?
+					.port();
+				match crate::universal::transport::lan::Announcer::broadcast() {
+					Ok(announcer) => {
+						app_builder.insert_resource(crate::server::lan::LanAnnouncer::new(
+							announcer,
+							config.name.clone(),
+							port,
+							listener.has_password(),
+						));
+					}
+					Err(e) => warn!("Cannot announce the server on the LAN: {}", e),
+				}
+			}
+			app_builder
+				.insert_resource(bevy::app::ScheduleRunnerSettings::run_loop(
+					std::time::Duration::from_secs_f64(1.0 / 60.0),
+				))
+				.add_plugin(bevy::app::ScheduleRunnerPlugin::default())
+				.add_plugin(crate::server::dedicated::DedicatedServerPlugin::default());
+			app_builder
+				.app
+				.world
+				.get_resource_mut::<crate::server::clients::Listeners>()
+				.expect("`Listeners` resource is missing, the server must be included")
+				.0
+				.push(Box::new(listener));
+		}
+
+		#[cfg(feature = "server")]
+		let client_type = match self.dedicated {
+			Some(_) => None,
+			None => Some(&self.client_type),
+		};
+		#[cfg(not(feature = "server"))]
+		let client_type = Some(&self.client_type);
+
+		match client_type {
+			None => (),
+			Some(ClientType::Logger) => {
 				app_builder
 					.add_plugin(bevy::app::ScheduleRunnerPlugin::default())
 					.add_system(shut_down_when_server_is_off.system());
 			}
 			#[cfg(feature = "client_wgpu")]
-			ClientType::WGPU => {
+			Some(ClientType::WGPU) => {
 				app_builder.add_plugins(crate::client_wgpu::ClientWgpuPluginGroup::default());
 			}
 			#[cfg(feature = "client_tui")]
-			ClientType::TUI => {
+			Some(ClientType::TUI) => {
 				app_builder.add_plugins(crate::client_tui::ClientTuiPluginGroup::default());
 			}
 		}
@@ -168,6 +227,16 @@ impl Engine {
 		self.game_configuration_path = game_configuration_path;
 		self
 	}
+
+	/// Run as a dedicated server accepting remote clients as configured, no client is run.
+	#[cfg(feature = "server")]
+	pub fn set_dedicated(
+		&mut self,
+		config: Option<crate::server::dedicated::DedicatedConfig>,
+	) -> &mut Self {
+		self.dedicated = config;
+		self
+	}
 }
 
 fn shut_down_when_server_is_off(
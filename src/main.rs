@@ -30,6 +30,45 @@ pub struct CLIOpts {
 	#[structopt(long)]
 	load_game: Option<PathBuf>,
 
+	/// Run as a dedicated server without a client, accepting remote clients on the `--listen`
+	/// address.  Type `help` on the standard input for the admin commands.
+	#[cfg(feature = "server")]
+	#[structopt(long)]
+	dedicated: bool,
+
+	/// The address a dedicated server listens on for clients
+	#[cfg(feature = "server")]
+	#[structopt(long, default_value = "0.0.0.0:24865")]
+	listen: String,
+
+	/// The name a dedicated server is announced on the LAN as
+	#[cfg(feature = "server")]
+	#[structopt(long, default_value = "Over Civ Server")]
+	server_name: String,
+
+	/// Password clients must give to join a dedicated server
+	#[cfg(feature = "server")]
+	#[structopt(long)]
+	password: Option<String>,
+
+	/// Do not announce a dedicated server on the LAN
+	#[cfg(feature = "server")]
+	#[structopt(long)]
+	no_lan: bool,
+
+	/// Check the ruleset in the given directory, `assets/rules` if none is given, and that every
+	/// language in `--lang-dir` names everything in it, then exit.  Exits with a failure code and
+	/// lists the problems if there are any.
+	#[cfg(feature = "server")]
+	#[structopt(long, value_name = "DIR")]
+	validate_rules: Option<Option<PathBuf>>,
+
+	/// The languages `--validate-rules` checks the ruleset against, the `lang` directory next to
+	/// the rules directory if none is given
+	#[cfg(feature = "server")]
+	#[structopt(long, value_name = "DIR")]
+	lang_dir: Option<PathBuf>,
+
 	/// Override the in-game language via the specified language code
 	#[structopt(long)]
 	language: Option<LanguageIdentifier>,
@@ -52,6 +91,20 @@ fn main() -> anyhow::Result<()> {
 
 	let opts = CLIOpts::from_args();
 
+	#[cfg(feature = "server")]
+	if let Some(rules_dir) = &opts.validate_rules {
+		let rules_dir = rules_dir
+			.clone()
+			.unwrap_or_else(|| PathBuf::from("assets").join("rules"));
+		let lang_dir = opts.lang_dir.clone().unwrap_or_else(|| {
+			rules_dir
+				.parent()
+				.unwrap_or_else(|| std::path::Path::new(""))
+				.join("lang")
+		});
+		std::process::exit(validate_rules(&rules_dir, &lang_dir));
+	}
+
 	let client_type = opts.client.unwrap_or(default_client_type);
 
 	let mut engine = Engine::new(opts.config_dir.unwrap_or(PathBuf::from("./config")))?;
@@ -60,10 +113,57 @@ fn main() -> anyhow::Result<()> {
 	engine.load_game_configuration(opts.load_game.or_else(|| {
 		if client_type == ClientType::Logger {
 			tracing::warn!("Logger-only client selected but no server file was set to be loaded, defaulting to `saves/server`");
-			Some(PathBuf::new().join("saves").join("server"))
+			Some(PathBuf::new().join(over_civ::universal::local_server::SAVES_DIRECTORY).join("server"))
 		} else {None}
 	}));
+	#[cfg(feature = "server")]
+	if opts.dedicated {
+		anyhow::ensure!(
+			!opts.no_server,
+			"a dedicated server cannot be run without the server"
+		);
+		engine.set_dedicated(Some(over_civ::server::dedicated::DedicatedConfig {
+			listen: opts.listen,
+			name: opts.server_name,
+			password: opts.password,
+			announce_on_lan: !opts.no_lan,
+		}));
+	}
 	engine.set_include_server(!opts.no_server);
 	engine.set_client_type(client_type);
 	engine.run().context("Failed to run the engine")
 }
+
+/// Check the ruleset in `rules_dir` against the languages in `lang_dir` and report on it, returning
+/// the exit code.
+#[cfg(feature = "server")]
+fn validate_rules(rules_dir: &std::path::Path, lang_dir: &std::path::Path) -> i32 {
+	use over_civ::server::game::ruleset;
+	match ruleset::validate(rules_dir, lang_dir) {
+		Ok(rules) => {
+			println!(
+				"The rules in {} are valid: {} units, {} buildings, {} techs, {} improvements, {} resources and {} civs",
+				rules_dir.display(),
+				rules.units.len(),
+				rules.buildings.len(),
+				rules.techs.len(),
+				rules.improvements.len(),
+				rules.resources.len(),
+				rules.civs.len(),
+			);
+			0
+		}
+		Err(errors) => {
+			eprintln!(
+				"The rules in {} have {} problem{}:",
+				rules_dir.display(),
+				errors.len(),
+				if errors.len() == 1 { "" } else { "s" },
+			);
+			for error in errors {
+				eprintln!("  {}", error);
+			}
+			1
+		}
+	}
+}
@@ -0,0 +1,497 @@
+//! The clients connected to the server and the handling of their commands.  Every client, local or
+//! remote, is served the same way through its transport.
+
+use crate::server::game::city::CityOrderRequest;
+use crate::server::game::combat::CombatPreviewRequest;
+use crate::server::game::movement::{PathPreviewRequest, UnitOrderRequest};
+use crate::server::game::player::PlayerId;
+use crate::server::game::research::{ResearchRequest, TechTreeRequest};
+use crate::server::game::Notified;
+use crate::server::replication::Replication;
+use crate::server::save::game::GameData;
+use crate::server::save::SaveConfig;
+use crate::server::sessions::{AwayControl, ReconnectConfig, Sessions};
+use crate::server::states::ServerState;
+use crate::server::turns::EndTurnRequest;
+use crate::universal::commands::{ClientCommand, Message, Sequencer, ServerCommand};
+use crate::universal::transport::{Listener, ServerTransport};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub u64);
+
+pub struct ConnectedClient {
+	id: ClientId,
+	transport: ServerTransport,
+	seq: Sequencer,
+	/// The player this client plays as once it has joined.
+	player: Option<PlayerId>,
+	name: String,
+	replication: Replication,
+}
+
+impl ConnectedClient {
+	pub fn id(&self) -> ClientId {
+		self.id
+	}
+
+	pub fn player(&self) -> Option<PlayerId> {
+		self.player
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn replication(&self) -> &Replication {
+		&self.replication
+	}
+}
+
+/// Resource of every connected client.
+#[derive(Default)]
+pub struct Clients {
+	next_id: u64,
+	clients: Vec<ConnectedClient>,
+	sessions: Sessions,
+}
+
+impl Clients {
+	pub fn add(&mut self, transport: ServerTransport) -> ClientId {
+		self.next_id += 1;
+		let id = ClientId(self.next_id);
+		self.clients.push(ConnectedClient {
+			id,
+			transport,
+			seq: Sequencer::default(),
+			player: None,
+			name: String::new(),
+			replication: Replication::default(),
+		});
+		id
+	}
+
+	pub fn get(&self, id: ClientId) -> Option<&ConnectedClient> {
+		self.clients.iter().find(|c| c.id == id)
+	}
+
+	fn get_mut(&mut self, id: ClientId) -> Option<&mut ConnectedClient> {
+		self.clients.iter_mut().find(|c| c.id == id)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &ConnectedClient> {
+		self.clients.iter()
+	}
+
+	pub(crate) fn replication_mut(&mut self, id: ClientId) -> Option<&mut Replication> {
+		self.get_mut(id).map(|c| &mut c.replication)
+	}
+
+	/// The client playing as `player`.
+	pub fn by_player(&self, player: PlayerId) -> Option<&ConnectedClient> {
+		self.clients.iter().find(|c| c.player == Some(player))
+	}
+
+	pub fn sessions(&self) -> &Sessions {
+		&self.sessions
+	}
+
+	/// How the civ of `player` is controlled while they are away, `None` if they are not away.
+	pub fn away_control(&self, player: PlayerId, config: &ReconnectConfig) -> Option<AwayControl> {
+		Some(config.while_away).filter(|_| self.sessions.is_away(player))
+	}
+
+	/// If someone plays as `player`, or their seat is kept for them while they are away.
+	pub fn is_seat_taken(&self, player: PlayerId) -> bool {
+		self.by_player(player).is_some() || self.sessions.is_away(player)
+	}
+
+	/// Send a command to a client, a client whose transport failed is dropped on its next receive.
+	pub fn send(&mut self, id: ClientId, command: ServerCommand) {
+		if let Some(client) = self.get_mut(id) {
+			let message = client.seq.next(command);
+			if let Err(e) = client.transport.send(message) {
+				debug!("Failed sending to client {:?}: {}", id, e);
+			}
+		}
+	}
+
+	/// Send a command to every client that has joined the game.
+	pub fn broadcast(&mut self, command: ServerCommand) {
+		let joined: Vec<ClientId> = self
+			.clients
+			.iter()
+			.filter(|c| c.player.is_some())
+			.map(|c| c.id)
+			.collect();
+		for id in joined {
+			self.send(id, command.clone());
+		}
+	}
+
+	/// Tell every joined client the game is over and free their players, they stay connected.
+	pub fn end_game(&mut self) {
+		self.broadcast(ServerCommand::GameEnded);
+		for client in self.clients.iter_mut() {
+			client.player = None;
+			client.replication.reset();
+		}
+		self.sessions.clear();
+	}
+
+	fn remove(&mut self, id: ClientId) -> Option<ConnectedClient> {
+		let idx = self.clients.iter().position(|c| c.id == id)?;
+		Some(self.clients.remove(idx))
+	}
+}
+
+/// Resource of everything the server accepts new clients from.
+#[derive(Default)]
+pub struct Listeners(pub Vec<Box<dyn Listener>>);
+
+/// Event of a message received from a client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientMessage {
+	pub client: ClientId,
+	pub message: Message<ClientCommand>,
+}
+
+/// Event sent when a client connects or disconnects.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+	Connected(ClientId),
+	Disconnected(ClientId),
+}
+
+pub(crate) fn accept_clients(
+	listeners: Res<Listeners>,
+	mut clients: ResMut<Clients>,
+	mut events: EventWriter<ClientEvent>,
+) {
+	for listener in listeners.0.iter() {
+		loop {
+			match listener.try_accept() {
+				Ok(Some(transport)) => {
+					let peer = transport.peer();
+					let id = clients.add(transport);
+					info!("Client {:?} connected from {}", id, peer);
+					events.send(ClientEvent::Connected(id));
+				}
+				Ok(None) => break,
+				Err(e) => {
+					trace!("Listener is closed: {}", e);
+					break;
+				}
+			}
+		}
+	}
+}
+
+/// Receive every waiting client message, dropping clients whose connection was lost.  Only runs
+/// while a game is loaded so that messages wait in their transport until then.
+pub(crate) fn receive_client_messages(
+	mut clients: ResMut<Clients>,
+	mut messages: EventWriter<ClientMessage>,
+	mut events: EventWriter<ClientEvent>,
+	save_config: Res<Option<SaveConfig>>,
+) {
+	let grace_period = save_config
+		.as_ref()
+		.map(|config| config.reconnect.grace_period())
+		.unwrap_or_default();
+	let mut lost = vec![];
+	for client in clients.clients.iter() {
+		loop {
+			match client.transport.try_recv() {
+				Ok(Some(message)) => messages.send(ClientMessage {
+					client: client.id,
+					message,
+				}),
+				Ok(None) => break,
+				Err(e) => {
+					info!("Client {:?} disconnected: {}", client.id, e);
+					lost.push(client.id);
+					break;
+				}
+			}
+		}
+	}
+	for id in lost {
+		if let Some(client) = clients.remove(id) {
+			if let Some(player) = client.player {
+				if grace_period.as_secs() > 0 {
+					info!("Keeping the seat of {:?} for {:?}", player, grace_period);
+					clients
+						.sessions
+						.set_away(player, Instant::now() + grace_period);
+					clients.broadcast(ServerCommand::PlayerAway { player });
+				} else {
+					clients.sessions.end(player);
+					clients.broadcast(ServerCommand::PlayerLeft { player });
+				}
+			}
+		}
+		events.send(ClientEvent::Disconnected(id));
+	}
+}
+
+/// The events client commands are handed on as, to the systems that carry them out.
+#[derive(SystemParam)]
+pub struct Requests<'a> {
+	end_turn: EventWriter<'a, EndTurnRequest>,
+	unit_orders: EventWriter<'a, UnitOrderRequest>,
+	path_previews: EventWriter<'a, PathPreviewRequest>,
+	combat_previews: EventWriter<'a, CombatPreviewRequest>,
+	city_orders: EventWriter<'a, CityOrderRequest>,
+	research: EventWriter<'a, ResearchRequest>,
+	tech_trees: EventWriter<'a, TechTreeRequest>,
+}
+
+/// Also runs while the game is paused, when the commands that play the game are rejected as nothing
+/// would carry them out.
+pub(crate) fn handle_client_messages(
+	mut messages: EventReader<ClientMessage>,
+	mut clients: ResMut<Clients>,
+	game: GameData,
+	mut requests: Requests,
+	state: Res<State<ServerState>>,
+) {
+	let paused = *state.current() == ServerState::Paused;
+	for ClientMessage { client, message } in messages.iter() {
+		let (client, seq) = (*client, message.seq);
+		let player = match clients.get(client) {
+			Some(c) => c.player,
+			None => continue,
+		};
+		let reject = |clients: &mut Clients, reason: &str| {
+			clients.send(
+				client,
+				ServerCommand::Rejected {
+					seq,
+					reason: reason.to_owned(),
+				},
+			)
+		};
+		let plays = matches!(
+			message.command,
+			ClientCommand::OrderUnit { .. }
+				| ClientCommand::PreviewPath { .. }
+				| ClientCommand::PreviewAttack { .. }
+				| ClientCommand::OrderCity { .. }
+				| ClientCommand::Research(_)
+				| ClientCommand::RequestTechTree
+				| ClientCommand::EndTurn
+		);
+		if paused && plays && player.is_some() {
+			reject(&mut clients, "the game is paused");
+			continue;
+		}
+		match &message.command {
+			ClientCommand::Join {
+				name,
+				player: requested,
+			} => {
+				if player.is_some() {
+					reject(&mut clients, "already joined");
+					continue;
+				}
+				let taken = |p: PlayerId| clients.is_seat_taken(p);
+				let seat = match requested {
+					Some(requested) if game.players().get(*requested).is_none() => {
+						reject(&mut clients, "no such player");
+						continue;
+					}
+					Some(requested) if taken(*requested) => {
+						reject(&mut clients, "player is already taken");
+						continue;
+					}
+					Some(requested) => *requested,
+					None => match game.players().iter().map(|p| p.id).find(|p| !taken(*p)) {
+						Some(seat) => seat,
+						None => {
+							reject(&mut clients, "no free player");
+							continue;
+						}
+					},
+				};
+				info!("Client {:?} joined as {:?} named {:?}", client, seat, name);
+				clients.broadcast(ServerCommand::PlayerJoined {
+					player: seat,
+					name: name.clone(),
+				});
+				if let Some(c) = clients.get_mut(client) {
+					c.player = Some(seat);
+					c.name = name.clone();
+				}
+				let token = clients.sessions.start(seat, name);
+				clients.send(
+					client,
+					ServerCommand::Joined {
+						player: seat,
+						token,
+					},
+				);
+			}
+			ClientCommand::Resume { token } => {
+				if player.is_some() {
+					reject(&mut clients, "already joined");
+					continue;
+				}
+				let (seat, name) = match clients.sessions.resume(*token) {
+					Some(resumed) => resumed,
+					None => {
+						reject(&mut clients, "the seat is no longer kept for you");
+						continue;
+					}
+				};
+				info!("Client {:?} resumed as {:?} named {:?}", client, seat, name);
+				clients.broadcast(ServerCommand::PlayerJoined {
+					player: seat,
+					name: name.clone(),
+				});
+				if let Some(c) = clients.get_mut(client) {
+					c.player = Some(seat);
+					c.name = name;
+				}
+				clients.send(
+					client,
+					ServerCommand::Joined {
+						player: seat,
+						token: *token,
+					},
+				);
+			}
+			ClientCommand::Leave => {
+				if let Some(c) = clients.get_mut(client) {
+					c.player = None;
+					c.replication.reset();
+				}
+				if let Some(player) = player {
+					clients.sessions.end(player);
+					clients.broadcast(ServerCommand::PlayerLeft { player });
+				}
+			}
+			ClientCommand::RequestState => {
+				// Only a player is entitled to see the game, and only what that player can see
+				let player = match player {
+					Some(player) => player,
+					None => {
+						reject(&mut clients, "must join before asking for the game state");
+						continue;
+					}
+				};
+				let state = Arc::new(game.state_for(player, |p| clients.sessions.is_away(p)));
+				let snapshot = clients
+					.replication_mut(client)
+					.map(|replication| replication.snapshot(state, Instant::now()));
+				if let Some(snapshot) = snapshot {
+					clients.send(client, snapshot);
+				}
+			}
+			ClientCommand::OrderUnit { unit, order } => match player {
+				Some(player) => requests.unit_orders.send(UnitOrderRequest {
+					client,
+					seq,
+					player,
+					unit: *unit,
+					order: order.clone(),
+				}),
+				None => reject(&mut clients, "must join before giving orders"),
+			},
+			ClientCommand::PreviewPath { unit, waypoints } => match player {
+				Some(player) => requests.path_previews.send(PathPreviewRequest {
+					client,
+					seq,
+					player,
+					unit: *unit,
+					waypoints: waypoints.clone(),
+				}),
+				None => reject(&mut clients, "must join before giving orders"),
+			},
+			ClientCommand::PreviewAttack { unit, target, kind } => match player {
+				Some(player) => requests.combat_previews.send(CombatPreviewRequest {
+					client,
+					seq,
+					player,
+					unit: *unit,
+					target: *target,
+					kind: *kind,
+				}),
+				None => reject(&mut clients, "must join before giving orders"),
+			},
+			ClientCommand::OrderCity { city, order } => match player {
+				Some(player) => requests.city_orders.send(CityOrderRequest {
+					client,
+					seq,
+					player,
+					city: *city,
+					order: order.clone(),
+				}),
+				None => reject(&mut clients, "must join before giving orders"),
+			},
+			ClientCommand::Research(order) => match player {
+				Some(player) => requests.research.send(ResearchRequest {
+					client,
+					seq,
+					player,
+					order: order.clone(),
+				}),
+				None => reject(&mut clients, "must join before giving orders"),
+			},
+			ClientCommand::RequestTechTree => match player {
+				Some(player) => requests.tech_trees.send(TechTreeRequest {
+					client,
+					seq,
+					player,
+				}),
+				None => reject(&mut clients, "must join before asking for the tech tree"),
+			},
+			ClientCommand::EndTurn => match player {
+				Some(player) => requests.end_turn.send(EndTurnRequest {
+					client,
+					seq,
+					player,
+				}),
+				None => reject(&mut clients, "must join before ending a turn"),
+			},
+			ClientCommand::Chat { text } => match player {
+				Some(from) => clients.broadcast(ServerCommand::Chat {
+					from,
+					text: text.clone(),
+				}),
+				None => reject(&mut clients, "must join before chatting"),
+			},
+			ClientCommand::Ack { seq } => {
+				trace!("Client {:?} acknowledged {}", client, seq);
+			}
+		}
+	}
+}
+
+/// Free the seats of away players whose grace period passed.
+pub(crate) fn expire_sessions(mut clients: ResMut<Clients>) {
+	if clients.sessions.away_players().next().is_none() {
+		return;
+	}
+	for player in clients.sessions.expire(Instant::now()) {
+		info!("{:?} did not come back in time and left", player);
+		clients.broadcast(ServerCommand::PlayerLeft { player });
+	}
+}
+
+/// Tell the clients of players what happened to them.  Players not connected miss out, their game
+/// state still shows the outcome.
+pub(crate) fn notify_players(mut notified: EventReader<Notified>, mut clients: ResMut<Clients>) {
+	for Notified {
+		player,
+		notification,
+	} in notified.iter()
+	{
+		if let Some(client) = clients.by_player(*player).map(ConnectedClient::id) {
+			clients.send(client, ServerCommand::Notify(notification.clone()));
+		}
+	}
+}
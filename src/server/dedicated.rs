@@ -0,0 +1,275 @@
+//! Dedicated server mode, the server runs without a client and is administered through commands
+//! typed on standard input.  It keeps running as games are loaded and unloaded until it is told to
+//! shut down or receives an interrupt or terminate signal.
+
+use crate::server::clients::Clients;
+use crate::universal::exit::RequestExit;
+use crate::universal::local_server::{
+	LocalServerCommand, LocalServerPublicState, LocalServerSaveCommand, LocalServerSaveList,
+	SAVES_DIRECTORY,
+};
+use bevy::prelude::*;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+/// How a dedicated server accepts remote clients.
+#[derive(Debug, Clone)]
+pub struct DedicatedConfig {
+	/// The address to listen on for clients.
+	pub listen: String,
+	/// The name the server is announced on the LAN as.
+	pub name: String,
+	/// Password clients must give to connect, if any.
+	pub password: Option<String>,
+	/// Announce the server on the LAN so clients can discover it.
+	pub announce_on_lan: bool,
+}
+
+/// Runs the dedicated server, add it along with the `ServerPluginGroup` and no client.
+#[derive(Default)]
+pub struct DedicatedServerPlugin;
+
+impl Plugin for DedicatedServerPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.insert_resource(AdminConsole::spawn())
+			.insert_resource(ShutdownSignal::register())
+			.add_system(read_admin_commands.system())
+			.add_system(on_shutdown_signal.system())
+			.add_system(log_server_state.system())
+			.add_system(log_save_list.system())
+			.add_system(crate::server::lan::announce_server.system());
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+	Help,
+	/// Log the server state and the connected clients.
+	Status,
+	/// List the saves in the saves directory.
+	Saves,
+	/// Load a save, by name in the saves directory or by path, creating it if it does not exist.
+	Load(PathBuf),
+	Save,
+	Pause,
+	Resume,
+	/// Save and unload the game, the server keeps running.
+	Unload,
+	/// Save the game and exit.
+	Shutdown,
+}
+
+impl FromStr for AdminCommand {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut words = s.trim().splitn(2, char::is_whitespace);
+		let command = words.next().unwrap_or_default().to_lowercase();
+		let arg = words.next().map(str::trim).filter(|arg| !arg.is_empty());
+		match (command.as_str(), arg) {
+			("help", None) | ("?", None) => Ok(AdminCommand::Help),
+			("status", None) => Ok(AdminCommand::Status),
+			("saves", None) => Ok(AdminCommand::Saves),
+			("load", Some(save)) => {
+				let path = PathBuf::from(save);
+				if path.components().count() == 1 {
+					Ok(AdminCommand::Load(
+						PathBuf::from(SAVES_DIRECTORY).join(path),
+					))
+				} else {
+					Ok(AdminCommand::Load(path))
+				}
+			}
+			("load", None) => Err("`load` needs the name or path of a save".to_owned()),
+			("save", None) => Ok(AdminCommand::Save),
+			("pause", None) => Ok(AdminCommand::Pause),
+			("resume", None) => Ok(AdminCommand::Resume),
+			("unload", None) => Ok(AdminCommand::Unload),
+			("shutdown", None) | ("quit", None) | ("exit", None) => Ok(AdminCommand::Shutdown),
+			(_, Some(_)) if AdminCommand::from_str(&command).is_ok() => {
+				Err(format!("`{}` takes no arguments", command))
+			}
+			_ => Err(format!("unknown command `{}`, try `help`", s.trim())),
+		}
+	}
+}
+
+const HELP: &str = "Admin commands:
+  status          show the server state and connected clients
+  saves           list the saves
+  load <save>     load a save by name or path, creating it if it does not exist
+  save            save the game
+  pause, resume   pause or resume the game
+  unload          save and unload the game, the server keeps running
+  shutdown        save the game and exit";
+
+/// Resource of the lines typed on standard input, read on a background thread.
+pub struct AdminConsole {
+	lines: Mutex<Receiver<String>>,
+}
+
+impl AdminConsole {
+	fn spawn() -> Self {
+		let (sender, receiver) = std::sync::mpsc::channel();
+		std::thread::spawn(move || {
+			for line in std::io::stdin().lock().lines() {
+				match line {
+					Ok(line) => {
+						// The server is gone, nobody is left to read the commands.
+						if sender.send(line).is_err() {
+							break;
+						}
+					}
+					Err(e) => {
+						warn!("Stopped reading admin commands: {}", e);
+						break;
+					}
+				}
+			}
+		});
+		Self {
+			lines: Mutex::new(receiver),
+		}
+	}
+}
+
+/// Resource set once an interrupt or terminate signal is received.
+pub struct ShutdownSignal(Arc<AtomicBool>);
+
+impl ShutdownSignal {
+	fn register() -> Self {
+		let flag = Arc::new(AtomicBool::new(false));
+		#[cfg(unix)]
+		for signal in &[signal_hook::SIGINT, signal_hook::SIGTERM] {
+			if let Err(e) = signal_hook::flag::register(*signal, flag.clone()) {
+				error!("Failed registering handler for signal {}: {}", signal, e);
+			}
+		}
+		Self(flag)
+	}
+}
+
+fn read_admin_commands(
+	console: Res<AdminConsole>,
+	public_state: Res<LocalServerPublicState>,
+	clients: Res<Clients>,
+	mut server_cmd: EventWriter<LocalServerCommand>,
+	mut save_cmd: EventWriter<LocalServerSaveCommand>,
+	mut exit: EventWriter<RequestExit>,
+) {
+	loop {
+		let line = match console
+			.lines
+			.lock()
+			.expect("poisoned AdminConsole lock")
+			.try_recv()
+		{
+			Ok(line) => line,
+			Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+		};
+		if line.trim().is_empty() {
+			continue;
+		}
+		let command = match line.parse() {
+			Ok(command) => command,
+			Err(e) => {
+				warn!("{}", e);
+				continue;
+			}
+		};
+		info!("Admin command: {:?}", command);
+		match command {
+			AdminCommand::Help => info!("{}", HELP),
+			AdminCommand::Status => {
+				info!("Server is {:?}", *public_state);
+				for client in clients.iter() {
+					info!(
+						"Client {:?} {:?} playing as {:?}",
+						client.id(),
+						client.name(),
+						client.player()
+					);
+				}
+			}
+			AdminCommand::Saves => save_cmd.send(LocalServerSaveCommand::List),
+			AdminCommand::Load(path) => server_cmd.send(LocalServerCommand::CreateStartServer {
+				path,
+				config_only_if_not_existing: false,
+			}),
+			AdminCommand::Save => server_cmd.send(LocalServerCommand::SaveServer),
+			AdminCommand::Pause => server_cmd.send(LocalServerCommand::PauseServer),
+			AdminCommand::Resume => server_cmd.send(LocalServerCommand::ResumeServer),
+			AdminCommand::Unload => {
+				server_cmd.send(LocalServerCommand::StopServer { force: false })
+			}
+			AdminCommand::Shutdown => exit.send(RequestExit),
+		}
+	}
+}
+
+fn on_shutdown_signal(
+	signal: Res<ShutdownSignal>,
+	mut exit: EventWriter<RequestExit>,
+	mut requested: Local<bool>,
+) {
+	if !*requested && signal.0.load(Ordering::Relaxed) {
+		info!("Received shutdown signal");
+		*requested = true;
+		exit.send(RequestExit);
+	}
+}
+
+fn log_server_state(mut states: EventReader<LocalServerPublicState>) {
+	for state in states.iter() {
+		match state {
+			LocalServerPublicState::Loading(completion) => {
+				trace!("Loading {:.0}%", completion * 100.0)
+			}
+			state => info!("Server is now {:?}", state),
+		}
+	}
+}
+
+fn log_save_list(mut save_lists: EventReader<LocalServerSaveList>) {
+	for list in save_lists.iter() {
+		if list.0.is_empty() {
+			info!("No saves found");
+		}
+		for save in &list.0 {
+			info!(
+				"Save {:?}: turn {:?}, {:?}, compatible: {}",
+				save.name, save.turn, save.civ, save.compatible
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::AdminCommand;
+	use crate::universal::local_server::SAVES_DIRECTORY;
+	use std::path::PathBuf;
+
+	#[test]
+	fn parse_admin_commands() {
+		assert_eq!("status".parse(), Ok(AdminCommand::Status));
+		assert_eq!("  Shutdown \n".parse(), Ok(AdminCommand::Shutdown));
+		assert_eq!(
+			"load my game".parse(),
+			Ok(AdminCommand::Load(
+				PathBuf::from(SAVES_DIRECTORY).join("my game")
+			))
+		);
+		assert_eq!(
+			"load /srv/saves/game".parse(),
+			Ok(AdminCommand::Load(PathBuf::from("/srv/saves/game")))
+		);
+		assert!("load".parse::<AdminCommand>().is_err());
+		assert!("save now".parse::<AdminCommand>().is_err());
+		assert!("launch".parse::<AdminCommand>().is_err());
+	}
+}
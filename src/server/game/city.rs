@@ -0,0 +1,666 @@
+//! Cities, founded by settlers.  A city always works its own tile and each of its citizens works
+//! another tile within `CityRules::radius` that no other city works.  The player can pick tiles for
+//! citizens to work, the city picks the best tiles by `CityRules::work_weights` for the rest.
+//!
+//! At the end of every turn a city stores the food its citizens do not eat and grows by a citizen
+//! once it stored enough, a city that cannot feed its citizens eats its stores and then starves.
+//!
+//! Cities claim the tiles around them as their territory, see `territory`.  Tiles in the territory
+//! of another player cannot be worked, and cities cannot be founded in it.
+
+use super::player::{PlayerId, Players};
+use super::production::{buildings_by_city, Building, ProductionRequest};
+use super::rules::Rules;
+use super::unit::{Unit, UnitId};
+use super::{GameEntity, GameIds, TurnEnded};
+use crate::server::clients::{ClientId, Clients};
+use crate::server::world::{Feature, Hex, WorldMap};
+use crate::universal::commands::state::{CityDetails, CityInfo, TurnStatus};
+use crate::universal::commands::{CityOrder, ProductionItem, ServerCommand};
+pub use crate::universal::ids::CityId;
+use crate::universal::yields::Yields;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+
+fn one() -> u32 {
+	1
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CityError {
+	#[error("the unit cannot found cities")]
+	CannotFound,
+	#[error("cities cannot be founded on this tile")]
+	InvalidTile,
+	#[error("too close to another city")]
+	TooClose,
+	#[error("the tile is in another player's territory")]
+	ForeignTerritory,
+	#[error("the city cannot work that tile")]
+	CannotWork,
+}
+
+/// A city on the map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct City {
+	pub id: CityId,
+	pub owner: PlayerId,
+	pub name: String,
+	pub position: Hex,
+	#[serde(default = "one")]
+	pub population: u32,
+	/// Food stored towards growing.
+	#[serde(default)]
+	pub food: u32,
+	/// The tiles citizens work, besides the city's own tile.
+	#[serde(default)]
+	pub worked: Vec<Hex>,
+	/// The worked tiles the player picked, in the order they were picked.
+	#[serde(default)]
+	pub locked: Vec<Hex>,
+	/// Production put into the first entry of the queue, see `production`.
+	#[serde(default)]
+	pub production: u32,
+	/// What the city builds, in order.
+	#[serde(default)]
+	pub queue: Vec<ProductionItem>,
+	/// Culture stored towards claiming the next tile, see `territory`.
+	#[serde(default)]
+	pub culture: u32,
+	/// The tiles the city claimed with culture, after its initial ones.
+	#[serde(default)]
+	pub claims: u32,
+}
+
+/// If `player` can found a city on `position` with cities already at `cities`.
+pub fn can_found(
+	map: &WorldMap,
+	rules: &Rules,
+	player: PlayerId,
+	cities: &[Hex],
+	position: Hex,
+) -> Result<(), CityError> {
+	let tile = map.get(position).ok_or(CityError::InvalidTile)?;
+	if tile.terrain.is_water()
+		|| tile.feature == Some(Feature::Ice)
+		|| rules.tile_yields(tile).is_none()
+	{
+		return Err(CityError::InvalidTile);
+	}
+	if cities
+		.iter()
+		.any(|city| map.distance(*city, position) < rules.cities.min_distance)
+	{
+		return Err(CityError::TooClose);
+	}
+	if matches!(tile.owner, Some(owner) if owner.player != player) {
+		return Err(CityError::ForeignTerritory);
+	}
+	Ok(())
+}
+
+/// The tiles taken by cities other than `except`, their own tiles and the tiles they work.
+pub fn taken_tiles<'c>(
+	cities: impl IntoIterator<Item = &'c City>,
+	except: CityId,
+) -> BTreeSet<Hex> {
+	cities
+		.into_iter()
+		.filter(|city| city.id != except)
+		.flat_map(|city| std::iter::once(city.position).chain(city.worked.iter().copied()))
+		.collect()
+}
+
+impl City {
+	/// A new city of a single citizen, it works no tile until `assign_tiles` is called.
+	pub fn new(id: CityId, owner: PlayerId, name: String, position: Hex) -> Self {
+		Self {
+			id,
+			owner,
+			name,
+			position,
+			population: 1,
+			food: 0,
+			worked: vec![],
+			locked: vec![],
+			production: 0,
+			queue: vec![],
+			culture: 0,
+			claims: 0,
+		}
+	}
+
+	/// The tiles the city could work with what they yield, nearest first.  Tiles in the territory
+	/// of other players cannot be worked.
+	pub fn workable_tiles(
+		&self,
+		map: &WorldMap,
+		rules: &Rules,
+		taken: &BTreeSet<Hex>,
+	) -> Vec<(Hex, Yields)> {
+		map.spiral(self.position, rules.cities.radius)
+			.into_iter()
+			.filter(|hex| *hex != self.position && !taken.contains(hex))
+			.filter_map(|hex| Some((hex, map.get(hex)?)))
+			.filter(|(_hex, tile)| !matches!(tile.owner, Some(owner) if owner.player != self.owner))
+			.filter_map(|(hex, tile)| Some((hex, rules.tile_yields(tile)?)))
+			.collect()
+	}
+
+	/// Put every citizen to work, on the tiles the player picked that can still be worked and then
+	/// on the best of the others.
+	pub fn assign_tiles(&mut self, map: &WorldMap, rules: &Rules, taken: &BTreeSet<Hex>) {
+		let mut workable = self.workable_tiles(map, rules, taken);
+		let population = self.population as usize;
+		self.locked
+			.retain(|hex| workable.iter().any(|(workable, _yields)| workable == hex));
+		self.locked.truncate(population);
+		workable.retain(|(hex, _yields)| !self.locked.contains(hex));
+		// A stable sort so ties go to the tile nearest the city
+		workable.sort_by_key(|(_hex, yields)| Reverse(yields.weighted(&rules.cities.work_weights)));
+		self.worked = self.locked.clone();
+		self.worked.extend(
+			workable
+				.into_iter()
+				.take(population - self.locked.len())
+				.map(|(hex, _yields)| hex),
+		);
+	}
+
+	/// Have a citizen work `hex` as the player picked, taking the citizen off the tile the player
+	/// picked first if every citizen already works a picked tile.
+	pub fn work_tile(
+		&mut self,
+		map: &WorldMap,
+		rules: &Rules,
+		taken: &BTreeSet<Hex>,
+		hex: Hex,
+	) -> Result<(), CityError> {
+		let hex = map.normalize(hex).ok_or(CityError::CannotWork)?;
+		if !self
+			.workable_tiles(map, rules, taken)
+			.iter()
+			.any(|(workable, _yields)| *workable == hex)
+		{
+			return Err(CityError::CannotWork);
+		}
+		if !self.locked.contains(&hex) {
+			self.locked.push(hex);
+			if self.locked.len() > self.population as usize {
+				self.locked.remove(0);
+			}
+		}
+		self.assign_tiles(map, rules, taken);
+		Ok(())
+	}
+
+	/// Let the city pick whether `hex` is worked.
+	pub fn release_tile(&mut self, map: &WorldMap, rules: &Rules, taken: &BTreeSet<Hex>, hex: Hex) {
+		let hex = map.normalize(hex).unwrap_or(hex);
+		self.locked.retain(|locked| *locked != hex);
+		self.assign_tiles(map, rules, taken);
+	}
+
+	/// What the city yields every turn, its own tile, the tiles its citizens work and its
+	/// `buildings`.
+	pub fn yields(&self, map: &WorldMap, rules: &Rules, buildings: &[SmolStr]) -> Yields {
+		let tile_yields = |hex: &Hex| {
+			map.get(*hex)
+				.and_then(|tile| rules.tile_yields(tile))
+				.unwrap_or_default()
+		};
+		tile_yields(&self.position)
+			+ rules.cities.center
+			+ self.worked.iter().map(tile_yields).sum()
+			+ buildings
+				.iter()
+				.filter_map(|kind| rules.buildings.get(kind))
+				.map(|building| building.yields)
+				.sum()
+	}
+
+	/// The food the citizens eat every turn.
+	pub fn food_eaten(&self, rules: &Rules) -> u32 {
+		self.population * rules.cities.food_per_citizen
+	}
+
+	/// Store the food left after the citizens ate, growing or starving if it is time to.
+	pub fn end_turn(
+		&mut self,
+		map: &WorldMap,
+		rules: &Rules,
+		taken: &BTreeSet<Hex>,
+		buildings: &[SmolStr],
+	) {
+		let food = self.yields(map, rules, buildings).food;
+		let eaten = self.food_eaten(rules);
+		if food >= eaten {
+			self.food += food - eaten;
+			let threshold = rules.growth_threshold(self.population);
+			if self.food >= threshold {
+				self.food -= threshold;
+				self.population += 1;
+				info!("{} grew to {} citizens", self.name, self.population);
+			}
+		} else if self.food >= eaten - food {
+			self.food -= eaten - food;
+		} else {
+			self.food = 0;
+			if self.population > 1 {
+				self.population -= 1;
+				info!("{} starved to {} citizens", self.name, self.population);
+			}
+		}
+		self.assign_tiles(map, rules, taken);
+	}
+
+	/// The city as it is sent to clients, without its details.
+	pub fn info(&self) -> CityInfo {
+		CityInfo {
+			id: self.id,
+			owner: self.owner,
+			name: self.name.clone(),
+			position: self.position,
+			population: self.population,
+			details: None,
+		}
+	}
+
+	/// How the city with `buildings` is doing, as it is sent to its owner.
+	pub fn details(&self, map: &WorldMap, rules: &Rules, buildings: &[SmolStr]) -> CityDetails {
+		CityDetails {
+			food: self.food,
+			growth_threshold: rules.growth_threshold(self.population),
+			yields: self.yields(map, rules, buildings),
+			food_eaten: self.food_eaten(rules),
+			worked: self.worked.clone(),
+			locked: self.locked.clone(),
+			production: self.production,
+			queue: self.queue.clone(),
+			buildings: buildings.to_vec(),
+			culture: self.culture,
+			culture_threshold: self.culture_threshold(rules),
+		}
+	}
+}
+
+/// Event of a unit ordered to found a city, sent once the order was checked to be the player's to
+/// give.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundCityRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub unit: UnitId,
+	pub name: String,
+}
+
+/// Event of a joined client giving an order to a city.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CityOrderRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub player: PlayerId,
+	pub city: CityId,
+	pub order: CityOrder,
+}
+
+/// Everything cities are founded with.
+#[derive(SystemParam)]
+pub struct Founding<'a> {
+	map: ResMut<'a, WorldMap>,
+	rules: Res<'a, Rules>,
+	ids: ResMut<'a, GameIds>,
+	players: Res<'a, Players>,
+	cities: Query<'a, &'static City>,
+}
+
+impl<'a> Founding<'a> {
+	/// The first name of the civ of `player` that no city has, if there is one left.
+	fn city_name(&self, player: PlayerId, cities: &[City]) -> Option<String> {
+		let civ = &self.players.get(player)?.civ;
+		self.rules
+			.civs
+			.get(civ)?
+			.city_names
+			.iter()
+			.find(|name| cities.iter().all(|city| city.name != **name))
+			.cloned()
+	}
+}
+
+pub(crate) fn found_cities(
+	mut requests: EventReader<FoundCityRequest>,
+	mut clients: ResMut<Clients>,
+	units: Query<(Entity, &Unit)>,
+	mut founding: Founding,
+	mut commands: Commands,
+) {
+	// Cities founded this update are not in the query yet
+	let mut founded: Vec<City> = vec![];
+	for request in requests.iter() {
+		let (entity, unit) = match units.iter().find(|(_entity, unit)| unit.id == request.unit) {
+			Some(found) => found,
+			None => continue,
+		};
+		let position = founding
+			.map
+			.normalize(unit.position)
+			.unwrap_or(unit.position);
+		let cities: Vec<City> = founding
+			.cities
+			.iter()
+			.cloned()
+			.chain(founded.iter().cloned())
+			.collect();
+		let positions: Vec<Hex> = cities.iter().map(|city| city.position).collect();
+		let founds_cities =
+			matches!(founding.rules.unit(&unit.kind), Some(unit_type) if unit_type.founds_cities);
+		let result = if founds_cities {
+			can_found(
+				&founding.map,
+				&founding.rules,
+				unit.owner,
+				&positions,
+				position,
+			)
+		} else {
+			Err(CityError::CannotFound)
+		};
+		if let Err(e) = result {
+			clients.send(
+				request.client,
+				ServerCommand::Rejected {
+					seq: request.seq,
+					reason: e.to_string(),
+				},
+			);
+			continue;
+		}
+		let id = founding.ids.next_city();
+		let name = match request.name.trim() {
+			"" => founding
+				.city_name(unit.owner, &cities)
+				.unwrap_or_else(|| format!("City {}", id.0)),
+			name => name.to_owned(),
+		};
+		let mut city = City::new(id, unit.owner, name, position);
+		city.claim_initial(&mut founding.map, &founding.rules);
+		city.assign_tiles(
+			&founding.map,
+			&founding.rules,
+			&taken_tiles(&cities, city.id),
+		);
+		info!("{:?} founded {} at {:?}", unit.owner, city.name, position);
+		commands.entity(entity).despawn();
+		commands.spawn().insert(city.clone()).insert(GameEntity);
+		founded.push(city);
+	}
+}
+
+pub(crate) fn order_cities(
+	mut requests: EventReader<CityOrderRequest>,
+	mut clients: ResMut<Clients>,
+	mut cities: Query<&mut City>,
+	map: Res<WorldMap>,
+	rules: Res<Rules>,
+	status: Res<TurnStatus>,
+	mut production: EventWriter<ProductionRequest>,
+) {
+	for request in requests.iter() {
+		let reject = |clients: &mut Clients, reason: &str| {
+			clients.send(
+				request.client,
+				ServerCommand::Rejected {
+					seq: request.seq,
+					reason: reason.to_owned(),
+				},
+			)
+		};
+		let snapshot: Vec<City> = cities.iter_mut().map(|city| city.clone()).collect();
+		let taken = taken_tiles(&snapshot, request.city);
+		let mut city = match cities
+			.iter_mut()
+			.find(|city| city.id == request.city && city.owner == request.player)
+		{
+			Some(city) => city,
+			None => {
+				reject(&mut clients, "not your city");
+				continue;
+			}
+		};
+		if !status.to_move.contains(&request.player) {
+			reject(&mut clients, "it is not your turn");
+			continue;
+		}
+		match request.order {
+			CityOrder::WorkTile(hex) => {
+				if let Err(e) = city.work_tile(&map, &rules, &taken, hex) {
+					reject(&mut clients, &e.to_string());
+				}
+			}
+			CityOrder::ReleaseTile(hex) => city.release_tile(&map, &rules, &taken, hex),
+			CityOrder::Enqueue(_) | CityOrder::Dequeue(_) | CityOrder::Purchase => {
+				production.send(ProductionRequest {
+					client: request.client,
+					seq: request.seq,
+					city: request.city,
+					order: request.order.clone(),
+				})
+			}
+		}
+	}
+}
+
+/// Feed, grow and starve every city at the end of the turn.
+pub(crate) fn grow_cities(
+	mut ended: EventReader<TurnEnded>,
+	mut cities: Query<&mut City>,
+	buildings: Query<&Building>,
+	map: Res<WorldMap>,
+	rules: Res<Rules>,
+) {
+	if ended.iter().count() == 0 {
+		return;
+	}
+	let buildings = buildings_by_city(buildings.iter());
+	let mut snapshot: Vec<City> = cities.iter_mut().map(|city| city.clone()).collect();
+	// By id so cities take tiles from each other the same no matter how they were loaded
+	snapshot.sort_by_key(|city| city.id);
+	for idx in 0..snapshot.len() {
+		let taken = taken_tiles(&snapshot, snapshot[idx].id);
+		let city_buildings = buildings
+			.get(&snapshot[idx].id)
+			.map_or(&[][..], Vec::as_slice);
+		snapshot[idx].end_turn(&map, &rules, &taken, city_buildings);
+	}
+	for mut city in cities.iter_mut() {
+		if let Some(updated) = snapshot.iter().find(|updated| updated.id == city.id) {
+			if *city != *updated {
+				*city = updated.clone();
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{can_found, taken_tiles, City, CityError, CityId};
+	use crate::server::game::player::PlayerId;
+	use crate::server::game::rules::Rules;
+	use crate::server::world::{
+		Elevation, Feature, Hex, HexDirection, Terrain, TileOwner, WorldMap,
+	};
+	use crate::universal::yields::Yields;
+	use std::collections::BTreeSet;
+
+	fn yields(food: u32, production: u32, gold: u32) -> Yields {
+		Yields {
+			food,
+			production,
+			gold,
+			..Yields::default()
+		}
+	}
+
+	#[test]
+	fn tile_yields() {
+		let mut map = WorldMap::filled(16, 10, Terrain::Plains);
+		let rules = Rules::bundled();
+		let hex = Hex::from_offset(4, 4);
+		let tile_yields = |map: &WorldMap| rules.tile_yields(map.get(hex).unwrap());
+		assert_eq!(tile_yields(&map), Some(yields(1, 1, 0)));
+		map.get_mut(hex).unwrap().elevation = Elevation::Hills;
+		map.get_mut(hex).unwrap().feature = Some(Feature::Forest);
+		assert_eq!(tile_yields(&map), Some(yields(1, 3, 0)));
+		map.set_river(hex, HexDirection::ALL[0]);
+		assert_eq!(tile_yields(&map), Some(yields(1, 3, 1)));
+		map.get_mut(hex).unwrap().terrain = Terrain::Desert;
+		map.get_mut(hex).unwrap().elevation = Elevation::Flat;
+		map.get_mut(hex).unwrap().feature = Some(Feature::FloodPlains);
+		assert_eq!(tile_yields(&map), Some(yields(3, 0, 1)));
+		map.get_mut(hex).unwrap().elevation = Elevation::Mountains;
+		assert_eq!(tile_yields(&map), None);
+		map.get_mut(hex).unwrap().elevation = Elevation::Flat;
+		map.get_mut(hex).unwrap().feature = Some(Feature::Ice);
+		assert_eq!(tile_yields(&map), None);
+	}
+
+	#[test]
+	fn city_yields_and_tiles() {
+		let mut map = WorldMap::filled(16, 10, Terrain::Plains);
+		let rules = Rules::bundled();
+		let center = Hex::from_offset(6, 4);
+		let grassland = Hex::from_offset(8, 4);
+		map.get_mut(grassland).unwrap().terrain = Terrain::Grassland;
+		let hills = Hex::from_offset(6, 6);
+		map.get_mut(hills).unwrap().elevation = Elevation::Hills;
+		let mut city = City::new(CityId(1), PlayerId(0), "Rome".to_owned(), center);
+		let taken = BTreeSet::new();
+
+		// Grassland is worth 6 and plains 5, but the plains hills are worth 7
+		city.assign_tiles(&map, &rules, &taken);
+		assert_eq!(city.worked, vec![hills]);
+		// Plains center plus its bonus plus the plains hills
+		assert_eq!(
+			city.yields(&map, &rules, &[]),
+			Yields {
+				food: 3,
+				production: 4,
+				gold: 0,
+				science: 1,
+				culture: 1,
+			}
+		);
+
+		city.population = 2;
+		city.assign_tiles(&map, &rules, &taken);
+		assert_eq!(city.worked, vec![hills, grassland]);
+
+		// A picked tile is kept over better ones, and the oldest pick goes once every citizen works a
+		// picked tile
+		let plain = Hex::from_offset(5, 4);
+		city.work_tile(&map, &rules, &taken, plain).unwrap();
+		assert_eq!(city.worked, vec![plain, hills]);
+		city.work_tile(&map, &rules, &taken, grassland).unwrap();
+		city.work_tile(&map, &rules, &taken, hills).unwrap();
+		assert_eq!(city.locked, vec![grassland, hills]);
+		city.release_tile(&map, &rules, &taken, grassland);
+		assert_eq!(city.worked, vec![hills, grassland]);
+		assert!(matches!(
+			city.work_tile(&map, &rules, &taken, center),
+			Err(CityError::CannotWork)
+		));
+
+		// Another city working the hills takes them away
+		let mut other = City::new(
+			CityId(2),
+			PlayerId(1),
+			"Carthage".to_owned(),
+			Hex::from_offset(6, 8),
+		);
+		other.worked = vec![hills];
+		let taken = taken_tiles(&[city.clone(), other], city.id);
+		city.assign_tiles(&map, &rules, &taken);
+		assert!(city.locked.is_empty());
+		assert_eq!(city.worked.len(), 2);
+		assert_eq!(city.worked[0], grassland);
+		assert!(!city.worked.contains(&hills));
+	}
+
+	#[test]
+	fn growth() {
+		let map = WorldMap::filled(16, 10, Terrain::Plains);
+		let rules = Rules::bundled();
+		assert_eq!(rules.growth_threshold(1), 15);
+		assert_eq!(rules.growth_threshold(3), 31);
+		let taken = BTreeSet::new();
+		let mut city = City::new(
+			CityId(1),
+			PlayerId(0),
+			"Rome".to_owned(),
+			Hex::from_offset(6, 4),
+		);
+		city.assign_tiles(&map, &rules, &taken);
+		// 2 food from the center and 1 from a plain, less 2 eaten
+		assert_eq!(
+			city.yields(&map, &rules, &[]).food - city.food_eaten(&rules),
+			1
+		);
+		for _ in 0..14 {
+			city.end_turn(&map, &rules, &taken, &[]);
+		}
+		assert_eq!((city.population, city.food), (1, 14));
+		city.end_turn(&map, &rules, &taken, &[]);
+		assert_eq!((city.population, city.food), (2, 0));
+		assert_eq!(city.worked.len(), 2);
+
+		// 5 food for 6 eaten, the city eats its stores and then starves
+		city.population = 3;
+		city.assign_tiles(&map, &rules, &taken);
+		city.food = 1;
+		city.end_turn(&map, &rules, &taken, &[]);
+		assert_eq!((city.population, city.food), (3, 0));
+		city.end_turn(&map, &rules, &taken, &[]);
+		assert_eq!((city.population, city.food), (2, 0));
+	}
+
+	#[test]
+	fn founding() {
+		let mut map = WorldMap::filled(16, 10, Terrain::Plains);
+		let rules = Rules::bundled();
+		let cities = vec![Hex::from_offset(4, 4)];
+		assert!(can_found(&map, &rules, PlayerId(0), &cities, Hex::from_offset(8, 4)).is_ok());
+		assert!(matches!(
+			can_found(&map, &rules, PlayerId(0), &cities, Hex::from_offset(7, 4)),
+			Err(CityError::TooClose)
+		));
+		let hex = Hex::from_offset(10, 4);
+		map.get_mut(hex).unwrap().terrain = Terrain::Coast;
+		assert!(matches!(
+			can_found(&map, &rules, PlayerId(0), &cities, hex),
+			Err(CityError::InvalidTile)
+		));
+		map.get_mut(hex).unwrap().terrain = Terrain::Plains;
+		map.get_mut(hex).unwrap().elevation = Elevation::Mountains;
+		assert!(matches!(
+			can_found(&map, &rules, PlayerId(0), &cities, hex),
+			Err(CityError::InvalidTile)
+		));
+
+		// Only in the player's own territory or in none
+		let hex = Hex::from_offset(8, 4);
+		map.get_mut(hex).unwrap().owner = Some(TileOwner {
+			player: PlayerId(1),
+			city: CityId(2),
+		});
+		assert!(matches!(
+			can_found(&map, &rules, PlayerId(0), &cities, hex),
+			Err(CityError::ForeignTerritory)
+		));
+		assert!(can_found(&map, &rules, PlayerId(1), &cities, hex).is_ok());
+	}
+}
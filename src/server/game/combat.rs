@@ -0,0 +1,607 @@
+//! Units fighting each other.  The strength of each side is the strength of its unit type for the
+//! kind of attack, changed by `CombatModifier`s, and the damage each side takes grows with how much
+//! stronger the other side is:  30 health at equal strength, times about 1.5 for every 10 strength
+//! the other side has over it, times a roll of 80% to 120%.
+//!
+//! The roll is drawn from the `GameRng` of the save so a fight goes the same way every time a game
+//! is replayed from the same save.  Every roll is equally likely, so the preview gets its odds by
+//! going through all of them.
+
+use super::city::City;
+use super::player::PlayerId;
+use super::rules::Rules;
+use super::unit::{Unit, UnitId, MAX_HEALTH};
+use super::vision::{is_visible, visible_tiles};
+use super::{GameRng, Wars};
+use crate::server::clients::{ClientId, Clients};
+use crate::server::world::{Elevation, Feature, Hex, HexDirection, WorldMap};
+use crate::universal::commands::{
+	AttackKind, CombatModifier, CombatPreview, CombatReport, CombatSide, ServerCommand,
+};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use rand::Rng;
+use std::cmp::Reverse;
+
+/// The lowest roll, in percent of the damage dealt.
+pub const MIN_ROLL: u32 = 80;
+/// The highest roll, in percent of the damage dealt.
+pub const MAX_ROLL: u32 = 120;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CombatError {
+	#[error("the unit has no movement left")]
+	NoMoves,
+	#[error("the unit cannot make {0:?} attacks")]
+	CannotAttack(AttackKind),
+	#[error("the target is out of range")]
+	OutOfRange,
+	#[error("there is nothing to attack there")]
+	NoTarget,
+}
+
+/// Event of a joined client asking for the odds of an attack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombatPreviewRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub player: PlayerId,
+	pub unit: UnitId,
+	pub target: Hex,
+	pub kind: AttackKind,
+}
+
+/// Event of a unit ordered to attack, sent once the order was checked to be the player's to give.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub unit: UnitId,
+	pub target: Hex,
+	pub kind: AttackKind,
+}
+
+/// Event sent for every fight, after its damage was dealt, with both sides of the report known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombatResolved(pub CombatReport);
+
+/// The damage dealt by a fight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+	pub attacker_damage: u32,
+	pub defender_damage: u32,
+}
+
+/// The damage taken by a side `diff` strength weaker than the other side, with a roll of `roll`.
+fn damage(diff: i32, roll: u32) -> u32 {
+	(30.0 * (0.04 * f64::from(diff)).exp() * f64::from(roll) / 100.0).round() as u32
+}
+
+/// How a fight with the odds of `preview` goes with a roll of `roll`.  A high roll is good for the
+/// attacker, and the winner of a fight always survives it.
+pub fn outcome(preview: &CombatPreview, roll: u32) -> Outcome {
+	let (attacker, defender) = (&preview.attacker, &preview.defender);
+	// Units that cannot fight are killed by any attack
+	if defender.strength == 0 {
+		return Outcome {
+			attacker_damage: 0,
+			defender_damage: defender.health,
+		};
+	}
+	let diff = attacker.strength as i32 - defender.strength as i32;
+	let defender_damage = damage(diff, roll).min(defender.health);
+	let attacker_damage = match preview.kind {
+		AttackKind::Melee if defender_damage == defender.health => {
+			damage(-diff, MIN_ROLL + MAX_ROLL - roll).min(attacker.health.saturating_sub(1))
+		}
+		AttackKind::Melee => damage(-diff, MIN_ROLL + MAX_ROLL - roll).min(attacker.health),
+		AttackKind::Ranged | AttackKind::Bombard => 0,
+	};
+	Outcome {
+		attacker_damage,
+		defender_damage,
+	}
+}
+
+/// A fight about to happen, as the attacker's owner sees it.
+pub struct Battle<'a> {
+	pub map: &'a WorldMap,
+	pub rules: &'a Rules,
+	/// The units the attacker's owner sees, its own included.
+	pub units: &'a [Unit],
+}
+
+impl<'a> Battle<'a> {
+	fn strength(&self, unit: &Unit, kind: AttackKind) -> u32 {
+		self.rules
+			.unit(&unit.kind)
+			.map(|unit_type| match kind {
+				AttackKind::Melee => unit_type.strength,
+				AttackKind::Ranged => unit_type.ranged_strength,
+				AttackKind::Bombard => unit_type.bombard_strength,
+			})
+			.unwrap_or_default()
+	}
+
+	fn range(&self, unit: &Unit, kind: AttackKind) -> u32 {
+		match kind {
+			AttackKind::Melee => 1,
+			AttackKind::Ranged | AttackKind::Bombard => self
+				.rules
+				.unit(&unit.kind)
+				.map(|unit_type| unit_type.range)
+				.unwrap_or_default(),
+		}
+	}
+
+	/// The unit of another player on `target` that `attacker` would fight, the strongest one if
+	/// there are several.
+	pub fn defender(&self, attacker: &Unit, target: Hex) -> Option<&'a Unit> {
+		let target = self.map.normalize(target)?;
+		self.units
+			.iter()
+			.filter(|unit| {
+				unit.owner != attacker.owner && self.map.normalize(unit.position) == Some(target)
+			})
+			.max_by_key(|unit| {
+				(
+					self.strength(unit, AttackKind::Melee) * unit.health,
+					Reverse(unit.id),
+				)
+			})
+	}
+
+	fn side(
+		&self,
+		unit: &Unit,
+		base_strength: u32,
+		modifiers: Vec<(CombatModifier, i32)>,
+	) -> CombatSide {
+		let total: i32 = modifiers.iter().map(|(_modifier, change)| change).sum();
+		CombatSide {
+			unit: unit.id,
+			health: unit.health,
+			base_strength,
+			strength: if base_strength == 0 {
+				0
+			} else {
+				(base_strength as i32 + total).max(1) as u32
+			},
+			modifiers,
+			damage: (0, 0),
+		}
+	}
+
+	/// The odds of `attacker` attacking `target`.
+	pub fn preview(
+		&self,
+		attacker: &Unit,
+		target: Hex,
+		kind: AttackKind,
+	) -> Result<CombatPreview, CombatError> {
+		if attacker.moves_left == 0 {
+			return Err(CombatError::NoMoves);
+		}
+		let base_strength = self.strength(attacker, kind);
+		if base_strength == 0 {
+			return Err(CombatError::CannotAttack(kind));
+		}
+		let target = self.map.normalize(target).ok_or(CombatError::NoTarget)?;
+		let distance = self.map.distance(attacker.position, target);
+		if distance == 0 || distance > self.range(attacker, kind) {
+			return Err(CombatError::OutOfRange);
+		}
+		let defender = self
+			.defender(attacker, target)
+			.ok_or(CombatError::NoTarget)?;
+
+		let health = |unit: &Unit| -(MAX_HEALTH.saturating_sub(unit.health) as i32 / 10);
+		let mut attack = vec![(CombatModifier::Health, health(attacker))];
+		let mut defense = vec![(CombatModifier::Health, health(defender))];
+		if kind != AttackKind::Bombard {
+			let tile = self.map.get(target).ok_or(CombatError::NoTarget)?;
+			let mut terrain = 0;
+			if tile.elevation == Elevation::Hills {
+				terrain += 3;
+			}
+			terrain += match tile.feature {
+				Some(Feature::Forest) | Some(Feature::Jungle) => 3,
+				Some(Feature::Marsh) => -2,
+				_ => 0,
+			};
+			defense.push((CombatModifier::Terrain, terrain));
+			if defender.fortified {
+				defense.push((CombatModifier::Fortified, 4));
+			}
+		}
+		if kind == AttackKind::Melee {
+			let from = self
+				.map
+				.normalize(attacker.position)
+				.unwrap_or(attacker.position);
+			let across_river = HexDirection::ALL
+				.iter()
+				.find(|direction| self.map.normalize(from.neighbor(**direction)) == Some(target))
+				.map(
+					|direction| matches!(self.map.get(from), Some(tile) if tile.has_river(*direction)),
+				)
+				.unwrap_or(false);
+			if across_river {
+				attack.push((CombatModifier::RiverCrossing, -5));
+			}
+			let flanking = self
+				.units
+				.iter()
+				.filter(|unit| {
+					unit.owner == attacker.owner
+						&& unit.id != attacker.id
+						&& self.strength(unit, AttackKind::Melee) > 0
+						&& self.map.distance(unit.position, target) == 1
+				})
+				.count() as i32;
+			attack.push((CombatModifier::Flanking, 2 * flanking));
+		}
+		attack.retain(|(_modifier, change)| *change != 0);
+		defense.retain(|(_modifier, change)| *change != 0);
+
+		let mut preview = CombatPreview {
+			kind,
+			attacker: self.side(attacker, base_strength, attack),
+			defender: self.side(
+				defender,
+				self.strength(defender, AttackKind::Melee),
+				defense,
+			),
+			kill_chance: 0.0,
+			death_chance: 0.0,
+		};
+		let outcomes: Vec<Outcome> = (MIN_ROLL..=MAX_ROLL)
+			.map(|roll| outcome(&preview, roll))
+			.collect();
+		let range = |damage: &dyn Fn(&Outcome) -> u32| {
+			let damages = outcomes.iter().map(damage);
+			(
+				damages.clone().min().unwrap_or(0),
+				damages.max().unwrap_or(0),
+			)
+		};
+		preview.attacker.damage = range(&|outcome| outcome.attacker_damage);
+		preview.defender.damage = range(&|outcome| outcome.defender_damage);
+		let chance = |killed: &dyn Fn(&Outcome) -> bool| {
+			outcomes.iter().filter(|outcome| killed(outcome)).count() as f32 / outcomes.len() as f32
+		};
+		preview.kill_chance = chance(&|outcome| outcome.defender_damage >= defender.health);
+		preview.death_chance = chance(&|outcome| outcome.attacker_damage >= attacker.health);
+		Ok(preview)
+	}
+}
+
+/// Everything units fight over.
+#[derive(SystemParam)]
+pub struct CombatWorld<'a> {
+	map: Res<'a, WorldMap>,
+	rules: Res<'a, Rules>,
+	wars: ResMut<'a, Wars>,
+	rng: ResMut<'a, GameRng>,
+	cities: Query<'a, &'static City>,
+}
+
+impl<'a> CombatWorld<'a> {
+	/// The tiles `player` sees, for a copy of every unit.
+	fn visible_tiles(&self, player: PlayerId, units: &[Unit]) -> Vec<bool> {
+		let units: Vec<&Unit> = units.iter().collect();
+		let cities: Vec<&City> = self.cities.iter().collect();
+		visible_tiles(&self.map, player, &units, &cities)
+	}
+
+	/// The units of a copy of every unit that `player` sees.
+	fn seen_by(&self, player: PlayerId, units: &[Unit]) -> Vec<Unit> {
+		let visible = self.visible_tiles(player, units);
+		units
+			.iter()
+			.filter(|unit| unit.owner == player || is_visible(&self.map, &visible, unit.position))
+			.cloned()
+			.collect()
+	}
+}
+
+pub(crate) fn preview_combat(
+	mut requests: EventReader<CombatPreviewRequest>,
+	mut clients: ResMut<Clients>,
+	units: Query<&Unit>,
+	world: CombatWorld,
+) {
+	for request in requests.iter() {
+		let all: Vec<Unit> = units.iter().cloned().collect();
+		let seen = world.seen_by(request.player, &all);
+		let battle = Battle {
+			map: &world.map,
+			rules: &world.rules,
+			units: &seen,
+		};
+		let preview = match seen
+			.iter()
+			.find(|unit| unit.id == request.unit && unit.owner == request.player)
+		{
+			Some(unit) => battle
+				.preview(unit, request.target, request.kind)
+				.map_err(|e| e.to_string()),
+			None => Err("not your unit".to_owned()),
+		};
+		let command = match preview {
+			Ok(preview) => ServerCommand::CombatPreview {
+				seq: request.seq,
+				preview,
+			},
+			Err(reason) => ServerCommand::Rejected {
+				seq: request.seq,
+				reason,
+			},
+		};
+		clients.send(request.client, command);
+	}
+}
+
+pub(crate) fn attack_units(
+	mut requests: EventReader<AttackRequest>,
+	mut clients: ResMut<Clients>,
+	mut units: Query<(Entity, &mut Unit)>,
+	mut world: CombatWorld,
+	mut commands: Commands,
+	mut resolved: EventWriter<CombatResolved>,
+) {
+	for request in requests.iter() {
+		let all: Vec<Unit> = units
+			.iter_mut()
+			.map(|(_entity, unit)| unit.clone())
+			.collect();
+		let attacker = match all.iter().find(|unit| unit.id == request.unit) {
+			Some(attacker) => attacker.clone(),
+			None => continue,
+		};
+		let seen = world.seen_by(attacker.owner, &all);
+		let battle = Battle {
+			map: &world.map,
+			rules: &world.rules,
+			units: &seen,
+		};
+		let preview = match battle.preview(&attacker, request.target, request.kind) {
+			Ok(preview) => preview,
+			Err(e) => {
+				clients.send(
+					request.client,
+					ServerCommand::Rejected {
+						seq: request.seq,
+						reason: e.to_string(),
+					},
+				);
+				continue;
+			}
+		};
+		let defender_id = preview.defender.unit;
+		let defender_owner = match all.iter().find(|unit| unit.id == defender_id) {
+			Some(defender) => defender.owner,
+			None => continue,
+		};
+		let target = world
+			.map
+			.normalize(request.target)
+			.unwrap_or(request.target);
+		// Everyone who sees either side sees the fight, found before anyone dies in it
+		let witnesses: Vec<(ClientId, bool, bool)> = clients
+			.iter()
+			.filter_map(|client| Some((client.id(), client.player()?)))
+			.map(|(client, player)| {
+				let visible = world.visible_tiles(player, &all);
+				(
+					client,
+					is_visible(&world.map, &visible, attacker.position),
+					is_visible(&world.map, &visible, target),
+				)
+			})
+			.filter(|(_client, sees_from, sees_target)| *sees_from || *sees_target)
+			.collect();
+
+		world.wars.declare(attacker.owner, defender_owner);
+		let roll = world.rng.0.gen_range(MIN_ROLL..=MAX_ROLL);
+		let Outcome {
+			attacker_damage,
+			defender_damage,
+		} = outcome(&preview, roll);
+		let mut report = CombatReport {
+			kind: request.kind,
+			attacker: Some(attacker.id),
+			defender: Some(defender_id),
+			from: Some(attacker.position),
+			target: Some(target),
+			attacker_damage,
+			defender_damage,
+			attacker_killed: attacker_damage >= attacker.health,
+			defender_killed: defender_damage >= preview.defender.health,
+			advanced: false,
+		};
+		let defenders_left = all.iter().any(|unit| {
+			unit.id != defender_id
+				&& unit.owner != attacker.owner
+				&& world.map.normalize(unit.position) == Some(target)
+		});
+		for (entity, mut unit) in units.iter_mut() {
+			if unit.id == defender_id {
+				unit.health -= defender_damage;
+				unit.fortified &= !report.defender_killed;
+				if report.defender_killed {
+					commands.entity(entity).despawn();
+				}
+			} else if unit.id == attacker.id {
+				unit.health -= attacker_damage;
+				unit.moves_left = 0;
+				unit.fortified = false;
+				unit.waypoints.clear();
+				if report.attacker_killed {
+					commands.entity(entity).despawn();
+				} else if request.kind == AttackKind::Melee
+					&& report.defender_killed
+					&& !defenders_left
+				{
+					unit.position = target;
+					report.advanced = true;
+				}
+			}
+		}
+		info!("{:?} attacked {:?}: {:?}", attacker.id, defender_id, report);
+		for (client, sees_from, sees_target) in witnesses {
+			clients.send(
+				client,
+				ServerCommand::Combat(report.as_seen(sees_from, sees_target)),
+			);
+		}
+		resolved.send(CombatResolved(report));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{outcome, Battle, CombatError, MAX_ROLL, MIN_ROLL};
+	use crate::server::game::player::PlayerId;
+	use crate::server::game::rules::Rules;
+	use crate::server::game::unit::{Unit, UnitId};
+	use crate::server::world::{Elevation, Hex, Terrain, WorldMap};
+	use crate::universal::commands::{AttackKind, CombatModifier};
+	use rand::{Rng, SeedableRng};
+	use rand_pcg::Pcg64;
+
+	fn unit(id: u64, owner: u32, kind: &str, col: i32, row: i32) -> Unit {
+		Unit::new(
+			UnitId(id),
+			PlayerId(owner),
+			kind,
+			Hex::from_offset(col, row),
+			&Rules::bundled(),
+		)
+	}
+
+	#[test]
+	fn modifiers() {
+		let mut map = WorldMap::filled(12, 8, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let target = Hex::from_offset(4, 3);
+		map.get_mut(target).unwrap().elevation = Elevation::Hills;
+		let attacker = unit(1, 0, "warrior", 3, 3);
+		let mut defender = unit(2, 1, "warrior", 4, 3);
+		defender.fortified = true;
+		defender.health = 50;
+		let flanker = unit(3, 0, "warrior", 5, 3);
+		let units = vec![attacker.clone(), defender, flanker];
+		let battle = Battle {
+			map: &map,
+			rules: &rules,
+			units: &units,
+		};
+		let preview = battle
+			.preview(&attacker, target, AttackKind::Melee)
+			.unwrap();
+		assert_eq!(
+			preview.attacker.modifiers,
+			vec![(CombatModifier::Flanking, 2)]
+		);
+		assert_eq!(preview.attacker.strength, 22);
+		assert_eq!(
+			preview.defender.modifiers,
+			vec![
+				(CombatModifier::Health, -5),
+				(CombatModifier::Terrain, 3),
+				(CombatModifier::Fortified, 4),
+			]
+		);
+		assert_eq!(preview.defender.strength, 22);
+		// Equal strength, 30 damage at an even roll
+		assert_eq!(outcome(&preview, 100).defender_damage, 30);
+		assert_eq!(outcome(&preview, 100).attacker_damage, 30);
+		assert_eq!(preview.defender.damage, (24, 36));
+		assert_eq!(preview.kill_chance, 0.0);
+
+		// Bombarding ignores terrain and fortification, and the catapult takes no damage
+		let catapult = unit(4, 0, "catapult", 2, 3);
+		let preview = battle
+			.preview(&catapult, target, AttackKind::Bombard)
+			.unwrap();
+		assert_eq!(
+			preview.defender.modifiers,
+			vec![(CombatModifier::Health, -5)]
+		);
+		assert_eq!(preview.attacker.damage, (0, 0));
+		assert!(preview.kill_chance > 0.5);
+
+		assert!(matches!(
+			battle.preview(&catapult, target, AttackKind::Melee),
+			Err(CombatError::OutOfRange)
+		));
+		assert!(matches!(
+			battle.preview(&attacker, target, AttackKind::Ranged),
+			Err(CombatError::CannotAttack(AttackKind::Ranged))
+		));
+		assert!(matches!(
+			battle.preview(&attacker, Hex::from_offset(3, 4), AttackKind::Melee),
+			Err(CombatError::NoTarget)
+		));
+	}
+
+	#[test]
+	fn odds() {
+		let map = WorldMap::filled(12, 8, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let attacker = unit(1, 0, "warrior", 3, 3);
+		let mut defender = unit(2, 1, "warrior", 4, 3);
+		defender.health = 40;
+		let settler = unit(3, 1, "settler", 2, 3);
+		let units = vec![attacker.clone(), defender.clone(), settler.clone()];
+		let battle = Battle {
+			map: &map,
+			rules: &rules,
+			units: &units,
+		};
+		let preview = battle
+			.preview(&attacker, defender.position, AttackKind::Melee)
+			.unwrap();
+		let rolls = MAX_ROLL - MIN_ROLL + 1;
+		let killing = (MIN_ROLL..=MAX_ROLL)
+			.filter(|roll| outcome(&preview, *roll).defender_damage >= defender.health)
+			.count() as u32;
+		assert!(killing > 0 && killing < rolls);
+		assert_eq!(preview.kill_chance, killing as f32 / rolls as f32);
+		// The winner survives
+		assert_eq!(preview.death_chance, 0.0);
+
+		// Units that cannot fight always die
+		let preview = battle
+			.preview(&attacker, settler.position, AttackKind::Melee)
+			.unwrap();
+		assert_eq!(preview.kill_chance, 1.0);
+		assert_eq!(preview.attacker.damage, (0, 0));
+	}
+
+	#[test]
+	fn same_seed_same_fights() {
+		let map = WorldMap::filled(12, 8, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let attacker = unit(1, 0, "warrior", 3, 3);
+		let defender = unit(2, 1, "warrior", 4, 3);
+		let units = vec![attacker.clone(), defender.clone()];
+		let battle = Battle {
+			map: &map,
+			rules: &rules,
+			units: &units,
+		};
+		let preview = battle
+			.preview(&attacker, defender.position, AttackKind::Melee)
+			.unwrap();
+		let fights = |seed| {
+			let mut rng = Pcg64::seed_from_u64(seed);
+			(0..10)
+				.map(|_| outcome(&preview, rng.gen_range(MIN_ROLL..=MAX_ROLL)))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(fights(7), fights(7));
+		assert_ne!(fights(7), fights(8));
+	}
+}
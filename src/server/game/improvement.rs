@@ -0,0 +1,198 @@
+//! Improvements units build on tiles, adding to what working the tile yields.  Building one takes
+//! the rest of the unit's turn and replaces the improvement that was on the tile.
+
+use super::player::Players;
+use super::rules::Rules;
+use super::unit::{Unit, UnitId};
+use crate::server::clients::{ClientId, Clients};
+use crate::server::world::{Elevation, Tile, WorldMap};
+use crate::universal::commands::ServerCommand;
+use bevy::prelude::*;
+use smol_str::SmolStr;
+use std::collections::BTreeSet;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImprovementError {
+	#[error("the unit cannot build improvements")]
+	CannotBuild,
+	#[error("the unit has no movement left")]
+	NoMoves,
+	#[error("there is no such improvement")]
+	Unknown,
+	#[error("needs the tech `{0}`")]
+	MissingTech(SmolStr),
+	#[error("it cannot be built on this tile")]
+	InvalidTile,
+	#[error("the tile already has it")]
+	AlreadyBuilt,
+	#[error("the tile is in another player's territory")]
+	ForeignTerritory,
+}
+
+/// Check that `unit`, whose owner knows `techs`, can build `improvement` on `tile`.
+pub fn can_improve(
+	rules: &Rules,
+	unit: &Unit,
+	techs: &BTreeSet<SmolStr>,
+	tile: &Tile,
+	improvement: &str,
+) -> Result<(), ImprovementError> {
+	if !matches!(rules.unit(&unit.kind), Some(unit_type) if unit_type.builds_improvements) {
+		return Err(ImprovementError::CannotBuild);
+	}
+	if unit.moves_left == 0 {
+		return Err(ImprovementError::NoMoves);
+	}
+	let improvement_type = rules
+		.improvements
+		.get(improvement)
+		.ok_or(ImprovementError::Unknown)?;
+	if let Some(tech) = &improvement_type.tech {
+		if !techs.contains(tech) {
+			return Err(ImprovementError::MissingTech(tech.clone()));
+		}
+	}
+	let elevation_fits = match tile.elevation {
+		Elevation::Flat => !improvement_type.hills,
+		Elevation::Hills => true,
+		Elevation::Mountains => false,
+	};
+	if !elevation_fits || !improvement_type.terrains.contains(&tile.terrain) {
+		return Err(ImprovementError::InvalidTile);
+	}
+	if matches!(tile.owner, Some(owner) if owner.player != unit.owner) {
+		return Err(ImprovementError::ForeignTerritory);
+	}
+	if tile.improvement.as_deref() == Some(improvement) {
+		return Err(ImprovementError::AlreadyBuilt);
+	}
+	Ok(())
+}
+
+/// Event of a unit ordered to build an improvement, sent once the order was checked to be the
+/// player's to give.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildImprovementRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub unit: UnitId,
+	pub improvement: SmolStr,
+}
+
+pub(crate) fn build_improvements(
+	mut requests: EventReader<BuildImprovementRequest>,
+	mut clients: ResMut<Clients>,
+	mut units: Query<&mut Unit>,
+	mut map: ResMut<WorldMap>,
+	rules: Res<Rules>,
+	players: Res<Players>,
+) {
+	for request in requests.iter() {
+		let mut unit = match units.iter_mut().find(|unit| unit.id == request.unit) {
+			Some(unit) => unit,
+			None => continue,
+		};
+		let techs = players
+			.get(unit.owner)
+			.map(|player| player.techs.clone())
+			.unwrap_or_default();
+		let tile = match map.get_mut(unit.position) {
+			Some(tile) => tile,
+			None => continue,
+		};
+		if let Err(e) = can_improve(&rules, &unit, &techs, tile, &request.improvement) {
+			clients.send(
+				request.client,
+				ServerCommand::Rejected {
+					seq: request.seq,
+					reason: e.to_string(),
+				},
+			);
+			continue;
+		}
+		info!(
+			"{:?} built a {} at {:?}",
+			unit.owner, request.improvement, unit.position
+		);
+		tile.improvement = Some(request.improvement.clone());
+		unit.moves_left = 0;
+		unit.waypoints.clear();
+		unit.fortified = false;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{can_improve, ImprovementError};
+	use crate::server::game::city::CityId;
+	use crate::server::game::player::PlayerId;
+	use crate::server::game::rules::Rules;
+	use crate::server::game::unit::{Unit, UnitId};
+	use crate::server::world::{Elevation, Hex, Terrain, Tile, TileOwner};
+	use smol_str::SmolStr;
+	use std::collections::BTreeSet;
+
+	#[test]
+	fn improvements() {
+		let rules = Rules::bundled();
+		let position = Hex::new(0, 0);
+		let worker = Unit::new(UnitId(1), PlayerId(0), "worker", position, &rules);
+		let warrior = Unit::new(UnitId(2), PlayerId(0), "warrior", position, &rules);
+		let mut techs: BTreeSet<SmolStr> = BTreeSet::new();
+		let mut tile = Tile {
+			terrain: Terrain::Plains,
+			..Tile::default()
+		};
+
+		assert!(can_improve(&rules, &worker, &techs, &tile, "farm").is_ok());
+		assert!(matches!(
+			can_improve(&rules, &warrior, &techs, &tile, "farm"),
+			Err(ImprovementError::CannotBuild)
+		));
+		assert!(matches!(
+			can_improve(&rules, &worker, &techs, &tile, "pasture"),
+			Err(ImprovementError::MissingTech(tech)) if tech == "animal_husbandry"
+		));
+		assert!(matches!(
+			can_improve(&rules, &worker, &techs, &tile, "railroad"),
+			Err(ImprovementError::Unknown)
+		));
+
+		// Mines go on hills only, and the mine adds to what the tile yields
+		techs.insert("mining".into());
+		assert!(matches!(
+			can_improve(&rules, &worker, &techs, &tile, "mine"),
+			Err(ImprovementError::InvalidTile)
+		));
+		tile.elevation = Elevation::Hills;
+		assert!(can_improve(&rules, &worker, &techs, &tile, "mine").is_ok());
+		let unimproved = rules.tile_yields(&tile).unwrap();
+		tile.improvement = Some("mine".into());
+		assert_eq!(
+			rules.tile_yields(&tile).unwrap().production,
+			unimproved.production + 2
+		);
+		assert!(matches!(
+			can_improve(&rules, &worker, &techs, &tile, "mine"),
+			Err(ImprovementError::AlreadyBuilt)
+		));
+
+		tile.improvement = None;
+		tile.owner = Some(TileOwner {
+			player: PlayerId(1),
+			city: CityId(1),
+		});
+		assert!(matches!(
+			can_improve(&rules, &worker, &techs, &tile, "mine"),
+			Err(ImprovementError::ForeignTerritory)
+		));
+
+		tile.owner = None;
+		tile.terrain = Terrain::Coast;
+		tile.elevation = Elevation::Flat;
+		assert!(matches!(
+			can_improve(&rules, &worker, &techs, &tile, "farm"),
+			Err(ImprovementError::InvalidTile)
+		));
+	}
+}
@@ -0,0 +1,92 @@
+//! Game state of a loaded server, everything here is persisted in the save.
+
+pub mod city;
+pub mod combat;
+pub mod improvement;
+pub mod movement;
+pub mod pathfinding;
+pub mod player;
+pub mod production;
+pub mod research;
+pub mod rules;
+pub mod ruleset;
+pub mod territory;
+pub mod unit;
+pub mod vision;
+
+use crate::universal::commands::Notification;
+use player::PlayerId;
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Marker for every entity that is part of the loaded game, they are all despawned on unload.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GameEntity;
+
+/// The current turn number, a new game starts at turn 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameTurn(pub u32);
+
+/// Event sent when the game advances to a new turn, the value is the new turn number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TurnStarted(pub u32);
+
+/// Event sent when every player moved and the turn is over, the value is the turn that ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TurnEnded(pub u32);
+
+/// Event of something happening to a player that their client is told about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notified {
+	pub player: PlayerId,
+	pub notification: Notification,
+}
+
+/// The pairs of players at war with each other.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wars(BTreeSet<(PlayerId, PlayerId)>);
+
+impl Wars {
+	fn pair(a: PlayerId, b: PlayerId) -> (PlayerId, PlayerId) {
+		(a.min(b), a.max(b))
+	}
+
+	pub fn declare(&mut self, a: PlayerId, b: PlayerId) {
+		if a != b {
+			self.0.insert(Self::pair(a, b));
+		}
+	}
+
+	pub fn make_peace(&mut self, a: PlayerId, b: PlayerId) {
+		self.0.remove(&Self::pair(a, b));
+	}
+
+	pub fn at_war(&self, a: PlayerId, b: PlayerId) -> bool {
+		self.0.contains(&Self::pair(a, b))
+	}
+}
+
+/// The random number generator of the game.  Everything random in the game draws from this so a
+/// game loaded from a save continues exactly as it would have without being saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameRng(pub Pcg64);
+
+/// Allocator of the persistent ids of game objects, entities are not stable across saves.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameIds {
+	next_unit: u64,
+	next_city: u64,
+}
+
+impl GameIds {
+	pub fn next_unit(&mut self) -> unit::UnitId {
+		self.next_unit += 1;
+		unit::UnitId(self.next_unit)
+	}
+
+	pub fn next_city(&mut self) -> city::CityId {
+		self.next_city += 1;
+		city::CityId(self.next_city)
+	}
+}
@@ -0,0 +1,528 @@
+//! Moving units on the orders of their players.  A unit ordered somewhere moves as far as it can
+//! right away, and on at the start of every turn until it got through all its waypoints.
+//!
+//! Paths are found on the map as the unit's owner knows it and around the units they can see, so
+//! neither a path nor its preview gives away anything hidden.  Every step is then checked against
+//! the real map, a unit running into something its owner did not know about stops there and finds
+//! a new path on its next move.
+
+use super::city::City;
+use super::city::FoundCityRequest;
+use super::combat::AttackRequest;
+use super::improvement::BuildImprovementRequest;
+use super::pathfinding::{after_step, find_path, find_route, step_cost, Obstacles};
+use super::player::PlayerId;
+use super::rules::Rules;
+use super::unit::{Unit, UnitId};
+use super::vision::{is_visible, visible_tiles, Visions};
+use super::{TurnStarted, Wars};
+use crate::server::clients::{ClientId, Clients};
+use crate::server::world::{Hex, HexDirection, WorldMap};
+use crate::universal::commands::state::TurnStatus;
+use crate::universal::commands::{PathStep, ServerCommand, UnitOrder};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+/// Event of a joined client giving an order to a unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitOrderRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub player: PlayerId,
+	pub unit: UnitId,
+	pub order: UnitOrder,
+}
+
+/// Event of a joined client asking for the path a unit would take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPreviewRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub player: PlayerId,
+	pub unit: UnitId,
+	pub waypoints: Vec<Hex>,
+}
+
+/// The game as units move through it, with a copy of every unit as they were when it was taken.
+pub struct Movement<'a> {
+	pub map: &'a WorldMap,
+	pub visions: &'a Visions,
+	pub wars: &'a Wars,
+	pub rules: &'a Rules,
+	pub units: Vec<Unit>,
+	pub cities: Vec<&'a City>,
+}
+
+impl<'a> Movement<'a> {
+	fn known_map(&self, player: PlayerId) -> WorldMap {
+		match self.visions.0.get(&player) {
+			Some(vision) => vision.known_map(self.map),
+			None => self.map.clone(),
+		}
+	}
+
+	/// The units of other players `unit` has to go around, only those its owner sees if `known`, and
+	/// the territory of the players its owner is not at war with as it is on `map`.  A unit the
+	/// borders of a player grew around can still move through that player's territory to get out.
+	fn obstacles(&self, unit: &Unit, known: bool, map: &WorldMap) -> Obstacles {
+		let visible = if known {
+			let units: Vec<&Unit> = self.units.iter().collect();
+			Some(visible_tiles(self.map, unit.owner, &units, &self.cities))
+		} else {
+			None
+		};
+		let mut obstacles = Obstacles::default();
+		for other in self.units.iter().filter(|other| other.owner != unit.owner) {
+			if matches!(&visible, Some(visible) if !is_visible(self.map, visible, other.position)) {
+				continue;
+			}
+			let position = match self.map.normalize(other.position) {
+				Some(position) => position,
+				None => continue,
+			};
+			obstacles.blocked.insert(position);
+			if self.wars.at_war(unit.owner, other.owner) {
+				obstacles
+					.zone_of_control
+					.extend(self.map.neighbors(position));
+			}
+		}
+		let inside = map
+			.get(unit.position)
+			.and_then(|tile| tile.owner)
+			.map(|owner| owner.player);
+		obstacles.blocked.extend(
+			map.iter()
+				.filter(|(_hex, tile)| {
+					matches!(tile.owner, Some(owner) if owner.player != unit.owner
+						&& Some(owner.player) != inside
+						&& !self.wars.at_war(unit.owner, owner.player))
+				})
+				.map(|(hex, _tile)| hex),
+		);
+		obstacles
+	}
+
+	/// The path `unit` would take through `waypoints`, `None` if it cannot get there.
+	pub fn route(&self, unit: &Unit, waypoints: &[Hex]) -> Option<Vec<PathStep>> {
+		let known = self.known_map(unit.owner);
+		find_route(
+			&known,
+			&self.obstacles(unit, true, &known),
+			unit.position,
+			waypoints,
+			unit.max_moves(self.rules),
+			unit.moves_left,
+		)
+	}
+
+	/// Move `unit` towards its waypoints as far as its movement left gets it.  Waypoints it cannot
+	/// get to are dropped.
+	pub fn advance(&self, unit: &mut Unit) {
+		let known = self.known_map(unit.owner);
+		let planned = self.obstacles(unit, true, &known);
+		let actual = self.obstacles(unit, false, self.map);
+		let max_moves = unit.max_moves(self.rules);
+		while unit.moves_left > 0 {
+			let target = match unit.waypoints.first() {
+				Some(target) => *target,
+				None => break,
+			};
+			if self.map.normalize(target) == self.map.normalize(unit.position) {
+				unit.waypoints.remove(0);
+				continue;
+			}
+			let path = find_path(
+				&known,
+				&planned,
+				unit.position,
+				target,
+				max_moves,
+				unit.moves_left,
+			);
+			let next = match path.as_ref().and_then(|path| path.first()) {
+				Some(next) => next.hex,
+				None => {
+					info!("{:?} cannot get to {:?}", unit.id, target);
+					unit.waypoints.clear();
+					break;
+				}
+			};
+			let from = self.map.normalize(unit.position).unwrap_or(unit.position);
+			let step = HexDirection::ALL
+				.iter()
+				.find(|direction| self.map.normalize(from.neighbor(**direction)) == Some(next))
+				.and_then(|direction| step_cost(self.map, &actual, from, *direction));
+			match step {
+				Some(step) => {
+					unit.moves_left = after_step(unit.moves_left, step);
+					unit.position = next;
+				}
+				// Something its owner did not know about is in the way
+				None => break,
+			}
+		}
+	}
+}
+
+/// Everything units move through.
+#[derive(SystemParam)]
+pub struct MoveWorld<'a> {
+	map: Res<'a, WorldMap>,
+	visions: Res<'a, Visions>,
+	wars: Res<'a, Wars>,
+	rules: Res<'a, Rules>,
+	cities: Query<'a, &'static City>,
+}
+
+impl<'a> MoveWorld<'a> {
+	fn movement(&self, units: Vec<Unit>) -> Movement<'_> {
+		Movement {
+			map: &self.map,
+			visions: &self.visions,
+			wars: &self.wars,
+			rules: &self.rules,
+			units,
+			cities: self.cities.iter().collect(),
+		}
+	}
+}
+
+/// Orders that are carried out by other systems.
+#[derive(SystemParam)]
+pub struct UnitActions<'a> {
+	attacks: EventWriter<'a, AttackRequest>,
+	found_cities: EventWriter<'a, FoundCityRequest>,
+	improvements: EventWriter<'a, BuildImprovementRequest>,
+}
+
+pub(crate) fn order_units(
+	mut requests: EventReader<UnitOrderRequest>,
+	mut clients: ResMut<Clients>,
+	mut units: Query<(Entity, &mut Unit)>,
+	world: MoveWorld,
+	status: Res<TurnStatus>,
+	mut commands: Commands,
+	mut actions: UnitActions,
+) {
+	for request in requests.iter() {
+		let reject = |clients: &mut Clients, reason: &str| {
+			clients.send(
+				request.client,
+				ServerCommand::Rejected {
+					seq: request.seq,
+					reason: reason.to_owned(),
+				},
+			)
+		};
+		let snapshot: Vec<Unit> = units
+			.iter_mut()
+			.map(|(_entity, unit)| unit.clone())
+			.collect();
+		let (entity, mut unit) = match units
+			.iter_mut()
+			.find(|(_entity, unit)| unit.id == request.unit && unit.owner == request.player)
+		{
+			Some(found) => found,
+			None => {
+				reject(&mut clients, "not your unit");
+				continue;
+			}
+		};
+		if !status.to_move.contains(&request.player) {
+			reject(&mut clients, "it is not your turn");
+			continue;
+		}
+		match &request.order {
+			UnitOrder::MoveTo(hex) => {
+				unit.waypoints = vec![*hex];
+				unit.fortified = false;
+			}
+			UnitOrder::AddWaypoint(hex) => {
+				unit.waypoints.push(*hex);
+				unit.fortified = false;
+			}
+			UnitOrder::Skip => unit.moves_left = 0,
+			UnitOrder::Fortify => {
+				unit.waypoints.clear();
+				unit.fortified = true;
+			}
+			UnitOrder::Disband => {
+				commands.entity(entity).despawn();
+				continue;
+			}
+			UnitOrder::Attack { target, kind } => {
+				actions.attacks.send(AttackRequest {
+					client: request.client,
+					seq: request.seq,
+					unit: unit.id,
+					target: *target,
+					kind: *kind,
+				});
+				continue;
+			}
+			UnitOrder::FoundCity { name } => {
+				actions.found_cities.send(FoundCityRequest {
+					client: request.client,
+					seq: request.seq,
+					unit: unit.id,
+					name: name.clone(),
+				});
+				continue;
+			}
+			UnitOrder::BuildImprovement { improvement } => {
+				actions.improvements.send(BuildImprovementRequest {
+					client: request.client,
+					seq: request.seq,
+					unit: unit.id,
+					improvement: improvement.clone(),
+				});
+				continue;
+			}
+		}
+		world.movement(snapshot).advance(&mut unit);
+	}
+}
+
+pub(crate) fn preview_paths(
+	mut requests: EventReader<PathPreviewRequest>,
+	mut clients: ResMut<Clients>,
+	units: Query<&Unit>,
+	world: MoveWorld,
+) {
+	let mut movement = None;
+	for request in requests.iter() {
+		let movement =
+			movement.get_or_insert_with(|| world.movement(units.iter().cloned().collect()));
+		let command = match movement
+			.units
+			.iter()
+			.find(|unit| unit.id == request.unit && unit.owner == request.player)
+		{
+			Some(unit) => ServerCommand::PathPreview {
+				seq: request.seq,
+				unit: unit.id,
+				path: movement.route(unit, &request.waypoints),
+			},
+			None => ServerCommand::Rejected {
+				seq: request.seq,
+				reason: "not your unit".to_owned(),
+			},
+		};
+		clients.send(request.client, command);
+	}
+}
+
+/// Give every unit its movement back and move it on towards its waypoints.
+pub(crate) fn start_unit_turns(
+	mut turns: EventReader<TurnStarted>,
+	mut units: Query<(Entity, &mut Unit)>,
+	world: MoveWorld,
+) {
+	if turns.iter().count() == 0 {
+		return;
+	}
+	let mut snapshot: Vec<(Entity, Unit)> = units
+		.iter_mut()
+		.map(|(entity, unit)| (entity, unit.clone()))
+		.collect();
+	// By id so units get in each other's way the same no matter how they were loaded
+	snapshot.sort_by_key(|(_entity, unit)| unit.id);
+	let order: Vec<Entity> = snapshot.iter().map(|(entity, _unit)| *entity).collect();
+	let mut movement = world.movement(snapshot.into_iter().map(|(_, unit)| unit).collect());
+	for (idx, entity) in order.into_iter().enumerate() {
+		if let Ok((_entity, mut unit)) = units.get_mut(entity) {
+			unit.moves_left = unit.max_moves(movement.rules);
+			movement.advance(&mut unit);
+			movement.units[idx] = unit.clone();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Movement;
+	use crate::server::game::city::CityId;
+	use crate::server::game::pathfinding::MOVE_POINT;
+	use crate::server::game::player::{Player, PlayerId, Players};
+	use crate::server::game::rules::Rules;
+	use crate::server::game::unit::{Unit, UnitId};
+	use crate::server::game::vision::Visions;
+	use crate::server::game::Wars;
+	use crate::server::world::{Hex, Terrain, TileOwner, WorldMap};
+
+	#[test]
+	fn waypoints_over_turns() {
+		let map = WorldMap::filled(20, 8, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let (visions, wars) = (Visions::default(), Wars::default());
+		let mut unit = Unit::new(
+			UnitId(1),
+			PlayerId(0),
+			"warrior",
+			Hex::from_offset(1, 2),
+			&rules,
+		);
+		unit.waypoints = vec![Hex::from_offset(4, 2), Hex::from_offset(4, 4)];
+		let movement = Movement {
+			map: &map,
+			visions: &visions,
+			wars: &wars,
+			rules: &rules,
+			units: vec![],
+			cities: vec![],
+		};
+		let route = movement.route(&unit, &unit.waypoints).unwrap();
+		assert_eq!(route.len(), 5);
+		assert_eq!(route.last().unwrap().turn, 2);
+
+		movement.advance(&mut unit);
+		assert_eq!(unit.position, Hex::from_offset(3, 2));
+		assert_eq!(unit.moves_left, 0);
+		unit.moves_left = unit.max_moves(&rules);
+		movement.advance(&mut unit);
+		assert_eq!(unit.waypoints, vec![Hex::from_offset(4, 4)]);
+		unit.moves_left = unit.max_moves(&rules);
+		movement.advance(&mut unit);
+		assert_eq!(unit.position, Hex::from_offset(4, 4));
+		assert!(unit.waypoints.is_empty());
+		assert_eq!(unit.moves_left, MOVE_POINT);
+	}
+
+	#[test]
+	fn stops_at_hidden_units() {
+		let map = WorldMap::filled(20, 8, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let players = Players(
+			(0..2)
+				.map(|id| Player::new(PlayerId(id), format!("Player {}", id), "rome".into()))
+				.collect(),
+		);
+		let scout = Unit::new(
+			UnitId(1),
+			PlayerId(0),
+			"scout",
+			Hex::from_offset(1, 2),
+			&rules,
+		);
+		let hidden = Unit::new(
+			UnitId(2),
+			PlayerId(1),
+			"warrior",
+			Hex::from_offset(4, 2),
+			&rules,
+		);
+		let mut visions = Visions::default();
+		visions.update(&map, &players, &[&scout, &hidden], &[]);
+		let wars = Wars::default();
+		let movement = Movement {
+			map: &map,
+			visions: &visions,
+			wars: &wars,
+			rules: &rules,
+			units: vec![scout.clone(), hidden.clone()],
+			cities: vec![],
+		};
+		// The path is planned straight through the unit the scout cannot see, the preview does not
+		// give it away
+		let mut unit = scout;
+		unit.waypoints = vec![Hex::from_offset(7, 2)];
+		let route = movement.route(&unit, &unit.waypoints).unwrap();
+		assert!(route.iter().any(|step| step.hex == hidden.position));
+		movement.advance(&mut unit);
+		assert_eq!(unit.position, Hex::from_offset(3, 2));
+		assert_eq!(unit.waypoints, vec![Hex::from_offset(7, 2)]);
+		assert_eq!(unit.moves_left, MOVE_POINT);
+
+		// Once it is seen the path goes around it
+		visions.update(&map, &players, &[&unit, &hidden], &[]);
+		let movement = Movement {
+			map: &map,
+			visions: &visions,
+			wars: &wars,
+			rules: &rules,
+			units: vec![unit.clone(), hidden.clone()],
+			cities: vec![],
+		};
+		let route = movement.route(&unit, &unit.waypoints).unwrap();
+		assert!(!route.iter().any(|step| step.hex == hidden.position));
+	}
+
+	#[test]
+	fn closed_borders() {
+		let mut map = WorldMap::filled(20, 8, Terrain::Grassland);
+		let rules = Rules::bundled();
+		// A strip of territory across the whole map, the long way round is blocked by it too
+		for row in 0..8 {
+			for col in [4, 14].iter() {
+				map.get_mut(Hex::from_offset(*col, row)).unwrap().owner = Some(TileOwner {
+					player: PlayerId(1),
+					city: CityId(1),
+				});
+			}
+		}
+		let visions = Visions::default();
+		let peace = Wars::default();
+		let mut war = Wars::default();
+		war.declare(PlayerId(0), PlayerId(1));
+		let mut unit = Unit::new(
+			UnitId(1),
+			PlayerId(0),
+			"warrior",
+			Hex::from_offset(1, 2),
+			&rules,
+		);
+		unit.waypoints = vec![Hex::from_offset(7, 2)];
+		let movement = |wars| Movement {
+			map: &map,
+			visions: &visions,
+			wars,
+			rules: &rules,
+			units: vec![],
+			cities: vec![],
+		};
+		assert_eq!(movement(&peace).route(&unit, &unit.waypoints), None);
+		movement(&peace).advance(&mut unit);
+		assert_eq!(unit.position, Hex::from_offset(1, 2));
+		assert!(unit.waypoints.is_empty());
+
+		// At war the territory is open
+		unit.waypoints = vec![Hex::from_offset(7, 2)];
+		assert!(movement(&war).route(&unit, &unit.waypoints).is_some());
+	}
+
+	#[test]
+	fn borders_grown_around() {
+		let mut map = WorldMap::filled(20, 8, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let (visions, wars) = (Visions::default(), Wars::default());
+		let mut unit = Unit::new(
+			UnitId(1),
+			PlayerId(0),
+			"warrior",
+			Hex::from_offset(5, 2),
+			&rules,
+		);
+		for hex in
+			std::iter::once(unit.position).chain(map.neighbors(unit.position).collect::<Vec<_>>())
+		{
+			map.get_mut(hex).unwrap().owner = Some(TileOwner {
+				player: PlayerId(1),
+				city: CityId(1),
+			});
+		}
+		unit.waypoints = vec![Hex::from_offset(9, 2)];
+		let movement = Movement {
+			map: &map,
+			visions: &visions,
+			wars: &wars,
+			rules: &rules,
+			units: vec![],
+			cities: vec![],
+		};
+		assert!(movement.route(&unit, &unit.waypoints).is_some());
+		movement.advance(&mut unit);
+		assert_eq!(unit.position, Hex::from_offset(7, 2));
+		assert_eq!(map.get(unit.position).unwrap().owner, None);
+	}
+}
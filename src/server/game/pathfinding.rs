@@ -0,0 +1,322 @@
+//! A* pathfinding of land units over the hex map.  Movement is counted in thirds of a movement
+//! point so that a road can cost a third of one.  A unit can always enter a tile while it has any
+//! movement left, if the tile costs more than it has left entering uses up the rest.
+//!
+//! Paths may take many turns, every step of a path has the turn it is reached in counted from the
+//! current turn.
+
+use crate::server::world::{Elevation, Feature, Hex, HexDirection, WorldMap};
+use crate::universal::commands::PathStep;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+
+/// A movement point, in the fractions movement is counted in.
+pub const MOVE_POINT: u32 = 3;
+
+/// What a path has to go around.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Obstacles {
+	/// Tiles the unit cannot enter, such as those holding units of other players.
+	pub blocked: BTreeSet<Hex>,
+	/// Tiles in the zone of control of an enemy unit, next to it.  Moving from one of them to
+	/// another uses up all movement.
+	pub zone_of_control: BTreeSet<Hex>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepCost {
+	pub cost: u32,
+	/// The step uses up all movement left, whatever its cost.
+	pub ends_move: bool,
+}
+
+/// The cost of moving from `from` into its neighbor in `direction`, `None` if it cannot be entered.
+/// Hexes must be in their canonical form.
+pub fn step_cost(
+	map: &WorldMap,
+	obstacles: &Obstacles,
+	from: Hex,
+	direction: HexDirection,
+) -> Option<StepCost> {
+	let to = map.normalize(from.neighbor(direction))?;
+	let (from_tile, to_tile) = (map.get(from)?, map.get(to)?);
+	if to_tile.terrain.is_water()
+		|| to_tile.elevation == Elevation::Mountains
+		|| obstacles.blocked.contains(&to)
+	{
+		return None;
+	}
+	let zone_of_control =
+		obstacles.zone_of_control.contains(&from) && obstacles.zone_of_control.contains(&to);
+	// A road on both tiles bridges any river between them
+	if from_tile.road && to_tile.road {
+		return Some(StepCost {
+			cost: MOVE_POINT / 3,
+			ends_move: zone_of_control,
+		});
+	}
+	let rough = to_tile.elevation == Elevation::Hills
+		|| matches!(
+			to_tile.feature,
+			Some(Feature::Forest) | Some(Feature::Jungle) | Some(Feature::Marsh)
+		);
+	Some(StepCost {
+		cost: if rough { MOVE_POINT * 2 } else { MOVE_POINT },
+		ends_move: zone_of_control || from_tile.has_river(direction),
+	})
+}
+
+/// The movement left after a step.
+pub fn after_step(moves_left: u32, step: StepCost) -> u32 {
+	if step.ends_move {
+		0
+	} else {
+		moves_left.saturating_sub(step.cost)
+	}
+}
+
+/// The cheapest path from `from` to `to` for a unit with `max_moves` every turn that has
+/// `moves_left` this turn, not including `from`.  `None` if `to` cannot be reached.
+pub fn find_path(
+	map: &WorldMap,
+	obstacles: &Obstacles,
+	from: Hex,
+	to: Hex,
+	max_moves: u32,
+	moves_left: u32,
+) -> Option<Vec<PathStep>> {
+	let from = map.normalize(from)?;
+	let to = map.normalize(to)?;
+	if from == to {
+		return Some(vec![]);
+	}
+	if max_moves == 0 {
+		return None;
+	}
+	// The cost of a node counts every movement fraction spent or wasted ending turns early, so a
+	// path reaching a tile in fewer turns is always cheaper.
+	let cost = |turn: u32, left: u32| turn as u64 * max_moves as u64 + (max_moves - left) as u64;
+	let estimate = |hex: Hex| map.distance(hex, to) as u64 * (MOVE_POINT / 3) as u64;
+	let start = PathStep {
+		hex: from,
+		turn: 0,
+		moves_left: moves_left.min(max_moves),
+	};
+	let mut best: BTreeMap<Hex, (u64, PathStep, Hex)> = BTreeMap::new();
+	let mut open = BinaryHeap::new();
+	open.push(Reverse((estimate(from), 0u64, from)));
+	best.insert(from, (0, start, from));
+	while let Some(Reverse((_estimate, node_cost, hex))) = open.pop() {
+		let (best_cost, node, _prev) = best[&hex];
+		if node_cost > best_cost {
+			continue;
+		}
+		if hex == to {
+			break;
+		}
+		// Out of movement, wait for the next turn
+		let (turn, left) = match node.moves_left {
+			0 => (node.turn + 1, max_moves),
+			left => (node.turn, left),
+		};
+		for direction in HexDirection::ALL.iter() {
+			let step = match step_cost(map, obstacles, hex, *direction) {
+				Some(step) => step,
+				None => continue,
+			};
+			let next = match map.normalize(hex.neighbor(*direction)) {
+				Some(next) => next,
+				None => continue,
+			};
+			let left = after_step(left, step);
+			let next_cost = cost(turn, left);
+			if matches!(best.get(&next), Some((known, _, _)) if *known <= next_cost) {
+				continue;
+			}
+			let step = PathStep {
+				hex: next,
+				turn,
+				moves_left: left,
+			};
+			best.insert(next, (next_cost, step, hex));
+			open.push(Reverse((next_cost + estimate(next), next_cost, next)));
+		}
+	}
+	let mut path = vec![];
+	let mut hex = to;
+	while hex != from {
+		let (_cost, step, prev) = best.get(&hex)?;
+		path.push(*step);
+		hex = *prev;
+	}
+	path.reverse();
+	Some(path)
+}
+
+/// The path through every waypoint in order, continuing each from where the last one ended.
+pub fn find_route(
+	map: &WorldMap,
+	obstacles: &Obstacles,
+	from: Hex,
+	waypoints: &[Hex],
+	max_moves: u32,
+	moves_left: u32,
+) -> Option<Vec<PathStep>> {
+	let mut route: Vec<PathStep> = vec![];
+	let (mut hex, mut turn, mut left) = (from, 0, moves_left);
+	for waypoint in waypoints {
+		let path = find_path(map, obstacles, hex, *waypoint, max_moves, left)?;
+		if let Some(last) = path.last() {
+			hex = last.hex;
+			left = last.moves_left;
+		}
+		route.extend(path.into_iter().map(|step| PathStep {
+			turn: step.turn + turn,
+			..step
+		}));
+		turn = route.last().map(|step| step.turn).unwrap_or(turn);
+	}
+	Some(route)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{find_path, find_route, Obstacles, MOVE_POINT};
+	use crate::server::world::{Elevation, Feature, Hex, HexDirection, Terrain, WorldMap};
+	use crate::universal::commands::PathStep;
+
+	const MOVES: u32 = 2 * MOVE_POINT;
+
+	fn hexes(path: &[PathStep]) -> Vec<(i32, i32)> {
+		path.iter().map(|step| step.hex.to_offset()).collect()
+	}
+
+	fn turns(path: &[PathStep]) -> Vec<u32> {
+		path.iter().map(|step| step.turn).collect()
+	}
+
+	fn path(
+		map: &WorldMap,
+		obstacles: &Obstacles,
+		from: (i32, i32),
+		to: (i32, i32),
+	) -> Vec<PathStep> {
+		let (from, to) = (
+			Hex::from_offset(from.0, from.1),
+			Hex::from_offset(to.0, to.1),
+		);
+		find_path(map, obstacles, from, to, MOVES, MOVES).unwrap()
+	}
+
+	#[test]
+	fn terrain_costs() {
+		let mut map = WorldMap::filled(12, 7, Terrain::Grassland);
+		let none = Obstacles::default();
+		let open = path(&map, &none, (1, 3), (5, 3));
+		assert_eq!(hexes(&open), vec![(2, 3), (3, 3), (4, 3), (5, 3)]);
+		assert_eq!(turns(&open), vec![0, 0, 1, 1]);
+
+		let flat = path(&map, &none, (2, 3), (4, 3));
+		assert_eq!(turns(&flat), vec![0, 0]);
+
+		// Hills and forests cost two movement points
+		for row in 0..7 {
+			map.get_mut(Hex::from_offset(3, row)).unwrap().elevation = Elevation::Hills;
+		}
+		map.get_mut(Hex::from_offset(3, 3)).unwrap().feature = Some(Feature::Forest);
+		let hills = path(&map, &none, (2, 3), (4, 3));
+		assert_eq!(hills[0].moves_left, 0);
+		assert_eq!(turns(&hills), vec![0, 1]);
+		// Any movement left is enough to enter
+		let tired = find_path(
+			&map,
+			&none,
+			Hex::from_offset(2, 3),
+			Hex::from_offset(3, 3),
+			MOVES,
+			1,
+		)
+		.unwrap();
+		assert_eq!(turns(&tired), vec![0]);
+
+		// Mountains and water cannot be entered
+		for row in 0..7 {
+			map.get_mut(Hex::from_offset(3, row)).unwrap().elevation = Elevation::Mountains;
+		}
+		map.get_mut(Hex::from_offset(9, 3)).unwrap().terrain = Terrain::Lake;
+		let from = Hex::from_offset(1, 3);
+		assert_eq!(
+			find_path(&map, &none, from, Hex::from_offset(9, 3), MOVES, MOVES),
+			None
+		);
+		// Unless the map wraps around
+		let wrapped = path(&map, &none, (1, 3), (5, 3));
+		assert!(hexes(&wrapped).contains(&(11, 3)));
+	}
+
+	#[test]
+	fn roads_and_rivers() {
+		let mut map = WorldMap::filled(12, 7, Terrain::Grassland);
+		let none = Obstacles::default();
+		for col in 1..6 {
+			map.get_mut(Hex::from_offset(col, 3)).unwrap().road = true;
+		}
+		let road = path(&map, &none, (1, 3), (5, 3));
+		assert_eq!(turns(&road), vec![0, 0, 0, 0]);
+		assert_eq!(road.last().unwrap().moves_left, MOVES - 4);
+
+		// Crossing a river uses up the turn, unless a road bridges it
+		let mut map = WorldMap::filled(12, 7, Terrain::Grassland);
+		map.set_river(Hex::from_offset(2, 3), HexDirection::East);
+		let crossing = path(&map, &none, (2, 3), (4, 3));
+		assert_eq!(crossing[0].moves_left, 0);
+		assert_eq!(turns(&crossing), vec![0, 1]);
+		map.get_mut(Hex::from_offset(2, 3)).unwrap().road = true;
+		map.get_mut(Hex::from_offset(3, 3)).unwrap().road = true;
+		let bridged = path(&map, &none, (2, 3), (4, 3));
+		assert_eq!(turns(&bridged), vec![0, 0]);
+	}
+
+	#[test]
+	fn zone_of_control() {
+		let map = WorldMap::filled(12, 7, Terrain::Grassland);
+		let enemy = Hex::from_offset(4, 3);
+		let obstacles = Obstacles {
+			blocked: std::iter::once(enemy).collect(),
+			zone_of_control: map.neighbors(enemy).collect(),
+		};
+		let moves = 3 * MOVE_POINT;
+		// Passing along the enemy from one tile next to it to another ends the move
+		let from = Hex::from_offset(3, 2);
+		let to = Hex::from_offset(5, 2);
+		let free = find_path(&map, &Obstacles::default(), from, to, moves, moves).unwrap();
+		assert_eq!(free.last().unwrap().moves_left, MOVE_POINT);
+		let past = find_path(&map, &obstacles, from, to, moves, moves).unwrap();
+		assert_eq!(hexes(&past), vec![(4, 2), (5, 2)]);
+		assert_eq!(past.last().unwrap().moves_left, 0);
+		// The enemy's own tile cannot be entered
+		let through = path(&map, &obstacles, (3, 3), (5, 3));
+		assert!(!hexes(&through).contains(&enemy.to_offset()));
+		assert_eq!(through.last().unwrap().hex, Hex::from_offset(5, 3));
+	}
+
+	#[test]
+	fn waypoints() {
+		let map = WorldMap::filled(12, 7, Terrain::Grassland);
+		let none = Obstacles::default();
+		let from = Hex::from_offset(1, 1);
+		let waypoints = [Hex::from_offset(3, 1), Hex::from_offset(3, 3)];
+		let route = find_route(&map, &none, from, &waypoints, MOVES, MOVE_POINT).unwrap();
+		assert_eq!(route.first().unwrap().turn, 0);
+		assert_eq!(route.last().unwrap().hex, waypoints[1]);
+		assert_eq!(route[1].hex, waypoints[0]);
+		assert_eq!(turns(&route), vec![0, 1, 1, 2]);
+		// Out of movement the first step is next turn
+		let tired = find_route(&map, &none, from, &waypoints, MOVES, 0).unwrap();
+		assert_eq!(tired.first().unwrap().turn, 1);
+		assert_eq!(
+			find_route(&map, &none, from, &[], MOVES, MOVES),
+			Some(vec![])
+		);
+	}
+}
@@ -0,0 +1,70 @@
+use crate::universal::commands::state::PlayerInfo;
+pub use crate::universal::ids::PlayerId;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+	pub id: PlayerId,
+	pub name: String,
+	/// The civilization this player leads.
+	pub civ: SmolStr,
+	/// Gold in the treasury, cities add the gold they yield at the end of every turn.
+	#[serde(default)]
+	pub gold: u32,
+	/// The techs the player knows, keys of `Rules::techs`.
+	#[serde(default)]
+	pub techs: BTreeSet<SmolStr>,
+	/// Beakers put into research that did not go into a tech yet, see `research`.
+	#[serde(default)]
+	pub research: u32,
+	/// The techs to research in order, the first is being researched.
+	#[serde(default)]
+	pub research_queue: Vec<SmolStr>,
+}
+
+impl Player {
+	/// A new player with an empty treasury who knows and researches no techs.
+	pub fn new(id: PlayerId, name: String, civ: SmolStr) -> Self {
+		Self {
+			id,
+			name,
+			civ,
+			gold: 0,
+			techs: BTreeSet::new(),
+			research: 0,
+			research_queue: vec![],
+		}
+	}
+
+	/// The player as it is sent to clients, `away` is only known to the connected clients.  Only
+	/// the player is sent their gold.
+	pub fn info(&self, away: bool, viewer: PlayerId) -> PlayerInfo {
+		PlayerInfo {
+			id: self.id,
+			name: self.name.clone(),
+			civ: self.civ.clone(),
+			away,
+			gold: Some(self.gold).filter(|_gold| viewer == self.id),
+		}
+	}
+}
+
+/// All players of the loaded game, in turn order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Players(pub Vec<Player>);
+
+impl Players {
+	pub fn get(&self, id: PlayerId) -> Option<&Player> {
+		self.0.iter().find(|p| p.id == id)
+	}
+
+	pub fn get_mut(&mut self, id: PlayerId) -> Option<&mut Player> {
+		self.0.iter_mut().find(|p| p.id == id)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Player> {
+		self.0.iter()
+	}
+}
@@ -0,0 +1,705 @@
+//! What cities build.  Every city has a queue of units and buildings, the production it yields at
+//! the end of every turn goes into the first of them and what is left over once it is built
+//! carries over to the next.  The first can be bought with gold instead, unless it is a wonder.
+//!
+//! Wonders are buildings only one city in the whole game can have.  Once one is built every other
+//! city drops it from its queue, keeping the production put into it for the next entry.  If
+//! several cities finish the same wonder at the end of the same turn the one with the most
+//! production left over gets it, then the one founded first.
+
+use super::city::{City, CityId};
+use super::player::{PlayerId, Players};
+use super::rules::Rules;
+use super::unit::Unit;
+use super::{GameEntity, GameIds, Notified, TurnEnded};
+use crate::server::clients::{ClientId, Clients};
+use crate::server::world::{Terrain, WorldMap};
+use crate::universal::commands::{CityOrder, Notification, ProductionItem, ServerCommand};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProductionError {
+	#[error("there is no such unit or building")]
+	Unknown,
+	#[error("needs the tech `{0}`")]
+	MissingTech(SmolStr),
+	#[error("needs the resource `{0}` near the city")]
+	MissingResource(SmolStr),
+	#[error("needs the city to be next to {0:?}")]
+	MissingTerrain(Vec<Terrain>),
+	#[error("needs the city to be on the coast")]
+	NotCoastal,
+	#[error("needs a `{0}` in the city")]
+	MissingBuilding(SmolStr),
+	#[error("the city already has it")]
+	AlreadyBuilt,
+	#[error("it is already in the queue")]
+	AlreadyQueued,
+	#[error("the wonder was already built")]
+	WonderBuilt,
+	#[error("the queue is full")]
+	QueueFull,
+	#[error("there is no such entry in the queue")]
+	NotQueued,
+	#[error("wonders cannot be bought")]
+	CannotPurchase,
+	#[error("costs {0} gold")]
+	NotEnoughGold(u32),
+}
+
+/// A building in a city.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Building {
+	pub city: CityId,
+	/// The building type, a key of `Rules::buildings`.
+	pub kind: SmolStr,
+}
+
+/// The buildings of every city that has any.
+pub fn buildings_by_city<'b>(
+	buildings: impl IntoIterator<Item = &'b Building>,
+) -> BTreeMap<CityId, Vec<SmolStr>> {
+	let mut by_city: BTreeMap<CityId, Vec<SmolStr>> = BTreeMap::new();
+	for building in buildings {
+		by_city
+			.entry(building.city)
+			.or_default()
+			.push(building.kind.clone());
+	}
+	for kinds in by_city.values_mut() {
+		kinds.sort();
+	}
+	by_city
+}
+
+/// Everything outside a city that decides what it can build.
+pub struct BuildContext<'a> {
+	pub map: &'a WorldMap,
+	pub rules: &'a Rules,
+	/// The techs of the city's owner.
+	pub techs: &'a BTreeSet<SmolStr>,
+	/// The buildings the city has.
+	pub buildings: &'a [SmolStr],
+	/// The wonders built anywhere in the game.
+	pub wonders: &'a BTreeSet<SmolStr>,
+}
+
+impl City {
+	/// The production cost of `item` if the city can build it.
+	pub fn can_build(
+		&self,
+		item: &ProductionItem,
+		context: &BuildContext,
+	) -> Result<u32, ProductionError> {
+		let map = context.map;
+		let (cost, prerequisites) = context
+			.rules
+			.production(item)
+			.ok_or(ProductionError::Unknown)?;
+		if let ProductionItem::Building(kind) = item {
+			if context.buildings.contains(kind) {
+				return Err(ProductionError::AlreadyBuilt);
+			}
+			if context.wonders.contains(kind) {
+				return Err(ProductionError::WonderBuilt);
+			}
+		}
+		if let Some(tech) = prerequisites.tech {
+			if !context.techs.contains(tech) {
+				return Err(ProductionError::MissingTech(tech.clone()));
+			}
+		}
+		if let Some(resource) = prerequisites.resource {
+			if !map
+				.spiral(self.position, context.rules.cities.radius)
+				.into_iter()
+				.any(
+					|hex| matches!(map.get(hex), Some(tile) if tile.resource.as_ref() == Some(resource)),
+				) {
+				return Err(ProductionError::MissingResource(resource.clone()));
+			}
+		}
+		let next_to = |terrains: &[Terrain]| {
+			map.neighbors(self.position)
+				.any(|hex| matches!(map.get(hex), Some(tile) if terrains.contains(&tile.terrain)))
+		};
+		if !prerequisites.adjacent_terrains.is_empty() && !next_to(prerequisites.adjacent_terrains)
+		{
+			return Err(ProductionError::MissingTerrain(
+				prerequisites.adjacent_terrains.to_vec(),
+			));
+		}
+		if prerequisites.coastal && !next_to(&[Terrain::Coast, Terrain::Ocean]) {
+			return Err(ProductionError::NotCoastal);
+		}
+		if let Some(missing) = prerequisites
+			.buildings
+			.iter()
+			.find(|required| !context.buildings.contains(required))
+		{
+			return Err(ProductionError::MissingBuilding(missing.clone()));
+		}
+		Ok(cost)
+	}
+
+	/// Add `item` to the end of the production queue.
+	pub fn enqueue(
+		&mut self,
+		item: ProductionItem,
+		context: &BuildContext,
+	) -> Result<(), ProductionError> {
+		self.can_build(&item, context)?;
+		if self.queue.len() >= context.rules.cities.queue_length {
+			return Err(ProductionError::QueueFull);
+		}
+		if matches!(item, ProductionItem::Building(_)) && self.queue.contains(&item) {
+			return Err(ProductionError::AlreadyQueued);
+		}
+		self.queue.push(item);
+		Ok(())
+	}
+
+	/// Take the entry at `index` out of the production queue, the production put into the first
+	/// entry is kept for the next.
+	pub fn dequeue(&mut self, index: usize) -> Result<ProductionItem, ProductionError> {
+		if index >= self.queue.len() {
+			return Err(ProductionError::NotQueued);
+		}
+		Ok(self.queue.remove(index))
+	}
+
+	/// The gold it costs to buy the first entry of the queue.
+	pub fn purchase_cost(&self, context: &BuildContext) -> Result<u32, ProductionError> {
+		let item = self.queue.first().ok_or(ProductionError::NotQueued)?;
+		if context.rules.is_wonder(item) {
+			return Err(ProductionError::CannotPurchase);
+		}
+		let cost = self.can_build(item, context)?;
+		Ok(cost.saturating_sub(self.production) * context.rules.cities.purchase_cost)
+	}
+
+	/// Buy the first entry of the queue with the owner's `gold`, returning it to be spawned.
+	pub fn purchase(
+		&mut self,
+		gold: &mut u32,
+		context: &BuildContext,
+	) -> Result<ProductionItem, ProductionError> {
+		let price = self.purchase_cost(context)?;
+		if *gold < price {
+			return Err(ProductionError::NotEnoughGold(price));
+		}
+		let cost = self.can_build(&self.queue[0], context)?;
+		*gold -= price;
+		self.production = self.production.saturating_sub(cost);
+		Ok(self.queue.remove(0))
+	}
+
+	/// Drop the entries at the start of the queue that can no longer be built, returning them with
+	/// the reason.
+	fn drop_unbuildable(&mut self, context: &BuildContext) -> Vec<(ProductionItem, String)> {
+		let mut dropped = vec![];
+		while let Some(item) = self.queue.first() {
+			match self.can_build(item, context) {
+				Ok(_cost) => break,
+				Err(e) => dropped.push((self.queue.remove(0), e.to_string())),
+			}
+		}
+		dropped
+	}
+}
+
+/// Put the production of every city into its queue at the end of a turn, returning what each
+/// player is to be told.  The `Notification::Built` ones are for the caller to spawn.
+pub fn produce(
+	cities: &mut [City],
+	map: &WorldMap,
+	rules: &Rules,
+	players: &Players,
+	buildings: &BTreeMap<CityId, Vec<SmolStr>>,
+) -> Vec<(PlayerId, Notification)> {
+	let no_techs = BTreeSet::new();
+	let mut wonders = wonders(rules, buildings);
+	let mut notifications = vec![];
+	// The cities that finished their first entry, with the production left over
+	let mut finished: Vec<(usize, u32)> = vec![];
+	for (idx, city) in cities.iter_mut().enumerate() {
+		let context = BuildContext {
+			map,
+			rules,
+			techs: players.get(city.owner).map_or(&no_techs, |p| &p.techs),
+			buildings: buildings.get(&city.id).map_or(&[], Vec::as_slice),
+			wonders: &wonders,
+		};
+		for (item, reason) in city.drop_unbuildable(&context) {
+			let dropped = Notification::Dropped {
+				city: city.id,
+				item,
+				reason,
+			};
+			notifications.push((city.owner, dropped));
+		}
+		city.production += city.yields(map, rules, context.buildings).production;
+		if let Some(item) = city.queue.first() {
+			let cost = city.can_build(item, &context).unwrap_or(u32::MAX);
+			if city.production >= cost {
+				finished.push((idx, city.production - cost));
+			}
+		}
+	}
+	// Most production left over first, then the city founded first, so the first of the cities
+	// finishing a wonder gets it
+	finished.sort_by_key(|(idx, left_over)| (Reverse(*left_over), cities[*idx].id));
+	for (idx, left_over) in finished {
+		let city = &mut cities[idx];
+		let item = city.queue.remove(0);
+		if let ProductionItem::Building(kind) = &item {
+			if rules.is_wonder(&item) && !wonders.insert(kind.clone()) {
+				notifications.push((
+					city.owner,
+					Notification::Dropped {
+						city: city.id,
+						item,
+						reason: ProductionError::WonderBuilt.to_string(),
+					},
+				));
+				continue;
+			}
+		}
+		city.production = left_over;
+		notifications.push((
+			city.owner,
+			Notification::Built {
+				city: city.id,
+				item,
+			},
+		));
+	}
+	notifications
+}
+
+/// Event of a production order to a city, sent once the order was checked to be the player's to
+/// give.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductionRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub city: CityId,
+	pub order: CityOrder,
+}
+
+/// Everything cities build with.
+#[derive(SystemParam)]
+pub struct Workshop<'a> {
+	map: Res<'a, WorldMap>,
+	rules: Res<'a, Rules>,
+	players: ResMut<'a, Players>,
+	ids: ResMut<'a, GameIds>,
+	buildings: Query<'a, &'static Building>,
+	notified: EventWriter<'a, Notified>,
+	commands: Commands<'a>,
+}
+
+impl<'a> Workshop<'a> {
+	/// Spawn what `city` finished and tell its owner.
+	fn finish(&mut self, city: &City, item: ProductionItem) {
+		info!("{} built {:?}", city.name, item);
+		match &item {
+			ProductionItem::Unit(kind) => {
+				let id = self.ids.next_unit();
+				let unit = Unit::new(id, city.owner, kind, city.position, &self.rules);
+				self.commands.spawn().insert(unit).insert(GameEntity);
+			}
+			ProductionItem::Building(kind) => {
+				let building = Building {
+					city: city.id,
+					kind: kind.clone(),
+				};
+				self.commands.spawn().insert(building).insert(GameEntity);
+			}
+		}
+		self.notified.send(Notified {
+			player: city.owner,
+			notification: Notification::Built {
+				city: city.id,
+				item,
+			},
+		});
+	}
+}
+
+/// The wonders among `buildings`.
+fn wonders(rules: &Rules, buildings: &BTreeMap<CityId, Vec<SmolStr>>) -> BTreeSet<SmolStr> {
+	buildings
+		.values()
+		.flatten()
+		.filter(|kind| matches!(rules.buildings.get(*kind), Some(building) if building.wonder))
+		.cloned()
+		.collect()
+}
+
+pub(crate) fn order_production(
+	mut requests: EventReader<ProductionRequest>,
+	mut clients: ResMut<Clients>,
+	mut cities: Query<&mut City>,
+	mut workshop: Workshop,
+) {
+	for request in requests.iter() {
+		let mut city = match cities.iter_mut().find(|city| city.id == request.city) {
+			Some(city) => city,
+			None => continue,
+		};
+		let buildings = buildings_by_city(workshop.buildings.iter());
+		let wonders = wonders(&workshop.rules, &buildings);
+		let techs = workshop
+			.players
+			.get(city.owner)
+			.map(|player| player.techs.clone())
+			.unwrap_or_default();
+		let context = BuildContext {
+			map: &workshop.map,
+			rules: &workshop.rules,
+			techs: &techs,
+			buildings: buildings.get(&city.id).map_or(&[], Vec::as_slice),
+			wonders: &wonders,
+		};
+		let result = match &request.order {
+			CityOrder::Enqueue(item) => city.enqueue(item.clone(), &context),
+			CityOrder::Dequeue(index) => city.dequeue(*index).map(|_item| ()),
+			CityOrder::Purchase => {
+				let mut no_gold = 0;
+				let gold = match workshop.players.get_mut(city.owner) {
+					Some(player) => &mut player.gold,
+					None => &mut no_gold,
+				};
+				match city.purchase(gold, &context) {
+					Ok(item) => {
+						workshop.finish(&city, item);
+						Ok(())
+					}
+					Err(e) => Err(e),
+				}
+			}
+			CityOrder::WorkTile(_) | CityOrder::ReleaseTile(_) => Ok(()),
+		};
+		if let Err(e) = result {
+			clients.send(
+				request.client,
+				ServerCommand::Rejected {
+					seq: request.seq,
+					reason: e.to_string(),
+				},
+			);
+		}
+	}
+}
+
+/// Put the production of every city into its queue at the end of the turn, and the gold they
+/// yield into their owners' treasuries.
+pub(crate) fn build_in_cities(
+	mut ended: EventReader<TurnEnded>,
+	mut cities: Query<&mut City>,
+	mut workshop: Workshop,
+) {
+	if ended.iter().count() == 0 {
+		return;
+	}
+	let buildings = buildings_by_city(workshop.buildings.iter());
+	let mut snapshot: Vec<City> = cities.iter_mut().map(|city| city.clone()).collect();
+	snapshot.sort_by_key(|city| city.id);
+	let notifications = produce(
+		&mut snapshot,
+		&workshop.map,
+		&workshop.rules,
+		&workshop.players,
+		&buildings,
+	);
+	for city in &snapshot {
+		let city_buildings = buildings.get(&city.id).map_or(&[][..], Vec::as_slice);
+		let gold = city
+			.yields(&workshop.map, &workshop.rules, city_buildings)
+			.gold;
+		if let Some(player) = workshop.players.get_mut(city.owner) {
+			player.gold += gold;
+		}
+	}
+	for (player, notification) in notifications {
+		match notification {
+			Notification::Built { city, item } => {
+				if let Some(city) = snapshot.iter().find(|c| c.id == city) {
+					workshop.finish(city, item);
+				}
+			}
+			notification => workshop.notified.send(Notified {
+				player,
+				notification,
+			}),
+		}
+	}
+	for mut city in cities.iter_mut() {
+		if let Some(updated) = snapshot.iter().find(|updated| updated.id == city.id) {
+			if *city != *updated {
+				*city = updated.clone();
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{buildings_by_city, produce, BuildContext, Building, ProductionError};
+	use crate::server::game::city::{City, CityId};
+	use crate::server::game::player::{Player, PlayerId, Players};
+	use crate::server::game::rules::Rules;
+	use crate::server::world::{Hex, Terrain, WorldMap};
+	use crate::universal::commands::{Notification, ProductionItem};
+	use smol_str::SmolStr;
+	use std::collections::{BTreeMap, BTreeSet};
+
+	fn unit(kind: &str) -> ProductionItem {
+		ProductionItem::Unit(kind.into())
+	}
+
+	fn building(kind: &str) -> ProductionItem {
+		ProductionItem::Building(kind.into())
+	}
+
+	fn players(techs: &[&str]) -> Players {
+		let mut player = Player::new(PlayerId(0), "Player 0".to_owned(), "rome".into());
+		player.techs = techs.iter().map(|tech| SmolStr::from(*tech)).collect();
+		Players(vec![player])
+	}
+
+	#[test]
+	fn prerequisites() {
+		let mut map = WorldMap::filled(16, 10, Terrain::Plains);
+		let rules = Rules::bundled();
+		let center = Hex::from_offset(6, 4);
+		let mut city = City::new(CityId(1), PlayerId(0), "Rome".to_owned(), center);
+		let mut techs: BTreeSet<SmolStr> = BTreeSet::new();
+		let mut buildings: Vec<SmolStr> = vec![];
+		let wonders = BTreeSet::new();
+		let can_build = |map: &WorldMap,
+		                 techs: &BTreeSet<SmolStr>,
+		                 buildings: &[SmolStr],
+		                 item: ProductionItem| {
+			let context = BuildContext {
+				map,
+				rules: &rules,
+				techs,
+				buildings,
+				wonders: &wonders,
+			};
+			City::new(CityId(1), PlayerId(0), "Rome".to_owned(), center).can_build(&item, &context)
+		};
+
+		assert_eq!(
+			can_build(&map, &techs, &buildings, unit("warrior")).unwrap(),
+			40
+		);
+		assert!(matches!(
+			can_build(&map, &techs, &buildings, building("granary")),
+			Err(ProductionError::MissingTech(tech)) if tech == "pottery"
+		));
+		assert!(matches!(
+			can_build(&map, &techs, &buildings, unit("galleon")),
+			Err(ProductionError::Unknown)
+		));
+
+		techs.extend(vec![
+			"pottery".into(),
+			"sailing".into(),
+			"animal_husbandry".into(),
+		]);
+		assert!(can_build(&map, &techs, &buildings, building("granary")).is_ok());
+		assert!(matches!(
+			can_build(&map, &techs, &buildings, building("harbor")),
+			Err(ProductionError::NotCoastal)
+		));
+		assert!(matches!(
+			can_build(&map, &techs, &buildings, building("caravansary")),
+			Err(ProductionError::MissingTerrain(_))
+		));
+		assert!(matches!(
+			can_build(&map, &techs, &buildings, unit("horseman")),
+			Err(ProductionError::MissingResource(_))
+		));
+		let mut neighbors = map.neighbors(center).collect::<Vec<_>>().into_iter();
+		map.get_mut(neighbors.next().unwrap()).unwrap().terrain = Terrain::Coast;
+		map.get_mut(neighbors.next().unwrap()).unwrap().terrain = Terrain::Desert;
+		map.get_mut(Hex::from_offset(8, 6)).unwrap().resource = Some("horses".into());
+		assert!(can_build(&map, &techs, &buildings, building("harbor")).is_ok());
+		assert!(can_build(&map, &techs, &buildings, building("caravansary")).is_ok());
+		assert!(can_build(&map, &techs, &buildings, unit("horseman")).is_ok());
+
+		techs.insert("writing".into());
+		assert!(matches!(
+			can_build(&map, &techs, &buildings, building("great_library")),
+			Err(ProductionError::MissingBuilding(library)) if library == "library"
+		));
+		buildings.push("library".into());
+		assert!(can_build(&map, &techs, &buildings, building("great_library")).is_ok());
+		assert!(matches!(
+			can_build(&map, &techs, &buildings, building("library")),
+			Err(ProductionError::AlreadyBuilt)
+		));
+
+		// Buildings are queued once, units as often as there is room
+		let context = BuildContext {
+			map: &map,
+			rules: &rules,
+			techs: &techs,
+			buildings: &buildings,
+			wonders: &wonders,
+		};
+		city.enqueue(building("granary"), &context).unwrap();
+		assert!(matches!(
+			city.enqueue(building("granary"), &context),
+			Err(ProductionError::AlreadyQueued)
+		));
+		while city.queue.len() < rules.cities.queue_length {
+			city.enqueue(unit("warrior"), &context).unwrap();
+		}
+		assert!(matches!(
+			city.enqueue(unit("warrior"), &context),
+			Err(ProductionError::QueueFull)
+		));
+		assert_eq!(city.dequeue(0).unwrap(), building("granary"));
+		assert!(matches!(city.dequeue(99), Err(ProductionError::NotQueued)));
+	}
+
+	#[test]
+	fn overflow_and_purchase() {
+		let map = WorldMap::filled(16, 10, Terrain::Plains);
+		let rules = Rules::bundled();
+		let players = players(&[]);
+		let mut city = City::new(
+			CityId(1),
+			PlayerId(0),
+			"Rome".to_owned(),
+			Hex::from_offset(6, 4),
+		);
+		city.assign_tiles(&map, &rules, &BTreeSet::new());
+		// 1 production from the plains center, 1 from its bonus and 1 from a worked plain
+		assert_eq!(city.yields(&map, &rules, &[]).production, 3);
+		city.queue = vec![unit("scout"), unit("warrior"), unit("warrior")];
+		city.production = 29;
+		let mut cities = vec![city];
+		let buildings = BTreeMap::new();
+		let built = produce(&mut cities, &map, &rules, &players, &buildings);
+		assert_eq!(
+			built,
+			vec![(
+				PlayerId(0),
+				Notification::Built {
+					city: CityId(1),
+					item: unit("scout"),
+				}
+			)]
+		);
+		// The 2 left over from the scout went into the warrior
+		assert_eq!(cities[0].production, 2);
+		assert_eq!(cities[0].queue, vec![unit("warrior"), unit("warrior")]);
+
+		// The 38 production the warrior still needs costs 2 gold each
+		let no_wonders = BTreeSet::new();
+		let context = BuildContext {
+			map: &map,
+			rules: &rules,
+			techs: &players.0[0].techs,
+			buildings: &[],
+			wonders: &no_wonders,
+		};
+		assert_eq!(cities[0].purchase_cost(&context).unwrap(), 76);
+		let mut gold = 75;
+		assert!(matches!(
+			cities[0].purchase(&mut gold, &context),
+			Err(ProductionError::NotEnoughGold(76))
+		));
+		gold = 80;
+		assert_eq!(
+			cities[0].purchase(&mut gold, &context).unwrap(),
+			unit("warrior")
+		);
+		assert_eq!((gold, cities[0].production), (4, 0));
+		assert_eq!(cities[0].queue, vec![unit("warrior")]);
+
+		cities[0].queue = vec![building("pyramids")];
+		assert!(matches!(
+			cities[0].purchase(&mut gold, &context),
+			Err(ProductionError::CannotPurchase)
+		));
+	}
+
+	#[test]
+	fn wonders() {
+		let map = WorldMap::filled(16, 10, Terrain::Plains);
+		let rules = Rules::bundled();
+		let mut players = players(&["masonry", "pottery"]);
+		let mut carthage = Player::new(PlayerId(1), "Player 1".to_owned(), "egypt".into());
+		carthage.techs = players.0[0].techs.clone();
+		players.0.push(carthage);
+		let city = |id: u64, owner: u32, col: i32, production: u32| {
+			let mut city = City::new(
+				CityId(id),
+				PlayerId(owner),
+				format!("City {}", id),
+				Hex::from_offset(col, 4),
+			);
+			city.assign_tiles(&map, &rules, &BTreeSet::new());
+			city.queue = vec![building("pyramids"), building("granary")];
+			city.production = production;
+			city
+		};
+		let outcome = |notifications: Vec<(PlayerId, Notification)>| {
+			notifications
+				.into_iter()
+				.map(|(_player, notification)| match notification {
+					Notification::Built { city, item } => (city.0, true, item),
+					Notification::Dropped { city, item, .. } => (city.0, false, item),
+					Notification::Researched { .. } => unreachable!("cities do not research"),
+				})
+				.map(|(city, built, item)| (city, built, item.key().clone()))
+				.collect::<Vec<_>>()
+		};
+
+		// The city with the most production left over gets the wonder
+		let mut cities = vec![city(1, 0, 2, 218), city(2, 1, 8, 219), city(3, 1, 14, 100)];
+		let notifications = produce(&mut cities, &map, &rules, &players, &BTreeMap::new());
+		assert_eq!(
+			outcome(notifications),
+			vec![(2, true, "pyramids".into()), (1, false, "pyramids".into())]
+		);
+		// The loser keeps its production for the granary
+		assert_eq!(cities[0].production, 221);
+		assert_eq!(cities[0].queue, vec![building("granary")]);
+		assert_eq!(cities[1].production, 2);
+
+		// The built wonder is dropped from every other queue at the end of the next turn, what was put
+		// into it builds the granary
+		let built = vec![Building {
+			city: CityId(2),
+			kind: "pyramids".into(),
+		}];
+		let buildings = buildings_by_city(&built);
+		let notifications = produce(&mut cities[2..], &map, &rules, &players, &buildings);
+		assert_eq!(
+			outcome(notifications),
+			vec![(3, false, "pyramids".into()), (3, true, "granary".into())]
+		);
+		assert_eq!(cities[2].production, 41);
+		assert!(cities[2].queue.is_empty());
+
+		// With as much left over the city founded first gets it
+		let mut cities = vec![city(5, 1, 8, 219), city(4, 0, 2, 219)];
+		let notifications = produce(&mut cities, &map, &rules, &players, &BTreeMap::new());
+		assert_eq!(
+			outcome(notifications),
+			vec![(4, true, "pyramids".into()), (5, false, "pyramids".into())]
+		);
+	}
+}
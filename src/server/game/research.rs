@@ -0,0 +1,433 @@
+//! What players research.  The science cities yield at the end of every turn is added to their
+//! owner's beakers, and once there are as many as the first tech of the research queue costs it is
+//! learned.  What is left over goes into the next tech of the queue, several cheap techs can be
+//! learned in one turn.  Beakers are kept while the queue is empty, for whatever is researched
+//! next.
+//!
+//! A tech can only be researched once all its prerequisites are known, so ordering a tech to be
+//! researched queues the prerequisites the player is missing before it.
+
+use super::city::City;
+use super::player::{Player, PlayerId, Players};
+use super::production::{buildings_by_city, Building};
+use super::rules::Rules;
+use super::{Notified, TurnEnded};
+use crate::server::clients::{ClientId, Clients};
+use crate::server::world::WorldMap;
+use crate::universal::commands::state::TurnStatus;
+use crate::universal::commands::{
+	Notification, ResearchOrder, ServerCommand, TechNode, TechStatus, TechTree,
+};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResearchError {
+	#[error("there is no such tech")]
+	Unknown,
+	#[error("the tech is already known")]
+	Known,
+	#[error("the tech is already in the queue")]
+	AlreadyQueued,
+}
+
+impl Player {
+	/// The techs to research to learn `tech`, its unknown prerequisites first and `tech` last, in an
+	/// order they can be researched in.  Techs in `skip` are left out like the known ones.
+	pub fn research_path(
+		&self,
+		rules: &Rules,
+		tech: &str,
+		skip: &[SmolStr],
+	) -> Result<Vec<SmolStr>, ResearchError> {
+		let (tech, _tech_type) = rules
+			.techs
+			.get_key_value(tech)
+			.ok_or(ResearchError::Unknown)?;
+		if self.techs.contains(tech) {
+			return Err(ResearchError::Known);
+		}
+		let mut path = vec![];
+		self.add_to_path(rules, tech, skip, &mut path);
+		Ok(path)
+	}
+
+	/// Add the unknown prerequisites of `tech` to `path`, then `tech`.  The rules have no
+	/// prerequisite cycles, see `ruleset`.
+	fn add_to_path(
+		&self,
+		rules: &Rules,
+		tech: &SmolStr,
+		skip: &[SmolStr],
+		path: &mut Vec<SmolStr>,
+	) {
+		if self.techs.contains(tech) || skip.contains(tech) || path.contains(tech) {
+			return;
+		}
+		if let Some(tech_type) = rules.techs.get(tech) {
+			for prerequisite in &tech_type.prerequisites {
+				self.add_to_path(rules, prerequisite, skip, path);
+			}
+		}
+		path.push(tech.clone());
+	}
+
+	/// Research `tech` next, replacing the queue with it and its missing prerequisites.
+	pub fn research(&mut self, rules: &Rules, tech: &str) -> Result<(), ResearchError> {
+		self.research_queue = self.research_path(rules, tech, &[])?;
+		Ok(())
+	}
+
+	/// Add `tech` and the missing prerequisites not queued yet to the end of the queue.
+	pub fn enqueue_research(&mut self, rules: &Rules, tech: &str) -> Result<(), ResearchError> {
+		if self.research_queue.iter().any(|queued| queued == tech) {
+			return Err(ResearchError::AlreadyQueued);
+		}
+		let path = self.research_path(rules, tech, &self.research_queue)?;
+		self.research_queue.extend(path);
+		Ok(())
+	}
+
+	/// Put `beakers` into research, returning the techs learned with them in the order they were.
+	pub fn add_beakers(&mut self, rules: &Rules, beakers: u32) -> Vec<SmolStr> {
+		self.research += beakers;
+		let mut learned = vec![];
+		while let Some(tech) = self.research_queue.first() {
+			// Techs that were learned some other way, or no longer are in the rules, are skipped
+			let cost = match rules.techs.get(tech) {
+				Some(tech_type) if !self.techs.contains(tech) => tech_type.cost,
+				_ => {
+					self.research_queue.remove(0);
+					continue;
+				}
+			};
+			if self.research < cost {
+				break;
+			}
+			self.research -= cost;
+			let tech = self.research_queue.remove(0);
+			self.techs.insert(tech.clone());
+			learned.push(tech);
+		}
+		learned
+	}
+
+	/// The turns it would take to learn `tech` with `per_turn` beakers if it were researched next,
+	/// 0 if it is known and `None` if it never would be.  A tech the stored beakers already pay for
+	/// still takes the turn to end.
+	pub fn turns_to_research(&self, rules: &Rules, tech: &str, per_turn: u32) -> Option<u32> {
+		let path = match self.research_path(rules, tech, &[]) {
+			Ok(path) => path,
+			Err(ResearchError::Known) => return Some(0),
+			Err(_) => return None,
+		};
+		let cost: u32 = path
+			.iter()
+			.filter_map(|tech| rules.techs.get(tech))
+			.map(|tech_type| tech_type.cost)
+			.sum();
+		match cost.saturating_sub(self.research) {
+			0 => Some(1),
+			_ if per_turn == 0 => None,
+			left => Some(left.div_ceil(per_turn)),
+		}
+	}
+
+	/// The tech tree as the player sees it, with `per_turn` beakers every turn.
+	pub fn tech_tree(&self, rules: &Rules, per_turn: u32) -> TechTree {
+		let mut columns = BTreeMap::new();
+		let mut techs: Vec<TechNode> = rules
+			.techs
+			.iter()
+			.map(|(tech, tech_type)| {
+				let status = if self.techs.contains(tech) {
+					TechStatus::Known
+				} else if let Some(idx) = self.research_queue.iter().position(|t| t == tech) {
+					TechStatus::Queued(idx as u32)
+				} else if tech_type
+					.prerequisites
+					.iter()
+					.all(|prerequisite| self.techs.contains(prerequisite))
+				{
+					TechStatus::Available
+				} else {
+					TechStatus::Locked
+				};
+				TechNode {
+					tech: tech.clone(),
+					cost: tech_type.cost,
+					prerequisites: tech_type.prerequisites.clone(),
+					unlocks: rules.unlocks(tech),
+					status,
+					column: column(rules, tech, &mut columns),
+					turns: self.turns_to_research(rules, tech, per_turn),
+				}
+			})
+			.collect();
+		techs.sort_by(|a, b| (a.column, &a.tech).cmp(&(b.column, &b.tech)));
+		TechTree {
+			techs,
+			queue: self.research_queue.clone(),
+			beakers: self.research,
+			beakers_per_turn: per_turn,
+		}
+	}
+}
+
+/// The column to draw `tech` in, the length of the longest chain of prerequisites leading to it.
+fn column(rules: &Rules, tech: &SmolStr, columns: &mut BTreeMap<SmolStr, u32>) -> u32 {
+	if let Some(column) = columns.get(tech) {
+		return *column;
+	}
+	let prerequisites = rules
+		.techs
+		.get(tech)
+		.map(|tech_type| tech_type.prerequisites.as_slice())
+		.unwrap_or_default();
+	let column = prerequisites
+		.iter()
+		.map(|prerequisite| column(rules, prerequisite, columns) + 1)
+		.max()
+		.unwrap_or(0);
+	columns.insert(tech.clone(), column);
+	column
+}
+
+/// Event of a joined client changing what the player researches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResearchRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub player: PlayerId,
+	pub order: ResearchOrder,
+}
+
+/// Event of a joined client asking for the tech tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TechTreeRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub player: PlayerId,
+}
+
+/// Everything beakers come from.
+#[derive(SystemParam)]
+pub struct Science<'a> {
+	map: Res<'a, WorldMap>,
+	rules: Res<'a, Rules>,
+	cities: Query<'a, &'static City>,
+	buildings: Query<'a, &'static Building>,
+}
+
+impl<'a> Science<'a> {
+	/// The beakers the cities of every player with any yield every turn.
+	pub fn per_turn(&self) -> BTreeMap<PlayerId, u32> {
+		let buildings = buildings_by_city(self.buildings.iter());
+		let mut beakers = BTreeMap::new();
+		for city in self.cities.iter() {
+			let city_buildings = buildings.get(&city.id).map_or(&[][..], Vec::as_slice);
+			*beakers.entry(city.owner).or_default() +=
+				city.yields(&self.map, &self.rules, city_buildings).science;
+		}
+		beakers
+	}
+}
+
+pub(crate) fn order_research(
+	mut requests: EventReader<ResearchRequest>,
+	mut clients: ResMut<Clients>,
+	mut players: ResMut<Players>,
+	rules: Res<Rules>,
+	status: Res<TurnStatus>,
+) {
+	for request in requests.iter() {
+		let result = match players.get_mut(request.player) {
+			_ if !status.to_move.contains(&request.player) => Err("it is not your turn".to_owned()),
+			Some(player) => match &request.order {
+				ResearchOrder::Research(tech) => player.research(&rules, tech),
+				ResearchOrder::Enqueue(tech) => player.enqueue_research(&rules, tech),
+				ResearchOrder::Clear => {
+					player.research_queue.clear();
+					Ok(())
+				}
+			}
+			.map_err(|e| e.to_string()),
+			None => Err("no such player".to_owned()),
+		};
+		if let Err(reason) = result {
+			clients.send(
+				request.client,
+				ServerCommand::Rejected {
+					seq: request.seq,
+					reason,
+				},
+			);
+		}
+	}
+}
+
+pub(crate) fn send_tech_trees(
+	mut requests: EventReader<TechTreeRequest>,
+	mut clients: ResMut<Clients>,
+	players: Res<Players>,
+	science: Science,
+) {
+	let mut per_turn = None;
+	for request in requests.iter() {
+		let player = match players.get(request.player) {
+			Some(player) => player,
+			None => continue,
+		};
+		let beakers = per_turn.get_or_insert_with(|| science.per_turn());
+		let tree = player.tech_tree(
+			&science.rules,
+			beakers.get(&request.player).copied().unwrap_or(0),
+		);
+		clients.send(
+			request.client,
+			ServerCommand::TechTree {
+				seq: request.seq,
+				tree,
+			},
+		);
+	}
+}
+
+/// Put the science of every city into its owner's research at the end of the turn.
+pub(crate) fn research_techs(
+	mut ended: EventReader<TurnEnded>,
+	mut players: ResMut<Players>,
+	science: Science,
+	mut notified: EventWriter<Notified>,
+) {
+	if ended.iter().count() == 0 {
+		return;
+	}
+	let per_turn = science.per_turn();
+	for player in players.0.iter_mut() {
+		let beakers = per_turn.get(&player.id).copied().unwrap_or(0);
+		for tech in player.add_beakers(&science.rules, beakers) {
+			info!("{} learned {}", player.name, tech);
+			notified.send(Notified {
+				player: player.id,
+				notification: Notification::Researched { tech },
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::ResearchError;
+	use crate::server::game::player::{Player, PlayerId};
+	use crate::server::game::rules::Rules;
+	use crate::universal::commands::{TechStatus, Unlock};
+	use smol_str::SmolStr;
+
+	fn player() -> Player {
+		Player::new(PlayerId(0), "Player 0".to_owned(), "rome".into())
+	}
+
+	fn techs(techs: &[&str]) -> Vec<SmolStr> {
+		techs.iter().map(|tech| SmolStr::from(*tech)).collect()
+	}
+
+	#[test]
+	fn queue_and_beakers() {
+		let rules = Rules::bundled();
+		let mut player = player();
+		// Mathematics needs archery and writing, which need animal husbandry and pottery
+		player.research(&rules, "mathematics").unwrap();
+		assert_eq!(
+			player.research_queue,
+			techs(&[
+				"animal_husbandry",
+				"archery",
+				"pottery",
+				"writing",
+				"mathematics"
+			])
+		);
+		player.research(&rules, "sailing").unwrap();
+		assert_eq!(player.research_queue, techs(&["pottery", "sailing"]));
+		player.enqueue_research(&rules, "writing").unwrap();
+		assert_eq!(
+			player.research_queue,
+			techs(&["pottery", "sailing", "writing"])
+		);
+		assert!(matches!(
+			player.enqueue_research(&rules, "sailing"),
+			Err(ResearchError::AlreadyQueued)
+		));
+		assert!(matches!(
+			player.research(&rules, "steam_power"),
+			Err(ResearchError::Unknown)
+		));
+
+		// Pottery costs 25 and sailing 35, what is left over goes into the next tech
+		assert_eq!(player.add_beakers(&rules, 20), techs(&[]));
+		assert_eq!(
+			player.add_beakers(&rules, 45),
+			techs(&["pottery", "sailing"])
+		);
+		assert_eq!(player.research, 5);
+		assert_eq!(player.research_queue, techs(&["writing"]));
+		assert!(matches!(
+			player.research(&rules, "pottery"),
+			Err(ResearchError::Known)
+		));
+
+		// Without a queue the beakers are kept for later
+		player.research_queue.clear();
+		assert_eq!(player.add_beakers(&rules, 50), techs(&[]));
+		player.research(&rules, "writing").unwrap();
+		assert_eq!(player.add_beakers(&rules, 0), techs(&["writing"]));
+		assert_eq!(player.research, 0);
+	}
+
+	#[test]
+	fn tech_tree() {
+		let rules = Rules::bundled();
+		let mut player = player();
+		player.techs = techs(&["pottery"]).into_iter().collect();
+		player.research = 10;
+		player.research(&rules, "writing").unwrap();
+
+		// 25 + 35 + 55 + 100 - 10 beakers at 8 a turn
+		assert_eq!(player.turns_to_research(&rules, "mathematics", 8), Some(26));
+		assert_eq!(player.turns_to_research(&rules, "pottery", 8), Some(0));
+		assert_eq!(player.turns_to_research(&rules, "mathematics", 0), None);
+		assert_eq!(player.turns_to_research(&rules, "nonsense", 8), None);
+		player.research = 60;
+		assert_eq!(player.turns_to_research(&rules, "writing", 0), Some(1));
+		player.research = 10;
+
+		let tree = player.tech_tree(&rules, 8);
+		assert_eq!(tree.queue, techs(&["writing"]));
+		let node = |tech: &str| tree.techs.iter().find(|node| node.tech == tech).unwrap();
+		assert_eq!(node("pottery").status, TechStatus::Known);
+		assert_eq!(node("writing").status, TechStatus::Queued(0));
+		assert_eq!(node("sailing").status, TechStatus::Available);
+		assert_eq!(node("mathematics").status, TechStatus::Locked);
+		assert_eq!(node("mathematics").column, 2);
+		assert_eq!(node("writing").turns, Some(6));
+		assert_eq!(
+			node("mining").unlocks,
+			vec![Unlock::Improvement("mine".into())]
+		);
+		assert_eq!(
+			node("writing").unlocks,
+			vec![
+				Unlock::Building("great_library".into()),
+				Unlock::Building("library".into())
+			]
+		);
+		// Prerequisites are drawn left of the techs that need them
+		assert!(tree
+			.techs
+			.windows(2)
+			.all(|pair| pair[0].column <= pair[1].column));
+	}
+}
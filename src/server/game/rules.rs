@@ -0,0 +1,349 @@
+//! The rules of the game, what every kind of unit is like, what tiles yield and how cities grow.  A
+//! unit refers to its type by the key in `Rules::units`, and so on for everything else with a key.
+//!
+//! The rules are loaded from the files in `assets/rules`, see `ruleset`.
+
+use crate::server::world::{Elevation, Feature, Terrain, Tile, WorldMap};
+use crate::universal::commands::{ProductionItem, Unlock};
+use crate::universal::yields::Yields;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+
+/// About one in this many tiles a resource can be on gets one.
+pub const RESOURCE_RARITY: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitType {
+	/// Movement points every turn.
+	pub moves: u32,
+	/// Strength in melee and when defending, 0 for units that cannot fight.
+	#[serde(default)]
+	pub strength: u32,
+	/// Strength of its ranged attacks, 0 if it has none.
+	#[serde(default)]
+	pub ranged_strength: u32,
+	/// Strength of its bombardments, 0 if it cannot bombard.
+	#[serde(default)]
+	pub bombard_strength: u32,
+	/// How many tiles away its ranged attacks and bombardments reach.
+	#[serde(default)]
+	pub range: u32,
+	/// The unit can found a city, it is used up doing so.
+	#[serde(default)]
+	pub founds_cities: bool,
+	/// The unit can build improvements on tiles.
+	#[serde(default)]
+	pub builds_improvements: bool,
+	/// Production it takes to build.
+	#[serde(default)]
+	pub cost: u32,
+	/// The tech needed to build it, a key of `Rules::techs`.
+	#[serde(default)]
+	pub tech: Option<SmolStr>,
+	/// A resource that must be on a tile within the city's radius, a key of `Rules::resources`.
+	#[serde(default)]
+	pub resource: Option<SmolStr>,
+	/// The city must be next to a tile of one of these terrains, if there are any.
+	#[serde(default)]
+	pub adjacent_terrains: Vec<Terrain>,
+	/// The city must be next to the sea.
+	#[serde(default)]
+	pub coastal: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildingType {
+	/// Production it takes to build.
+	pub cost: u32,
+	/// Added to the yields of the city it is in.
+	#[serde(default)]
+	pub yields: Yields,
+	/// The tech needed to build it, a key of `Rules::techs`.
+	#[serde(default)]
+	pub tech: Option<SmolStr>,
+	/// Buildings the city needs to have before building it, keys of `Rules::buildings`.
+	#[serde(default)]
+	pub requires: Vec<SmolStr>,
+	/// A resource that must be on a tile within the city's radius, a key of `Rules::resources`.
+	#[serde(default)]
+	pub resource: Option<SmolStr>,
+	/// The city must be next to a tile of one of these terrains, if there are any.
+	#[serde(default)]
+	pub adjacent_terrains: Vec<Terrain>,
+	/// The city must be next to the sea.
+	#[serde(default)]
+	pub coastal: bool,
+	/// Only one can be built in the whole game.
+	#[serde(default)]
+	pub wonder: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TechType {
+	/// Science it takes to research.
+	pub cost: u32,
+	/// Techs that need to be researched first, keys of `Rules::techs`.
+	#[serde(default)]
+	pub prerequisites: Vec<SmolStr>,
+}
+
+/// An improvement built on tiles by units, adding to what they yield.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImprovementType {
+	#[serde(default)]
+	pub yields: Yields,
+	/// The terrains it can be built on.
+	pub terrains: Vec<Terrain>,
+	/// It can only be built on hills.
+	#[serde(default)]
+	pub hills: bool,
+	/// The tech needed to build it, a key of `Rules::techs`.
+	#[serde(default)]
+	pub tech: Option<SmolStr>,
+}
+
+/// A resource found on tiles, adding to what they yield.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceType {
+	#[serde(default)]
+	pub yields: Yields,
+	/// The terrains it is found on.
+	pub terrains: Vec<Terrain>,
+	/// The features it is found under, besides on bare terrain.
+	#[serde(default)]
+	pub features: Vec<Feature>,
+	/// The tech that shows where it is, a key of `Rules::techs`.  Always seen if `None`.
+	#[serde(default)]
+	pub revealed_by: Option<SmolStr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CivType {
+	/// Names given to the civ's cities, in order.
+	pub city_names: Vec<String>,
+}
+
+/// What working a tile yields, its terrain's yields plus those of its feature, its hills and its
+/// river.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileYields {
+	pub terrains: BTreeMap<Terrain, Yields>,
+	pub features: BTreeMap<Feature, Yields>,
+	pub hills: Yields,
+	/// Added if a river runs along any edge of the tile.
+	pub river: Yields,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CityRules {
+	/// How many tiles out from its center a city works tiles.
+	pub radius: u32,
+	/// The least distance between two cities.
+	pub min_distance: u32,
+	/// Added to the yields of a city's own tile, which is worked without a citizen.
+	pub center: Yields,
+	/// Food every citizen eats each turn.
+	pub food_per_citizen: u32,
+	/// Food a city of 1 citizen needs to grow.
+	pub growth_base: u32,
+	/// More food needed to grow for every citizen after the first.
+	pub growth_per_citizen: u32,
+	/// How much each yield counts when tiles are picked for citizens to work.
+	pub work_weights: Yields,
+	/// Gold it costs to buy each point of production something still needs.
+	pub purchase_cost: u32,
+	/// The most entries a production queue can have.
+	pub queue_length: usize,
+}
+
+/// How cities claim tiles as their territory, see `territory`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BorderRules {
+	/// How many tiles out from its center a new city claims the tiles.
+	pub initial_radius: u32,
+	/// How many tiles out from its center a city's territory can grow.
+	pub max_radius: u32,
+	/// Culture a city needs to claim its first tile after the initial ones.
+	pub culture_base: u32,
+	/// More culture needed for every tile claimed after the initial ones.
+	pub culture_per_tile: u32,
+	/// How much each yield of a tile counts when picking the next tile to claim.
+	pub weights: Yields,
+	/// Added to the score of a tile with a resource.
+	pub resource_score: u32,
+	/// Taken from the score of a tile for every step it is away from the city.
+	pub distance_penalty: u32,
+}
+
+/// What a city needs to build something, see `Rules::production`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prerequisites<'a> {
+	pub tech: Option<&'a SmolStr>,
+	pub resource: Option<&'a SmolStr>,
+	pub adjacent_terrains: &'a [Terrain],
+	pub coastal: bool,
+	/// Buildings the city needs to have, keys of `Rules::buildings`.
+	pub buildings: &'a [SmolStr],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rules {
+	pub units: BTreeMap<SmolStr, UnitType>,
+	pub buildings: BTreeMap<SmolStr, BuildingType>,
+	pub techs: BTreeMap<SmolStr, TechType>,
+	pub improvements: BTreeMap<SmolStr, ImprovementType>,
+	pub resources: BTreeMap<SmolStr, ResourceType>,
+	pub civs: BTreeMap<SmolStr, CivType>,
+	pub tiles: TileYields,
+	pub cities: CityRules,
+	pub borders: BorderRules,
+	/// The units every player starts the game with, keys of `Rules::units`.
+	pub start_units: Vec<SmolStr>,
+}
+
+impl Rules {
+	pub fn unit(&self, kind: &str) -> Option<&UnitType> {
+		self.units.get(kind)
+	}
+
+	/// The production cost of `item` and what a city needs to build it, `None` if there is no such
+	/// unit or building.
+	pub fn production(&self, item: &ProductionItem) -> Option<(u32, Prerequisites<'_>)> {
+		match item {
+			ProductionItem::Unit(key) => self.units.get(key).map(|unit| {
+				let prerequisites = Prerequisites {
+					tech: unit.tech.as_ref(),
+					resource: unit.resource.as_ref(),
+					adjacent_terrains: &unit.adjacent_terrains,
+					coastal: unit.coastal,
+					buildings: &[],
+				};
+				(unit.cost, prerequisites)
+			}),
+			ProductionItem::Building(key) => self.buildings.get(key).map(|building| {
+				let prerequisites = Prerequisites {
+					tech: building.tech.as_ref(),
+					resource: building.resource.as_ref(),
+					adjacent_terrains: &building.adjacent_terrains,
+					coastal: building.coastal,
+					buildings: &building.requires,
+				};
+				(building.cost, prerequisites)
+			}),
+		}
+	}
+
+	/// If `item` is a wonder, only one of which can be built in the whole game.
+	pub fn is_wonder(&self, item: &ProductionItem) -> bool {
+		match item {
+			ProductionItem::Unit(_) => false,
+			ProductionItem::Building(key) => {
+				matches!(self.buildings.get(key), Some(building) if building.wonder)
+			}
+		}
+	}
+
+	/// Everything `tech` allows to be built, ordered by kind and key.
+	pub fn unlocks(&self, tech: &str) -> Vec<Unlock> {
+		let unlocked = |required: &Option<SmolStr>| required.as_deref() == Some(tech);
+		let units = self
+			.units
+			.iter()
+			.filter(|(_key, unit)| unlocked(&unit.tech))
+			.map(|(key, _unit)| Unlock::Unit(key.clone()));
+		let buildings = self
+			.buildings
+			.iter()
+			.filter(|(_key, building)| unlocked(&building.tech))
+			.map(|(key, _building)| Unlock::Building(key.clone()));
+		let improvements = self
+			.improvements
+			.iter()
+			.filter(|(_key, improvement)| unlocked(&improvement.tech))
+			.map(|(key, _improvement)| Unlock::Improvement(key.clone()));
+		units.chain(buildings).chain(improvements).collect()
+	}
+
+	/// What working `tile` yields, `None` if it cannot be worked.
+	pub fn tile_yields(&self, tile: &Tile) -> Option<Yields> {
+		if tile.elevation == Elevation::Mountains {
+			return None;
+		}
+		let mut yields = *self.tiles.terrains.get(&tile.terrain)?;
+		if let Some(feature) = tile.feature {
+			yields = yields + *self.tiles.features.get(&feature)?;
+		}
+		if tile.elevation == Elevation::Hills {
+			yields = yields + self.tiles.hills;
+		}
+		if tile.rivers != 0 {
+			yields = yields + self.tiles.river;
+		}
+		if let Some(resource) = tile.resource.as_ref().and_then(|r| self.resources.get(r)) {
+			yields = yields + resource.yields;
+		}
+		if let Some(improvement) = tile
+			.improvement
+			.as_ref()
+			.and_then(|i| self.improvements.get(i))
+		{
+			yields = yields + improvement.yields;
+		}
+		Some(yields)
+	}
+
+	/// The resources that can be on `tile`, those found on its terrain and under its feature.
+	pub fn resources_for(&self, tile: &Tile) -> Vec<&SmolStr> {
+		self.resources
+			.iter()
+			.filter(|(_key, resource)| {
+				let feature = match tile.feature {
+					Some(feature) => resource.features.contains(&feature),
+					None => true,
+				};
+				feature && resource.terrains.contains(&tile.terrain)
+			})
+			.map(|(key, _resource)| key)
+			.collect()
+	}
+
+	/// Scatter resources over a new map, about one in `RESOURCE_RARITY` of the tiles any can be on
+	/// gets one.
+	pub fn place_resources(&self, map: &mut WorldMap, rng: &mut impl Rng) {
+		for (_hex, tile) in map.iter_mut() {
+			let resources = self.resources_for(tile);
+			if resources.is_empty() || tile.elevation == Elevation::Mountains {
+				continue;
+			}
+			let roll = rng.gen_range(0..RESOURCE_RARITY * resources.len());
+			if roll < resources.len() {
+				tile.resource = Some(resources[roll].clone());
+			}
+		}
+	}
+
+	/// The rules shipped in `assets/rules`.
+	#[cfg(test)]
+	pub fn bundled() -> Self {
+		let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("assets")
+			.join(super::ruleset::RULES_PATH);
+		super::ruleset::load_dir(&path).expect("the bundled rules are not valid")
+	}
+
+	/// The food a city of `population` needs to grow.
+	pub fn growth_threshold(&self, population: u32) -> u32 {
+		self.cities.growth_base + self.cities.growth_per_citizen * population.saturating_sub(1)
+	}
+}
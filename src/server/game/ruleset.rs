@@ -0,0 +1,805 @@
+//! Loading the rules from the ruleset files, RON files ending in `.rules.ron` under
+//! `assets/rules`.  Each file is a `RulesFile` that can define any part of the rules, the files are
+//! merged into one `Rules` and every key a part refers to is checked to exist.
+//!
+//! The server loads the files as assets and inserts the `Rules` resource once all of them loaded
+//! without errors, again whenever one changes.  `load_dir` loads them without bevy.
+//!
+//! Everything with a key in the rules is named in the languages by an attribute of the message of
+//! its section, such as `rules-unit.settler` for the settler unit type, `check_names` checks that
+//! every language does.
+
+use super::rules::{
+	BorderRules, BuildingType, CityRules, CivType, ImprovementType, ResourceType, Rules, TechType,
+	TileYields, UnitType,
+};
+use crate::server::world::{Feature, Terrain};
+use crate::universal::yields::Yields;
+use bevy::asset::{
+	AssetLoader, AssetServerSettings, BoxedFuture, FileAssetIo, LoadContext, LoadState, LoadedAsset,
+};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+use smol_str::SmolStr;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+/// The asset folder of the ruleset files.
+pub const RULES_PATH: &str = "rules";
+
+/// The extension of ruleset files.
+pub const RULES_EXTENSION: &str = "rules.ron";
+
+/// A problem with the rules, in `file` at `key`, such as `units.archer` for the archer unit type.
+/// The key is empty for problems with a whole file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RulesError {
+	pub file: String,
+	pub key: String,
+	pub problem: String,
+}
+
+impl std::fmt::Display for RulesError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.key.is_empty() {
+			write!(f, "{}: {}", self.file, self.problem)
+		} else {
+			write!(f, "{}: {}: {}", self.file, self.key, self.problem)
+		}
+	}
+}
+
+impl std::error::Error for RulesError {}
+
+impl RulesError {
+	fn new(file: &str, key: impl Into<String>, problem: impl Into<String>) -> Self {
+		Self {
+			file: file.to_owned(),
+			key: key.into(),
+			problem: problem.into(),
+		}
+	}
+}
+
+/// A ruleset file, every part is optional so the rules can be split over files however suits.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulesFile {
+	pub terrains: BTreeMap<Terrain, Yields>,
+	pub features: BTreeMap<Feature, Yields>,
+	pub hills: Option<Yields>,
+	pub river: Option<Yields>,
+	pub resources: BTreeMap<SmolStr, ResourceType>,
+	pub units: BTreeMap<SmolStr, UnitType>,
+	pub buildings: BTreeMap<SmolStr, BuildingType>,
+	pub techs: BTreeMap<SmolStr, TechType>,
+	pub improvements: BTreeMap<SmolStr, ImprovementType>,
+	pub civs: BTreeMap<SmolStr, CivType>,
+	pub cities: Option<CityRules>,
+	pub borders: Option<BorderRules>,
+	pub start_units: Option<Vec<SmolStr>>,
+}
+
+impl RulesFile {
+	pub fn parse(text: &str) -> Result<Self, String> {
+		ron::from_str(text).map_err(|e| e.to_string())
+	}
+}
+
+/// A ruleset file as an asset, it keeps its parse error to be reported with the other problems of
+/// the rules.
+#[derive(Debug, TypeUuid)]
+#[uuid = "6f3c1b9e-2a47-4d85-9c0e-51d7a4e8b2f3"]
+pub struct RulesFileAsset {
+	pub path: String,
+	pub file: Result<RulesFile, String>,
+}
+
+#[derive(Default)]
+pub struct RulesFileAssetLoader;
+
+impl AssetLoader for RulesFileAssetLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, anyhow::Result<()>> {
+		Box::pin(async move {
+			let file = std::str::from_utf8(bytes)
+				.map_err(|e| e.to_string())
+				.and_then(RulesFile::parse);
+			let path = load_context.path().display().to_string();
+			load_context.set_default_asset(LoadedAsset::new(RulesFileAsset { path, file }));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&[RULES_EXTENSION]
+	}
+}
+
+/// Merges the parts of ruleset files, keeping the file each key was defined in.
+#[derive(Default)]
+struct Merger {
+	origins: BTreeMap<String, String>,
+	errors: Vec<RulesError>,
+}
+
+impl Merger {
+	/// The file `key` was defined in.
+	fn file(&self, key: &str) -> &str {
+		self.origins.get(key).map(String::as_str).unwrap_or("")
+	}
+
+	/// Note that `file` defines `key`, returning false if another file did already.
+	fn define(&mut self, file: &str, key: String) -> bool {
+		if let Some(first) = self.origins.get(&key) {
+			let problem = format!("already defined in {}", first);
+			self.errors.push(RulesError::new(file, key, problem));
+			return false;
+		}
+		self.origins.insert(key, file.to_owned());
+		true
+	}
+
+	fn map<K: Ord + Debug, V>(
+		&mut self,
+		file: &str,
+		section: &str,
+		into: &mut BTreeMap<K, V>,
+		from: BTreeMap<K, V>,
+	) {
+		for (key, value) in from {
+			// Ruleset keys are strings, and the others are enum variants
+			let name = format!("{:?}", key);
+			let key_name = format!("{}.{}", section, name.trim_matches('"'));
+			if self.define(file, key_name) {
+				into.insert(key, value);
+			}
+		}
+	}
+
+	fn one<T>(&mut self, file: &str, key: &str, into: &mut Option<T>, from: Option<T>) {
+		if let Some(value) = from {
+			if self.define(file, key.to_owned()) {
+				*into = Some(value);
+			}
+		}
+	}
+}
+
+/// Merge ruleset files into rules, `source` names where the files are from in the errors that
+/// belong to no single file.  Every problem found is returned, not just the first.
+pub fn assemble<'f>(
+	source: &str,
+	files: impl IntoIterator<Item = (&'f str, &'f Result<RulesFile, String>)>,
+) -> Result<Rules, Vec<RulesError>> {
+	let mut merger = Merger::default();
+	let mut merged = RulesFile::default();
+	for (file, parsed) in files {
+		let parsed = match parsed {
+			Ok(parsed) => parsed.clone(),
+			Err(e) => {
+				merger.errors.push(RulesError::new(file, "", e.clone()));
+				continue;
+			}
+		};
+		merger.map(file, "terrains", &mut merged.terrains, parsed.terrains);
+		merger.map(file, "features", &mut merged.features, parsed.features);
+		merger.map(file, "resources", &mut merged.resources, parsed.resources);
+		merger.map(file, "units", &mut merged.units, parsed.units);
+		merger.map(file, "buildings", &mut merged.buildings, parsed.buildings);
+		merger.map(file, "techs", &mut merged.techs, parsed.techs);
+		merger.map(
+			file,
+			"improvements",
+			&mut merged.improvements,
+			parsed.improvements,
+		);
+		merger.map(file, "civs", &mut merged.civs, parsed.civs);
+		merger.one(file, "hills", &mut merged.hills, parsed.hills);
+		merger.one(file, "river", &mut merged.river, parsed.river);
+		merger.one(file, "cities", &mut merged.cities, parsed.cities);
+		merger.one(file, "borders", &mut merged.borders, parsed.borders);
+		merger.one(
+			file,
+			"start_units",
+			&mut merged.start_units,
+			parsed.start_units,
+		);
+	}
+	let mut errors = std::mem::take(&mut merger.errors);
+	let mut missing =
+		|key: &str| errors.push(RulesError::new(source, key, "not defined in any file"));
+	let (hills, river, cities, borders, start_units) = match (
+		merged.hills,
+		merged.river,
+		merged.cities,
+		merged.borders,
+		merged.start_units,
+	) {
+		(Some(hills), Some(river), Some(cities), Some(borders), Some(start_units)) => {
+			(hills, river, cities, borders, start_units)
+		}
+		(hills, river, cities, borders, start_units) => {
+			for (key, is_missing) in [
+				("hills", hills.is_none()),
+				("river", river.is_none()),
+				("cities", cities.is_none()),
+				("borders", borders.is_none()),
+				("start_units", start_units.is_none()),
+			]
+			.iter()
+			{
+				if *is_missing {
+					missing(key);
+				}
+			}
+			return Err(errors);
+		}
+	};
+	let rules = Rules {
+		units: merged.units,
+		buildings: merged.buildings,
+		techs: merged.techs,
+		improvements: merged.improvements,
+		resources: merged.resources,
+		civs: merged.civs,
+		tiles: TileYields {
+			terrains: merged.terrains,
+			features: merged.features,
+			hills,
+			river,
+		},
+		cities,
+		borders,
+		start_units,
+	};
+	errors.extend(cross_references(&rules, &merger));
+	errors.extend(tech_cycles(&rules, &merger));
+	if errors.is_empty() {
+		Ok(rules)
+	} else {
+		Err(errors)
+	}
+}
+
+/// Check that every key the rules refer to exists.
+fn cross_references(rules: &Rules, merger: &Merger) -> Vec<RulesError> {
+	let mut errors = vec![];
+	let mut check = |key: String, kind: &str, reference: &SmolStr, exists: bool| {
+		if !exists {
+			let file = merger.file(&key).to_owned();
+			errors.push(RulesError::new(
+				&file,
+				key,
+				format!("refers to unknown {} `{}`", kind, reference),
+			));
+		}
+	};
+	let tech = |tech: &SmolStr| rules.techs.contains_key(tech);
+	let resource = |resource: &SmolStr| rules.resources.contains_key(resource);
+	for (name, unit) in &rules.units {
+		if let Some(required) = &unit.tech {
+			check(format!("units.{}", name), "tech", required, tech(required));
+		}
+		if let Some(required) = &unit.resource {
+			let exists = resource(required);
+			check(format!("units.{}", name), "resource", required, exists);
+		}
+	}
+	for (name, building) in &rules.buildings {
+		if let Some(required) = &building.tech {
+			check(
+				format!("buildings.{}", name),
+				"tech",
+				required,
+				tech(required),
+			);
+		}
+		for required in &building.requires {
+			let exists = rules.buildings.contains_key(required);
+			check(format!("buildings.{}", name), "building", required, exists);
+		}
+		if let Some(required) = &building.resource {
+			let exists = resource(required);
+			check(format!("buildings.{}", name), "resource", required, exists);
+		}
+	}
+	for (name, tech_type) in &rules.techs {
+		for required in &tech_type.prerequisites {
+			check(format!("techs.{}", name), "tech", required, tech(required));
+		}
+	}
+	for (name, improvement) in &rules.improvements {
+		if let Some(required) = &improvement.tech {
+			check(
+				format!("improvements.{}", name),
+				"tech",
+				required,
+				tech(required),
+			);
+		}
+	}
+	for (name, resource) in &rules.resources {
+		if let Some(required) = &resource.revealed_by {
+			check(
+				format!("resources.{}", name),
+				"tech",
+				required,
+				tech(required),
+			);
+		}
+	}
+	for unit in &rules.start_units {
+		let exists = rules.units.contains_key(unit);
+		check("start_units".to_owned(), "unit", unit, exists);
+	}
+	errors
+}
+
+/// Check that no tech is a prerequisite of itself, directly or through other techs, so every tech
+/// can be researched.
+fn tech_cycles(rules: &Rules, merger: &Merger) -> Vec<RulesError> {
+	let mut errors = vec![];
+	for name in rules.techs.keys() {
+		let mut seen = BTreeSet::new();
+		let mut next: Vec<&SmolStr> = rules.techs[name].prerequisites.iter().collect();
+		while let Some(tech) = next.pop() {
+			if tech == name {
+				let key = format!("techs.{}", name);
+				let file = merger.file(&key).to_owned();
+				errors.push(RulesError::new(&file, key, "is a prerequisite of itself"));
+				break;
+			}
+			if seen.insert(tech) {
+				if let Some(tech_type) = rules.techs.get(tech) {
+					next.extend(&tech_type.prerequisites);
+				}
+			}
+		}
+	}
+	errors
+}
+
+/// Load the ruleset files in a directory and the directories in it.
+pub fn load_dir(path: &Path) -> Result<Rules, Vec<RulesError>> {
+	let dir_error =
+		|problem: String| vec![RulesError::new(&path.display().to_string(), "", problem)];
+	let mut paths = vec![];
+	rules_paths(path, &mut paths).map_err(|e| dir_error(e.to_string()))?;
+	paths.sort();
+	let files: Vec<(String, Result<RulesFile, String>)> = paths
+		.iter()
+		.map(|path| {
+			let parsed = std::fs::read_to_string(path)
+				.map_err(|e| e.to_string())
+				.and_then(|text| RulesFile::parse(&text));
+			(path.display().to_string(), parsed)
+		})
+		.collect();
+	assemble(
+		&path.display().to_string(),
+		files.iter().map(|(file, parsed)| (file.as_str(), parsed)),
+	)
+}
+
+/// Add the paths of the ruleset files in `path` and its subdirectories to `paths`.
+fn rules_paths(path: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	for entry in std::fs::read_dir(path)? {
+		let path = entry?.path();
+		if path.is_dir() {
+			rules_paths(&path, paths)?;
+		} else if path
+			.display()
+			.to_string()
+			.ends_with(&format!(".{}", RULES_EXTENSION))
+		{
+			paths.push(path);
+		}
+	}
+	Ok(())
+}
+
+/// The message naming the things in each section of the rules, their keys are its attributes.
+pub const NAME_MESSAGES: [(&str, &str); 6] = [
+	("units", "rules-unit"),
+	("buildings", "rules-building"),
+	("techs", "rules-tech"),
+	("improvements", "rules-improvement"),
+	("resources", "rules-resource"),
+	("civs", "rules-civ"),
+];
+
+/// Every key in the rules with a name, as its section and key.
+fn named_keys(rules: &Rules) -> Vec<(&'static str, &SmolStr)> {
+	let mut keys = vec![];
+	keys.extend(rules.units.keys().map(|key| ("units", key)));
+	keys.extend(rules.buildings.keys().map(|key| ("buildings", key)));
+	keys.extend(rules.techs.keys().map(|key| ("techs", key)));
+	keys.extend(rules.improvements.keys().map(|key| ("improvements", key)));
+	keys.extend(rules.resources.keys().map(|key| ("resources", key)));
+	keys.extend(rules.civs.keys().map(|key| ("civs", key)));
+	keys
+}
+
+/// Fluent identifiers start with a letter and go on with letters, digits, `_` and `-`.
+fn is_message_key(key: &str) -> bool {
+	let mut chars = key.chars();
+	matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Check that every language, the directories in `lang_dir`, names everything in the rules.
+pub fn check_names(rules: &Rules, lang_dir: &Path) -> Vec<RulesError> {
+	let mut errors = vec![];
+	let mut languages = vec![];
+	if let Err(e) = read_languages(lang_dir, &mut languages, &mut errors) {
+		let file = lang_dir.display().to_string();
+		return vec![RulesError::new(&file, "", e.to_string())];
+	}
+	for (section, key) in named_keys(rules) {
+		let message = NAME_MESSAGES
+			.iter()
+			.find(|(name, _)| *name == section)
+			.map_or("", |(_, message)| message);
+		let key_name = format!("{}.{}", section, key);
+		if !is_message_key(key) {
+			errors.push(RulesError::new(
+				RULES_PATH,
+				key_name,
+				"is not a valid message key, it must start with a letter and have only letters, digits, `_` and `-`",
+			));
+			continue;
+		}
+		let message = format!("{}.{}", message, key);
+		for (language, messages) in &languages {
+			if !messages.contains(&message) {
+				let problem = format!("has no message `{}`", message);
+				errors.push(RulesError::new(language, key_name.clone(), problem));
+			}
+		}
+	}
+	errors
+}
+
+/// Read the messages of every language in `lang_dir`, as `message` and `message.attribute`.
+fn read_languages(
+	lang_dir: &Path,
+	languages: &mut Vec<(String, BTreeSet<String>)>,
+	errors: &mut Vec<RulesError>,
+) -> std::io::Result<()> {
+	let mut dirs = vec![];
+	for entry in std::fs::read_dir(lang_dir)? {
+		let path = entry?.path();
+		if path.is_dir() {
+			dirs.push(path);
+		}
+	}
+	dirs.sort();
+	for dir in dirs {
+		let mut messages = BTreeSet::new();
+		for entry in std::fs::read_dir(&dir)? {
+			let path = entry?.path();
+			if path.extension() != Some("ftl".as_ref()) {
+				continue;
+			}
+			let file = path.display().to_string();
+			let text = std::fs::read_to_string(&path)?;
+			let resource = fluent_syntax::parser::parse(text.as_str()).unwrap_or_else(
+				|(resource, parse_errors)| {
+					for e in parse_errors {
+						let before = text.get(..e.pos.start).unwrap_or(&text);
+						let line = before.matches('\n').count() + 1;
+						errors.push(RulesError::new(
+							&file,
+							format!("line {}", line),
+							e.kind.to_string(),
+						));
+					}
+					resource
+				},
+			);
+			for entry in resource.body {
+				if let fluent_syntax::ast::Entry::Message(message) = entry {
+					for attribute in &message.attributes {
+						messages.insert(format!("{}.{}", message.id.name, attribute.id.name));
+					}
+					messages.insert(message.id.name.to_owned());
+				}
+			}
+		}
+		languages.push((dir.display().to_string(), messages));
+	}
+	Ok(())
+}
+
+/// Load the ruleset files in `rules_dir` like `load_dir` and check that they are named in every
+/// language in `lang_dir`, for checking rules without running the game.
+pub fn validate(rules_dir: &Path, lang_dir: &Path) -> Result<Rules, Vec<RulesError>> {
+	let rules = load_dir(rules_dir)?;
+	let errors = check_names(&rules, lang_dir);
+	if errors.is_empty() {
+		Ok(rules)
+	} else {
+		Err(errors)
+	}
+}
+
+/// The ruleset files loading as assets, and the problems of the rules last loaded from them.
+#[derive(Default)]
+pub struct RulesAssets {
+	handles: Vec<Handle<RulesFileAsset>>,
+	pub errors: Vec<RulesError>,
+}
+
+/// The rules as far as they are loaded, for systems that wait on them.
+#[derive(SystemParam)]
+pub struct LoadedRules<'a> {
+	rules: Option<Res<'a, Rules>>,
+	assets: Res<'a, RulesAssets>,
+}
+
+impl<'a> LoadedRules<'a> {
+	/// The rules once they are loaded, or the problems that keep them from loading.  Rules loaded
+	/// before are kept if they are changed into rules with problems.
+	pub fn get(&self) -> Result<Option<&Rules>, &[RulesError]> {
+		match &self.rules {
+			Some(rules) => Ok(Some(rules)),
+			None if self.assets.errors.is_empty() => Ok(None),
+			None => Err(&self.assets.errors),
+		}
+	}
+}
+
+/// Start loading the ruleset files as assets.  They are looked for in the asset folder rather than
+/// loaded with `AssetServer::load_folder`, which also loads files that are not ruleset files and
+/// never finish, and without any files the rules would be waited on forever.
+pub(crate) fn load_rules(
+	asset_server: Res<AssetServer>,
+	settings: Option<Res<AssetServerSettings>>,
+	mut rules_assets: ResMut<RulesAssets>,
+) {
+	let asset_folder = settings.map_or_else(
+		|| AssetServerSettings::default().asset_folder,
+		|settings| settings.asset_folder.clone(),
+	);
+	let root = FileAssetIo::get_root_path().join(asset_folder);
+	let mut paths = vec![];
+	let problem = match rules_paths(&root.join(RULES_PATH), &mut paths) {
+		Err(e) => Some(e.to_string()),
+		Ok(()) if paths.is_empty() => Some(format!("there are no .{} files", RULES_EXTENSION)),
+		Ok(()) => None,
+	};
+	if let Some(problem) = problem {
+		let error = RulesError::new(RULES_PATH, "", problem);
+		error!("Failed loading the rules: {}", error);
+		rules_assets.errors = vec![error];
+		return;
+	}
+	paths.sort();
+	rules_assets.handles = paths
+		.iter()
+		.filter_map(|path| path.strip_prefix(&root).ok())
+		.map(|path| asset_server.load(path))
+		.collect();
+}
+
+pub(crate) fn assemble_rules(
+	mut events: EventReader<AssetEvent<RulesFileAsset>>,
+	assets: Res<Assets<RulesFileAsset>>,
+	asset_server: Res<AssetServer>,
+	mut rules_assets: ResMut<RulesAssets>,
+	mut commands: Commands,
+) {
+	// Files that cannot be read send no event, they would be waited on forever
+	if rules_assets.errors.is_empty() {
+		let failed = rules_assets
+			.handles
+			.iter()
+			.filter(|handle| asset_server.get_load_state(*handle) == LoadState::Failed)
+			.count();
+		if failed > 0 {
+			let error = RulesError::new(
+				RULES_PATH,
+				"",
+				format!("{} of the ruleset files could not be read", failed),
+			);
+			error!("Failed loading the rules: {}", error);
+			rules_assets.errors = vec![error];
+			return;
+		}
+	}
+	if events.iter().count() == 0 {
+		return;
+	}
+	let files: Option<Vec<&RulesFileAsset>> = rules_assets
+		.handles
+		.iter()
+		.map(|handle| assets.get(handle))
+		.collect();
+	let mut files = match files {
+		Some(files) => files,
+		// Still loading
+		None => return,
+	};
+	files.sort_by(|a, b| a.path.cmp(&b.path));
+	match assemble(
+		RULES_PATH,
+		files.iter().map(|asset| (asset.path.as_str(), &asset.file)),
+	) {
+		Ok(rules) => {
+			info!("Loaded the rules from {} files", files.len());
+			rules_assets.errors.clear();
+			commands.insert_resource(rules);
+		}
+		Err(errors) => {
+			for error in &errors {
+				error!("Rules problem: {}", error);
+			}
+			rules_assets.errors = errors;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{assemble, check_names, RulesError, RulesFile};
+	use crate::server::game::rules::Rules;
+	use crate::server::world::{Feature, Terrain};
+	use std::path::Path;
+
+	#[test]
+	fn bundled_rules() {
+		let rules = Rules::bundled();
+		assert_eq!(rules.unit("warrior").unwrap().strength, 20);
+		assert_eq!(
+			rules.unit("catapult").unwrap().tech.as_deref(),
+			Some("mathematics")
+		);
+		assert!(rules.unit("settler").unwrap().founds_cities);
+		assert_eq!(rules.tiles.terrains[&Terrain::Grassland].food, 2);
+		assert_eq!(rules.tiles.features[&Feature::Forest].production, 1);
+		assert_eq!(rules.start_units, vec!["settler", "warrior"]);
+		assert!(rules.buildings["great_library"].wonder);
+	}
+
+	#[test]
+	fn names() {
+		let lang_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("assets")
+			.join("lang");
+		let mut rules = Rules::bundled();
+		assert_eq!(check_names(&rules, &lang_dir), vec![]);
+
+		let warrior = rules.units["warrior"].clone();
+		rules.units.insert("axeman".into(), warrior.clone());
+		rules.units.insert("2nd_warrior".into(), warrior);
+		let errors = check_names(&rules, &lang_dir);
+		let problems: Vec<_> = errors
+			.iter()
+			.map(|e| (e.file.rsplit('/').next().unwrap(), e.key.as_str()))
+			.collect();
+		assert_eq!(
+			problems,
+			vec![
+				("rules", "units.2nd_warrior"),
+				("de-DE", "units.axeman"),
+				("en-US", "units.axeman"),
+			]
+		);
+		assert_eq!(errors[1].problem, "has no message `rules-unit.axeman`");
+	}
+
+	#[test]
+	fn cross_reference_errors() {
+		let parse = |text: &str| RulesFile::parse(text);
+		let base = parse(
+			r#"#![enable(implicit_some)]
+			(
+				hills: (production: 1),
+				river: (gold: 1),
+				cities: (
+					radius: 3, min_distance: 4, center: (), food_per_citizen: 2, growth_base: 15,
+					growth_per_citizen: 8, work_weights: (food: 1), purchase_cost: 2, queue_length: 8,
+				),
+				borders: (
+					initial_radius: 1, max_radius: 4, culture_base: 10, culture_per_tile: 5,
+					weights: (food: 1), resource_score: 3, distance_penalty: 2,
+				),
+				start_units: ["warrior"],
+				techs: {"mining": (cost: 25)},
+			)"#,
+		);
+		let units = parse(
+			r#"#![enable(implicit_some)]
+			(
+				units: {
+					"warrior": (moves: 2, strength: 20, tech: "bronze_working"),
+					"miner": (moves: 2, tech: "mining"),
+				},
+				techs: {"mining": (cost: 30)},
+			)"#,
+		);
+		let broken = parse("(units: {\"scout\": (moves: \"fast\")})");
+		assert!(base.is_ok() && units.is_ok() && broken.is_err());
+
+		let errors = assemble(
+			"rules",
+			vec![("base.rules.ron", &base), ("units.rules.ron", &units)],
+		)
+		.unwrap_err();
+		let error = |file: &str, key: &str, problem: &str| RulesError {
+			file: file.to_owned(),
+			key: key.to_owned(),
+			problem: problem.to_owned(),
+		};
+		assert_eq!(
+			errors,
+			vec![
+				error(
+					"units.rules.ron",
+					"techs.mining",
+					"already defined in base.rules.ron"
+				),
+				error(
+					"units.rules.ron",
+					"units.warrior",
+					"refers to unknown tech `bronze_working`"
+				),
+			]
+		);
+		assert_eq!(
+			errors[1].to_string(),
+			"units.rules.ron: units.warrior: refers to unknown tech `bronze_working`"
+		);
+
+		let errors = assemble("rules", vec![("broken.rules.ron", &broken)]).unwrap_err();
+		assert_eq!(errors[0].file, "broken.rules.ron");
+		assert!(errors[0].problem.contains("Expected"));
+		// Problems with a whole file have no key to show
+		assert_eq!(
+			errors[0].to_string(),
+			format!("broken.rules.ron: {}", errors[0].problem)
+		);
+		assert_eq!(
+			errors[1..]
+				.iter()
+				.map(|e| e.key.as_str())
+				.collect::<Vec<_>>(),
+			vec!["hills", "river", "cities", "borders", "start_units"]
+		);
+
+		// Techs that need each other can never be researched
+		let cyclic = parse(
+			r#"#![enable(implicit_some)]
+			(
+				techs: {
+					"a": (cost: 10, prerequisites: ["b"]),
+					"b": (cost: 10, prerequisites: ["mining", "a"]),
+					"c": (cost: 10, prerequisites: ["a"]),
+				},
+			)"#,
+		);
+		let errors = assemble(
+			"rules",
+			vec![("base.rules.ron", &base), ("cyclic.rules.ron", &cyclic)],
+		)
+		.unwrap_err();
+		assert_eq!(
+			errors
+				.iter()
+				.filter(|e| e.problem == "is a prerequisite of itself")
+				.map(|e| (e.file.as_str(), e.key.as_str()))
+				.collect::<Vec<_>>(),
+			vec![
+				("cyclic.rules.ron", "techs.a"),
+				("cyclic.rules.ron", "techs.b")
+			]
+		);
+	}
+}
@@ -0,0 +1,235 @@
+//! The territory of cities, the tiles they claim.  A new city claims its own tile and the tiles
+//! within `BorderRules::initial_radius` of it that are in no territory yet.  At the end of every turn
+//! a city stores the culture it yields, and once it stored enough it claims another tile next to
+//! its territory: the one with the best score by `BorderRules`, the nearest of those if several
+//! score the same.
+//!
+//! A tile belongs to the city that claimed it first, claims never take tiles from other cities.
+//! Cities claim in the order they were founded, so when cities of different players could claim
+//! the same tile at the end of the same turn the city founded first gets it.
+//!
+//! Other players cannot found cities, work tiles or build improvements in a player's territory, and
+//! their units can only enter it while they are at war with the player.
+
+use super::city::{City, CityId};
+use super::production::{buildings_by_city, Building};
+use super::rules::Rules;
+use super::TurnEnded;
+use crate::server::world::{Hex, TileOwner, WorldMap};
+use bevy::prelude::*;
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+
+impl City {
+	/// The owner of the tiles the city claims.
+	fn tile_owner(&self) -> TileOwner {
+		TileOwner {
+			player: self.owner,
+			city: self.id,
+		}
+	}
+
+	/// Claim the city's own tile and the tiles around it that are in no territory yet.
+	pub fn claim_initial(&self, map: &mut WorldMap, rules: &Rules) {
+		let owner = self.tile_owner();
+		for hex in map.spiral(self.position, rules.borders.initial_radius) {
+			if let Some(tile) = map.get_mut(hex) {
+				if tile.owner.is_none() || hex == self.position {
+					tile.owner = Some(owner);
+				}
+			}
+		}
+	}
+
+	/// The culture the city needs to claim its next tile.
+	pub fn culture_threshold(&self, rules: &Rules) -> u32 {
+		rules.borders.culture_base + rules.borders.culture_per_tile * self.claims
+	}
+
+	/// The tile the city would claim next, `None` if there is none left it can claim.
+	pub fn next_claim(&self, map: &WorldMap, rules: &Rules) -> Option<Hex> {
+		let borders = &rules.borders;
+		let owner = self.tile_owner();
+		let mut best: Option<(i64, Hex)> = None;
+		// Nearest first, so the first of the best tiles is the nearest
+		for hex in map.spiral(self.position, borders.max_radius) {
+			let tile = match map.get(hex) {
+				Some(tile) if tile.owner.is_none() => tile,
+				_ => continue,
+			};
+			if !map
+				.neighbors(hex)
+				.any(|neighbor| matches!(map.get(neighbor), Some(t) if t.owner == Some(owner)))
+			{
+				continue;
+			}
+			let mut score = rules
+				.tile_yields(tile)
+				.map_or(0, |yields| yields.weighted(&borders.weights)) as i64;
+			if tile.resource.is_some() {
+				score += borders.resource_score as i64;
+			}
+			score -= (map.distance(self.position, hex) * borders.distance_penalty) as i64;
+			if matches!(best, Some((best_score, _)) if best_score >= score) {
+				continue;
+			}
+			best = Some((score, hex));
+		}
+		best.map(|(_score, hex)| hex)
+	}
+}
+
+/// Store the culture of every city with `buildings` at the end of a turn and claim the tiles it
+/// pays for, returning the tiles claimed by each city.  `cities` must be ordered by id.
+pub fn grow_borders(
+	cities: &mut [City],
+	map: &mut WorldMap,
+	rules: &Rules,
+	buildings: &BTreeMap<CityId, Vec<SmolStr>>,
+) -> Vec<(CityId, Hex)> {
+	let mut claimed = vec![];
+	for city in cities.iter_mut() {
+		// Cities of saves from before there was territory claim theirs now
+		if !matches!(map.get(city.position), Some(tile) if tile.owner == Some(city.tile_owner())) {
+			city.claim_initial(map, rules);
+		}
+		let city_buildings = buildings.get(&city.id).map_or(&[][..], Vec::as_slice);
+		city.culture += city.yields(map, rules, city_buildings).culture;
+		loop {
+			let threshold = city.culture_threshold(rules);
+			if city.culture < threshold {
+				break;
+			}
+			let hex = match city.next_claim(map, rules) {
+				Some(hex) => hex,
+				// Nothing left to claim, the culture is kept in case a tile frees up
+				None => break,
+			};
+			if let Some(tile) = map.get_mut(hex) {
+				tile.owner = Some(city.tile_owner());
+			}
+			city.culture -= threshold;
+			city.claims += 1;
+			claimed.push((city.id, hex));
+		}
+	}
+	claimed
+}
+
+/// Grow the borders of every city at the end of the turn.
+pub(crate) fn expand_borders(
+	mut ended: EventReader<TurnEnded>,
+	mut cities: Query<&mut City>,
+	buildings: Query<&Building>,
+	mut map: ResMut<WorldMap>,
+	rules: Res<Rules>,
+) {
+	if ended.iter().count() == 0 {
+		return;
+	}
+	let buildings = buildings_by_city(buildings.iter());
+	let mut snapshot: Vec<City> = cities.iter_mut().map(|city| city.clone()).collect();
+	snapshot.sort_by_key(|city| city.id);
+	for (city, hex) in grow_borders(&mut snapshot, &mut map, &rules, &buildings) {
+		debug!("{:?} claimed {:?}", city, hex);
+	}
+	for mut city in cities.iter_mut() {
+		if let Some(updated) = snapshot.iter().find(|updated| updated.id == city.id) {
+			if *city != *updated {
+				*city = updated.clone();
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::grow_borders;
+	use crate::server::game::city::{City, CityId};
+	use crate::server::game::player::PlayerId;
+	use crate::server::game::rules::Rules;
+	use crate::server::world::{Elevation, Hex, Terrain, WorldMap};
+	use std::collections::{BTreeMap, BTreeSet};
+
+	fn territory(map: &WorldMap, city: CityId) -> BTreeSet<Hex> {
+		map.iter()
+			.filter(|(_hex, tile)| matches!(tile.owner, Some(owner) if owner.city == city))
+			.map(|(hex, _tile)| hex)
+			.collect()
+	}
+
+	#[test]
+	fn border_growth() {
+		let mut map = WorldMap::filled(20, 12, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let center = Hex::from_offset(6, 5);
+		let mut city = City::new(CityId(1), PlayerId(0), "Rome".to_owned(), center);
+		city.claim_initial(&mut map, &rules);
+		assert_eq!(
+			territory(&map, city.id),
+			map.spiral(center, 1).into_iter().collect()
+		);
+
+		// The resource makes the hill the best tile next to the territory
+		let hill = Hex::from_offset(8, 5);
+		map.get_mut(hill).unwrap().elevation = Elevation::Hills;
+		map.get_mut(hill).unwrap().resource = Some("iron".into());
+		assert_eq!(city.next_claim(&map, &rules), Some(hill));
+
+		// A city without citizens at work only has the culture of its center
+		city.culture = rules.borders.culture_base - 1;
+		let mut cities = vec![city];
+		let claimed = grow_borders(&mut cities, &mut map, &rules, &BTreeMap::new());
+		assert_eq!(claimed, vec![(CityId(1), hill)]);
+		assert_eq!(cities[0].claims, 1);
+		assert_eq!(cities[0].culture, 0);
+		assert_eq!(
+			cities[0].culture_threshold(&rules),
+			rules.borders.culture_base + rules.borders.culture_per_tile
+		);
+	}
+
+	#[test]
+	fn overlapping_claims() {
+		let mut map = WorldMap::filled(20, 12, Terrain::Grassland);
+		let rules = Rules::bundled();
+		let rome = City::new(
+			CityId(1),
+			PlayerId(0),
+			"Rome".to_owned(),
+			Hex::from_offset(4, 5),
+		);
+		let thebes = City::new(
+			CityId(2),
+			PlayerId(1),
+			"Thebes".to_owned(),
+			Hex::from_offset(8, 5),
+		);
+		rome.claim_initial(&mut map, &rules);
+		thebes.claim_initial(&mut map, &rules);
+
+		// Both want the tiles between them, the city founded first claims first
+		let mut cities = vec![rome, thebes];
+		for city in cities.iter_mut() {
+			city.culture = 1000;
+		}
+		let between = Hex::from_offset(6, 5);
+		grow_borders(&mut cities, &mut map, &rules, &BTreeMap::new());
+		assert_eq!(map.get(between).unwrap().owner.unwrap().city, CityId(1));
+		assert!(territory(&map, CityId(1)).is_disjoint(&territory(&map, CityId(2))));
+		// The cities claimed until their culture ran out or there was nothing left to claim
+		assert!(cities[0].claims > 0);
+		assert!(cities[0].culture < cities[0].culture_threshold(&rules));
+
+		// A city founded in its player's territory takes its own tile, but no other claimed tile
+		let before = territory(&map, CityId(1));
+		let outpost = City::new(CityId(3), PlayerId(0), "Antium".to_owned(), between);
+		outpost.claim_initial(&mut map, &rules);
+		let taken: BTreeSet<Hex> = before
+			.difference(&territory(&map, CityId(1)))
+			.copied()
+			.collect();
+		assert_eq!(taken, vec![between].into_iter().collect());
+		assert!(territory(&map, CityId(3)).contains(&between));
+	}
+}
@@ -0,0 +1,79 @@
+use super::pathfinding::MOVE_POINT;
+use super::player::PlayerId;
+use super::rules::Rules;
+use crate::server::world::Hex;
+use crate::universal::commands::state::UnitInfo;
+pub use crate::universal::ids::UnitId;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+/// Health of an unhurt unit.
+pub const MAX_HEALTH: u32 = 100;
+
+fn max_health() -> u32 {
+	MAX_HEALTH
+}
+
+/// A unit on the map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Unit {
+	pub id: UnitId,
+	pub owner: PlayerId,
+	/// The unit type, a key of `Rules::units`.
+	pub kind: SmolStr,
+	pub position: Hex,
+	/// Movement left this turn, in thirds of a movement point.
+	#[serde(default)]
+	pub moves_left: u32,
+	#[serde(default = "max_health")]
+	pub health: u32,
+	/// Where the unit is headed, it moves through these in order over as many turns as it takes.
+	#[serde(default)]
+	pub waypoints: Vec<Hex>,
+	/// Dug in where it is until given another order.
+	#[serde(default)]
+	pub fortified: bool,
+}
+
+impl Unit {
+	/// A new unhurt unit with all its movement.
+	pub fn new(id: UnitId, owner: PlayerId, kind: &str, position: Hex, rules: &Rules) -> Self {
+		let mut unit = Self {
+			id,
+			owner,
+			kind: kind.into(),
+			position,
+			moves_left: 0,
+			health: MAX_HEALTH,
+			waypoints: vec![],
+			fortified: false,
+		};
+		unit.moves_left = unit.max_moves(rules);
+		unit
+	}
+
+	/// Movement every turn, in thirds of a movement point.
+	pub fn max_moves(&self, rules: &Rules) -> u32 {
+		rules
+			.unit(&self.kind)
+			.map(|unit_type| unit_type.moves * MOVE_POINT)
+			.unwrap_or_default()
+	}
+
+	/// The unit as it is sent to the clients of `viewer`, only its owner sees where it is headed.
+	pub fn info(&self, viewer: PlayerId) -> UnitInfo {
+		UnitInfo {
+			id: self.id,
+			owner: self.owner,
+			kind: self.kind.clone(),
+			position: self.position,
+			moves_left: self.moves_left,
+			health: self.health,
+			waypoints: if viewer == self.owner {
+				self.waypoints.clone()
+			} else {
+				vec![]
+			},
+		}
+	}
+}
@@ -0,0 +1,333 @@
+//! What each player can see.  A player sees the tiles within sight of their units and cities, and
+//! remembers every tile they have seen as it last looked along with the cities on it.  The state
+//! sent to a client is built from this, so it never holds anything its player cannot see.
+
+use super::city::City;
+use super::player::{PlayerId, Players};
+use super::unit::Unit;
+use crate::server::world::{Hex, Terrain, Tile, WorldMap};
+use crate::universal::commands::state::{
+	CityInfo, GameState, PlayerInfo, TileVisibility, TurnStatus,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How many tiles away a unit sees.
+pub const UNIT_SIGHT: u32 = 2;
+
+/// How many tiles away a city sees.
+pub const CITY_SIGHT: u32 = 3;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerVision {
+	/// Every tile as the player last saw it by `WorldMap::tile_index`, `None` if never seen.
+	tiles: Vec<Option<Tile>>,
+	/// The cities of other players as the player last saw them.
+	cities: Vec<CityInfo>,
+}
+
+impl PlayerVision {
+	pub fn is_explored(&self, idx: usize) -> bool {
+		matches!(self.tiles.get(idx), Some(Some(_)))
+	}
+
+	/// The map as the player knows it, tiles never seen are taken to be flat grassland so that
+	/// paths can lead into the unknown.
+	pub fn known_map(&self, map: &WorldMap) -> WorldMap {
+		let mut known = WorldMap::new(map.width(), map.height());
+		for (idx, (_hex, tile)) in known.iter_mut().enumerate() {
+			*tile = match self.tiles.get(idx) {
+				Some(Some(seen)) => seen.clone(),
+				_ => Tile {
+					terrain: Terrain::Grassland,
+					..Tile::default()
+				},
+			};
+		}
+		known
+	}
+}
+
+/// The vision of every player, saved with the game and kept up to date by `update_visions`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Visions(pub BTreeMap<PlayerId, PlayerVision>);
+
+/// The tiles `player` currently sees, by `WorldMap::tile_index`.
+pub fn visible_tiles(
+	map: &WorldMap,
+	player: PlayerId,
+	units: &[&Unit],
+	cities: &[&City],
+) -> Vec<bool> {
+	let mut visible = vec![false; map.tile_count()];
+	let sights = units
+		.iter()
+		.filter(|u| u.owner == player)
+		.map(|u| (u.position, UNIT_SIGHT))
+		.chain(
+			cities
+				.iter()
+				.filter(|c| c.owner == player)
+				.map(|c| (c.position, CITY_SIGHT)),
+		);
+	for (center, sight) in sights {
+		for hex in map.spiral(center, sight) {
+			if let Some(idx) = map.tile_index(hex) {
+				visible[idx] = true;
+			}
+		}
+	}
+	visible
+}
+
+pub(crate) fn is_visible(map: &WorldMap, visible: &[bool], hex: Hex) -> bool {
+	matches!(map.tile_index(hex), Some(idx) if visible[idx])
+}
+
+impl Visions {
+	/// Remember what every player currently sees.
+	pub fn update(&mut self, map: &WorldMap, players: &Players, units: &[&Unit], cities: &[&City]) {
+		for player in players.iter() {
+			let visible = visible_tiles(map, player.id, units, cities);
+			let vision = self.0.entry(player.id).or_default();
+			vision.tiles.resize(map.tile_count(), None);
+			for (idx, (_hex, tile)) in map.iter().enumerate() {
+				if visible[idx] && vision.tiles[idx].as_ref() != Some(tile) {
+					vision.tiles[idx] = Some(tile.clone());
+				}
+			}
+			// Forget the cities on visible tiles, they are seen as they are now
+			vision
+				.cities
+				.retain(|city| !is_visible(map, &visible, city.position));
+			vision.cities.extend(
+				cities
+					.iter()
+					.filter(|c| c.owner != player.id && is_visible(map, &visible, c.position))
+					.map(|c| c.info()),
+			);
+		}
+	}
+
+	/// The game state as `player` is allowed to see it.
+	pub fn state_for(
+		&self,
+		player: PlayerId,
+		turn: u32,
+		map: &WorldMap,
+		players: Vec<PlayerInfo>,
+		units: &[&Unit],
+		cities: &[&City],
+	) -> GameState {
+		let visible = visible_tiles(map, player, units, cities);
+		let vision = self.0.get(&player);
+		let mut seen_map = WorldMap::new(map.width(), map.height());
+		let mut visibility = vec![TileVisibility::Unexplored; map.tile_count()];
+		for (idx, (hex, tile)) in map.iter().enumerate() {
+			let (seen, tile_visibility) = if visible[idx] {
+				(Some(tile), TileVisibility::Visible)
+			} else {
+				match vision.and_then(|v| v.tiles.get(idx)) {
+					Some(Some(remembered)) => (Some(remembered), TileVisibility::Fogged),
+					_ => (None, TileVisibility::Unexplored),
+				}
+			};
+			if let (Some(seen), Some(seen_tile)) = (seen, seen_map.get_mut(hex)) {
+				*seen_tile = seen.clone();
+			}
+			visibility[idx] = tile_visibility;
+		}
+		let mut state = GameState {
+			turn,
+			map: seen_map,
+			visibility,
+			players,
+			units: units
+				.iter()
+				.filter(|u| u.owner == player || is_visible(map, &visible, u.position))
+				.map(|u| u.info(player))
+				.collect(),
+			cities: cities
+				.iter()
+				.filter(|c| c.owner == player || is_visible(map, &visible, c.position))
+				.map(|c| c.info())
+				.chain(
+					vision
+						.iter()
+						.flat_map(|v| v.cities.iter())
+						.filter(|c| c.owner != player && !is_visible(map, &visible, c.position))
+						.cloned(),
+				)
+				.collect(),
+			turn_status: TurnStatus::default(),
+		};
+		state.units.sort_by_key(|u| u.id);
+		state.cities.sort_by_key(|c| c.id);
+		state
+	}
+}
+
+pub(crate) fn update_visions(
+	mut visions: ResMut<Visions>,
+	map: Res<WorldMap>,
+	players: Res<Players>,
+	units: Query<&Unit>,
+	cities: Query<&City>,
+) {
+	let units: Vec<&Unit> = units.iter().collect();
+	let cities: Vec<&City> = cities.iter().collect();
+	visions.update(&map, &players, &units, &cities);
+}
+
+#[cfg(test)]
+mod test {
+	use super::Visions;
+	use crate::server::game::city::{City, CityId};
+	use crate::server::game::player::{Player, PlayerId, Players};
+	use crate::server::game::rules::Rules;
+	use crate::server::game::unit::{Unit, UnitId};
+	use crate::server::replication::Replication;
+	use crate::server::world::{Hex, Terrain, WorldMap};
+	use crate::universal::commands::state::{GameState, TileVisibility};
+	use crate::universal::commands::ServerCommand;
+	use std::sync::Arc;
+	use std::time::Instant;
+
+	struct Game {
+		map: WorldMap,
+		players: Players,
+		units: Vec<Unit>,
+		cities: Vec<City>,
+		visions: Visions,
+	}
+
+	impl Game {
+		fn new() -> Self {
+			let player =
+				|id: u32| Player::new(PlayerId(id), format!("Player {}", id), "rome".into());
+			let rules = Rules::bundled();
+			let unit = |id: u64, owner: u32, col: i32| {
+				let position = Hex::from_offset(col, 5);
+				Unit::new(UnitId(id), PlayerId(owner), "warrior", position, &rules)
+			};
+			Game {
+				map: WorldMap::new(40, 10),
+				players: Players(vec![player(0), player(1)]),
+				units: vec![unit(1, 0, 2), unit(2, 1, 20)],
+				cities: vec![City::new(
+					CityId(1),
+					PlayerId(1),
+					"Memphis".to_owned(),
+					Hex::from_offset(22, 5),
+				)],
+				visions: Visions::default(),
+			}
+		}
+
+		fn update(&mut self) {
+			let units: Vec<&Unit> = self.units.iter().collect();
+			let cities: Vec<&City> = self.cities.iter().collect();
+			self.visions
+				.update(&self.map, &self.players, &units, &cities);
+		}
+
+		fn state_for(&self, player: PlayerId) -> GameState {
+			let units: Vec<&Unit> = self.units.iter().collect();
+			let cities: Vec<&City> = self.cities.iter().collect();
+			let players = self.players.iter().map(|p| p.info(false, player)).collect();
+			self.visions
+				.state_for(player, 0, &self.map, players, &units, &cities)
+		}
+
+		fn move_unit(&mut self, id: u64, col: i32) {
+			let unit = self.units.iter_mut().find(|u| u.id == UnitId(id)).unwrap();
+			unit.position = Hex::from_offset(col, 5);
+			self.update();
+		}
+	}
+
+	#[test]
+	fn fog_of_war() {
+		let mut game = Game::new();
+		game.map.get_mut(Hex::from_offset(21, 5)).unwrap().terrain = Terrain::Desert;
+		game.update();
+		let state = game.state_for(PlayerId(0));
+		assert_eq!(state.units.len(), 1);
+		assert!(state.cities.is_empty());
+		assert_eq!(
+			state.visibility(Hex::from_offset(2, 5)),
+			TileVisibility::Visible
+		);
+		assert_eq!(
+			state.visibility(Hex::from_offset(21, 5)),
+			TileVisibility::Unexplored
+		);
+		// Nothing is known about unexplored tiles
+		assert_eq!(
+			state.map.get(Hex::from_offset(21, 5)).unwrap().terrain,
+			Terrain::default()
+		);
+
+		// Scouting next to the enemy shows their unit, city and land
+		game.move_unit(1, 21);
+		let state = game.state_for(PlayerId(0));
+		assert_eq!(state.units.len(), 2);
+		assert_eq!(state.cities.len(), 1);
+		assert_eq!(
+			state.map.get(Hex::from_offset(21, 5)).unwrap().terrain,
+			Terrain::Desert
+		);
+
+		// Once gone the land and city are remembered as they were, but not the unit
+		game.move_unit(1, 10);
+		game.map.get_mut(Hex::from_offset(21, 5)).unwrap().terrain = Terrain::Plains;
+		game.cities[0].name = "Thebes".to_owned();
+		let state = game.state_for(PlayerId(0));
+		assert_eq!(state.units.len(), 1);
+		assert_eq!(
+			state.visibility(Hex::from_offset(21, 5)),
+			TileVisibility::Fogged
+		);
+		assert_eq!(
+			state.map.get(Hex::from_offset(21, 5)).unwrap().terrain,
+			Terrain::Desert
+		);
+		assert_eq!(state.cities[0].name, "Memphis");
+	}
+
+	#[test]
+	fn hidden_units_never_replicated() {
+		let mut game = Game::new();
+		game.update();
+		let now = Instant::now();
+		let mut replication = Replication::default();
+		let mut stream = vec![replication.snapshot(Arc::new(game.state_for(PlayerId(0))), now)];
+		// The enemy unit wanders all over the map without ever coming into sight
+		for col in (8..40).chain(0..3).step_by(3) {
+			game.move_unit(2, 20 + col % 10);
+			game.move_unit(1, col % 4);
+			let state = Arc::new(game.state_for(PlayerId(0)));
+			stream.extend(replication.update(state, now));
+		}
+		assert!(stream.len() > 1);
+		for command in stream {
+			let units = match &command {
+				ServerCommand::State(state) => state.units.clone(),
+				ServerCommand::Delta(delta) => delta.units.clone(),
+				command => panic!("unexpected {:?}", command),
+			};
+			assert!(
+				units.iter().all(|u| u.owner == PlayerId(0)),
+				"{:?}",
+				command
+			);
+			if let ServerCommand::State(state) = &command {
+				assert!(state.cities.is_empty());
+			}
+			if let ServerCommand::Delta(delta) = &command {
+				assert!(delta.cities.is_empty());
+			}
+		}
+	}
+}
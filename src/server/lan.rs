@@ -0,0 +1,58 @@
+//! Announces the server on the LAN so clients on the same network can find it.
+
+use crate::server::clients::Clients;
+use crate::server::game::player::Players;
+use crate::universal::commands::{GAME_VERSION, PROTOCOL_VERSION};
+use crate::universal::transport::lan::{Announcer, ServerAnnouncement, ANNOUNCE_INTERVAL};
+use bevy::prelude::*;
+use std::time::Instant;
+
+/// Resource that makes the server announce itself, only inserted when it accepts remote clients.
+pub struct LanAnnouncer {
+	announcer: Announcer,
+	name: String,
+	port: u16,
+	password: bool,
+	last_announced: Option<Instant>,
+}
+
+impl LanAnnouncer {
+	pub fn new(announcer: Announcer, name: String, port: u16, password: bool) -> Self {
+		Self {
+			announcer,
+			name,
+			port,
+			password,
+			last_announced: None,
+		}
+	}
+}
+
+pub(crate) fn announce_server(
+	announcer: Option<ResMut<LanAnnouncer>>,
+	clients: Res<Clients>,
+	players: Option<Res<Players>>,
+) {
+	let mut announcer = match announcer {
+		Some(announcer) => announcer,
+		None => return,
+	};
+	let now = Instant::now();
+	if matches!(announcer.last_announced, Some(last) if now.duration_since(last) < ANNOUNCE_INTERVAL)
+	{
+		return;
+	}
+	announcer.last_announced = Some(now);
+	let announcement = ServerAnnouncement {
+		name: announcer.name.clone(),
+		port: announcer.port,
+		players: clients.iter().filter(|c| c.player().is_some()).count() as u32,
+		max_players: players.map_or(0, |players| players.0.len() as u32),
+		protocol: PROTOCOL_VERSION,
+		game_version: GAME_VERSION.to_owned(),
+		password: announcer.password,
+	};
+	if let Err(e) = announcer.announcer.announce(&announcement) {
+		debug!("Failed announcing the server on the LAN: {}", e);
+	}
+}
@@ -1,7 +1,17 @@
+pub mod clients;
+pub mod dedicated;
+pub mod game;
+pub mod lan;
+pub mod replication;
 pub mod save;
+pub mod sessions;
+pub mod simulation;
 mod states;
+pub mod turns;
+pub mod world;
 
 use crate::universal::local_server::LocalServerPublicState;
+use crate::universal::transport::channel;
 use bevy::app::PluginGroupBuilder;
 use bevy::prelude::*;
 
@@ -20,7 +30,37 @@ impl PluginGroup for ServerPluginGroup {
 
 impl Plugin for ServerPlugin {
 	fn build(&self, app: &mut AppBuilder) {
+		let (local_connector, local_listener) = channel::local_listener();
 		app.insert_resource(LocalServerPublicState::Off)
-			.init_resource::<Option<save::SaveConfig>>();
+			.insert_resource(local_connector)
+			.insert_resource(clients::Listeners(vec![Box::new(local_listener)]))
+			.init_resource::<clients::Clients>()
+			.add_event::<clients::ClientMessage>()
+			.add_event::<clients::ClientEvent>()
+			.add_system(clients::accept_clients.system())
+			.init_resource::<Option<save::SaveConfig>>()
+			.init_resource::<simulation::SimulationClock>()
+			.add_asset::<game::ruleset::RulesFileAsset>()
+			.init_asset_loader::<game::ruleset::RulesFileAssetLoader>()
+			.init_resource::<game::ruleset::RulesAssets>()
+			.add_startup_system(game::ruleset::load_rules.system())
+			.add_system(game::ruleset::assemble_rules.system())
+			.add_event::<simulation::SimulationTick>()
+			.add_event::<game::TurnStarted>()
+			.add_event::<game::TurnEnded>()
+			.add_event::<turns::EndTurnRequest>()
+			.add_event::<game::movement::UnitOrderRequest>()
+			.add_event::<game::movement::PathPreviewRequest>()
+			.add_event::<game::combat::AttackRequest>()
+			.add_event::<game::combat::CombatPreviewRequest>()
+			.add_event::<game::combat::CombatResolved>()
+			.add_event::<game::city::FoundCityRequest>()
+			.add_event::<game::city::CityOrderRequest>()
+			.add_event::<game::production::ProductionRequest>()
+			.add_event::<game::improvement::BuildImprovementRequest>()
+			.add_event::<game::research::ResearchRequest>()
+			.add_event::<game::research::TechTreeRequest>()
+			.add_event::<game::Notified>()
+			.add_system(save::browser::on_save_cmd.system());
 	}
 }
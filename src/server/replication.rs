@@ -0,0 +1,152 @@
+//! Replication of the game state to the joined clients.  Each client is sent the full state when it
+//! asks for it, after that only a `StateDelta` of what changed since the state it was last sent,
+//! with a full snapshot every `SNAPSHOT_INTERVAL` to bound how long a client can drift.  The state
+//! of a client is only what its player can see, see `vision`.
+
+use crate::server::clients::{ClientId, Clients};
+use crate::server::game::player::PlayerId;
+use crate::server::game::TurnStarted;
+use crate::server::save::game::GameData;
+use crate::server::simulation::SimulationTick;
+use crate::universal::commands::state::GameState;
+use crate::universal::commands::ServerCommand;
+use bevy::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Changes are replicated every this many simulation ticks, and at the start of every turn.
+pub const REPLICATION_INTERVAL_TICKS: u64 = 5;
+
+/// How often a client is sent the full state even though it is in sync.
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The state a client was last sent, the base of the next delta.
+#[derive(Debug, Default)]
+pub struct Replication {
+	/// Shared between clients that were sent the same state.
+	sent: Option<Arc<GameState>>,
+	last_snapshot: Option<Instant>,
+}
+
+impl Replication {
+	/// If the client was sent the full state and is kept up to date.
+	pub fn is_synced(&self) -> bool {
+		self.sent.is_some()
+	}
+
+	/// The full state to send to the client.
+	pub fn snapshot(&mut self, state: Arc<GameState>, now: Instant) -> ServerCommand {
+		let command = ServerCommand::State((*state).clone());
+		self.sent = Some(state);
+		self.last_snapshot = Some(now);
+		command
+	}
+
+	/// What to send the client to bring it up to `state`, if anything.
+	pub fn update(&mut self, state: Arc<GameState>, now: Instant) -> Option<ServerCommand> {
+		let sent = self.sent.as_ref()?;
+		if Arc::ptr_eq(sent, &state) {
+			return None;
+		}
+		if matches!(self.last_snapshot, Some(last) if now.duration_since(last) < SNAPSHOT_INTERVAL)
+		{
+			if let Some(delta) = sent.diff(&state) {
+				self.sent = Some(state);
+				return Some(delta)
+					.filter(|delta| !delta.is_empty())
+					.map(ServerCommand::Delta);
+			}
+		}
+		Some(self.snapshot(state, now))
+	}
+
+	/// Stop replicating until the client asks for the full state again.
+	pub fn reset(&mut self) {
+		*self = Self::default();
+	}
+}
+
+pub(crate) fn replicate_state(
+	mut ticks: EventReader<SimulationTick>,
+	mut turns: EventReader<TurnStarted>,
+	mut clients: ResMut<Clients>,
+	game: GameData,
+) {
+	let ticked = ticks
+		.iter()
+		.filter(|tick| tick.0 % REPLICATION_INTERVAL_TICKS == 0)
+		.count();
+	if ticked + turns.iter().count() == 0 {
+		return;
+	}
+	let synced: Vec<(ClientId, PlayerId)> = clients
+		.iter()
+		.filter(|c| c.replication().is_synced())
+		.filter_map(|c| Some((c.id(), c.player()?)))
+		.collect();
+	// Built once for each player, shared by clients of the same player
+	let mut states: BTreeMap<PlayerId, Arc<GameState>> = BTreeMap::new();
+	let now = Instant::now();
+	for (id, player) in synced {
+		let state = states
+			.entry(player)
+			.or_insert_with(|| Arc::new(game.state_for(player, |p| clients.sessions().is_away(p))))
+			.clone();
+		let command = clients
+			.replication_mut(id)
+			.and_then(|replication| replication.update(state, now));
+		if let Some(command) = command {
+			clients.send(id, command);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Replication, SNAPSHOT_INTERVAL};
+	use crate::universal::commands::state::{GameState, TileVisibility, TurnStatus};
+	use crate::universal::commands::ServerCommand;
+	use crate::universal::map::WorldMap;
+	use std::sync::Arc;
+	use std::time::Instant;
+
+	#[test]
+	fn deltas_between_snapshots() {
+		let now = Instant::now();
+		let state = Arc::new(GameState {
+			turn: 0,
+			map: WorldMap::new(2, 2),
+			visibility: vec![TileVisibility::Visible; 4],
+			players: vec![],
+			units: vec![],
+			cities: vec![],
+			turn_status: TurnStatus::default(),
+		});
+		let mut replication = Replication::default();
+		assert_eq!(replication.update(state.clone(), now), None);
+
+		let mut client = match replication.snapshot(state.clone(), now) {
+			ServerCommand::State(state) => state,
+			command => panic!("expected the full state, got {:?}", command),
+		};
+		assert_eq!(replication.update(state.clone(), now), None);
+		assert_eq!(replication.update(Arc::new((*state).clone()), now), None);
+
+		let mut next = (*state).clone();
+		next.turn = 1;
+		let next = Arc::new(next);
+		match replication.update(next.clone(), now) {
+			Some(ServerCommand::Delta(delta)) => assert!(client.apply(&delta)),
+			command => panic!("expected a delta, got {:?}", command),
+		}
+		assert_eq!(client, *next);
+
+		assert_eq!(
+			replication.update(state.clone(), now + SNAPSHOT_INTERVAL),
+			Some(ServerCommand::State((*state).clone()))
+		);
+		replication.reset();
+		assert!(!replication.is_synced());
+	}
+}
@@ -0,0 +1,152 @@
+//! Autosaves are extra copies of the game file kept in the `autosaves` directory of a save, in a
+//! fixed window like the rolled log files: `game-0.ron` is the newest, and once there are `keep`
+//! of them the oldest is deleted to make room.
+//!
+//! The game is written on a background thread to `game-next.ron` first, the window is only rotated
+//! once that write is done so a failed or unfinished autosave never pushes out an older one.
+
+use super::game::{GameData, GameWriter};
+use super::{SaveConfig, SaveConfigError};
+use crate::server::game::TurnStarted;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const AUTOSAVE_DIRECTORY: &str = "autosaves";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutosaveConfig {
+	/// Autosave at the start of every this many turns, 0 disables autosaving.
+	pub every_turns: u32,
+	/// How many autosaves to keep before the oldest is deleted.
+	pub keep: u32,
+}
+
+impl Default for AutosaveConfig {
+	fn default() -> Self {
+		Self {
+			every_turns: 5,
+			keep: 5,
+		}
+	}
+}
+
+/// An autosave being written in the background, with where to rotate it in once it is done.
+pub struct PendingAutosave {
+	writer: GameWriter,
+	save_path: PathBuf,
+	keep: u32,
+}
+
+/// The path of an autosave, index 0 is the newest.
+pub fn autosave_path(save_path: &Path, index: u32) -> PathBuf {
+	save_path
+		.join(AUTOSAVE_DIRECTORY)
+		.join(format!("game-{}.ron", index))
+}
+
+/// Shift every autosave one back in the window to make room for a new `game-0.ron`, deleting the
+/// oldest if the window is full.
+fn rotate(save_path: &Path, keep: u32) -> Result<(), SaveConfigError> {
+	std::fs::create_dir_all(save_path.join(AUTOSAVE_DIRECTORY))
+		.map_err(|e| SaveConfigError::LoadError(e, "creating autosave directory"))?;
+	let oldest = autosave_path(save_path, keep.saturating_sub(1));
+	if oldest.exists() {
+		std::fs::remove_file(&oldest)
+			.map_err(|e| SaveConfigError::LoadError(e, "removing oldest autosave"))?;
+	}
+	for index in (0..keep.saturating_sub(1)).rev() {
+		let from = autosave_path(save_path, index);
+		if from.exists() {
+			std::fs::rename(&from, autosave_path(save_path, index + 1))
+				.map_err(|e| SaveConfigError::LoadError(e, "rotating autosaves"))?;
+		}
+	}
+	Ok(())
+}
+
+/// Where an autosave is written before it is rotated in as `game-0.ron`.
+fn next_autosave_path(save_path: &Path) -> PathBuf {
+	save_path.join(AUTOSAVE_DIRECTORY).join("game-next.ron")
+}
+
+/// Make the fully written `game-next.ron` the newest autosave, rotating out the oldest.
+fn rotate_in(save_path: &Path, keep: u32) -> Result<(), SaveConfigError> {
+	rotate(save_path, keep)?;
+	std::fs::rename(next_autosave_path(save_path), autosave_path(save_path, 0))
+		.map_err(|e| SaveConfigError::LoadError(e, "moving in the new autosave"))
+}
+
+pub(crate) fn autosave(
+	mut turns: EventReader<TurnStarted>,
+	save_config: Res<Option<SaveConfig>>,
+	game: GameData,
+	mut pending: ResMut<Option<PendingAutosave>>,
+) {
+	let save_config = match &*save_config {
+		Some(save_config) => save_config,
+		None => return,
+	};
+	let AutosaveConfig { every_turns, keep } = save_config.autosave;
+	if every_turns == 0 || keep == 0 {
+		return;
+	}
+	if !turns.iter().any(|turn| turn.0 % every_turns == 0) {
+		return;
+	}
+	if pending.is_some() {
+		warn!("Skipping an autosave as the previous one is still being written");
+		return;
+	}
+	let save_path = save_config.save_path().to_owned();
+	if let Err(e) = std::fs::create_dir_all(save_path.join(AUTOSAVE_DIRECTORY)) {
+		error!(
+			"Failed creating the autosave directory in `{:?}`: {}",
+			save_path, e
+		);
+		return;
+	}
+	*pending = Some(PendingAutosave {
+		writer: GameWriter::spawn_to(game.capture(), next_autosave_path(&save_path)),
+		save_path,
+		keep,
+	});
+}
+
+/// Rotate a finished autosave into the window.
+pub(crate) fn finish_autosave(mut pending: ResMut<Option<PendingAutosave>>) {
+	let autosave = match &*pending {
+		Some(autosave) => autosave,
+		None => return,
+	};
+	let result = match autosave.writer.try_finish() {
+		Some(result) => result,
+		None => return,
+	};
+	if let Err(e) = result.and_then(|()| rotate_in(&autosave.save_path, autosave.keep)) {
+		error!("Failed autosaving to `{:?}`: {:?}", autosave.save_path, e);
+	}
+	*pending = None;
+}
+
+#[cfg(test)]
+mod test {
+	use super::{autosave_path, next_autosave_path, rotate_in, AUTOSAVE_DIRECTORY};
+
+	#[test]
+	fn rotation() {
+		let dir =
+			std::env::temp_dir().join(format!("over_civ_autosave_rotation_{}", std::process::id()));
+		std::fs::create_dir_all(dir.join(AUTOSAVE_DIRECTORY)).unwrap();
+		for save in 0..5 {
+			std::fs::write(next_autosave_path(&dir), save.to_string()).unwrap();
+			rotate_in(&dir, 3).unwrap();
+		}
+		let contents: Vec<String> = (0..4)
+			.map(|index| std::fs::read_to_string(autosave_path(&dir, index)).unwrap_or_default())
+			.collect();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(contents, vec!["4", "3", "2", ""]);
+	}
+}
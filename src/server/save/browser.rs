@@ -0,0 +1,229 @@
+//! Listing and managing the saves in a saves directory without loading them.
+
+use super::game::GAME_FILE_NAME;
+use super::{migration, SaveConfig, SaveConfigError};
+use crate::server::game::player::Players;
+use crate::server::game::GameTurn;
+use crate::universal::local_server::{
+	LocalServerPublicState, LocalServerSaveCommand, LocalServerSaveList, SaveInfo, SAVES_DIRECTORY,
+};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Only the parts of a game file that are shown in the save list, the rest is skipped.
+#[derive(Deserialize)]
+struct GameSummary {
+	turn: GameTurn,
+	players: Players,
+	map: MapSummary,
+}
+
+#[derive(Deserialize)]
+struct MapSummary {
+	width: u32,
+	height: u32,
+}
+
+/// Summarize the save in a directory, `None` if it is not a save at all.
+pub fn save_info(path: &Path) -> Option<SaveInfo> {
+	let config_path = path.join("config.ron");
+	if !config_path.is_file() {
+		return None;
+	}
+	let game_path = path.join(GAME_FILE_NAME);
+	let last_played = std::fs::metadata(&game_path)
+		.or_else(|_| std::fs::metadata(&config_path))
+		.and_then(|m| m.modified())
+		.ok();
+	let mut info = SaveInfo {
+		name: path.file_name()?.to_string_lossy().into_owned(),
+		path: path.to_owned(),
+		last_played,
+		turn: None,
+		civ: None,
+		map_size: None,
+		compatible: false,
+	};
+	let config = match SaveConfig::load_path(path) {
+		Ok(config) => config,
+		Err(e) => {
+			warn!("Save at `{:?}` has an unloadable config: {}", path, e);
+			return Some(info);
+		}
+	};
+	if game_path.is_file() {
+		let summary = std::fs::read_to_string(&game_path)
+			.map_err(|e| SaveConfigError::LoadError(e, "reading game file"))
+			.and_then(|data| {
+				migration::load::<GameSummary>(data, GAME_FILE_NAME, migration::GAME_MIGRATIONS)
+			});
+		match summary {
+			Ok(summary) => {
+				info.turn = Some(summary.turn.0);
+				info.civ = summary.players.iter().next().map(|p| p.civ.to_string());
+				info.map_size = Some((summary.map.width, summary.map.height));
+				info.compatible = true;
+			}
+			Err(e) => warn!("Save at `{:?}` has an unloadable game: {}", path, e),
+		}
+	} else {
+		info.civ = config.players.first().map(|p| p.civ.to_string());
+		info.map_size = Some((config.map.width, config.map.height));
+		info.compatible = true;
+	}
+	Some(info)
+}
+
+/// Every save in the saves directory, most recently played first.
+pub fn scan_saves(root: &Path) -> Vec<SaveInfo> {
+	let entries = match std::fs::read_dir(root) {
+		Ok(entries) => entries,
+		Err(e) => {
+			if root.exists() {
+				warn!("Failed reading saves directory `{:?}`: {}", root, e);
+			}
+			return vec![];
+		}
+	};
+	let mut saves: Vec<SaveInfo> = entries
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.path().is_dir())
+		.filter_map(|entry| save_info(&entry.path()))
+		.collect();
+	saves.sort_by(|a, b| {
+		b.last_played
+			.cmp(&a.last_played)
+			.then_with(|| a.name.cmp(&b.name))
+	});
+	saves
+}
+
+/// Delete a save directory, refusing anything that does not look like a save.
+pub fn delete_save(path: &Path) -> Result<(), SaveConfigError> {
+	if !path.join("config.ron").is_file() {
+		return Err(SaveConfigError::InvalidSave(path.to_owned()));
+	}
+	std::fs::remove_dir_all(path).map_err(|e| SaveConfigError::LoadError(e, "deleting save"))
+}
+
+/// Copy a save to a new directory next to it, returning the path of the copy.
+pub fn duplicate_save(path: &Path) -> Result<PathBuf, SaveConfigError> {
+	let name = match (path.file_name(), path.parent()) {
+		(Some(name), Some(_)) if path.join("config.ron").is_file() => name.to_string_lossy(),
+		_ => return Err(SaveConfigError::InvalidSave(path.to_owned())),
+	};
+	let target = (1..)
+		.map(|n| path.with_file_name(format!("{}-copy-{}", name, n)))
+		.find(|target| !target.exists())
+		.expect("ran out of save names");
+	copy_dir(path, &target).map_err(|e| SaveConfigError::LoadError(e, "duplicating save"))?;
+	Ok(target)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+	std::fs::create_dir_all(to)?;
+	for entry in std::fs::read_dir(from)? {
+		let entry = entry?;
+		let target = to.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			copy_dir(&entry.path(), &target)?;
+		} else {
+			std::fs::copy(entry.path(), target)?;
+		}
+	}
+	Ok(())
+}
+
+pub(crate) fn on_save_cmd(
+	mut cmds: EventReader<LocalServerSaveCommand>,
+	mut save_list: EventWriter<LocalServerSaveList>,
+	public_state: Res<LocalServerPublicState>,
+	save_config: Res<Option<SaveConfig>>,
+) {
+	let mut changed = false;
+	for cmd in cmds.iter() {
+		changed = true;
+		match cmd {
+			LocalServerSaveCommand::List => {}
+			LocalServerSaveCommand::Delete { path } => {
+				let loaded = *public_state != LocalServerPublicState::Off
+					&& matches!(&*save_config, Some(config) if config.save_path() == path);
+				if loaded {
+					warn!("Cannot delete the loaded save `{:?}`", path);
+				} else if let Err(e) = delete_save(path) {
+					error!("Failed deleting save `{:?}`: {:?}", path, e);
+				} else {
+					info!("Deleted save `{:?}`", path);
+				}
+			}
+			LocalServerSaveCommand::Duplicate { path } => match duplicate_save(path) {
+				Ok(target) => info!("Duplicated save `{:?}` to `{:?}`", path, target),
+				Err(e) => error!("Failed duplicating save `{:?}`: {:?}", path, e),
+			},
+		}
+	}
+	if changed {
+		save_list.send(LocalServerSaveList(scan_saves(Path::new(SAVES_DIRECTORY))));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{delete_save, duplicate_save, scan_saves};
+	use crate::server::game::rules::Rules;
+	use crate::server::save::game::GameSave;
+	use crate::server::save::{SaveConfig, SaveLoadState};
+
+	#[test]
+	fn browse() {
+		let root =
+			std::env::temp_dir().join(format!("over_civ_save_browser_{}", std::process::id()));
+		let config = match SaveConfig::load_or_create_path(root.join("first")).unwrap() {
+			SaveLoadState::Created(config) => config,
+			SaveLoadState::Existing(_) => panic!("save should be new"),
+		};
+		let mut game = GameSave::new_game(
+			&SaveConfig {
+				map: crate::server::world::generator::MapGenerationConfig {
+					width: 12,
+					height: 8,
+					..Default::default()
+				},
+				..config.clone()
+			},
+			&Rules::bundled(),
+		);
+		game.turn.0 = 7;
+		game.write(config.save_path()).unwrap();
+		SaveConfig::load_or_create_path(root.join("second")).unwrap();
+		std::fs::create_dir_all(root.join("not-a-save")).unwrap();
+
+		let copy = duplicate_save(&root.join("first")).unwrap();
+		assert_eq!(copy, root.join("first-copy-1"));
+		let mut saves = scan_saves(&root);
+		saves.sort_by(|a, b| a.name.cmp(&b.name));
+		let names: Vec<&str> = saves.iter().map(|s| s.name.as_str()).collect();
+		assert_eq!(names, vec!["first", "first-copy-1", "second"]);
+		assert_eq!(saves[0].turn, Some(7));
+		assert_eq!(saves[0].map_size, Some((12, 8)));
+		assert_eq!(saves[0].civ.as_deref(), Some("rome"));
+		assert_eq!(saves[1].turn, Some(7));
+		assert_eq!(saves[2].turn, None);
+		assert!(saves.iter().all(|s| s.compatible));
+
+		std::fs::write(root.join("second").join("config.ron"), "(version: 1000)").unwrap();
+		delete_save(&copy).unwrap();
+		assert!(delete_save(&root.join("not-a-save")).is_err());
+		let saves = scan_saves(&root);
+		std::fs::remove_dir_all(&root).unwrap();
+		assert_eq!(saves.len(), 2);
+		assert!(
+			!saves
+				.iter()
+				.find(|s| s.name == "second")
+				.unwrap()
+				.compatible
+		);
+	}
+}
@@ -0,0 +1,410 @@
+//! The state of a whole game, stored as `game.ron` next to the `config.ron` of a save.
+
+use super::{migration, SaveConfig, SaveConfigError};
+use crate::server::game::city::City;
+use crate::server::game::player::{Player, PlayerId, Players};
+use crate::server::game::production::{buildings_by_city, Building};
+use crate::server::game::rules::Rules;
+use crate::server::game::unit::Unit;
+use crate::server::game::vision::Visions;
+use crate::server::game::{GameEntity, GameIds, GameRng, GameTurn, Wars};
+use crate::server::turns::{TurnManager, TurnProgress};
+use crate::server::world::{generator, WorldMap};
+use crate::universal::commands::state::{GameState, TurnStatus};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+pub const GAME_FILE_NAME: &str = "game.ron";
+
+/// Mixed into the map seed so the game RNG does not repeat the map generator's sequence.
+const GAME_RNG_SALT: u64 = 0x0BE5_C1F1_6A3E_5EED;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSave {
+	/// Format version this was written with, always the current version once loaded.
+	#[serde(skip_deserializing, default = "migration::game_version")]
+	version: u32,
+	pub turn: GameTurn,
+	pub rng: GameRng,
+	pub ids: GameIds,
+	pub map: WorldMap,
+	pub players: Players,
+	pub units: Vec<Unit>,
+	pub cities: Vec<City>,
+	/// Games from before production have no buildings.
+	#[serde(default)]
+	pub buildings: Vec<Building>,
+	/// What every player has seen, games from before fog of war start with nothing seen.
+	#[serde(default)]
+	pub visions: Visions,
+	/// Games from before wars start at peace.
+	#[serde(default)]
+	pub wars: Wars,
+	/// Games from before the turn manager start the turn over.
+	#[serde(default)]
+	pub turn_progress: TurnProgress,
+}
+
+impl GameSave {
+	/// A new game as set up by the save configuration, this generates the map so it can be slow.
+	/// Every player starts with the start units of the rules, on a map with the resources of the
+	/// rules scattered over it.
+	pub fn new_game(config: &SaveConfig, rules: &Rules) -> Self {
+		let players: Vec<Player> = config
+			.players
+			.iter()
+			.enumerate()
+			.map(|(idx, player)| {
+				Player::new(
+					PlayerId(idx as u32),
+					player.name.clone(),
+					player.civ.clone(),
+				)
+			})
+			.collect();
+		let mut rng = Pcg64::seed_from_u64(config.map.seed ^ GAME_RNG_SALT);
+		let mut map = generator::generate(&config.map);
+		rules.place_resources(&mut map, &mut rng);
+		let mut ids = GameIds::default();
+		let mut units = vec![];
+		let starts = generator::start_positions(&map, players.len());
+		for (player, start) in players.iter().zip(starts) {
+			for kind in &rules.start_units {
+				units.push(Unit::new(ids.next_unit(), player.id, kind, start, rules));
+			}
+		}
+		GameSave {
+			version: migration::game_version(),
+			turn: GameTurn::default(),
+			rng: GameRng(rng),
+			ids,
+			map,
+			players: Players(players),
+			units,
+			cities: vec![],
+			buildings: vec![],
+			visions: Visions::default(),
+			wars: Wars::default(),
+			turn_progress: TurnProgress::default(),
+		}
+	}
+
+	/// If the save directory has a game in it, otherwise a new game needs to be started.
+	pub fn exists(save_path: &Path) -> bool {
+		save_path.join(GAME_FILE_NAME).is_file()
+	}
+
+	/// Read the game from the save directory, `progress` is called with the completion from 0.0
+	/// to 1.0 as it reads.
+	pub fn read(save_path: &Path, mut progress: impl FnMut(f64)) -> Result<Self, SaveConfigError> {
+		let mut file = std::fs::File::open(save_path.join(GAME_FILE_NAME))
+			.map_err(|e| SaveConfigError::LoadError(e, "opening game file"))?;
+		let len = file
+			.metadata()
+			.map_err(|e| SaveConfigError::LoadError(e, "reading game file metadata"))?
+			.len()
+			.max(1);
+		let mut data = Vec::with_capacity(len as usize);
+		let mut buffer = [0u8; 64 * 1024];
+		loop {
+			let read = file
+				.read(&mut buffer)
+				.map_err(|e| SaveConfigError::LoadError(e, "reading game file"))?;
+			if read == 0 {
+				break;
+			}
+			data.extend_from_slice(&buffer[..read]);
+			// Reading is most of the time spent, parsing is the rest.
+			progress(data.len() as f64 / len as f64 * 0.8);
+		}
+		let data = String::from_utf8(data).map_err(|e| {
+			SaveConfigError::LoadError(
+				std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+				"decoding game file",
+			)
+		})?;
+		let game = migration::load(data, GAME_FILE_NAME, migration::GAME_MIGRATIONS)?;
+		progress(1.0);
+		Ok(game)
+	}
+
+	/// Write the game into the save directory.
+	pub fn write(&self, save_path: &Path) -> Result<(), SaveConfigError> {
+		self.write_to(&save_path.join(GAME_FILE_NAME))?;
+		info!("Saved game to: {:?}", save_path);
+		Ok(())
+	}
+
+	/// Write the game to a file, a previous file is only replaced once the new one is fully written.
+	pub fn write_to(&self, path: &Path) -> Result<(), SaveConfigError> {
+		let data = ron::ser::to_string(self)?;
+		let tmp_path = path.with_extension("ron.tmp");
+		std::fs::write(&tmp_path, data)
+			.map_err(|e| SaveConfigError::LoadError(e, "writing game file"))?;
+		std::fs::rename(&tmp_path, path)
+			.map_err(|e| SaveConfigError::LoadError(e, "replacing game file"))?;
+		Ok(())
+	}
+
+	/// Insert this game into the world, the previous game must have already been unloaded.
+	pub fn restore(self, commands: &mut Commands) {
+		commands.insert_resource(self.turn);
+		commands.insert_resource(self.rng);
+		commands.insert_resource(self.ids);
+		commands.insert_resource(self.map);
+		commands.insert_resource(self.players);
+		commands.insert_resource(self.visions);
+		commands.insert_resource(self.wars);
+		commands.insert_resource(self.turn_progress);
+		commands.insert_resource(TurnManager::default());
+		commands.insert_resource(TurnStatus::default());
+		for unit in self.units {
+			commands.spawn().insert(unit).insert(GameEntity);
+		}
+		for city in self.cities {
+			commands.spawn().insert(city).insert(GameEntity);
+		}
+		for building in self.buildings {
+			commands.spawn().insert(building).insert(GameEntity);
+		}
+	}
+
+	/// Remove the loaded game from the world.
+	pub fn unload(commands: &mut Commands, game_entities: impl Iterator<Item = Entity>) {
+		commands.remove_resource::<GameTurn>();
+		commands.remove_resource::<GameRng>();
+		commands.remove_resource::<GameIds>();
+		commands.remove_resource::<WorldMap>();
+		commands.remove_resource::<Players>();
+		commands.remove_resource::<Visions>();
+		commands.remove_resource::<Wars>();
+		commands.remove_resource::<TurnProgress>();
+		commands.remove_resource::<TurnManager>();
+		commands.remove_resource::<TurnStatus>();
+		for entity in game_entities {
+			commands.entity(entity).despawn();
+		}
+	}
+}
+
+/// Access to everything of the loaded game that is saved.
+#[derive(SystemParam)]
+pub struct GameData<'a> {
+	turn: Res<'a, GameTurn>,
+	rng: Res<'a, GameRng>,
+	ids: Res<'a, GameIds>,
+	map: Res<'a, WorldMap>,
+	players: Res<'a, Players>,
+	units: Query<'a, &'static Unit>,
+	cities: Query<'a, &'static City>,
+	buildings: Query<'a, &'static Building>,
+	visions: Res<'a, Visions>,
+	wars: Res<'a, Wars>,
+	turn_progress: Res<'a, TurnProgress>,
+	/// Not saved, but part of the state sent to clients.
+	turn_status: Res<'a, TurnStatus>,
+	rules: Res<'a, Rules>,
+}
+
+impl<'a> GameData<'a> {
+	pub fn capture(&self) -> GameSave {
+		let mut units: Vec<Unit> = self.units.iter().cloned().collect();
+		units.sort_by_key(|u| u.id);
+		let mut cities: Vec<City> = self.cities.iter().cloned().collect();
+		cities.sort_by_key(|c| c.id);
+		let mut buildings: Vec<Building> = self.buildings.iter().cloned().collect();
+		buildings.sort_by(|a, b| (a.city, &a.kind).cmp(&(b.city, &b.kind)));
+		GameSave {
+			version: migration::game_version(),
+			turn: *self.turn,
+			rng: self.rng.clone(),
+			ids: self.ids.clone(),
+			map: self.map.clone(),
+			players: self.players.clone(),
+			units,
+			cities,
+			buildings,
+			visions: self.visions.clone(),
+			wars: self.wars.clone(),
+			turn_progress: self.turn_progress.clone(),
+		}
+	}
+
+	pub fn players(&self) -> &Players {
+		&self.players
+	}
+
+	/// The game state as `player` is allowed to see it, `is_away` tells which players are away.
+	pub fn state_for(&self, player: PlayerId, is_away: impl Fn(PlayerId) -> bool) -> GameState {
+		let units: Vec<&Unit> = self.units.iter().collect();
+		let cities: Vec<&City> = self.cities.iter().collect();
+		let mut state = self.visions.state_for(
+			player,
+			self.turn.0,
+			&self.map,
+			self.players
+				.iter()
+				.map(|p| p.info(is_away(p.id), player))
+				.collect(),
+			&units,
+			&cities,
+		);
+		state.turn_status = (*self.turn_status).clone();
+		let buildings = buildings_by_city(self.buildings.iter());
+		for info in state.cities.iter_mut().filter(|c| c.owner == player) {
+			if let Some(city) = self.cities.iter().find(|c| c.id == info.id) {
+				let city_buildings = buildings.get(&city.id).map_or(&[][..], Vec::as_slice);
+				info.details = Some(city.details(&self.map, &self.rules, city_buildings));
+			}
+		}
+		state
+	}
+
+	/// Capture the game and write it into the save directory of the configuration.
+	pub fn save(&self, save_config: &SaveConfig) -> Result<(), SaveConfigError> {
+		self.capture().write(save_config.save_path())
+	}
+}
+
+/// Loads the game of a save on a background thread, or creates a new game if the save has none.
+pub struct GameLoader {
+	progress: Arc<AtomicU64>,
+	result: Mutex<Receiver<Result<GameSave, SaveConfigError>>>,
+}
+
+impl GameLoader {
+	pub fn spawn(config: SaveConfig, rules: Rules) -> Self {
+		let progress = Arc::new(AtomicU64::new(0.0f64.to_bits()));
+		let (sender, receiver) = std::sync::mpsc::channel();
+		let thread_progress = progress.clone();
+		std::thread::spawn(move || {
+			let set_progress = |completion: f64| {
+				thread_progress.store(completion.to_bits(), Ordering::Relaxed);
+			};
+			let result = if GameSave::exists(config.save_path()) {
+				info!("Loading game from: {:?}", config.save_path());
+				GameSave::read(config.save_path(), set_progress)
+			} else {
+				info!("Creating new game with map seed {}", config.map.seed);
+				let game = GameSave::new_game(&config, &rules);
+				set_progress(1.0);
+				Ok(game)
+			};
+			// If the receiver is gone then the load was cancelled, nothing to do.
+			let _ = sender.send(result);
+		});
+		Self {
+			progress,
+			result: Mutex::new(receiver),
+		}
+	}
+
+	/// Completion from 0.0 to 1.0.
+	pub fn progress(&self) -> f64 {
+		f64::from_bits(self.progress.load(Ordering::Relaxed))
+	}
+
+	/// The loaded game once the background thread is done.
+	pub fn try_finish(&self) -> Option<Result<GameSave, SaveConfigError>> {
+		match self
+			.result
+			.lock()
+			.expect("poisoned GameLoader lock")
+			.try_recv()
+		{
+			Ok(result) => Some(result),
+			Err(TryRecvError::Empty) => None,
+			Err(TryRecvError::Disconnected) => Some(Err(SaveConfigError::ThreadStopped)),
+		}
+	}
+}
+
+/// Writes a captured game into a save directory on a background thread, so a large game does not
+/// stall the update it was saved in.
+pub struct GameWriter {
+	result: Mutex<Receiver<Result<(), SaveConfigError>>>,
+}
+
+impl GameWriter {
+	pub fn spawn(game: GameSave, save_path: PathBuf) -> Self {
+		Self::spawn_with(move || game.write(&save_path))
+	}
+
+	/// Write the game to a file rather than into a save directory.
+	pub fn spawn_to(game: GameSave, path: PathBuf) -> Self {
+		Self::spawn_with(move || game.write_to(&path))
+	}
+
+	fn spawn_with(write: impl FnOnce() -> Result<(), SaveConfigError> + Send + 'static) -> Self {
+		let (sender, receiver) = std::sync::mpsc::channel();
+		std::thread::spawn(move || {
+			let _ = sender.send(write());
+		});
+		Self {
+			result: Mutex::new(receiver),
+		}
+	}
+
+	/// The result of the write once the background thread is done.
+	pub fn try_finish(&self) -> Option<Result<(), SaveConfigError>> {
+		match self
+			.result
+			.lock()
+			.expect("poisoned GameWriter lock")
+			.try_recv()
+		{
+			Ok(result) => Some(result),
+			Err(TryRecvError::Empty) => None,
+			Err(TryRecvError::Disconnected) => Some(Err(SaveConfigError::ThreadStopped)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::GameSave;
+	use crate::server::game::rules::Rules;
+	use crate::server::game::unit::Unit;
+	use crate::server::save::SaveConfig;
+	use crate::server::world::Hex;
+
+	#[test]
+	fn round_trip() {
+		let mut config = SaveConfig::default();
+		config.map.seed = 3;
+		config.map.width = 16;
+		config.map.height = 10;
+		let rules = Rules::bundled();
+		let mut game = GameSave::new_game(&config, &rules);
+		assert_eq!(game.units.len(), 4);
+		let owner = game.players.0[1].id;
+		let id = game.ids.next_unit();
+		let mut unit = Unit::new(id, owner, "scout", Hex::from_offset(3, 4), &rules);
+		unit.waypoints = vec![Hex::from_offset(5, 4), Hex::from_offset(5, 6)];
+		unit.health = 40;
+		game.units.push(unit);
+		game.turn.0 = 12;
+
+		let dir =
+			std::env::temp_dir().join(format!("over_civ_game_round_trip_{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		game.write(&dir).unwrap();
+		let mut last_progress = 0.0;
+		let loaded = GameSave::read(&dir, |p| {
+			assert!(p >= last_progress);
+			last_progress = p;
+		})
+		.unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(last_progress, 1.0);
+		assert_eq!(loaded, game);
+	}
+}
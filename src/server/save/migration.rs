@@ -0,0 +1,136 @@
+//! Save files carry the format version they were written with.  Older files are upgraded when
+//! loaded by running them through every registered migration since their version, oldest first,
+//! before they are parsed.
+//!
+//! Migrations work on the ron text so that each one only needs to understand the format it
+//! upgrades from, not the current one.
+
+use super::SaveConfigError;
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+pub struct Migration {
+	/// What changed in this version, logged when a save is migrated.
+	pub description: &'static str,
+	pub migrate: fn(String) -> Result<String, SaveConfigError>,
+}
+
+/// Migrations of `config.ron`, the migration at index N upgrades version N to N + 1 so the current
+/// version is the length of this list.
+pub const CONFIG_MIGRATIONS: &[Migration] = &[Migration {
+	description: "add the format version",
+	migrate: unversioned,
+}];
+
+/// Migrations of `game.ron`, indexed the same way as `CONFIG_MIGRATIONS`.
+pub const GAME_MIGRATIONS: &[Migration] = &[Migration {
+	description: "add the format version",
+	migrate: unversioned,
+}];
+
+pub fn config_version() -> u32 {
+	CONFIG_MIGRATIONS.len() as u32
+}
+
+pub fn game_version() -> u32 {
+	GAME_MIGRATIONS.len() as u32
+}
+
+/// Version 0 is every save from before versioning, it is the same as version 1 without the
+/// version field.
+fn unversioned(data: String) -> Result<String, SaveConfigError> {
+	Ok(data)
+}
+
+/// Only the version of a save file, everything else in it is skipped.
+#[derive(Deserialize)]
+struct VersionProbe {
+	#[serde(default)]
+	version: u32,
+}
+
+/// Parse a save file, migrating it up to the current version first if it is older.
+pub fn load<T: DeserializeOwned>(
+	mut data: String,
+	file: &'static str,
+	migrations: &[Migration],
+) -> Result<T, SaveConfigError> {
+	let found = ron::de::from_str::<VersionProbe>(&data)?.version;
+	let supported = migrations.len() as u32;
+	if found > supported {
+		return Err(SaveConfigError::NewerVersion {
+			file,
+			found,
+			supported,
+		});
+	}
+	for (version, migration) in migrations.iter().enumerate().skip(found as usize) {
+		info!(
+			"Migrating {} from version {} to {}: {}",
+			file,
+			version,
+			version + 1,
+			migration.description
+		);
+		data = (migration.migrate)(data)?;
+	}
+	Ok(ron::de::from_str(&data)?)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{load, Migration};
+	use crate::server::save::SaveConfigError;
+	use serde::Deserialize;
+
+	#[derive(Debug, PartialEq, Deserialize)]
+	struct Data {
+		name: String,
+		size: u32,
+	}
+
+	const MIGRATIONS: &[Migration] = &[
+		Migration {
+			description: "rename title to name",
+			migrate: |data| Ok(data.replace("title:", "name:")),
+		},
+		Migration {
+			description: "add size",
+			migrate: |data| Ok(data.replacen('(', "(size: 4, ", 1)),
+		},
+	];
+
+	#[test]
+	fn migrate() {
+		let expected = Data {
+			name: "test".to_owned(),
+			size: 4,
+		};
+		// Unversioned files are version 0
+		let data: Data = load("(title: \"test\")".to_owned(), "test", MIGRATIONS).unwrap();
+		assert_eq!(data, expected);
+		let data: Data = load(
+			"(version: 1, name: \"test\")".to_owned(),
+			"test",
+			MIGRATIONS,
+		)
+		.unwrap();
+		assert_eq!(data, expected);
+		let data: Data = load(
+			"(version: 2, name: \"test\", size: 4)".to_owned(),
+			"test",
+			MIGRATIONS,
+		)
+		.unwrap();
+		assert_eq!(data, expected);
+		match load::<Data>("(version: 3)".to_owned(), "test", MIGRATIONS) {
+			Err(SaveConfigError::NewerVersion {
+				found: 3,
+				supported: 2,
+				..
+			}) => {}
+			other => panic!("expected NewerVersion, got {:?}", other),
+		}
+	}
+}
@@ -0,0 +1,160 @@
+pub mod autosave;
+pub mod browser;
+pub mod game;
+pub mod migration;
+
+use crate::server::sessions::ReconnectConfig;
+use crate::server::turns::TurnConfig;
+use crate::server::world::generator::MapGenerationConfig;
+use autosave::AutosaveConfig;
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveConfig {
+	/// Format version this was written with, always the current version once loaded.
+	#[serde(skip_deserializing, default = "migration::config_version")]
+	version: u32,
+	#[serde(skip)]
+	save_path: PathBuf,
+	/// Settings used to generate the map when the game is first started.
+	pub map: MapGenerationConfig,
+	/// The players of a new game, in turn order.
+	pub players: Vec<PlayerConfig>,
+	pub autosave: AutosaveConfig,
+	/// How long the seat of a player whose connection dropped is kept for them.
+	pub reconnect: ReconnectConfig,
+	/// How the players take their turns.
+	pub turns: TurnConfig,
+}
+
+impl Default for SaveConfig {
+	fn default() -> Self {
+		Self {
+			version: migration::config_version(),
+			save_path: PathBuf::default(),
+			map: MapGenerationConfig::default(),
+			players: vec![
+				PlayerConfig {
+					name: "Player 1".to_owned(),
+					civ: "rome".into(),
+				},
+				PlayerConfig {
+					name: "Player 2".to_owned(),
+					civ: "egypt".into(),
+				},
+			],
+			autosave: AutosaveConfig::default(),
+			reconnect: ReconnectConfig::default(),
+			turns: TurnConfig::default(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerConfig {
+	pub name: String,
+	pub civ: SmolStr,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SaveConfigError {
+	#[error("IO error while {1}")]
+	LoadError(#[source] std::io::Error, &'static str),
+	#[error("non-valid save at path: {0:?}")]
+	InvalidSave(PathBuf),
+	#[error("ron format error")]
+	RonError(#[from] ron::Error),
+	#[error("{file} has format version {found} but only up to version {supported} is supported")]
+	NewerVersion {
+		file: &'static str,
+		found: u32,
+		supported: u32,
+	},
+	#[error("the background save thread stopped without a result")]
+	ThreadStopped,
+}
+
+pub enum SaveLoadState {
+	Created(SaveConfig),
+	Existing(SaveConfig),
+}
+
+impl SaveConfig {
+	/// The save directory this configuration was loaded from.
+	pub fn save_path(&self) -> &Path {
+		&self.save_path
+	}
+
+	pub fn load_path(path: impl AsRef<Path>) -> Result<SaveConfig, SaveConfigError> {
+		let path = path.as_ref();
+		let config_path = path.to_owned().join("config.ron");
+		let config_string = std::fs::read_to_string(&config_path)
+			.map_err(|e| SaveConfigError::LoadError(e, "reading config.ron file"))?;
+		let mut save_config: SaveConfig =
+			migration::load(config_string, "config.ron", migration::CONFIG_MIGRATIONS)?;
+		save_config.save_path = path.to_owned();
+		trace!("Loaded a SaveConfig at: {:?}", path);
+		Ok(save_config)
+	}
+
+	pub fn load_or_create_path(path: impl AsRef<Path>) -> Result<SaveLoadState, SaveConfigError> {
+		let path = path.as_ref();
+		let config_path = path.join("config.ron");
+		if config_path.exists() {
+			// A save that fails to load is reported as is rather than written over
+			return Self::load_path(path).map(SaveLoadState::Existing);
+		}
+		if !path.is_dir() {
+			std::fs::create_dir_all(&path)
+				.map_err(|e| SaveConfigError::LoadError(e, "creating save directory"))?;
+		}
+
+		let empty_config = SaveConfig {
+			save_path: path.to_owned(),
+			..Default::default()
+		};
+		let config_string = ron::ser::to_string_pretty(
+			&empty_config,
+			PrettyConfig::new()
+				.with_new_line("\n".to_owned())
+				.with_indentor("\t".to_owned()),
+		)? + "\n";
+
+		info!("Writing a new SaveConfig to: {:?}", &config_path);
+		std::fs::write(&config_path, config_string)
+			.map_err(|e| SaveConfigError::LoadError(e, "writing empty configuration"))?;
+
+		Ok(SaveLoadState::Created(empty_config))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{SaveConfig, SaveConfigError};
+
+	#[test]
+	fn load_or_create_newer_version() {
+		let path =
+			std::env::temp_dir().join(format!("over_civ_newer_config_{}", std::process::id()));
+		std::fs::create_dir_all(&path).unwrap();
+		std::fs::write(path.join("config.ron"), "(version: 1000)").unwrap();
+		let result = SaveConfig::load_or_create_path(&path);
+		let config = std::fs::read_to_string(path.join("config.ron")).unwrap();
+		std::fs::remove_dir_all(&path).unwrap();
+		match result {
+			Err(SaveConfigError::NewerVersion {
+				file: "config.ron",
+				found: 1000,
+				..
+			}) => {}
+			Err(e) => panic!("expected NewerVersion, got {:?}", e),
+			Ok(_) => panic!("newer save should not load"),
+		}
+		assert_eq!(config, "(version: 1000)");
+	}
+}
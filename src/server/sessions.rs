@@ -0,0 +1,176 @@
+//! Sessions keep a player's seat for a client whose connection dropped so that it can take it back
+//! with its `ResumeToken` when it reconnects, instead of losing its place in a long game.  The seat
+//! is kept for the grace period of the save's `ReconnectConfig`, after which the player has left.
+
+use crate::server::game::player::PlayerId;
+use crate::universal::commands::ResumeToken;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+	/// How long the seat of a dropped player is kept for them, 0 frees it immediately.
+	pub grace_secs: u64,
+	/// What happens to the civ of a player while they are away.
+	pub while_away: AwayControl,
+}
+
+impl Default for ReconnectConfig {
+	fn default() -> Self {
+		Self {
+			grace_secs: 300,
+			while_away: AwayControl::Freeze,
+		}
+	}
+}
+
+impl ReconnectConfig {
+	pub fn grace_period(&self) -> Duration {
+		Duration::from_secs(self.grace_secs)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AwayControl {
+	/// The civ does nothing, the game waits for the player to come back.
+	Freeze,
+	/// The turns of the civ are ended for it so the game goes on without the player, the civ does
+	/// nothing in them.  Configurations from before the rename call this `Ai`.
+	#[serde(alias = "Ai")]
+	SkipTurns,
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+	player: PlayerId,
+	name: String,
+	token: ResumeToken,
+	/// Set while the player's connection is dropped, the seat is freed once this passes.
+	away_until: Option<Instant>,
+}
+
+/// The session of every joined player, kept by `Clients`.
+#[derive(Debug, Default)]
+pub struct Sessions {
+	sessions: Vec<Session>,
+}
+
+impl Sessions {
+	/// Start a session for a player that just joined, replacing any previous one.
+	pub fn start(&mut self, player: PlayerId, name: &str) -> ResumeToken {
+		self.end(player);
+		let token = ResumeToken(rand::random());
+		self.sessions.push(Session {
+			player,
+			name: name.to_owned(),
+			token,
+			away_until: None,
+		});
+		token
+	}
+
+	/// The player left for good.
+	pub fn end(&mut self, player: PlayerId) {
+		self.sessions.retain(|s| s.player != player);
+	}
+
+	/// The player's connection dropped, keep their seat until `until`.
+	pub fn set_away(&mut self, player: PlayerId, until: Instant) {
+		if let Some(session) = self.sessions.iter_mut().find(|s| s.player == player) {
+			session.away_until = Some(until);
+		}
+	}
+
+	/// Take back an away player's seat, returning the player and the name they joined with.
+	pub fn resume(&mut self, token: ResumeToken) -> Option<(PlayerId, String)> {
+		let session = self
+			.sessions
+			.iter_mut()
+			.find(|s| s.token == token && s.away_until.is_some())?;
+		session.away_until = None;
+		Some((session.player, session.name.clone()))
+	}
+
+	/// If the player's seat is kept for them while their connection is dropped.
+	pub fn is_away(&self, player: PlayerId) -> bool {
+		self.sessions
+			.iter()
+			.any(|s| s.player == player && s.away_until.is_some())
+	}
+
+	pub fn away_players(&self) -> impl Iterator<Item = PlayerId> + '_ {
+		self.sessions
+			.iter()
+			.filter(|s| s.away_until.is_some())
+			.map(|s| s.player)
+	}
+
+	/// End the sessions of away players whose grace period passed, returning those players.
+	pub fn expire(&mut self, now: Instant) -> Vec<PlayerId> {
+		let expired: Vec<PlayerId> = self
+			.sessions
+			.iter()
+			.filter(|s| matches!(s.away_until, Some(until) if until <= now))
+			.map(|s| s.player)
+			.collect();
+		for player in &expired {
+			self.end(*player);
+		}
+		expired
+	}
+
+	pub fn clear(&mut self) {
+		self.sessions.clear();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Sessions;
+	use crate::server::game::player::PlayerId;
+	use crate::universal::commands::ResumeToken;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn resume_within_grace_period() {
+		let now = Instant::now();
+		let grace = Duration::from_secs(60);
+		let mut sessions = Sessions::default();
+		let token = sessions.start(PlayerId(0), "Alice");
+		let other = sessions.start(PlayerId(1), "Bob");
+		assert_ne!(token, other);
+
+		// Only an away player can be resumed
+		assert_eq!(sessions.resume(token), None);
+		sessions.set_away(PlayerId(0), now + grace);
+		assert!(sessions.is_away(PlayerId(0)));
+		assert_eq!(
+			sessions.away_players().collect::<Vec<_>>(),
+			vec![PlayerId(0)]
+		);
+		assert_eq!(sessions.resume(ResumeToken(token.0 ^ 1)), None);
+		assert_eq!(
+			sessions.resume(token),
+			Some((PlayerId(0), "Alice".to_owned()))
+		);
+		assert!(!sessions.is_away(PlayerId(0)));
+		assert_eq!(sessions.resume(token), None);
+
+		// The seat is freed once the grace period passes
+		sessions.set_away(PlayerId(1), now + grace);
+		assert!(sessions.expire(now).is_empty());
+		assert_eq!(sessions.expire(now + grace), vec![PlayerId(1)]);
+		sessions.set_away(PlayerId(1), now + grace);
+		assert_eq!(sessions.resume(other), None);
+
+		// Joining again gives a new token
+		let again = sessions.start(PlayerId(0), "Alice");
+		sessions.set_away(PlayerId(0), now + grace);
+		assert_eq!(sessions.resume(token), None);
+		assert_eq!(
+			sessions.resume(again),
+			Some((PlayerId(0), "Alice".to_owned()))
+		);
+	}
+}
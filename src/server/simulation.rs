@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+/// Default simulation rate of a running server, in ticks per second.
+pub const DEFAULT_TICKS_PER_SECOND: u32 = 20;
+
+/// Maximum number of ticks that will be simulated in a single update, if the server falls further
+/// behind than this then the remaining time is dropped instead of trying to catch up forever.
+const MAX_TICKS_PER_UPDATE: u32 = 5;
+
+/// The fixed-rate clock of the server simulation, it only advances while the server is `Running`.
+pub struct SimulationClock {
+	step: Duration,
+	accumulated: Duration,
+	tick: u64,
+}
+
+impl Default for SimulationClock {
+	fn default() -> Self {
+		Self::with_ticks_per_second(DEFAULT_TICKS_PER_SECOND)
+	}
+}
+
+impl SimulationClock {
+	pub fn with_ticks_per_second(ticks_per_second: u32) -> Self {
+		Self {
+			step: Duration::from_secs(1) / ticks_per_second.max(1),
+			accumulated: Duration::default(),
+			tick: 0,
+		}
+	}
+
+	/// The duration of a single simulation tick.
+	pub fn step(&self) -> Duration {
+		self.step
+	}
+
+	/// The number of ticks simulated since the server was loaded.
+	pub fn tick(&self) -> u64 {
+		self.tick
+	}
+
+	/// Reset the clock back to tick 0, as is done when a new game is loaded.
+	pub fn reset(&mut self) {
+		self.accumulated = Duration::default();
+		self.tick = 0;
+	}
+
+	/// Add the elapsed time to the clock and return the range of ticks that should be simulated.
+	fn advance(&mut self, delta: Duration) -> std::ops::Range<u64> {
+		self.accumulated += delta;
+		let first = self.tick;
+		while self.accumulated >= self.step {
+			self.accumulated -= self.step;
+			if self.tick - first < MAX_TICKS_PER_UPDATE as u64 {
+				self.tick += 1;
+			} else {
+				warn!(
+					"Simulation is falling behind, dropping {:?} of simulation time",
+					self.accumulated + self.step
+				);
+				self.accumulated = Duration::default();
+			}
+		}
+		first..self.tick
+	}
+}
+
+/// Event sent once for every fixed-rate simulation step while the server is `Running`, the value is
+/// the tick number being simulated.  Systems that advance the game over time should read this
+/// instead of using `Time` directly so they freeze while the server is paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulationTick(pub u64);
+
+pub(crate) fn advance_simulation(
+	time: Res<Time>,
+	mut clock: ResMut<SimulationClock>,
+	mut ticks: EventWriter<SimulationTick>,
+) {
+	for tick in clock.advance(time.delta()) {
+		ticks.send(SimulationTick(tick));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::SimulationClock;
+	use std::time::Duration;
+
+	#[test]
+	fn advance() {
+		let mut clock = SimulationClock::with_ticks_per_second(10);
+		assert_eq!(clock.advance(Duration::from_millis(50)), 0..0);
+		assert_eq!(clock.advance(Duration::from_millis(50)), 0..1);
+		assert_eq!(clock.advance(Duration::from_millis(250)), 1..3);
+		assert_eq!(clock.advance(Duration::from_millis(50)), 3..4);
+		// Falling far behind only simulates up to the per-update limit
+		assert_eq!(clock.advance(Duration::from_secs(10)), 4..9);
+		assert_eq!(clock.advance(Duration::from_millis(10)), 9..9);
+		clock.reset();
+		assert_eq!(clock.tick(), 0);
+	}
+}
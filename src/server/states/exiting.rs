@@ -1,7 +1,10 @@
+use crate::server::save::game::GameWriter;
+use crate::universal::exit::Exiting;
 use bevy::prelude::*;
 
 pub fn register_systems(app: &mut AppBuilder) {
 	let state = super::ServerState::Exiting;
+	app.init_resource::<Option<GameWriter>>();
 	app.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
 		.add_system_set(SystemSet::on_update(state.clone()).with_system(on_update.system()))
 		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
@@ -11,8 +14,26 @@ fn on_enter() {
 	trace!("Server Exiting State: Enter");
 }
 
-fn on_update() {
+/// Hold off the exit until a game being saved on the way out is fully written.
+fn on_update(exiting: Option<ResMut<Exiting>>, mut pending_save: ResMut<Option<GameWriter>>) {
 	trace!("Server Exiting State: Update");
+	let result = match &*pending_save {
+		Some(writer) => writer.try_finish(),
+		None => return,
+	};
+	match result {
+		None => {
+			if let Some(mut exiting) = exiting {
+				exiting.delay();
+			}
+		}
+		Some(result) => {
+			if let Err(e) = result {
+				error!("Failed saving game while exiting: {:?}", e);
+			}
+			*pending_save = None;
+		}
+	}
 }
 
 fn on_exit() {
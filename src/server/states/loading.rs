@@ -1,10 +1,14 @@
+use crate::server::game::ruleset::LoadedRules;
+use crate::server::save::game::GameLoader;
 use crate::server::save::SaveConfig;
+use crate::server::simulation::SimulationClock;
 use crate::universal::exit::Exiting;
 use crate::universal::local_server::{LocalServerCommand, LocalServerPublicState};
 use bevy::prelude::*;
 
 pub fn register_systems(app: &mut AppBuilder) {
 	let state = super::ServerState::Loading;
+	app.init_resource::<Option<GameLoader>>();
 	app.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
 		.add_system_set(
 			SystemSet::on_update(state.clone())
@@ -26,18 +30,72 @@ fn on_enter(
 }
 
 fn on_update(
+	mut commands: Commands,
 	mut public_state: ResMut<LocalServerPublicState>,
 	mut update_public_state: EventWriter<LocalServerPublicState>,
+	mut state: ResMut<State<super::ServerState>>,
+	mut loader: ResMut<Option<GameLoader>>,
+	save_config: Res<Option<SaveConfig>>,
+	rules: LoadedRules,
 ) {
 	// trace!("Server Loading State: Update");
-	if let LocalServerPublicState::Loading(completion) = &mut *public_state {
-		*completion += (1.0 - *completion) * 0.01;
+	if loader.is_none() {
+		let config = match &*save_config {
+			Some(config) => config,
+			None => {
+				error!("Server Loading without a save configuration");
+				state
+					.set(super::ServerState::Unloading)
+					.expect("Failed to transition server from Loading to Unloading state");
+				return;
+			}
+		};
+		// The game is only loaded once the rules are
+		match rules.get() {
+			Ok(Some(rules)) => *loader = Some(GameLoader::spawn(config.clone(), rules.clone())),
+			Ok(None) => return,
+			Err(errors) => {
+				error!(
+					"Cannot load a game, the rules have {} problems",
+					errors.len()
+				);
+				state
+					.set(super::ServerState::Unloading)
+					.expect("Failed to transition server from Loading to Unloading state");
+				return;
+			}
+		}
+	}
+	let loader = match &*loader {
+		Some(loader) => loader,
+		None => return,
+	};
+	match loader.try_finish() {
+		None => {
+			*public_state = LocalServerPublicState::Loading(loader.progress());
+		}
+		Some(Ok(game)) => {
+			game.restore(&mut commands);
+			*public_state = LocalServerPublicState::Loading(1.0);
+			state
+				.set(super::ServerState::Running)
+				.expect("Failed to transition server from Loading to Running state");
+		}
+		Some(Err(e)) => {
+			error!("Failed loading the game: {:?}", e);
+			state
+				.set(super::ServerState::Unloading)
+				.expect("Failed to transition server from Loading to Unloading state");
+		}
 	}
 	update_public_state.send(public_state.clone());
 }
 
-fn on_exit() {
+fn on_exit(mut clock: ResMut<SimulationClock>, mut loader: ResMut<Option<GameLoader>>) {
 	trace!("Server Loading State: Exit");
+	// Dropping an unfinished loader abandons its thread, its result is just discarded.
+	*loader = None;
+	clock.reset();
 }
 
 fn on_shutdown(exiting: Option<Res<Exiting>>, mut state: ResMut<State<super::ServerState>>) {
@@ -63,6 +121,11 @@ fn on_server_public_cmd(
 					"Failed transitioning to Server Unloading state from the Loading state",
 				);
 			}
+			LocalServerCommand::PauseServer
+			| LocalServerCommand::ResumeServer
+			| LocalServerCommand::SaveServer => {
+				warn!("`{:?}` requested while the server is still loading", &cmd);
+			}
 		}
 	}
 }
@@ -1,12 +1,17 @@
 mod exiting;
 mod loading;
 mod not_running;
+mod paused;
+mod running;
 mod unloading;
 
+use crate::server::save::game::{GameData, GameWriter};
+use crate::server::save::SaveConfig;
+use crate::universal::exit::Exiting;
 use bevy::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-enum ServerState {
+pub(crate) enum ServerState {
 	NotRunning,
 	Loading,
 	Running,
@@ -26,5 +31,50 @@ impl Plugin for ServerStatePlugin {
 		loading::register_systems(app);
 		unloading::register_systems(app);
 		not_running::register_systems(app);
+		running::register_systems(app);
+		paused::register_systems(app);
+	}
+}
+
+/// Save the loaded game, returns false and logs why if it could not be saved.
+fn save_game(save_config: &Option<SaveConfig>, game: &GameData) -> bool {
+	let save_config = match save_config {
+		Some(save_config) => save_config,
+		None => {
+			error!("Cannot save a game that has no save configuration");
+			return false;
+		}
+	};
+	match game.save(save_config) {
+		Ok(()) => true,
+		Err(e) => {
+			error!(
+				"Failed saving game to `{:?}`: {:?}",
+				save_config.save_path(),
+				e
+			);
+			false
+		}
+	}
+}
+
+/// Start saving the loaded game because the app is exiting, the `Exiting` state delays the exit
+/// until the save is written.
+fn save_on_exit(
+	save_config: &Option<SaveConfig>,
+	game: &GameData,
+	exiting: &mut Exiting,
+	pending_save: &mut Option<GameWriter>,
+) {
+	match save_config {
+		Some(save_config) => {
+			info!("Saving game before exiting");
+			exiting.delay();
+			*pending_save = Some(GameWriter::spawn(
+				game.capture(),
+				save_config.save_path().to_owned(),
+			));
+		}
+		None => error!("Cannot save a game that has no save configuration"),
 	}
 }
@@ -81,6 +81,11 @@ fn on_server_public_cmd(
 			LocalServerCommand::StopServer { force: _ } => {
 				info!("Server Stop requested when server is already not running");
 			}
+			LocalServerCommand::PauseServer
+			| LocalServerCommand::ResumeServer
+			| LocalServerCommand::SaveServer => {
+				warn!("`{:?}` requested when server is not running", &cmd);
+			}
 		}
 	}
 }
@@ -0,0 +1,87 @@
+use crate::server::clients::{expire_sessions, handle_client_messages, receive_client_messages};
+use crate::server::save::game::{GameData, GameWriter};
+use crate::server::save::SaveConfig;
+use crate::universal::exit::Exiting;
+use crate::universal::local_server::{LocalServerCommand, LocalServerPublicState};
+use bevy::prelude::*;
+
+/// The Paused state is pushed on top of the Running state, so the Running state's update systems,
+/// and thus the simulation tick, do not run until this state is popped again.
+pub fn register_systems(app: &mut AppBuilder) {
+	let state = super::ServerState::Paused;
+	app.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+		.add_system_set(
+			SystemSet::on_update(state.clone())
+				.with_system(receive_client_messages.system())
+				.with_system(handle_client_messages.system())
+				.with_system(expire_sessions.system())
+				.with_system(on_server_public_cmd.system())
+				.with_system(on_shutdown.system()),
+		)
+		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
+}
+
+fn on_enter(
+	mut public_state: ResMut<LocalServerPublicState>,
+	mut update_public_state: EventWriter<LocalServerPublicState>,
+) {
+	trace!("Server Paused State: Enter");
+	*public_state = LocalServerPublicState::Paused;
+	update_public_state.send(public_state.clone());
+}
+
+fn on_exit() {
+	trace!("Server Paused State: Exit");
+}
+
+fn on_shutdown(
+	exiting: Option<ResMut<Exiting>>,
+	mut state: ResMut<State<super::ServerState>>,
+	save_config: Res<Option<SaveConfig>>,
+	game: GameData,
+	mut pending_save: ResMut<Option<GameWriter>>,
+) {
+	if let Some(mut exiting) = exiting {
+		super::save_on_exit(&save_config, &game, &mut exiting, &mut pending_save);
+		state
+			.overwrite_replace(super::ServerState::Exiting)
+			.expect("Failed to transition Server to exiting state");
+	}
+}
+
+fn on_server_public_cmd(
+	mut cmds: EventReader<LocalServerCommand>,
+	mut state: ResMut<State<super::ServerState>>,
+	save_config: Res<Option<SaveConfig>>,
+	game: GameData,
+) {
+	for cmd in cmds.iter() {
+		match cmd {
+			LocalServerCommand::CreateStartServer { .. } => {
+				warn!("requested to CreateStartServer when already running a server");
+			}
+			LocalServerCommand::StopServer { force } => {
+				if !*force && !super::save_game(&save_config, &game) {
+					error!("Not stopping the server as the game failed to save, force the stop to discard it");
+					continue;
+				}
+				info!("Unloading server from within paused state");
+				state
+					.replace(super::ServerState::Unloading)
+					.expect("Failed transitioning to Server Unloading state from the Paused state");
+			}
+			LocalServerCommand::SaveServer => {
+				super::save_game(&save_config, &game);
+			}
+			LocalServerCommand::PauseServer => {
+				info!("Server Pause requested when server is already paused");
+			}
+			LocalServerCommand::ResumeServer => {
+				info!("Resuming server");
+				state
+					.pop()
+					.expect("Failed popping the Server Paused state back to the Running state");
+			}
+		}
+	}
+}
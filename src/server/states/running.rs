@@ -0,0 +1,155 @@
+use crate::server::clients::{
+	expire_sessions, handle_client_messages, notify_players, receive_client_messages,
+};
+use crate::server::game::city::{found_cities, grow_cities, order_cities};
+use crate::server::game::combat::{attack_units, preview_combat};
+use crate::server::game::improvement::build_improvements;
+use crate::server::game::movement::{order_units, preview_paths, start_unit_turns};
+use crate::server::game::production::{build_in_cities, order_production};
+use crate::server::game::research::{order_research, research_techs, send_tech_trees};
+use crate::server::game::territory::expand_borders;
+use crate::server::game::vision::update_visions;
+use crate::server::replication::replicate_state;
+use crate::server::save::autosave::{autosave, finish_autosave, PendingAutosave};
+use crate::server::save::game::{GameData, GameWriter};
+use crate::server::save::SaveConfig;
+use crate::server::simulation::advance_simulation;
+use crate::server::turns::{
+	end_turns, in_turn_end_phase, in_turn_start_phase, start_turn, TurnSystem,
+};
+use crate::universal::exit::Exiting;
+use crate::universal::local_server::{LocalServerCommand, LocalServerPublicState};
+use bevy::prelude::*;
+
+pub fn register_systems(app: &mut AppBuilder) {
+	let state = super::ServerState::Running;
+	app.init_resource::<Option<PendingAutosave>>();
+	app.add_system_set(SystemSet::on_enter(state.clone()).with_system(on_enter.system()))
+		.add_system_set(
+			SystemSet::on_update(state.clone())
+				.with_system(receive_client_messages.system())
+				.with_system(handle_client_messages.system())
+				.with_system(expire_sessions.system())
+				.with_system(end_turns.system().label(TurnSystem::End))
+				.with_system(
+					start_turn
+						.system()
+						.label(TurnSystem::Start)
+						.after(TurnSystem::End),
+				)
+				.with_system(order_units.system().label("order_units"))
+				.with_system(preview_paths.system())
+				.with_system(attack_units.system().after("order_units"))
+				.with_system(preview_combat.system())
+				.with_system(found_cities.system().after("order_units"))
+				.with_system(build_improvements.system().after("order_units"))
+				.with_system(order_cities.system().label("order_cities"))
+				.with_system(order_production.system().after("order_cities"))
+				.with_system(in_turn_end_phase(expand_borders.system()).label("expand_borders"))
+				.with_system(
+					in_turn_end_phase(grow_cities.system())
+						.label("grow_cities")
+						.after("expand_borders"),
+				)
+				.with_system(
+					in_turn_end_phase(build_in_cities.system())
+						.label("build_in_cities")
+						.after("grow_cities"),
+				)
+				.with_system(in_turn_end_phase(research_techs.system()).after("build_in_cities"))
+				.with_system(order_research.system())
+				.with_system(send_tech_trees.system())
+				.with_system(notify_players.system())
+				.with_system(
+					in_turn_start_phase(start_unit_turns.system()).before("update_visions"),
+				)
+				.with_system(in_turn_start_phase(update_visions.system()).label("update_visions"))
+				.with_system(in_turn_start_phase(replicate_state.system()).after("update_visions"))
+				.with_system(advance_simulation.system())
+				.with_system(in_turn_start_phase(autosave.system()))
+				.with_system(finish_autosave.system())
+				.with_system(on_server_public_cmd.system())
+				.with_system(on_shutdown.system()),
+		)
+		.add_system_set(SystemSet::on_pause(state.clone()).with_system(on_pause.system()))
+		.add_system_set(SystemSet::on_resume(state.clone()).with_system(on_resume.system()))
+		.add_system_set(SystemSet::on_exit(state.clone()).with_system(on_exit.system()));
+}
+
+fn on_enter(
+	mut public_state: ResMut<LocalServerPublicState>,
+	mut update_public_state: EventWriter<LocalServerPublicState>,
+) {
+	trace!("Server Running State: Enter");
+	*public_state = LocalServerPublicState::Running;
+	update_public_state.send(public_state.clone());
+}
+
+fn on_pause() {
+	trace!("Server Running State: Pause");
+}
+
+fn on_resume(
+	mut public_state: ResMut<LocalServerPublicState>,
+	mut update_public_state: EventWriter<LocalServerPublicState>,
+) {
+	trace!("Server Running State: Resume");
+	*public_state = LocalServerPublicState::Running;
+	update_public_state.send(public_state.clone());
+}
+
+fn on_exit() {
+	trace!("Server Running State: Exit");
+}
+
+fn on_shutdown(
+	exiting: Option<ResMut<Exiting>>,
+	mut state: ResMut<State<super::ServerState>>,
+	save_config: Res<Option<SaveConfig>>,
+	game: GameData,
+	mut pending_save: ResMut<Option<GameWriter>>,
+) {
+	if let Some(mut exiting) = exiting {
+		super::save_on_exit(&save_config, &game, &mut exiting, &mut pending_save);
+		state
+			.overwrite_replace(super::ServerState::Exiting)
+			.expect("Failed to transition Server to exiting state");
+	}
+}
+
+fn on_server_public_cmd(
+	mut cmds: EventReader<LocalServerCommand>,
+	mut state: ResMut<State<super::ServerState>>,
+	save_config: Res<Option<SaveConfig>>,
+	game: GameData,
+) {
+	for cmd in cmds.iter() {
+		match cmd {
+			LocalServerCommand::CreateStartServer { .. } => {
+				warn!("requested to CreateStartServer when already running a server");
+			}
+			LocalServerCommand::StopServer { force } => {
+				if !*force && !super::save_game(&save_config, &game) {
+					error!("Not stopping the server as the game failed to save, force the stop to discard it");
+					continue;
+				}
+				info!("Unloading server from within running state");
+				state.set(super::ServerState::Unloading).expect(
+					"Failed transitioning to Server Unloading state from the Running state",
+				);
+			}
+			LocalServerCommand::SaveServer => {
+				super::save_game(&save_config, &game);
+			}
+			LocalServerCommand::PauseServer => {
+				info!("Pausing server");
+				state
+					.push(super::ServerState::Paused)
+					.expect("Failed pushing the Server Paused state over the Running state");
+			}
+			LocalServerCommand::ResumeServer => {
+				info!("Server Resume requested when server is already running");
+			}
+		}
+	}
+}
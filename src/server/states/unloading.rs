@@ -1,3 +1,6 @@
+use crate::server::clients::Clients;
+use crate::server::game::GameEntity;
+use crate::server::save::game::GameSave;
 use crate::universal::exit::Exiting;
 use crate::universal::local_server::{LocalServerCommand, LocalServerPublicState};
 use bevy::prelude::*;
@@ -17,8 +20,13 @@ pub fn register_systems(app: &mut AppBuilder) {
 fn on_enter(
 	mut public_state: ResMut<LocalServerPublicState>,
 	mut update_public_state: EventWriter<LocalServerPublicState>,
+	mut commands: Commands,
+	game_entities: Query<Entity, With<GameEntity>>,
+	mut clients: ResMut<Clients>,
 ) {
 	trace!("Server Unloading State: Enter");
+	clients.end_game();
+	GameSave::unload(&mut commands, game_entities.iter());
 	*public_state = LocalServerPublicState::ShuttingDown;
 	update_public_state.send(public_state.clone());
 }
@@ -52,6 +60,11 @@ fn on_server_public_cmd(mut cmds: EventReader<LocalServerCommand>) {
 				warn!("`{:?}` requested when already stopping", &cmd);
 				// Already stopping...
 			}
+			LocalServerCommand::PauseServer
+			| LocalServerCommand::ResumeServer
+			| LocalServerCommand::SaveServer => {
+				warn!("`{:?}` requested while unloading", &cmd);
+			}
 		}
 	}
 }
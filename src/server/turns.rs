@@ -0,0 +1,383 @@
+//! The turn manager of a running game.  Every turn the players are split into groups by the
+//! `TurnMode`: a group for each player with sequential turns, a single group with simultaneous
+//! turns, and in hybrid mode players share a group with every player they are not at war with.  The
+//! groups move one after another, a group is done once each of its players ended their turn or its
+//! timer ran out.  The groups are formed at the start of the turn, wars declared during it only
+//! regroup the players from the next turn on.
+//!
+//! The turns of seats nobody joined, and of away players set to `AwayControl::SkipTurns`, are ended
+//! for them with nothing done in them.  Nothing moves while no client has joined the game.
+//!
+//! The end and start of a turn are phases other systems schedule into with `in_turn_end_phase` and
+//! `in_turn_start_phase`, reading `TurnEnded` and `TurnStarted` to know when they happen.
+
+use crate::server::clients::{ClientId, Clients};
+use crate::server::game::player::{PlayerId, Players};
+use crate::server::game::{GameTurn, TurnEnded, TurnStarted, Wars};
+use crate::server::save::SaveConfig;
+use crate::server::sessions::AwayControl;
+use crate::server::simulation::SimulationClock;
+use crate::universal::commands::state::TurnStatus;
+use crate::universal::commands::ServerCommand;
+use bevy::ecs::schedule::ParallelSystemDescriptor;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TurnConfig {
+	pub mode: TurnMode,
+	/// Seconds each group has to move before its turn is ended for it, 0 for no limit.
+	pub timer_secs: u64,
+}
+
+impl Default for TurnConfig {
+	fn default() -> Self {
+		Self {
+			mode: TurnMode::Sequential,
+			timer_secs: 0,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnMode {
+	/// Each player moves in turn order while the others wait.
+	Sequential,
+	/// Every player moves at the same time.
+	Simultaneous,
+	/// Players move at the same time, except that players at war with each other move one after the
+	/// other.
+	Hybrid,
+}
+
+/// The groups of players that move one after another, each group in turn order.
+pub fn turn_order(mode: TurnMode, players: &Players, wars: &Wars) -> Vec<Vec<PlayerId>> {
+	let mut groups: Vec<Vec<PlayerId>> = vec![];
+	for player in players.iter().map(|p| p.id) {
+		let group = match mode {
+			TurnMode::Sequential => None,
+			TurnMode::Simultaneous => groups.first_mut(),
+			TurnMode::Hybrid => groups
+				.iter_mut()
+				.find(|group| group.iter().all(|other| !wars.at_war(player, *other))),
+		};
+		match group {
+			Some(group) => group.push(player),
+			None => groups.push(vec![player]),
+		}
+	}
+	groups
+}
+
+/// How far the current turn is, saved with the game.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TurnProgress {
+	/// The index of the group that is moving.
+	group: usize,
+	/// The players of the group that ended their turn.
+	ended: Vec<PlayerId>,
+	/// The groups of the turn, kept from its start so that a war declared during the turn does not
+	/// regroup the players that are yet to move.  Empty until the turn started.
+	#[serde(default)]
+	order: Vec<Vec<PlayerId>>,
+}
+
+impl TurnProgress {
+	/// If the turn started, see `start`.
+	pub fn started(&self) -> bool {
+		!self.order.is_empty()
+	}
+
+	/// Start the turn with the groups of `order`.
+	pub fn start(&mut self, order: Vec<Vec<PlayerId>>) {
+		self.order = order;
+	}
+
+	/// The players of the moving group that have not ended their turn yet.
+	pub fn to_move(&self) -> Vec<PlayerId> {
+		self.order
+			.get(self.group)
+			.into_iter()
+			.flatten()
+			.filter(|p| !self.ended.contains(p))
+			.copied()
+			.collect()
+	}
+
+	/// End the turn of a player, returning false if it is not the player's turn.
+	pub fn end_turn(&mut self, player: PlayerId) -> bool {
+		if !self.to_move().contains(&player) {
+			return false;
+		}
+		self.ended.push(player);
+		true
+	}
+
+	/// Move on to the next group, returning true if every group moved and the turn is over.  The
+	/// next turn groups the players anew.
+	pub fn next_group(&mut self) -> bool {
+		self.ended.clear();
+		self.group += 1;
+		if self.group < self.order.len() {
+			return false;
+		}
+		self.group = 0;
+		self.order.clear();
+		true
+	}
+}
+
+/// Labels of the turn manager systems, see `in_turn_end_phase` and `in_turn_start_phase`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum TurnSystem {
+	End,
+	Start,
+}
+
+/// Schedule a system into the end of the turn, it runs after a turn ended and before the next one
+/// starts on the update `TurnEnded` is sent.
+pub fn in_turn_end_phase(
+	system: impl ParallelSystemDescriptorCoercion,
+) -> ParallelSystemDescriptor {
+	system.after(TurnSystem::End).before(TurnSystem::Start)
+}
+
+/// Schedule a system into the start of the turn, it runs after the new turn started on the update
+/// `TurnStarted` is sent.
+pub fn in_turn_start_phase(
+	system: impl ParallelSystemDescriptorCoercion,
+) -> ParallelSystemDescriptor {
+	system.after(TurnSystem::Start)
+}
+
+/// Event of a joined client asking to end its player's turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndTurnRequest {
+	pub client: ClientId,
+	pub seq: u64,
+	pub player: PlayerId,
+}
+
+/// State of the turn manager that is not saved, reset when a game is loaded.
+#[derive(Debug, Default)]
+pub struct TurnManager {
+	/// If the moving group had its timer started.
+	group_started: bool,
+	/// The simulation tick the moving group's turn is ended at.
+	deadline: Option<u64>,
+	/// A turn ended and the next one starts this update.
+	start_pending: bool,
+}
+
+/// Everything the turn manager works with.
+#[derive(SystemParam)]
+pub struct Turns<'a> {
+	progress: ResMut<'a, TurnProgress>,
+	manager: ResMut<'a, TurnManager>,
+	status: ResMut<'a, TurnStatus>,
+	players: Res<'a, Players>,
+	wars: Res<'a, Wars>,
+	clock: Res<'a, SimulationClock>,
+	save_config: Res<'a, Option<SaveConfig>>,
+}
+
+impl<'a> Turns<'a> {
+	fn config(&self) -> TurnConfig {
+		self.save_config
+			.as_ref()
+			.map(|config| config.turns.clone())
+			.unwrap_or_default()
+	}
+
+	/// Start the timer of the moving group.
+	fn start_group(&mut self) {
+		let timer_secs = self.config().timer_secs;
+		self.manager.group_started = true;
+		self.manager.deadline = Some(timer_secs).filter(|secs| *secs > 0).map(|secs| {
+			let ticks = secs * 1_000_000 / self.clock.step().as_micros().max(1) as u64;
+			self.clock.tick() + ticks
+		});
+	}
+
+	fn update_status(&mut self) {
+		let status = TurnStatus {
+			to_move: self.progress.to_move(),
+			seconds_left: self.manager.deadline.map(|deadline| {
+				let ticks = deadline.saturating_sub(self.clock.tick());
+				(self.clock.step() * ticks as u32).as_secs() as u32
+			}),
+		};
+		if *self.status != status {
+			*self.status = status;
+		}
+	}
+}
+
+pub(crate) fn end_turns(
+	mut requests: EventReader<EndTurnRequest>,
+	mut turns: Turns,
+	mut clients: ResMut<Clients>,
+	turn: Res<GameTurn>,
+	mut ended: EventWriter<TurnEnded>,
+) {
+	if !turns.progress.started() {
+		let order = turn_order(turns.config().mode, &turns.players, &turns.wars);
+		turns.progress.start(order);
+	}
+	for request in requests.iter() {
+		if !turns.progress.end_turn(request.player) {
+			clients.send(
+				request.client,
+				ServerCommand::Rejected {
+					seq: request.seq,
+					reason: "it is not your turn".to_owned(),
+				},
+			);
+		}
+	}
+	// Nobody is playing so nothing moves
+	if clients.iter().all(|c| c.player().is_none()) {
+		return;
+	}
+	if !turns.manager.group_started {
+		turns.start_group();
+	}
+	let reconnect = turns
+		.save_config
+		.as_ref()
+		.map(|config| config.reconnect.clone())
+		.unwrap_or_default();
+	let timed_out =
+		matches!(turns.manager.deadline, Some(deadline) if turns.clock.tick() >= deadline);
+	for player in turns.progress.to_move() {
+		let skipped = clients.by_player(player).is_none()
+			&& clients.away_control(player, &reconnect) != Some(AwayControl::Freeze);
+		if skipped || timed_out {
+			turns.progress.end_turn(player);
+		}
+	}
+	if turns.progress.to_move().is_empty() {
+		if turns.progress.next_group() {
+			info!("Turn {} ended", turn.0);
+			ended.send(TurnEnded(turn.0));
+			turns.manager.start_pending = true;
+		}
+		turns.manager.group_started = false;
+		turns.manager.deadline = None;
+	}
+	turns.update_status();
+}
+
+pub(crate) fn start_turn(
+	mut manager: ResMut<TurnManager>,
+	mut turn: ResMut<GameTurn>,
+	mut clients: ResMut<Clients>,
+	mut started: EventWriter<TurnStarted>,
+) {
+	if !manager.start_pending {
+		return;
+	}
+	manager.start_pending = false;
+	turn.0 += 1;
+	info!("Turn {} started", turn.0);
+	started.send(TurnStarted(turn.0));
+	clients.broadcast(ServerCommand::TurnStarted { turn: turn.0 });
+}
+
+#[cfg(test)]
+mod test {
+	use super::{turn_order, TurnMode, TurnProgress};
+	use crate::server::game::player::{Player, PlayerId, Players};
+	use crate::server::game::Wars;
+
+	fn players() -> Players {
+		Players(
+			(0..4)
+				.map(|id| Player::new(PlayerId(id), format!("Player {}", id), "rome".into()))
+				.collect(),
+		)
+	}
+
+	#[test]
+	fn turn_orders() {
+		let players = players();
+		let mut wars = Wars::default();
+		let ids = |ids: &[u32]| ids.iter().map(|id| PlayerId(*id)).collect::<Vec<_>>();
+		assert_eq!(
+			turn_order(TurnMode::Sequential, &players, &wars),
+			vec![ids(&[0]), ids(&[1]), ids(&[2]), ids(&[3])]
+		);
+		assert_eq!(
+			turn_order(TurnMode::Simultaneous, &players, &wars),
+			vec![ids(&[0, 1, 2, 3])]
+		);
+		assert_eq!(
+			turn_order(TurnMode::Hybrid, &players, &wars),
+			vec![ids(&[0, 1, 2, 3])]
+		);
+		wars.declare(PlayerId(2), PlayerId(0));
+		wars.declare(PlayerId(1), PlayerId(3));
+		assert_eq!(
+			turn_order(TurnMode::Hybrid, &players, &wars),
+			vec![ids(&[0, 1]), ids(&[2, 3])]
+		);
+		// War does not change simultaneous turns
+		assert_eq!(
+			turn_order(TurnMode::Simultaneous, &players, &wars),
+			vec![ids(&[0, 1, 2, 3])]
+		);
+		wars.make_peace(PlayerId(0), PlayerId(2));
+		assert_eq!(
+			turn_order(TurnMode::Hybrid, &players, &wars),
+			vec![ids(&[0, 1, 2]), ids(&[3])]
+		);
+	}
+
+	#[test]
+	fn progress() {
+		let order = vec![vec![PlayerId(0), PlayerId(1)], vec![PlayerId(2)]];
+		let mut progress = TurnProgress::default();
+		assert!(!progress.started());
+		progress.start(order.clone());
+		assert_eq!(progress.to_move(), vec![PlayerId(0), PlayerId(1)]);
+		assert!(!progress.end_turn(PlayerId(2)));
+		assert!(progress.end_turn(PlayerId(1)));
+		assert!(!progress.end_turn(PlayerId(1)));
+		assert_eq!(progress.to_move(), vec![PlayerId(0)]);
+		assert!(progress.end_turn(PlayerId(0)));
+		assert!(progress.to_move().is_empty());
+		assert!(!progress.next_group());
+		assert_eq!(progress.to_move(), vec![PlayerId(2)]);
+		assert!(progress.end_turn(PlayerId(2)));
+		assert!(progress.next_group());
+		assert!(!progress.started());
+		progress.start(order);
+		assert_eq!(progress.to_move(), vec![PlayerId(0), PlayerId(1)]);
+	}
+
+	#[test]
+	fn war_during_turn() {
+		let players = players();
+		let mut wars = Wars::default();
+		let mut progress = TurnProgress::default();
+		progress.start(turn_order(TurnMode::Hybrid, &players, &wars));
+		assert!(progress.end_turn(PlayerId(0)));
+		assert!(progress.end_turn(PlayerId(1)));
+
+		// The war splits the group from the next turn on, the players yet to move still do once
+		wars.declare(PlayerId(0), PlayerId(2));
+		assert_eq!(progress.to_move(), vec![PlayerId(2), PlayerId(3)]);
+		assert!(progress.end_turn(PlayerId(2)));
+		assert!(!progress.end_turn(PlayerId(0)));
+		assert!(progress.end_turn(PlayerId(3)));
+		assert!(progress.next_group());
+
+		progress.start(turn_order(TurnMode::Hybrid, &players, &wars));
+		assert_eq!(
+			progress.to_move(),
+			vec![PlayerId(0), PlayerId(1), PlayerId(3)]
+		);
+	}
+}
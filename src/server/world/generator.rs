@@ -0,0 +1,543 @@
+//! Deterministic procedural map generation.
+//!
+//! Only the seeded PCG generator and plain IEEE arithmetic are used so the same
+//! `MapGenerationConfig` always generates the exact same map on every platform.
+
+use super::{Elevation, Feature, Hex, Terrain, WorldMap, DEFAULT_MAP_HEIGHT, DEFAULT_MAP_WIDTH};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Smallest map dimension that will be generated, smaller configured sizes are raised to this.
+const MIN_MAP_SIZE: u32 = 4;
+
+/// Connected bodies of water up to this many tiles become lakes instead of ocean.
+const MAX_LAKE_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Climate {
+	Cold,
+	Temperate,
+	Hot,
+	Arid,
+	Wet,
+}
+
+impl Default for Climate {
+	fn default() -> Self {
+		Climate::Temperate
+	}
+}
+
+impl Climate {
+	/// The `(temperature, moisture)` offsets this climate applies to the whole map.
+	fn offsets(self) -> (f64, f64) {
+		match self {
+			Climate::Cold => (-0.15, 0.0),
+			Climate::Temperate => (0.0, 0.0),
+			Climate::Hot => (0.15, 0.0),
+			Climate::Arid => (0.05, -0.2),
+			Climate::Wet => (0.0, 0.2),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeaLevel {
+	Low,
+	Normal,
+	High,
+}
+
+impl Default for SeaLevel {
+	fn default() -> Self {
+		SeaLevel::Normal
+	}
+}
+
+impl SeaLevel {
+	fn land_multiplier(self) -> f64 {
+		match self {
+			SeaLevel::Low => 1.15,
+			SeaLevel::Normal => 1.0,
+			SeaLevel::High => 0.85,
+		}
+	}
+}
+
+/// Map generation settings of a save, stored in its `config.ron`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapGenerationConfig {
+	/// The same seed with the same settings always generates the same map.  A new save gets a
+	/// random seed.
+	pub seed: u64,
+	pub width: u32,
+	pub height: u32,
+	/// Fraction of the map that is land, from 0.0 to 1.0, before the `sea_level` adjusts it.
+	pub land_ratio: f64,
+	pub climate: Climate,
+	/// Number of major landmasses to grow.
+	pub continents: u32,
+	pub sea_level: SeaLevel,
+}
+
+impl Default for MapGenerationConfig {
+	fn default() -> Self {
+		Self {
+			seed: rand::random(),
+			width: DEFAULT_MAP_WIDTH,
+			height: DEFAULT_MAP_HEIGHT,
+			land_ratio: 0.35,
+			climate: Climate::default(),
+			continents: 3,
+			sea_level: SeaLevel::default(),
+		}
+	}
+}
+
+/// Periodic east to west value noise, sampled with `u` and `v` from 0.0 to 1.0 across the map.
+struct ValueNoise {
+	octaves: Vec<(usize, usize, Vec<f64>)>,
+}
+
+impl ValueNoise {
+	fn new(rng: &mut Pcg64, aspect: f64, base_cells: usize, octaves: usize) -> Self {
+		let octaves = (0..octaves)
+			.map(|octave| {
+				let cells_x = base_cells << octave;
+				let cells_y = ((cells_x as f64 * aspect).round() as usize).max(1);
+				let lattice = (0..cells_x * (cells_y + 1))
+					.map(|_| rng.gen::<f64>())
+					.collect();
+				(cells_x, cells_y, lattice)
+			})
+			.collect();
+		Self { octaves }
+	}
+
+	fn sample(&self, u: f64, v: f64) -> f64 {
+		let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+		let mut total = 0.0;
+		let mut amplitude = 1.0;
+		let mut max = 0.0;
+		for (cells_x, cells_y, lattice) in &self.octaves {
+			let x = u * *cells_x as f64;
+			let y = v.clamp(0.0, 1.0) * *cells_y as f64;
+			let (x0, y0) = (x.floor(), y.floor().min(*cells_y as f64 - 1.0));
+			let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+			let x0 = (x0 as i64).rem_euclid(*cells_x as i64) as usize;
+			let x1 = (x0 + 1) % cells_x;
+			let y0 = y0 as usize;
+			let at = |x: usize, y: usize| lattice[y * cells_x + x];
+			let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+			let bottom = at(x0, y0 + 1) + (at(x1, y0 + 1) - at(x0, y0 + 1)) * tx;
+			total += (top + (bottom - top) * ty) * amplitude;
+			max += amplitude;
+			amplitude *= 0.5;
+		}
+		total / max
+	}
+}
+
+/// Per-tile values, indexed by `WorldMap::tile_index`.
+type TileValues<T> = Vec<T>;
+
+/// Generate a new map from the configuration.
+pub fn generate(config: &MapGenerationConfig) -> WorldMap {
+	let width = config.width.max(MIN_MAP_SIZE);
+	let height = config.height.max(MIN_MAP_SIZE);
+	let mut map = WorldMap::new(width, height);
+	let mut rng = Pcg64::seed_from_u64(config.seed);
+	let aspect = height as f64 / width as f64;
+
+	let land_noise = ValueNoise::new(&mut rng, aspect, 4, 4);
+	let ridge_noise = ValueNoise::new(&mut rng, aspect, 6, 3);
+	let temperature_noise = ValueNoise::new(&mut rng, aspect, 5, 2);
+	let moisture_noise = ValueNoise::new(&mut rng, aspect, 6, 3);
+	// Normalized position of the center of every tile, odd rows are shifted half a tile east.
+	let uv: TileValues<(f64, f64)> = (0..map.tile_count())
+		.map(|idx| {
+			let (col, row) = map.tile_hex(idx).to_offset();
+			let col = col as f64 + 0.5 * (row & 1) as f64;
+			(col / width as f64, (row as f64 + 0.5) / height as f64)
+		})
+		.collect();
+	// 0.0 at the equator to 1.0 at the poles.
+	let latitude: TileValues<f64> = uv.iter().map(|(_u, v)| (v * 2.0 - 1.0).abs()).collect();
+
+	let land_score = continent_scores(&map, config, &mut rng, &uv, &latitude, &land_noise);
+	let land_target = (map.tile_count() as f64
+		* config.land_ratio.clamp(0.0, 1.0)
+		* config.sea_level.land_multiplier())
+	.round() as usize;
+	let is_land = top_fraction(&land_score, land_target.min(map.tile_count()));
+
+	// Mountains and hills, ridged noise makes them form chains instead of blobs.
+	let elevation_score: TileValues<f64> = (0..map.tile_count())
+		.map(|idx| {
+			let (u, v) = uv[idx];
+			let ridge = 1.0 - (ridge_noise.sample(u, v) * 2.0 - 1.0).abs();
+			land_score[idx] * 0.4 + ridge * 0.6
+		})
+		.collect();
+	let land_count = is_land.iter().filter(|l| **l).count();
+	let land_elevation: TileValues<f64> = elevation_score
+		.iter()
+		.zip(is_land.iter())
+		.map(|(e, land)| if *land { *e } else { f64::MIN })
+		.collect();
+	let mountain_count = land_count * 7 / 100;
+	let is_mountain = top_fraction(&land_elevation, mountain_count);
+	let is_hill_or_mountain = top_fraction(&land_elevation, mountain_count + land_count * 18 / 100);
+
+	let is_lake = find_lakes(&map, &is_land);
+	let near_water: TileValues<bool> = (0..map.tile_count())
+		.map(|idx| {
+			map.neighbors(map.tile_hex(idx))
+				.filter_map(|h| map.tile_index(h))
+				.any(|n| !is_land[n])
+		})
+		.collect();
+	let near_land: TileValues<bool> = (0..map.tile_count())
+		.map(|idx| {
+			map.neighbors(map.tile_hex(idx))
+				.filter_map(|h| map.tile_index(h))
+				.any(|n| is_land[n])
+		})
+		.collect();
+	let near_lake: TileValues<bool> = (0..map.tile_count())
+		.map(|idx| {
+			map.neighbors(map.tile_hex(idx))
+				.filter_map(|h| map.tile_index(h))
+				.any(|n| is_lake[n])
+		})
+		.collect();
+
+	let (temperature_offset, moisture_offset) = config.climate.offsets();
+	for (idx, (_hex, tile)) in map.iter_mut().enumerate() {
+		// Always draw exactly one roll per tile so every branch below consumes the same randomness.
+		let roll: f64 = rng.gen();
+		let (u, v) = uv[idx];
+		if !is_land[idx] {
+			tile.elevation = Elevation::Flat;
+			tile.terrain = if is_lake[idx] {
+				Terrain::Lake
+			} else if near_land[idx] {
+				Terrain::Coast
+			} else {
+				Terrain::Ocean
+			};
+			tile.feature = if !is_lake[idx] && latitude[idx] > 0.9 && roll < 0.8 {
+				Some(Feature::Ice)
+			} else {
+				None
+			};
+			continue;
+		}
+
+		tile.elevation = if is_mountain[idx] {
+			Elevation::Mountains
+		} else if is_hill_or_mountain[idx] {
+			Elevation::Hills
+		} else {
+			Elevation::Flat
+		};
+		let elevation_chill = match tile.elevation {
+			Elevation::Flat => 0.0,
+			Elevation::Hills => 0.05,
+			Elevation::Mountains => 0.15,
+		};
+		let temperature =
+			1.0 - latitude[idx] + temperature_offset + (temperature_noise.sample(u, v) - 0.5) * 0.2
+				- elevation_chill;
+		let moisture = moisture_noise.sample(u, v)
+			+ moisture_offset
+			+ if near_water[idx] { 0.15 } else { 0.0 };
+
+		tile.terrain = if temperature < 0.12 {
+			Terrain::Snow
+		} else if temperature < 0.28 {
+			Terrain::Tundra
+		} else if temperature > 0.6 && moisture < 0.35 {
+			Terrain::Desert
+		} else if moisture < 0.5 {
+			Terrain::Plains
+		} else {
+			Terrain::Grassland
+		};
+
+		let flat = tile.elevation == Elevation::Flat;
+		tile.feature = if tile.elevation == Elevation::Mountains {
+			None
+		} else if tile.terrain == Terrain::Desert {
+			if flat && near_lake[idx] {
+				Some(Feature::FloodPlains)
+			} else if flat && roll < 0.04 {
+				Some(Feature::Oasis)
+			} else {
+				None
+			}
+		} else if tile.terrain == Terrain::Snow {
+			None
+		} else if temperature > 0.7 && moisture > 0.65 && roll < 0.7 {
+			Some(Feature::Jungle)
+		} else if flat && tile.terrain == Terrain::Grassland && moisture > 0.75 && roll < 0.15 {
+			Some(Feature::Marsh)
+		} else if moisture > 0.55 && temperature < 0.75 && roll < 0.6 {
+			Some(Feature::Forest)
+		} else {
+			None
+		};
+	}
+
+	map
+}
+
+/// Where the players of a new game start, spread over the land as far from each other as possible.
+/// Fewer than `count` are returned if there is not enough land.
+pub fn start_positions(map: &WorldMap, count: usize) -> Vec<Hex> {
+	let is_land = |hex: &Hex| matches!(map.get(*hex), Some(tile) if !tile.terrain.is_water());
+	let candidates: Vec<Hex> = map
+		.iter()
+		.filter(|(_hex, tile)| {
+			!tile.terrain.is_water()
+				&& tile.elevation != Elevation::Mountains
+				&& tile.terrain != Terrain::Snow
+		})
+		.map(|(hex, _tile)| hex)
+		.collect();
+	let land_around: Vec<usize> = candidates
+		.iter()
+		.map(|hex| map.spiral(*hex, 2).iter().filter(|h| is_land(h)).count())
+		.collect();
+	let mut starts: Vec<Hex> = Vec::with_capacity(count);
+	while starts.len() < count {
+		// The first start has the most land around it, every later one is the farthest from the
+		// starts so far
+		let next = candidates
+			.iter()
+			.zip(&land_around)
+			.filter(|(hex, _land)| !starts.contains(hex))
+			.max_by_key(|(hex, land)| {
+				let nearest = starts.iter().map(|s| map.distance(*s, **hex)).min();
+				(nearest, **land)
+			});
+		match next {
+			Some((hex, _land)) => starts.push(*hex),
+			None => break,
+		}
+	}
+	starts
+}
+
+/// How strongly every tile wants to be land, continents are grown around spread out random
+/// centers with noise breaking up their shapes.
+fn continent_scores(
+	map: &WorldMap,
+	config: &MapGenerationConfig,
+	rng: &mut Pcg64,
+	uv: &[(f64, f64)],
+	latitude: &[f64],
+	noise: &ValueNoise,
+) -> TileValues<f64> {
+	let continents = config.continents.max(1);
+	let land_per_continent =
+		map.tile_count() as f64 * config.land_ratio.max(0.01) / continents as f64;
+	let mut centers: Vec<(Hex, f64)> = Vec::with_capacity(continents as usize);
+	for _ in 0..continents {
+		// Pick the candidate furthest from the existing centers so continents spread out.
+		let center = (0..16)
+			.map(|_| {
+				let col = rng.gen_range(0..map.width() as i32);
+				let min_row = (map.height() / 5) as i32;
+				let max_row = (map.height() as i32 - min_row).max(min_row + 1);
+				Hex::from_offset(col, rng.gen_range(min_row..max_row))
+			})
+			.max_by_key(|candidate| {
+				centers
+					.iter()
+					.map(|(c, _)| map.distance(*c, *candidate))
+					.min()
+					.unwrap_or(0)
+			})
+			.expect("always has candidates");
+		// A hex area of radius `r` is about `3r²` tiles.
+		let radius = (land_per_continent / 3.0).sqrt() * rng.gen_range(1.1..1.5);
+		centers.push((center, radius.max(1.0)));
+	}
+
+	(0..map.tile_count())
+		.map(|idx| {
+			let hex = map.tile_hex(idx);
+			let falloff = centers
+				.iter()
+				.map(|(center, radius)| 1.0 - map.distance(*center, hex) as f64 / radius)
+				.fold(0.0, f64::max);
+			let (u, v) = uv[idx];
+			let polar = (latitude[idx] - 0.8).max(0.0) * 2.0;
+			falloff * 0.65 + noise.sample(u, v) * 0.35 - polar
+		})
+		.collect()
+}
+
+/// Marks the `count` highest scored tiles, ties go to the lower index so it stays deterministic.
+fn top_fraction(scores: &[f64], count: usize) -> TileValues<bool> {
+	let mut order: Vec<usize> = (0..scores.len()).collect();
+	order.sort_by(|a, b| {
+		scores[*b]
+			.partial_cmp(&scores[*a])
+			.unwrap_or(std::cmp::Ordering::Equal)
+			.then(a.cmp(b))
+	});
+	let mut ret = vec![false; scores.len()];
+	for idx in order.into_iter().take(count) {
+		ret[idx] = true;
+	}
+	ret
+}
+
+/// Small enclosed bodies of water are lakes.
+fn find_lakes(map: &WorldMap, is_land: &[bool]) -> TileValues<bool> {
+	let mut is_lake = vec![false; map.tile_count()];
+	let mut visited = vec![false; map.tile_count()];
+	let mut queue = VecDeque::new();
+	for start in 0..map.tile_count() {
+		if is_land[start] || visited[start] {
+			continue;
+		}
+		let mut body = vec![];
+		visited[start] = true;
+		queue.push_back(start);
+		while let Some(idx) = queue.pop_front() {
+			body.push(idx);
+			for n in map
+				.neighbors(map.tile_hex(idx))
+				.filter_map(|h| map.tile_index(h))
+			{
+				if !is_land[n] && !visited[n] {
+					visited[n] = true;
+					queue.push_back(n);
+				}
+			}
+		}
+		if body.len() <= MAX_LAKE_SIZE {
+			for idx in body {
+				is_lake[idx] = true;
+			}
+		}
+	}
+	is_lake
+}
+
+#[cfg(test)]
+mod test {
+	use super::{generate, start_positions, Climate, MapGenerationConfig, SeaLevel};
+	use crate::server::world::{Elevation, Feature, Terrain, WorldMap};
+
+	fn config(seed: u64) -> MapGenerationConfig {
+		MapGenerationConfig {
+			seed,
+			width: 24,
+			height: 12,
+			land_ratio: 0.4,
+			climate: Climate::Temperate,
+			continents: 2,
+			sea_level: SeaLevel::Normal,
+		}
+	}
+
+	/// A compact text rendering of a map, two characters per tile for terrain and feature.
+	fn snapshot(map: &WorldMap) -> String {
+		let mut ret = String::new();
+		for (hex, tile) in map.iter() {
+			let (col, row) = hex.to_offset();
+			if col == 0 && row & 1 == 1 {
+				ret.push(' ');
+			}
+			let terrain = match tile.terrain {
+				Terrain::Ocean => '~',
+				Terrain::Coast => '-',
+				Terrain::Lake => 'o',
+				Terrain::Grassland => 'g',
+				Terrain::Plains => 'p',
+				Terrain::Desert => 'd',
+				Terrain::Tundra => 't',
+				Terrain::Snow => 's',
+			};
+			ret.push(match tile.elevation {
+				Elevation::Flat => terrain,
+				Elevation::Hills => terrain.to_ascii_uppercase(),
+				Elevation::Mountains => '^',
+			});
+			ret.push(match tile.feature {
+				None => ' ',
+				Some(Feature::Forest) => 'F',
+				Some(Feature::Jungle) => 'J',
+				Some(Feature::Marsh) => 'm',
+				Some(Feature::Oasis) => 'O',
+				Some(Feature::FloodPlains) => 'f',
+				Some(Feature::Ice) => 'I',
+			});
+			if col as u32 == map.width() - 1 {
+				ret.push('\n');
+			}
+		}
+		ret
+	}
+
+	#[test]
+	fn starts_spread_over_land() {
+		let map = generate(&config(7));
+		let starts = start_positions(&map, 4);
+		assert_eq!(starts, start_positions(&map, 4));
+		assert_eq!(starts.len(), 4);
+		for (idx, start) in starts.iter().enumerate() {
+			assert!(!map.get(*start).unwrap().terrain.is_water());
+			for other in &starts[idx + 1..] {
+				assert!(map.distance(*start, *other) >= 4, "{:?}", starts);
+			}
+		}
+		assert!(start_positions(&WorldMap::new(4, 4), 2).is_empty());
+	}
+
+	#[test]
+	fn deterministic() {
+		assert_eq!(generate(&config(42)), generate(&config(42)));
+		assert_ne!(generate(&config(42)), generate(&config(43)));
+	}
+
+	#[test]
+	fn land_ratio() {
+		let mut config = config(7);
+		config.width = 60;
+		config.height = 40;
+		let map = generate(&config);
+		let land = map.iter().filter(|(_, t)| !t.terrain.is_water()).count();
+		assert_eq!(land, 960);
+		config.sea_level = SeaLevel::High;
+		let map = generate(&config);
+		assert!(map.iter().filter(|(_, t)| !t.terrain.is_water()).count() < land);
+	}
+
+	#[test]
+	fn snapshot_seed_1() {
+		assert_eq!(snapshot(&generate(&config(1))), SNAPSHOT_SEED_1);
+	}
+
+	const SNAPSHOT_SEED_1: &str = concat!(
+		"-I-I-I~I~I~I~I~I~ ~I-I- s o s -I- -I- ~I~I-I-I-I\n",
+		" t tF- ~ ~ ~ ~ ~ - - t t t T TFtFt t - ~ - t t t \n",
+		"g p gF- ~ ~ ~ ~ - p p p p P P g gFgFg - - gFgFg \n",
+		" p g g - - ~ ~ ~ - p P p p p p p gF- - - gFp p p \n",
+		"p p gFg g - ~ ~ ~ - p g p p p g p - ~ - gJg p P \n",
+		" P P p p g - ~ ~ ~ - - g g G G - - ~ ~ - GJp p P \n",
+		"^ ^ D p g g - ~ ~ ~ ~ - g - - - ~ ~ ~ - g ^ P P \n",
+		" ^ p p g - - ~ ~ ~ ~ ~ - - ~ ~ ~ ~ ~ ~ - GJgJP ^ \n",
+		"^ ^ p g - ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ - g gFgF^ \n",
+		" P P p - ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ - g gFg p \n",
+		"TFtFt - ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ ~ - t t tF\n",
+		" -I-I-I~I~I~I~I~I~I~ ~I~I~I~I~I~ ~ ~I~I~I-I-I-I- \n",
+	);
+}
@@ -0,0 +1,23 @@
+//! The game world owned by the server, currently just the hex map and its generator.
+
+pub mod generator;
+
+pub use crate::universal::{hex, map};
+pub use hex::{Hex, HexDirection};
+pub use map::{Elevation, Feature, Terrain, Tile, TileOwner, WorldMap};
+
+/// Default size of the map generated for a new game.
+pub const DEFAULT_MAP_WIDTH: u32 = 80;
+pub const DEFAULT_MAP_HEIGHT: u32 = 50;
+
+#[cfg(test)]
+impl WorldMap {
+	/// Create a map of only `terrain` for tests.
+	pub fn filled(width: u32, height: u32, terrain: Terrain) -> Self {
+		let mut map = WorldMap::new(width, height);
+		for (_hex, tile) in map.iter_mut() {
+			tile.terrain = terrain;
+		}
+		map
+	}
+}
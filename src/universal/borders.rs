@@ -0,0 +1,153 @@
+//! The borders of the players' territory as an overlay over the map, shared by the clients however
+//! they draw the map.  A border runs along every edge between a tile of a player's territory and a
+//! tile that is not, each side of it is an edge of its own so every player's border can be drawn
+//! in their colour just inside their territory.
+
+use crate::universal::hex::{Hex, HexDirection};
+use crate::universal::ids::PlayerId;
+use crate::universal::map::WorldMap;
+
+/// An edge of a tile of `owner`'s territory that a border runs along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BorderEdge {
+	/// The tile in the territory, in its canonical form.
+	pub hex: Hex,
+	pub direction: HexDirection,
+	pub owner: PlayerId,
+	/// The owner of the tile on the other side, `None` if it is in no territory or not shown.
+	pub neighbor: Option<PlayerId>,
+}
+
+impl BorderEdge {
+	/// The ends of the edge where hexes are `size` from their center to a corner, from the center
+	/// of `hex` with y going south.
+	pub fn corners(&self, size: f32) -> [(f32, f32); 2] {
+		let index = HexDirection::ALL
+			.iter()
+			.position(|direction| *direction == self.direction)
+			.unwrap_or_default();
+		// The neighbor in the direction is 60 degrees further counter-clockwise for each, the
+		// edge facing it runs between the corners 30 degrees to either side
+		let corner = |degrees: f32| {
+			let angle = degrees.to_radians();
+			(size * angle.cos(), -size * angle.sin())
+		};
+		let middle = 60.0 * index as f32;
+		[corner(middle - 30.0), corner(middle + 30.0)]
+	}
+}
+
+/// Colours to tell the players apart by, repeating in games with more players.
+const PLAYER_COLOURS: [[u8; 3]; 8] = [
+	[220, 40, 40],
+	[40, 90, 220],
+	[240, 200, 30],
+	[40, 170, 60],
+	[160, 60, 200],
+	[240, 130, 20],
+	[30, 200, 200],
+	[230, 100, 180],
+];
+
+/// The colour `player` and their borders are drawn in, as red, green and blue.
+pub fn player_colour(player: PlayerId) -> [u8; 3] {
+	PLAYER_COLOURS[player.0 as usize % PLAYER_COLOURS.len()]
+}
+
+/// The border edges of the tiles of `map` that are `shown`, row by row from the north-west corner.
+/// Tiles that are not shown count as in no territory.
+pub fn border_edges(map: &WorldMap, shown: impl Fn(Hex) -> bool) -> Vec<BorderEdge> {
+	let owner = |hex: Hex| match map.get(hex) {
+		Some(tile) if shown(hex) => tile.owner.map(|owner| owner.player),
+		_ => None,
+	};
+	let mut edges = vec![];
+	for (hex, _tile) in map.iter() {
+		let owner_here = match owner(hex) {
+			Some(owner_here) => owner_here,
+			None => continue,
+		};
+		for direction in HexDirection::ALL.iter() {
+			// Off the north and south of the map is no one's territory either
+			let neighbor = map.normalize(hex.neighbor(*direction)).and_then(&owner);
+			if neighbor != Some(owner_here) {
+				edges.push(BorderEdge {
+					hex,
+					direction: *direction,
+					owner: owner_here,
+					neighbor,
+				});
+			}
+		}
+	}
+	edges
+}
+
+#[cfg(test)]
+mod test {
+	use super::{border_edges, BorderEdge};
+	use crate::universal::hex::{Hex, HexDirection};
+	use crate::universal::ids::{CityId, PlayerId};
+	use crate::universal::map::{TileOwner, WorldMap};
+
+	#[test]
+	fn borders() {
+		let mut map = WorldMap::new(8, 6);
+		let claim = |map: &mut WorldMap, hex: Hex, player: u32| {
+			map.get_mut(hex).unwrap().owner = Some(TileOwner {
+				player: PlayerId(player),
+				city: CityId(player.into()),
+			});
+		};
+		let center = Hex::from_offset(3, 2);
+		for hex in map.spiral(center, 1) {
+			claim(&mut map, hex, 0);
+		}
+		let east = Hex::from_offset(5, 2);
+		claim(&mut map, east, 1);
+
+		// Only the outer edges of the ring around the center are borders
+		let edges = border_edges(&map, |_hex| true);
+		let of = |player: u32| edges.iter().filter(move |e| e.owner == PlayerId(player));
+		assert_eq!(of(0).count(), 18);
+		assert!(of(0).all(|edge| edge.hex != center));
+		assert_eq!(of(1).count(), 6);
+		let shared: Vec<_> = of(1)
+			.filter(|edge| edge.neighbor == Some(PlayerId(0)))
+			.map(|edge| edge.direction)
+			.collect();
+		assert_eq!(shared, vec![HexDirection::West]);
+
+		// Tiles not shown have no border, and the borders next to them face no one
+		let hidden = Hex::from_offset(4, 2);
+		let edges = border_edges(&map, |hex| hex != hidden);
+		assert!(edges.iter().all(|edge| edge.hex != hidden));
+		assert!(edges
+			.iter()
+			.filter(|edge| edge.hex == east)
+			.all(|edge| edge.neighbor.is_none()));
+	}
+
+	#[test]
+	fn edge_corners() {
+		let edge = |direction| BorderEdge {
+			hex: Hex::ZERO,
+			direction,
+			owner: PlayerId(0),
+			neighbor: None,
+		};
+		let round = |(x, y): (f32, f32)| ((x * 100.0).round(), (y * 100.0).round());
+		// The east edge is upright, halfway to the center of the neighbor
+		let [(x0, y0), (x1, y1)] = edge(HexDirection::East).corners(1.0);
+		assert_eq!(round((x0, y0)), (87.0, 50.0));
+		assert_eq!(round((x1, y1)), (87.0, -50.0));
+		assert_eq!(
+			round(Hex::ZERO.neighbor(HexDirection::East).to_pixel(1.0)),
+			(173.0, 0.0)
+		);
+		// The north-west edge ends at the top corner, north being up
+		let [top, left] = edge(HexDirection::NorthWest).corners(1.0);
+		assert_eq!(round(top), (0.0, -100.0));
+		assert_eq!(round(left), (-87.0, -50.0));
+	}
+}
@@ -1 +1,85 @@
-pub enum ClientCommand {}
+use super::combat::AttackKind;
+use super::research::ResearchOrder;
+use super::server::ResumeToken;
+use super::state::ProductionItem;
+use crate::universal::hex::Hex;
+use crate::universal::ids::{CityId, PlayerId, UnitId};
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+/// Commands sent from a client to the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ClientCommand {
+	/// Join the game as `name`, taking the seat of `player` or any free seat if `None`.  Answered
+	/// with `ServerCommand::Joined` or `ServerCommand::Rejected`.
+	Join {
+		name: String,
+		player: Option<PlayerId>,
+	},
+	/// Take back the seat kept for a dropped connection, answered like `Join`.
+	Resume { token: ResumeToken },
+	/// Leave the game, the seat is freed for someone else.
+	Leave,
+	/// Ask for the full game state, answered with `ServerCommand::State`.  Also sent to resync when
+	/// the checksum of a `ServerCommand::Delta` does not match the client's copy of the state.
+	RequestState,
+	/// Give an order to one of the player's units.
+	OrderUnit { unit: UnitId, order: UnitOrder },
+	/// Ask for the path a unit would take through `waypoints` without ordering it, answered with
+	/// `ServerCommand::PathPreview`.
+	PreviewPath { unit: UnitId, waypoints: Vec<Hex> },
+	/// Ask for the odds of `unit` attacking `target` without attacking, answered with
+	/// `ServerCommand::CombatPreview`.
+	PreviewAttack {
+		unit: UnitId,
+		target: Hex,
+		kind: AttackKind,
+	},
+	/// Give an order to one of the player's cities.
+	OrderCity { city: CityId, order: CityOrder },
+	/// Change what the player researches.
+	Research(ResearchOrder),
+	/// Ask for the tech tree, answered with `ServerCommand::TechTree`.
+	RequestTechTree,
+	/// The player is done with their turn.
+	EndTurn,
+	/// Send a chat message to every player.
+	Chat { text: String },
+	/// Acknowledge that the server message with this sequence number was handled.
+	Ack { seq: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UnitOrder {
+	/// Move towards a hex, continuing over later turns until it is reached.
+	MoveTo(Hex),
+	/// Add a hex to move on to once the unit reached where it is headed.
+	AddWaypoint(Hex),
+	/// Do nothing this turn.
+	Skip,
+	/// Stay in place and dig in until given another order.
+	Fortify,
+	/// Remove the unit from the game.
+	Disband,
+	/// Attack the unit on a tile, declaring war on its owner if not at war with them already.
+	Attack { target: Hex, kind: AttackKind },
+	/// Found a city where the unit is, using up the unit.  An empty name picks one.
+	FoundCity { name: String },
+	/// Build an improvement on the unit's tile, replacing the one there.
+	BuildImprovement { improvement: SmolStr },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CityOrder {
+	/// Have a citizen work a tile, taking it off the tile picked for it with the lowest yields if
+	/// every citizen is working.
+	WorkTile(Hex),
+	/// Let the city pick whether a tile the player picked is worked.
+	ReleaseTile(Hex),
+	/// Add something to the end of the production queue.
+	Enqueue(ProductionItem),
+	/// Take the entry at this index out of the production queue.
+	Dequeue(usize),
+	/// Buy the first entry of the production queue with gold, it is done at once.
+	Purchase,
+}
@@ -0,0 +1,94 @@
+//! Combat as the clients see it, the odds of an attack before it is made and what came of it after.
+
+use crate::universal::hex::Hex;
+use crate::universal::ids::UnitId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AttackKind {
+	/// Attack a neighboring tile, both sides take damage and the attacker moves in if it wins.
+	Melee,
+	/// Shoot at a tile in range, only the defender takes damage.
+	Ranged,
+	/// Shell a tile in range, only the defender takes damage and it gets nothing from its terrain or
+	/// from being fortified.
+	Bombard,
+}
+
+/// What changes the strength of a side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CombatModifier {
+	/// A hurt unit fights weaker.
+	Health,
+	/// The defender's hills, forest or marsh.
+	Terrain,
+	/// The defender is fortified.
+	Fortified,
+	/// The attacker attacks across a river.
+	RiverCrossing,
+	/// Other units of the attacker next to the defender.
+	Flanking,
+}
+
+/// One side of a fight as it is expected to go.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombatSide {
+	pub unit: UnitId,
+	pub health: u32,
+	/// The strength of the unit's type for the attack.
+	pub base_strength: u32,
+	/// Everything that changes `base_strength`, with how much it changes it by.
+	pub modifiers: Vec<(CombatModifier, i32)>,
+	/// The strength the side fights with.
+	pub strength: u32,
+	/// The least and most damage the side can take.
+	pub damage: (u32, u32),
+}
+
+/// The odds of an attack, shown before the attack is made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CombatPreview {
+	pub kind: AttackKind,
+	pub attacker: CombatSide,
+	pub defender: CombatSide,
+	/// The chance from 0 to 1 that the defender is killed.
+	pub kill_chance: f32,
+	/// The chance from 0 to 1 that the attacker is killed.
+	pub death_chance: f32,
+}
+
+/// How an attack went.  A player who cannot see the tile of one of the sides is not told which unit
+/// fought on that side or where it was.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombatReport {
+	pub kind: AttackKind,
+	pub attacker: Option<UnitId>,
+	pub defender: Option<UnitId>,
+	/// Where the attacker attacked from.
+	pub from: Option<Hex>,
+	/// Where the defender was.
+	pub target: Option<Hex>,
+	pub attacker_damage: u32,
+	pub defender_damage: u32,
+	pub attacker_killed: bool,
+	pub defender_killed: bool,
+	/// The attacker moved into the defender's tile.
+	pub advanced: bool,
+}
+
+impl CombatReport {
+	/// The report as a player sees it who can see the attacker's tile if `sees_from` and the
+	/// defender's tile if `sees_target`.
+	pub fn as_seen(&self, sees_from: bool, sees_target: bool) -> Self {
+		let mut report = self.clone();
+		if !sees_from {
+			report.attacker = None;
+			report.from = None;
+		}
+		if !sees_target {
+			report.defender = None;
+			report.target = None;
+		}
+		report
+	}
+}
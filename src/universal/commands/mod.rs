@@ -1,5 +1,212 @@
+//! The protocol between the server and its clients.  The local server and remote servers are only
+//! ever talked to through these messages so a client works the same with either.
+//!
+//! `ClientCommand`s are sent from a client to the server and `ServerCommand`s from the server to a
+//! client, each wrapped in a `Message` with the sequence number of its sender.
+
 pub mod client;
+pub mod combat;
+pub mod research;
 pub mod server;
+pub mod state;
+
+pub use client::{CityOrder, ClientCommand, UnitOrder};
+pub use combat::{AttackKind, CombatModifier, CombatPreview, CombatReport, CombatSide};
+pub use research::{ResearchOrder, TechNode, TechStatus, TechTree, Unlock};
+pub use server::{Notification, PathStep, ResumeToken, ServerCommand};
+pub use state::ProductionItem;
+
+use serde::{Deserialize, Serialize};
+
+/// Version of the message format, a client and server must have the same version to talk.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Version of the game, a client and server must have the same version for the game rules to match.
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A command along with the sequence number its sender gave it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message<C> {
+	pub seq: u64,
+	pub command: C,
+}
+
+/// Numbers outgoing messages, each side of a connection numbers its own messages starting from 1 so
+/// an `Ack` or a reply can refer to the message it is about.
+#[derive(Debug, Clone, Default)]
+pub struct Sequencer {
+	last: u64,
+}
+
+impl Sequencer {
+	pub fn next<C>(&mut self, command: C) -> Message<C> {
+		self.last += 1;
+		Message {
+			seq: self.last,
+			command,
+		}
+	}
+
+	/// The sequence number of the last message numbered.
+	pub fn last(&self) -> u64 {
+		self.last
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::state::{GameState, PlayerInfo, TileVisibility, TurnStatus, UnitInfo};
+	use super::{
+		AttackKind, CityOrder, ClientCommand, CombatReport, Message, Notification, PathStep,
+		ProductionItem, ResearchOrder, ResumeToken, Sequencer, ServerCommand, TechNode, TechStatus,
+		TechTree, UnitOrder, Unlock,
+	};
+	use crate::universal::hex::Hex;
+	use crate::universal::ids::{CityId, PlayerId, UnitId};
+	use crate::universal::map::WorldMap;
+
+	#[test]
+	fn serialization() {
+		let mut seq = Sequencer::default();
+		let client = vec![
+			seq.next(ClientCommand::Join {
+				name: "Player 1".to_owned(),
+				player: Some(PlayerId(0)),
+			}),
+			seq.next(ClientCommand::RequestState),
+			seq.next(ClientCommand::OrderUnit {
+				unit: UnitId(3),
+				order: UnitOrder::MoveTo(Hex::new(4, -2)),
+			}),
+			seq.next(ClientCommand::PreviewPath {
+				unit: UnitId(3),
+				waypoints: vec![Hex::new(4, -2), Hex::new(5, -2)],
+			}),
+			seq.next(ClientCommand::OrderUnit {
+				unit: UnitId(3),
+				order: UnitOrder::Attack {
+					target: Hex::new(5, -2),
+					kind: AttackKind::Ranged,
+				},
+			}),
+			seq.next(ClientCommand::OrderCity {
+				city: CityId(1),
+				order: CityOrder::WorkTile(Hex::new(3, -1)),
+			}),
+			seq.next(ClientCommand::OrderCity {
+				city: CityId(1),
+				order: CityOrder::Enqueue(ProductionItem::Building("granary".into())),
+			}),
+			seq.next(ClientCommand::Research(ResearchOrder::Research(
+				"writing".into(),
+			))),
+			seq.next(ClientCommand::OrderUnit {
+				unit: UnitId(4),
+				order: UnitOrder::BuildImprovement {
+					improvement: "farm".into(),
+				},
+			}),
+			seq.next(ClientCommand::EndTurn),
+			seq.next(ClientCommand::Chat {
+				text: "hello".to_owned(),
+			}),
+			seq.next(ClientCommand::Ack { seq: 2 }),
+			seq.next(ClientCommand::Leave),
+			seq.next(ClientCommand::Resume {
+				token: ResumeToken(42),
+			}),
+		];
+		assert_eq!(
+			client.iter().map(|m| m.seq).collect::<Vec<_>>(),
+			(1..=14).collect::<Vec<_>>()
+		);
+		let json = serde_json::to_string(&client).unwrap();
+		let decoded: Vec<Message<ClientCommand>> = serde_json::from_str(&json).unwrap();
+		assert_eq!(decoded, client);
 
-pub use client::ClientCommand;
-pub use server::ServerCommand;
+		let server = vec![
+			seq.next(ServerCommand::Joined {
+				player: PlayerId(0),
+				token: ResumeToken(42),
+			}),
+			seq.next(ServerCommand::PlayerAway {
+				player: PlayerId(1),
+			}),
+			seq.next(ServerCommand::State(GameState {
+				turn: 2,
+				map: WorldMap::new(3, 2),
+				visibility: vec![TileVisibility::Fogged; 6],
+				players: vec![PlayerInfo {
+					id: PlayerId(0),
+					name: "Player 1".to_owned(),
+					civ: "rome".into(),
+					away: false,
+					gold: None,
+				}],
+				units: vec![UnitInfo {
+					id: UnitId(3),
+					owner: PlayerId(0),
+					kind: "warrior".into(),
+					position: Hex::new(1, 1),
+					moves_left: 6,
+					health: 100,
+					waypoints: vec![],
+				}],
+				cities: vec![],
+				turn_status: TurnStatus {
+					to_move: vec![PlayerId(0)],
+					seconds_left: None,
+				},
+			})),
+			seq.next(ServerCommand::PathPreview {
+				seq: 3,
+				unit: UnitId(3),
+				path: Some(vec![PathStep {
+					hex: Hex::new(4, -2),
+					turn: 1,
+					moves_left: 3,
+				}]),
+			}),
+			seq.next(ServerCommand::Combat(CombatReport {
+				kind: AttackKind::Ranged,
+				attacker: None,
+				defender: Some(UnitId(5)),
+				from: None,
+				target: Some(Hex::new(5, -2)),
+				attacker_damage: 0,
+				defender_damage: 27,
+				attacker_killed: false,
+				defender_killed: false,
+				advanced: false,
+			})),
+			seq.next(ServerCommand::Notify(Notification::Built {
+				city: CityId(1),
+				item: ProductionItem::Unit("warrior".into()),
+			})),
+			seq.next(ServerCommand::TechTree {
+				seq: 9,
+				tree: TechTree {
+					techs: vec![TechNode {
+						tech: "pottery".into(),
+						cost: 25,
+						prerequisites: vec![],
+						unlocks: vec![Unlock::Building("granary".into())],
+						status: TechStatus::Queued(0),
+						column: 0,
+						turns: Some(4),
+					}],
+					queue: vec!["pottery".into()],
+					beakers: 10,
+					beakers_per_turn: 4,
+				},
+			}),
+			seq.next(ServerCommand::Rejected {
+				seq: 3,
+				reason: "not your unit".to_owned(),
+			}),
+		];
+		let json = serde_json::to_string(&server).unwrap();
+		let decoded: Vec<Message<ServerCommand>> = serde_json::from_str(&json).unwrap();
+		assert_eq!(decoded, server);
+	}
+}
@@ -0,0 +1,63 @@
+//! Research as the clients see it, the tech tree with what every tech unlocks and how long it would
+//! take to research.
+
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+/// A change to what the player researches, techs are keys of the server's rules.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResearchOrder {
+	/// Research a tech next, after the prerequisites the player does not know yet.  Replaces the
+	/// queue.
+	Research(SmolStr),
+	/// Research a tech after everything in the queue, adding the prerequisites it still needs.
+	Enqueue(SmolStr),
+	/// Stop researching, beakers are kept for whatever is researched next.
+	Clear,
+}
+
+/// Something a tech allows the player to build.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Unlock {
+	Unit(SmolStr),
+	Building(SmolStr),
+	Improvement(SmolStr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TechStatus {
+	Known,
+	/// In the research queue, 0 is being researched.
+	Queued(u32),
+	/// Every prerequisite is known.
+	Available,
+	/// Some prerequisite is not known yet.
+	Locked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TechNode {
+	pub tech: SmolStr,
+	/// Beakers it takes to research.
+	pub cost: u32,
+	pub prerequisites: Vec<SmolStr>,
+	pub unlocks: Vec<Unlock>,
+	pub status: TechStatus,
+	/// Where to draw the tech, one more than the highest column of its prerequisites.
+	pub column: u32,
+	/// Turns it would take to research with its missing prerequisites if it were researched next,
+	/// `None` if it never would be, as for a player who makes no beakers.
+	pub turns: Option<u32>,
+}
+
+/// The whole tech tree as the player knows it, the answer to `ClientCommand::RequestTechTree`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TechTree {
+	/// By column, then by key.
+	pub techs: Vec<TechNode>,
+	pub queue: Vec<SmolStr>,
+	/// Beakers put into research so far.
+	pub beakers: u32,
+	/// Beakers the player's cities make every turn.
+	pub beakers_per_turn: u32,
+}
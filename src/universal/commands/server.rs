@@ -1 +1,85 @@
-pub enum ServerCommand {}
+use super::combat::{CombatPreview, CombatReport};
+use super::research::TechTree;
+use super::state::{GameState, ProductionItem, StateDelta};
+use crate::universal::hex::Hex;
+use crate::universal::ids::{CityId, PlayerId, UnitId};
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+/// Given to a client when it joins, it resumes playing as the same player with it after its
+/// connection dropped, for as long as the server keeps the player's seat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResumeToken(pub u64);
+
+/// A step of a unit's path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathStep {
+	pub hex: Hex,
+	/// The turn the unit gets there, 0 for the current turn.
+	pub turn: u32,
+	/// Movement the unit has left there, in thirds of a movement point.
+	pub moves_left: u32,
+}
+
+/// Something that happened to the client's player that they should be told about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Notification {
+	/// A city finished building something.
+	Built { city: CityId, item: ProductionItem },
+	/// A city can no longer build something in its queue, such as a wonder built elsewhere first,
+	/// and dropped it.  The production put into it is kept for the next entry.
+	Dropped {
+		city: CityId,
+		item: ProductionItem,
+		reason: String,
+	},
+	/// The player learned a tech.
+	Researched { tech: SmolStr },
+}
+
+/// Commands sent from the server to a client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServerCommand {
+	/// The client joined the game and plays as `player`, `token` resumes playing after a dropped
+	/// connection.
+	Joined {
+		player: PlayerId,
+		token: ResumeToken,
+	},
+	/// A client command was refused, `seq` is the sequence number of the client's message.
+	Rejected { seq: u64, reason: String },
+	/// Another player joined the game.
+	PlayerJoined { player: PlayerId, name: String },
+	/// Another player's connection dropped, their seat is kept for them to resume.
+	PlayerAway { player: PlayerId },
+	/// Another player left the game.
+	PlayerLeft { player: PlayerId },
+	/// The full game state as the client is allowed to see it, sent when asked for and every now and
+	/// then so a client's copy cannot drift from the server's for long.
+	State(GameState),
+	/// What changed in the game state since the last `State` or `Delta`.
+	Delta(StateDelta),
+	/// The answer to `ClientCommand::PreviewPath` with sequence number `seq`, the path the unit
+	/// would take or `None` if it cannot get there.
+	PathPreview {
+		seq: u64,
+		unit: UnitId,
+		path: Option<Vec<PathStep>>,
+	},
+	/// The answer to `ClientCommand::PreviewAttack` with sequence number `seq`.
+	CombatPreview { seq: u64, preview: CombatPreview },
+	/// A fight the client's player can see, to animate or log.
+	Combat(CombatReport),
+	/// Something happened to the client's player.
+	Notify(Notification),
+	/// The answer to `ClientCommand::RequestTechTree` with sequence number `seq`.
+	TechTree { seq: u64, tree: TechTree },
+	/// The server unloaded the game, the client must join again once another game is loaded.
+	GameEnded,
+	/// A new turn started.
+	TurnStarted { turn: u32 },
+	/// A chat message from a player.
+	Chat { from: PlayerId, text: String },
+	/// Acknowledge that the client message with this sequence number was handled.
+	Ack { seq: u64 },
+}
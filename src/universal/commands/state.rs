@@ -0,0 +1,504 @@
+//! The game state as it is sent to clients.  A client is sent the full state once and then only
+//! `StateDelta`s of what changed, with a checksum of the resulting state so it notices if its copy
+//! no longer matches the server's.
+//!
+//! The state is only what the client's player is allowed to see: unexplored tiles are sent as
+//! default tiles, fogged tiles and the cities on them as the player last saw them, and units only
+//! when they are the player's own or on a visible tile.
+
+use crate::universal::borders::{border_edges, BorderEdge};
+use crate::universal::hex::Hex;
+use crate::universal::ids::{CityId, PlayerId, UnitId};
+use crate::universal::map::{Tile, WorldMap};
+use crate::universal::yields::Yields;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GameState {
+	pub turn: u32,
+	pub map: WorldMap,
+	/// The visibility of every tile of the map, by `WorldMap::tile_index`.
+	pub visibility: Vec<TileVisibility>,
+	pub players: Vec<PlayerInfo>,
+	pub units: Vec<UnitInfo>,
+	pub cities: Vec<CityInfo>,
+	pub turn_status: TurnStatus,
+}
+
+/// Who is still to move this turn.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TurnStatus {
+	/// The players that can move and have not ended their turn yet.
+	pub to_move: Vec<PlayerId>,
+	/// Seconds until their turn is ended for them, if the turns are timed.
+	pub seconds_left: Option<u32>,
+}
+
+/// How much a player knows about a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TileVisibility {
+	/// Never seen, nothing is known about the tile.
+	Unexplored,
+	/// Seen before, the tile is known as it was last seen.
+	Fogged,
+	/// Currently seen by one of the player's units or cities.
+	Visible,
+}
+
+impl Default for TileVisibility {
+	fn default() -> Self {
+		TileVisibility::Unexplored
+	}
+}
+
+impl GameState {
+	pub fn visibility(&self, hex: Hex) -> TileVisibility {
+		self.map
+			.tile_index(hex)
+			.and_then(|idx| self.visibility.get(idx).copied())
+			.unwrap_or_default()
+	}
+
+	/// The player whose territory the tile is in, as far as the client's player knows.
+	pub fn territory(&self, hex: Hex) -> Option<PlayerId> {
+		match self.visibility(hex) {
+			TileVisibility::Unexplored => None,
+			_ => self.map.get(hex)?.owner.map(|owner| owner.player),
+		}
+	}
+
+	/// The borders of the explored tiles, to draw over the map.
+	pub fn borders(&self) -> Vec<BorderEdge> {
+		border_edges(&self.map, |hex| {
+			self.visibility(hex) != TileVisibility::Unexplored
+		})
+	}
+
+	/// Checksum of the whole state, the same on every platform for the same state.
+	pub fn checksum(&self) -> u64 {
+		let mut hasher = Fnv64::default();
+		self.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	/// What changed from this state to `next`, or `None` if it cannot be expressed as a delta and
+	/// the full state must be sent instead.
+	pub fn diff(&self, next: &GameState) -> Option<StateDelta> {
+		if self.map.width() != next.map.width() || self.map.height() != next.map.height() {
+			return None;
+		}
+		Some(StateDelta {
+			turn: Some(next.turn).filter(|turn| *turn != self.turn),
+			tiles: self
+				.map
+				.iter()
+				.zip(&self.visibility)
+				.zip(next.map.iter().zip(&next.visibility))
+				.filter(|(old, new)| old != new)
+				.map(|(_, ((hex, tile), visibility))| (hex, tile.clone(), *visibility))
+				.collect(),
+			players: changed(&self.players, &next.players, |p| p.id),
+			removed_players: removed(&self.players, &next.players, |p| p.id),
+			units: changed(&self.units, &next.units, |u| u.id),
+			removed_units: removed(&self.units, &next.units, |u| u.id),
+			cities: changed(&self.cities, &next.cities, |c| c.id),
+			removed_cities: removed(&self.cities, &next.cities, |c| c.id),
+			turn_status: Some(&next.turn_status)
+				.filter(|status| **status != self.turn_status)
+				.cloned(),
+			checksum: next.checksum(),
+		})
+	}
+
+	/// Apply a delta from `diff`, returning if the result matches the checksum of the delta.
+	pub fn apply(&mut self, delta: &StateDelta) -> bool {
+		if let Some(turn) = delta.turn {
+			self.turn = turn;
+		}
+		self.visibility
+			.resize(self.map.tile_count(), TileVisibility::default());
+		for (hex, tile, visibility) in &delta.tiles {
+			if let Some(idx) = self.map.tile_index(*hex) {
+				self.visibility[idx] = *visibility;
+			}
+			if let Some(old) = self.map.get_mut(*hex) {
+				*old = tile.clone();
+			}
+		}
+		apply(
+			&mut self.players,
+			&delta.players,
+			&delta.removed_players,
+			|p| p.id,
+		);
+		apply(&mut self.units, &delta.units, &delta.removed_units, |u| {
+			u.id
+		});
+		apply(
+			&mut self.cities,
+			&delta.cities,
+			&delta.removed_cities,
+			|c| c.id,
+		);
+		if let Some(turn_status) = &delta.turn_status {
+			self.turn_status = turn_status.clone();
+		}
+		self.units.sort_by_key(|u| u.id);
+		self.cities.sort_by_key(|c| c.id);
+		self.checksum() == delta.checksum
+	}
+}
+
+/// The changes to a `GameState`, from `GameState::diff`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateDelta {
+	/// The new turn, if it changed.
+	pub turn: Option<u32>,
+	/// The tiles whose state or visibility changed, with their canonical hex.
+	pub tiles: Vec<(Hex, Tile, TileVisibility)>,
+	/// Players that are new or changed.
+	pub players: Vec<PlayerInfo>,
+	pub removed_players: Vec<PlayerId>,
+	/// Units that are new or changed.
+	pub units: Vec<UnitInfo>,
+	pub removed_units: Vec<UnitId>,
+	/// Cities that are new or changed.
+	pub cities: Vec<CityInfo>,
+	pub removed_cities: Vec<CityId>,
+	/// The new turn status, if it changed.
+	pub turn_status: Option<TurnStatus>,
+	/// The checksum of the state once this is applied.
+	pub checksum: u64,
+}
+
+impl StateDelta {
+	/// If nothing changed.
+	pub fn is_empty(&self) -> bool {
+		self.turn.is_none()
+			&& self.tiles.is_empty()
+			&& self.players.is_empty()
+			&& self.removed_players.is_empty()
+			&& self.units.is_empty()
+			&& self.removed_units.is_empty()
+			&& self.cities.is_empty()
+			&& self.removed_cities.is_empty()
+			&& self.turn_status.is_none()
+	}
+}
+
+/// The entries of `next` that are not in `old` or differ from it.
+fn changed<T: Clone + PartialEq, K: PartialEq>(
+	old: &[T],
+	next: &[T],
+	key: impl Fn(&T) -> K,
+) -> Vec<T> {
+	next.iter()
+		.filter(|entry| !old.iter().any(|o| key(o) == key(entry) && o == *entry))
+		.cloned()
+		.collect()
+}
+
+/// The keys of the entries of `old` that are not in `next`.
+fn removed<T, K: PartialEq>(old: &[T], next: &[T], key: impl Fn(&T) -> K) -> Vec<K> {
+	old.iter()
+		.map(&key)
+		.filter(|k| !next.iter().any(|n| key(n) == *k))
+		.collect()
+}
+
+fn apply<T: Clone, K: PartialEq>(
+	entries: &mut Vec<T>,
+	changed: &[T],
+	removed: &[K],
+	key: impl Fn(&T) -> K,
+) {
+	entries.retain(|entry| !removed.contains(&key(entry)));
+	for entry in changed {
+		match entries.iter_mut().find(|e| key(e) == key(entry)) {
+			Some(existing) => *existing = entry.clone(),
+			None => entries.push(entry.clone()),
+		}
+	}
+}
+
+/// 64-bit FNV-1a, unlike the standard hasher its output is stable across platforms and releases.
+struct Fnv64(u64);
+
+impl Default for Fnv64 {
+	fn default() -> Self {
+		Fnv64(0xcbf2_9ce4_8422_2325)
+	}
+}
+
+impl Hasher for Fnv64 {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for byte in bytes {
+			self.0 ^= *byte as u64;
+			self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+		}
+	}
+
+	// Integers are hashed as little endian bytes, the default hashes them in the platform's byte
+	// order
+
+	fn write_u16(&mut self, i: u16) {
+		self.write(&i.to_le_bytes());
+	}
+
+	fn write_u32(&mut self, i: u32) {
+		self.write(&i.to_le_bytes());
+	}
+
+	fn write_u64(&mut self, i: u64) {
+		self.write(&i.to_le_bytes());
+	}
+
+	fn write_u128(&mut self, i: u128) {
+		self.write(&i.to_le_bytes());
+	}
+
+	fn write_usize(&mut self, i: usize) {
+		// The same on 32 and 64 bit platforms
+		self.write_u64(i as u64);
+	}
+
+	fn write_i16(&mut self, i: i16) {
+		self.write_u16(i as u16);
+	}
+
+	fn write_i32(&mut self, i: i32) {
+		self.write_u32(i as u32);
+	}
+
+	fn write_i64(&mut self, i: i64) {
+		self.write_u64(i as u64);
+	}
+
+	fn write_i128(&mut self, i: i128) {
+		self.write_u128(i as u128);
+	}
+
+	fn write_isize(&mut self, i: isize) {
+		self.write_u64(i as i64 as u64);
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PlayerInfo {
+	pub id: PlayerId,
+	pub name: String,
+	pub civ: SmolStr,
+	/// The player's connection dropped and their seat is kept for them.
+	pub away: bool,
+	/// Gold in the player's treasury, only sent to the player.
+	pub gold: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct UnitInfo {
+	pub id: UnitId,
+	pub owner: PlayerId,
+	pub kind: SmolStr,
+	pub position: Hex,
+	/// Movement left this turn, in thirds of a movement point.
+	pub moves_left: u32,
+	pub health: u32,
+	/// Where the unit is headed, only sent to its owner.
+	pub waypoints: Vec<Hex>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CityInfo {
+	pub id: CityId,
+	pub owner: PlayerId,
+	pub name: String,
+	pub position: Hex,
+	pub population: u32,
+	/// How the city is doing, only sent to its owner.
+	pub details: Option<CityDetails>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CityDetails {
+	/// Food stored towards growing.
+	pub food: u32,
+	/// The food the city needs to grow.
+	pub growth_threshold: u32,
+	/// What the city yields every turn, before its citizens eat.
+	pub yields: Yields,
+	/// Food the city eats every turn.
+	pub food_eaten: u32,
+	/// The tiles its citizens work.
+	pub worked: Vec<Hex>,
+	/// The worked tiles the player picked, the others are picked automatically.
+	pub locked: Vec<Hex>,
+	/// Production put into the first entry of the queue.
+	pub production: u32,
+	/// What the city builds, in order.
+	pub queue: Vec<ProductionItem>,
+	/// The buildings the city has.
+	pub buildings: Vec<SmolStr>,
+	/// Culture stored towards claiming the next tile.
+	pub culture: u32,
+	/// The culture the city needs to claim the next tile.
+	pub culture_threshold: u32,
+}
+
+/// Something a city builds, by its key in the server's rules.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProductionItem {
+	Unit(SmolStr),
+	Building(SmolStr),
+}
+
+impl ProductionItem {
+	pub fn key(&self) -> &SmolStr {
+		match self {
+			ProductionItem::Unit(key) | ProductionItem::Building(key) => key,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{CityInfo, Fnv64, GameState, PlayerInfo, TileVisibility, TurnStatus, UnitInfo};
+	use crate::universal::hex::Hex;
+	use crate::universal::ids::{CityId, PlayerId, UnitId};
+	use crate::universal::map::{Terrain, WorldMap};
+	use std::hash::Hasher;
+
+	fn state() -> GameState {
+		GameState {
+			turn: 3,
+			map: WorldMap::new(4, 3),
+			visibility: vec![TileVisibility::Visible; 12],
+			players: vec![
+				PlayerInfo {
+					id: PlayerId(0),
+					name: "Player 1".to_owned(),
+					civ: "rome".into(),
+					away: false,
+					gold: None,
+				},
+				PlayerInfo {
+					id: PlayerId(1),
+					name: "Player 2".to_owned(),
+					civ: "egypt".into(),
+					away: false,
+					gold: None,
+				},
+			],
+			units: vec![
+				UnitInfo {
+					id: UnitId(1),
+					owner: PlayerId(0),
+					kind: "warrior".into(),
+					position: Hex::new(1, 1),
+					moves_left: 6,
+					health: 100,
+					waypoints: vec![],
+				},
+				UnitInfo {
+					id: UnitId(2),
+					owner: PlayerId(1),
+					kind: "settler".into(),
+					position: Hex::new(2, 0),
+					moves_left: 6,
+					health: 100,
+					waypoints: vec![],
+				},
+			],
+			cities: vec![],
+			turn_status: TurnStatus {
+				to_move: vec![PlayerId(0), PlayerId(1)],
+				seconds_left: Some(60),
+			},
+		}
+	}
+
+	#[test]
+	fn diff_and_apply() {
+		let old = state();
+		let mut next = old.clone();
+		next.turn = 4;
+		next.map.get_mut(Hex::new(1, 1)).unwrap().terrain = Terrain::Desert;
+		next.visibility[0] = TileVisibility::Fogged;
+		next.players[1].away = true;
+		next.turn_status.to_move.remove(0);
+		next.units[0].position = Hex::new(2, 1);
+		next.units.remove(1);
+		next.units.push(UnitInfo {
+			id: UnitId(3),
+			owner: PlayerId(1),
+			kind: "warrior".into(),
+			position: Hex::new(0, 2),
+			moves_left: 6,
+			health: 100,
+			waypoints: vec![],
+		});
+		next.cities.push(CityInfo {
+			id: CityId(1),
+			owner: PlayerId(1),
+			name: "Memphis".to_owned(),
+			position: Hex::new(2, 0),
+			population: 1,
+			details: None,
+		});
+
+		let delta = old.diff(&next).unwrap();
+		assert_eq!(delta.turn, Some(4));
+		assert_eq!(delta.tiles.len(), 2);
+		assert_eq!(delta.players, vec![next.players[1].clone()]);
+		assert_eq!(delta.units.len(), 2);
+		assert_eq!(delta.removed_units, vec![UnitId(2)]);
+		assert_eq!(delta.cities.len(), 1);
+		assert_eq!(delta.turn_status, Some(next.turn_status.clone()));
+
+		let mut applied = old.clone();
+		assert!(applied.apply(&delta));
+		assert_eq!(applied, next);
+		assert!(old.diff(&old).unwrap().is_empty());
+
+		// A copy that drifted from the server no longer matches the checksum
+		let mut drifted = old.clone();
+		drifted.map.get_mut(Hex::from_offset(3, 2)).unwrap().terrain = Terrain::Desert;
+		assert!(!drifted.apply(&delta));
+
+		// A different map size needs the full state
+		let mut resized = old.clone();
+		resized.map = WorldMap::new(5, 3);
+		assert!(old.diff(&resized).is_none());
+	}
+
+	#[test]
+	fn checksum_is_stable() {
+		let state = state();
+		assert_eq!(state.checksum(), state.clone().checksum());
+		let mut other = state.clone();
+		other.turn += 1;
+		assert_ne!(state.checksum(), other.checksum());
+
+		// Integers hash the same whatever the platform's byte order and pointer size
+		let hash = |write: &dyn Fn(&mut Fnv64)| {
+			let mut hasher = Fnv64::default();
+			write(&mut hasher);
+			hasher.finish()
+		};
+		assert_eq!(
+			hash(&|h| h.write_u32(0x0102_0304)),
+			hash(&|h| h.write(&[4, 3, 2, 1]))
+		);
+		assert_eq!(
+			hash(&|h| h.write_i16(-2)),
+			hash(&|h| h.write(&[0xfe, 0xff]))
+		);
+		assert_eq!(hash(&|h| h.write_usize(7)), hash(&|h| h.write_u64(7)));
+		assert_eq!(hash(&|h| h.write_isize(-1)), hash(&|h| h.write_i64(-1)));
+	}
+}
@@ -0,0 +1,367 @@
+//! The client's connection to a server, the same for the local server and a remote one.
+
+use crate::universal::commands::{ClientCommand, Message, Sequencer, ServerCommand};
+use crate::universal::i18n::I18n;
+use crate::universal::transport::channel::LocalConnector;
+use crate::universal::transport::lan::{
+	DiscoveredServer, DiscoveredServers, DiscoveryListener, DISCOVERY_EXPIRY,
+};
+use crate::universal::transport::{tcp, ClientTransport, TransportError};
+use bevy::prelude::*;
+use fluent::FluentValue;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Default)]
+pub(super) struct ConnectionPlugin;
+
+impl Plugin for ConnectionPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<Option<Connection>>()
+			.init_resource::<Option<PendingConnection>>()
+			.add_event::<ConnectionCommand>()
+			.add_event::<ConnectionEvent>()
+			.add_event::<ServerMessage>()
+			.add_system(on_connection_cmd.system())
+			.add_system(finish_connecting.system())
+			.add_system(receive_server_messages.system())
+			.init_resource::<Option<DiscoveryListener>>()
+			.init_resource::<DiscoveredServers>()
+			.add_event::<DiscoveryCommand>()
+			.add_system(on_discovery_cmd.system())
+			.add_system(discover_servers.system());
+	}
+}
+
+/// Where to find a server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerAddress {
+	/// The server running in this process.
+	Local,
+	/// A server reached over TCP, as `host:port` or just `host` for the default port.
+	Remote(String),
+}
+
+impl std::fmt::Display for ServerAddress {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ServerAddress::Local => f.write_str("local"),
+			ServerAddress::Remote(address) => f.write_str(address),
+		}
+	}
+}
+
+/// Event to connect to or disconnect from a server.
+#[derive(Debug)]
+pub enum ConnectionCommand {
+	/// Connect to a server, the password is only needed for remote servers that have one.
+	Connect {
+		address: ServerAddress,
+		password: Option<String>,
+	},
+	Disconnect,
+}
+
+/// Event sent when the connection to a server changes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+	Connected(ServerAddress),
+	/// The connection failed or was lost, with the reason.
+	Disconnected(String),
+}
+
+/// Why joining or playing on a server stopped, shown on the join screen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinFailure {
+	Connection(String),
+	Rejected(String),
+	TimedOut,
+	Lost(String),
+	GameEnded,
+}
+
+impl JoinFailure {
+	/// The attribute of the `join-error` message that describes this failure.
+	pub fn msg_attr(&self) -> &'static str {
+		match self {
+			JoinFailure::Connection(_) => "connection",
+			JoinFailure::Rejected(_) => "rejected",
+			JoinFailure::TimedOut => "timed-out",
+			JoinFailure::Lost(_) => "lost",
+			JoinFailure::GameEnded => "game-ended",
+		}
+	}
+
+	pub fn reason(&self) -> &str {
+		match self {
+			JoinFailure::Connection(reason)
+			| JoinFailure::Rejected(reason)
+			| JoinFailure::Lost(reason) => reason,
+			JoinFailure::TimedOut | JoinFailure::GameEnded => "",
+		}
+	}
+}
+
+/// Event to start or stop listening for servers announcing themselves on the LAN, the servers
+/// found are kept in the `DiscoveredServers` resource.
+#[derive(Debug)]
+pub enum DiscoveryCommand {
+	Start,
+	Stop,
+}
+
+/// Event of every message received from the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerMessage(pub Message<ServerCommand>);
+
+/// Resource of a connection to a remote server still being made on a background thread.
+pub struct PendingConnection {
+	address: ServerAddress,
+	result: Mutex<Receiver<Result<ClientTransport, TransportError>>>,
+}
+
+impl PendingConnection {
+	fn spawn(address: ServerAddress, remote: String, password: Option<String>) -> Self {
+		let (sender, receiver) = std::sync::mpsc::channel();
+		std::thread::spawn(move || {
+			// If the receiver is gone then connecting was cancelled, nothing to do.
+			let _ = sender.send(tcp::connect(&remote, password.as_deref()));
+		});
+		Self {
+			address,
+			result: Mutex::new(receiver),
+		}
+	}
+
+	pub fn address(&self) -> &ServerAddress {
+		&self.address
+	}
+}
+
+/// Resource of the connection to the server, if there is one.
+pub struct Connection {
+	address: ServerAddress,
+	transport: ClientTransport,
+	seq: Sequencer,
+}
+
+impl Connection {
+	pub fn new(address: ServerAddress, transport: ClientTransport) -> Self {
+		Self {
+			address,
+			transport,
+			seq: Sequencer::default(),
+		}
+	}
+
+	pub fn address(&self) -> &ServerAddress {
+		&self.address
+	}
+
+	/// Send a command to the server, returns the sequence number it was sent with.
+	pub fn send(&mut self, command: ClientCommand) -> Result<u64, TransportError> {
+		let message = self.seq.next(command);
+		let seq = message.seq;
+		self.transport.send(message)?;
+		Ok(seq)
+	}
+}
+
+fn on_connection_cmd(
+	mut cmds: EventReader<ConnectionCommand>,
+	mut connection: ResMut<Option<Connection>>,
+	mut pending: ResMut<Option<PendingConnection>>,
+	mut events: EventWriter<ConnectionEvent>,
+	local_connector: Option<Res<LocalConnector>>,
+) {
+	for cmd in cmds.iter() {
+		match cmd {
+			ConnectionCommand::Connect { address, password } => {
+				if connection.is_some() {
+					warn!("Replacing the existing server connection");
+				}
+				*connection = None;
+				*pending = None;
+				match address {
+					ServerAddress::Local => {
+						let transport = match &local_connector {
+							Some(connector) => connector.connect(),
+							None => Err(TransportError::NoLocalServer),
+						};
+						*connection = connected(address.clone(), transport, &mut events);
+					}
+					ServerAddress::Remote(remote) => {
+						info!("Connecting to server: {}", remote);
+						*pending = Some(PendingConnection::spawn(
+							address.clone(),
+							remote.clone(),
+							password.clone(),
+						));
+					}
+				}
+			}
+			ConnectionCommand::Disconnect => {
+				if pending.take().is_some() {
+					events.send(ConnectionEvent::Disconnected("cancelled".to_owned()));
+				}
+				if let Some(mut conn) = connection.take() {
+					let _ = conn.send(ClientCommand::Leave);
+					events.send(ConnectionEvent::Disconnected("disconnected".to_owned()));
+				}
+			}
+		}
+	}
+}
+
+fn connected(
+	address: ServerAddress,
+	transport: Result<ClientTransport, TransportError>,
+	events: &mut EventWriter<ConnectionEvent>,
+) -> Option<Connection> {
+	match transport {
+		Ok(transport) => {
+			info!("Connected to server: {}", transport.peer());
+			events.send(ConnectionEvent::Connected(address.clone()));
+			Some(Connection::new(address, transport))
+		}
+		Err(e) => {
+			error!("Failed connecting to server `{}`: {}", address, e);
+			events.send(ConnectionEvent::Disconnected(e.to_string()));
+			None
+		}
+	}
+}
+
+fn finish_connecting(
+	mut connection: ResMut<Option<Connection>>,
+	mut pending: ResMut<Option<PendingConnection>>,
+	mut events: EventWriter<ConnectionEvent>,
+) {
+	let result = match &*pending {
+		None => return,
+		Some(p) => match p
+			.result
+			.lock()
+			.expect("poisoned PendingConnection lock")
+			.try_recv()
+		{
+			Ok(result) => result,
+			Err(TryRecvError::Empty) => return,
+			Err(TryRecvError::Disconnected) => Err(TransportError::Disconnected),
+		},
+	};
+	if let Some(p) = pending.take() {
+		*connection = connected(p.address, result, &mut events);
+	}
+}
+
+fn receive_server_messages(
+	mut connection: ResMut<Option<Connection>>,
+	mut messages: EventWriter<ServerMessage>,
+	mut events: EventWriter<ConnectionEvent>,
+) {
+	let lost = match &*connection {
+		None => return,
+		Some(conn) => loop {
+			match conn.transport.try_recv() {
+				Ok(Some(message)) => messages.send(ServerMessage(message)),
+				Ok(None) => break None,
+				Err(e) => break Some(e),
+			}
+		},
+	};
+	if let Some(e) = lost {
+		warn!("Lost connection to server: {}", e);
+		*connection = None;
+		events.send(ConnectionEvent::Disconnected(e.to_string()));
+	}
+}
+
+fn on_discovery_cmd(
+	mut cmds: EventReader<DiscoveryCommand>,
+	mut listener: ResMut<Option<DiscoveryListener>>,
+	mut servers: ResMut<DiscoveredServers>,
+) {
+	for cmd in cmds.iter() {
+		match cmd {
+			DiscoveryCommand::Start if listener.is_none() => {
+				match DiscoveryListener::bind_default() {
+					Ok(bound) => *listener = Some(bound),
+					// Another client on this machine may already have the port.
+					Err(e) => warn!("Cannot discover LAN servers: {}", e),
+				}
+			}
+			DiscoveryCommand::Start => (),
+			DiscoveryCommand::Stop => {
+				*listener = None;
+				servers.0.clear();
+			}
+		}
+	}
+}
+
+fn discover_servers(
+	listener: Res<Option<DiscoveryListener>>,
+	mut servers: ResMut<DiscoveredServers>,
+) {
+	let listener = match &*listener {
+		Some(listener) => listener,
+		None => return,
+	};
+	loop {
+		match listener.try_recv() {
+			Ok(Some(server)) => servers.insert(server),
+			Ok(None) => break,
+			Err(e) => {
+				warn!("Failed receiving LAN server announcements: {}", e);
+				break;
+			}
+		}
+	}
+	// Only touched when needed so that change detection shows when the list changed.
+	let now = Instant::now();
+	if servers
+		.0
+		.iter()
+		.any(|server| now.saturating_duration_since(server.last_seen) >= DISCOVERY_EXPIRY)
+	{
+		servers.expire(now);
+	}
+}
+
+/// The line describing a server found on the LAN in the join screens.
+pub fn discovered_server_label(lang: &I18n, server: &DiscoveredServer) -> String {
+	let announcement = &server.announcement;
+	let mut args = vec![
+		("name", FluentValue::from(announcement.name.as_str())),
+		("address", FluentValue::from(server.address.as_str())),
+	];
+	if !announcement.compatible() {
+		args.push((
+			"version",
+			FluentValue::from(announcement.game_version.as_str()),
+		));
+		return lang
+			.get_attr_with_args_list("lan-game", "incompatible", args)
+			.into_owned();
+	}
+	args.push(("players", FluentValue::from(announcement.players)));
+	args.push(("max", FluentValue::from(announcement.max_players)));
+	if announcement.password {
+		lang.get_attr_with_args_list("lan-game", "locked", args)
+	} else {
+		lang.get_with_args_list("lan-game", args)
+	}
+	.into_owned()
+}
+
+/// The line saying why joining or playing on a server stopped.
+pub fn join_failure_label(lang: &I18n, failure: &JoinFailure) -> String {
+	lang.get_attr_with_args_list(
+		"join-error",
+		failure.msg_attr(),
+		std::iter::once(("reason", failure.reason())),
+	)
+	.into_owned()
+}
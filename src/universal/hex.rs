@@ -0,0 +1,254 @@
+//! Axial hex coordinates, using pointy-topped hexes where `q` is the column axis and `r` the row
+//! axis.  The implicit third cube coordinate is `s = -q - r`.
+
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Mul, Neg, Sub};
+
+#[derive(
+	Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct Hex {
+	pub q: i32,
+	pub r: i32,
+}
+
+/// The six neighboring directions of a pointy-topped hex, in counter-clockwise order starting
+/// from the east.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HexDirection {
+	East,
+	NorthEast,
+	NorthWest,
+	West,
+	SouthWest,
+	SouthEast,
+}
+
+impl HexDirection {
+	pub const ALL: [HexDirection; 6] = [
+		HexDirection::East,
+		HexDirection::NorthEast,
+		HexDirection::NorthWest,
+		HexDirection::West,
+		HexDirection::SouthWest,
+		HexDirection::SouthEast,
+	];
+
+	/// The unit offset of this direction.
+	pub const fn offset(self) -> Hex {
+		match self {
+			HexDirection::East => Hex::new(1, 0),
+			HexDirection::NorthEast => Hex::new(1, -1),
+			HexDirection::NorthWest => Hex::new(0, -1),
+			HexDirection::West => Hex::new(-1, 0),
+			HexDirection::SouthWest => Hex::new(-1, 1),
+			HexDirection::SouthEast => Hex::new(0, 1),
+		}
+	}
+
+	pub const fn opposite(self) -> HexDirection {
+		match self {
+			HexDirection::East => HexDirection::West,
+			HexDirection::NorthEast => HexDirection::SouthWest,
+			HexDirection::NorthWest => HexDirection::SouthEast,
+			HexDirection::West => HexDirection::East,
+			HexDirection::SouthWest => HexDirection::NorthEast,
+			HexDirection::SouthEast => HexDirection::NorthWest,
+		}
+	}
+}
+
+impl Hex {
+	pub const ZERO: Hex = Hex::new(0, 0);
+
+	pub const fn new(q: i32, r: i32) -> Self {
+		Self { q, r }
+	}
+
+	/// Create a hex from its "odd-r" offset coordinates, where odd rows are shoved right by half a
+	/// hex.  This is the layout a rectangular map is stored in.
+	pub const fn from_offset(col: i32, row: i32) -> Self {
+		Self::new(col - (row - (row & 1)) / 2, row)
+	}
+
+	/// The "odd-r" offset coordinates of this hex as `(col, row)`.
+	pub const fn to_offset(self) -> (i32, i32) {
+		(self.q + (self.r - (self.r & 1)) / 2, self.r)
+	}
+
+	/// The third cube coordinate.
+	pub const fn s(self) -> i32 {
+		-self.q - self.r
+	}
+
+	pub fn neighbor(self, direction: HexDirection) -> Hex {
+		self + direction.offset()
+	}
+
+	pub fn neighbors(self) -> [Hex; 6] {
+		let mut ret = [self; 6];
+		for (hex, dir) in ret.iter_mut().zip(HexDirection::ALL.iter()) {
+			*hex = self.neighbor(*dir);
+		}
+		ret
+	}
+
+	/// The number of steps between two hexes, ignoring any map wrapping.
+	pub fn distance(self, other: Hex) -> u32 {
+		let d = self - other;
+		((d.q.abs() + d.r.abs() + d.s().abs()) / 2) as u32
+	}
+
+	/// All hexes exactly `radius` steps away, in counter-clockwise order.  A radius of 0 is just
+	/// this hex.
+	pub fn ring(self, radius: u32) -> Vec<Hex> {
+		if radius == 0 {
+			return vec![self];
+		}
+		let mut ret = Vec::with_capacity(6 * radius as usize);
+		let mut hex = self + HexDirection::SouthWest.offset() * radius as i32;
+		for dir in HexDirection::ALL.iter() {
+			for _ in 0..radius {
+				ret.push(hex);
+				hex = hex.neighbor(*dir);
+			}
+		}
+		ret
+	}
+
+	/// All hexes within `radius` steps, ordered from the center outwards ring by ring.
+	pub fn spiral(self, radius: u32) -> Vec<Hex> {
+		let mut ret = Vec::with_capacity(1 + 3 * radius as usize * (radius as usize + 1));
+		for r in 0..=radius {
+			ret.extend(self.ring(r));
+		}
+		ret
+	}
+
+	/// The hexes on a straight line from this hex to `other`, both ends included.
+	pub fn line_to(self, other: Hex) -> Vec<Hex> {
+		let n = self.distance(other);
+		if n == 0 {
+			return vec![self];
+		}
+		// Nudge the line slightly off of hex edges so ties always break the same way.
+		let (aq, ar) = (self.q as f64 + 1e-6, self.r as f64 + 1e-6);
+		let (bq, br) = (other.q as f64 + 1e-6, other.r as f64 + 1e-6);
+		(0..=n)
+			.map(|i| {
+				let t = i as f64 / n as f64;
+				Hex::round(aq + (bq - aq) * t, ar + (br - ar) * t)
+			})
+			.collect()
+	}
+
+	/// The center of this hex where hexes are `size` from their center to a corner, with y going
+	/// south.
+	pub fn to_pixel(self, size: f32) -> (f32, f32) {
+		let x = size * 3f32.sqrt() * (self.q as f32 + self.r as f32 / 2.0);
+		(x, size * 1.5 * self.r as f32)
+	}
+
+	/// Round fractional axial coordinates to the hex containing them.
+	pub fn round(q: f64, r: f64) -> Hex {
+		let s = -q - r;
+		let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+		let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+		if dq > dr && dq > ds {
+			rq = -rr - rs;
+		} else if dr > ds {
+			rr = -rq - rs;
+		}
+		Hex::new(rq as i32, rr as i32)
+	}
+}
+
+impl Add for Hex {
+	type Output = Hex;
+
+	fn add(self, rhs: Hex) -> Hex {
+		Hex::new(self.q + rhs.q, self.r + rhs.r)
+	}
+}
+
+impl Sub for Hex {
+	type Output = Hex;
+
+	fn sub(self, rhs: Hex) -> Hex {
+		Hex::new(self.q - rhs.q, self.r - rhs.r)
+	}
+}
+
+impl Neg for Hex {
+	type Output = Hex;
+
+	fn neg(self) -> Hex {
+		Hex::new(-self.q, -self.r)
+	}
+}
+
+impl Mul<i32> for Hex {
+	type Output = Hex;
+
+	fn mul(self, rhs: i32) -> Hex {
+		Hex::new(self.q * rhs, self.r * rhs)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Hex, HexDirection};
+
+	#[test]
+	fn distance() {
+		let a = Hex::new(0, 0);
+		assert_eq!(a.distance(a), 0);
+		for dir in HexDirection::ALL.iter() {
+			assert_eq!(a.distance(a.neighbor(*dir)), 1);
+			assert_eq!(a.neighbor(*dir).neighbor(dir.opposite()), a);
+		}
+		assert_eq!(a.distance(Hex::new(3, -1)), 3);
+		assert_eq!(Hex::new(-2, 4).distance(Hex::new(3, -3)), 7);
+	}
+
+	#[test]
+	fn ring_and_spiral() {
+		let c = Hex::new(2, -5);
+		assert_eq!(c.ring(0), vec![c]);
+		for radius in 1..5 {
+			let ring = c.ring(radius);
+			assert_eq!(ring.len(), 6 * radius as usize);
+			assert!(ring.iter().all(|h| c.distance(*h) == radius));
+		}
+		let spiral = c.spiral(3);
+		assert_eq!(spiral.len(), 37);
+		assert_eq!(spiral[0], c);
+		let mut deduped = spiral.clone();
+		deduped.sort();
+		deduped.dedup();
+		assert_eq!(deduped.len(), spiral.len());
+	}
+
+	#[test]
+	fn line() {
+		let a = Hex::new(0, 0);
+		let b = Hex::new(4, -2);
+		let line = a.line_to(b);
+		assert_eq!(line.len(), 5);
+		assert_eq!(line.first(), Some(&a));
+		assert_eq!(line.last(), Some(&b));
+		for pair in line.windows(2) {
+			assert_eq!(pair[0].distance(pair[1]), 1);
+		}
+		assert_eq!(a.line_to(a), vec![a]);
+	}
+
+	#[test]
+	fn offset() {
+		for row in -3..4 {
+			for col in -3..4 {
+				assert_eq!(Hex::from_offset(col, row).to_offset(), (col, row));
+			}
+		}
+	}
+}
@@ -1,5 +1,9 @@
 use bevy::prelude::*;
 use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The directory the local server keeps its saves in, each save is a directory inside of it.
+pub const SAVES_DIRECTORY: &str = "saves";
 
 #[derive(Default)]
 pub(super) struct LocalServerPlugin;
@@ -7,7 +11,9 @@ pub(super) struct LocalServerPlugin;
 impl Plugin for LocalServerPlugin {
 	fn build(&self, app: &mut AppBuilder) {
 		app.add_event::<LocalServerCommand>()
-			.add_event::<LocalServerPublicState>();
+			.add_event::<LocalServerPublicState>()
+			.add_event::<LocalServerSaveCommand>()
+			.add_event::<LocalServerSaveList>();
 	}
 }
 
@@ -18,9 +24,49 @@ pub enum LocalServerCommand {
 		path: PathBuf,
 		config_only_if_not_existing: bool,
 	},
-	StopServer {
-		force: bool,
-	},
+	/// Stop the server, unless `force` is set the game is saved first.
+	StopServer { force: bool },
+	/// Save the game of a loaded server into its save directory.
+	SaveServer,
+	/// Freeze the simulation of a running server, clients stay connected.
+	PauseServer,
+	/// Continue the simulation of a paused server.
+	ResumeServer,
+}
+
+/// Event to manage the saves of the LocalServer, these work whether or not a game is loaded.  Each
+/// is answered with a `LocalServerSaveList` event.
+#[derive(Debug)]
+pub enum LocalServerSaveCommand {
+	/// Scan the saves directory.
+	List,
+	/// Delete a save, the save currently loaded by the server cannot be deleted.
+	Delete { path: PathBuf },
+	/// Copy a save to a new save next to it.
+	Duplicate { path: PathBuf },
+}
+
+/// Event with every save in the saves directory, most recently played first.
+#[derive(Debug, Clone, Default)]
+pub struct LocalServerSaveList(pub Vec<SaveInfo>);
+
+/// A summary of a save to show in a save browser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveInfo {
+	/// The name of the save directory.
+	pub name: String,
+	/// The path to pass to `LocalServerCommand::CreateStartServer` to load this save.
+	pub path: PathBuf,
+	/// When the save was last written.
+	pub last_played: Option<SystemTime>,
+	/// The turn of the game, `None` if the game has not been started yet.
+	pub turn: Option<u32>,
+	/// The civilization of the first player.
+	pub civ: Option<String>,
+	/// The map size as `(width, height)`.
+	pub map_size: Option<(u32, u32)>,
+	/// If this binary can load the save, it is not if the save is from a newer version or broken.
+	pub compatible: bool,
 }
 
 /// A resource that is inserted when the local server is compiled in, and doesn't when its not.
@@ -29,7 +75,7 @@ pub enum LocalServerCommand {
 ///
 /// Do not add this resource in yourself unless you've implemented a local server for the client to
 /// communicate with.
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum LocalServerPublicState {
 	/// A LocalServer is not running
 	Off,
@@ -38,6 +84,8 @@ pub enum LocalServerPublicState {
 	Loading(f64),
 	/// A LocalServer is running and ready for connection
 	Running,
+	/// A LocalServer is loaded but its simulation is frozen, it can still be connected to
+	Paused,
 	/// A LocalServer is shutting down
 	ShuttingDown,
 }
@@ -0,0 +1,329 @@
+use super::hex::{Hex, HexDirection};
+use super::ids::{CityId, PlayerId};
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::convert::TryFrom;
+
+/// Base terrain of a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Terrain {
+	Ocean,
+	Coast,
+	Lake,
+	Grassland,
+	Plains,
+	Desert,
+	Tundra,
+	Snow,
+}
+
+impl Default for Terrain {
+	fn default() -> Self {
+		Terrain::Ocean
+	}
+}
+
+impl Terrain {
+	pub fn is_water(self) -> bool {
+		matches!(self, Terrain::Ocean | Terrain::Coast | Terrain::Lake)
+	}
+}
+
+/// A feature covering the base terrain of a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Feature {
+	Forest,
+	Jungle,
+	Marsh,
+	Oasis,
+	FloodPlains,
+	Ice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Elevation {
+	Flat,
+	Hills,
+	Mountains,
+}
+
+impl Default for Elevation {
+	fn default() -> Self {
+		Elevation::Flat
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Tile {
+	pub terrain: Terrain,
+	pub feature: Option<Feature>,
+	pub elevation: Elevation,
+	/// The edges a river runs along, a bit for each `HexDirection`.  Set with `WorldMap::set_river`
+	/// so both tiles of an edge agree.
+	#[serde(default)]
+	pub rivers: u8,
+	#[serde(default)]
+	pub road: bool,
+	/// The resource on the tile, a key of the server's rules.
+	#[serde(default)]
+	pub resource: Option<SmolStr>,
+	/// The improvement built on the tile, a key of the server's rules.
+	#[serde(default)]
+	pub improvement: Option<SmolStr>,
+	/// The city whose territory the tile is in.
+	#[serde(default)]
+	pub owner: Option<TileOwner>,
+}
+
+/// The city a tile of its territory belongs to, and that city's owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TileOwner {
+	pub player: PlayerId,
+	pub city: CityId,
+}
+
+impl Tile {
+	/// If a river runs along the edge of the tile in `direction`.
+	pub fn has_river(&self, direction: HexDirection) -> bool {
+		self.rivers & (1 << direction as u8) != 0
+	}
+}
+
+/// The hex map of a game, a rectangle of `width` by `height` tiles stored in "odd-r" offset layout
+/// that wraps around east to west like a cylinder.  Does not wrap north to south.
+///
+/// Any `Hex` passed in is accepted in any of its wrapped forms, use `normalize` to get the single
+/// canonical form that is returned by the queries here.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "RawWorldMap")]
+pub struct WorldMap {
+	width: u32,
+	height: u32,
+	tiles: Vec<Tile>,
+}
+
+/// Unchecked `WorldMap` as it was deserialized.
+#[derive(Deserialize)]
+struct RawWorldMap {
+	width: u32,
+	height: u32,
+	tiles: Vec<Tile>,
+}
+
+impl TryFrom<RawWorldMap> for WorldMap {
+	type Error = String;
+
+	fn try_from(raw: RawWorldMap) -> Result<Self, Self::Error> {
+		if raw.width == 0 || raw.height == 0 {
+			return Err(format!("empty map of size {}x{}", raw.width, raw.height));
+		}
+		if raw.tiles.len() != raw.width as usize * raw.height as usize {
+			return Err(format!(
+				"map of size {}x{} has {} tiles",
+				raw.width,
+				raw.height,
+				raw.tiles.len()
+			));
+		}
+		Ok(WorldMap {
+			width: raw.width,
+			height: raw.height,
+			tiles: raw.tiles,
+		})
+	}
+}
+
+impl WorldMap {
+	/// Create a map of only default tiles.
+	pub fn new(width: u32, height: u32) -> Self {
+		assert!(width > 0 && height > 0, "a WorldMap must not be empty");
+		Self {
+			width,
+			height,
+			tiles: vec![Tile::default(); width as usize * height as usize],
+		}
+	}
+
+	pub fn width(&self) -> u32 {
+		self.width
+	}
+
+	pub fn height(&self) -> u32 {
+		self.height
+	}
+
+	/// The canonical form of the hex with its column wrapped into the map, or `None` if it is
+	/// north or south of the map.
+	pub fn normalize(&self, hex: Hex) -> Option<Hex> {
+		let (col, row) = hex.to_offset();
+		if row < 0 || row >= self.height as i32 {
+			return None;
+		}
+		Some(Hex::from_offset(col.rem_euclid(self.width as i32), row))
+	}
+
+	pub fn contains(&self, hex: Hex) -> bool {
+		self.normalize(hex).is_some()
+	}
+
+	/// Total number of tiles on the map.
+	pub fn tile_count(&self) -> usize {
+		self.tiles.len()
+	}
+
+	/// The dense index of a tile, from 0 to `tile_count`, in the same order as `iter`.  Useful to
+	/// store extra per-tile data alongside the map.
+	pub fn tile_index(&self, hex: Hex) -> Option<usize> {
+		let (col, row) = self.normalize(hex)?.to_offset();
+		Some(row as usize * self.width as usize + col as usize)
+	}
+
+	/// The canonical hex of a dense tile index, the inverse of `tile_index`.
+	pub fn tile_hex(&self, idx: usize) -> Hex {
+		let width = self.width as usize;
+		Hex::from_offset((idx % width) as i32, (idx / width) as i32)
+	}
+
+	pub fn get(&self, hex: Hex) -> Option<&Tile> {
+		self.tile_index(hex).map(move |idx| &self.tiles[idx])
+	}
+
+	pub fn get_mut(&mut self, hex: Hex) -> Option<&mut Tile> {
+		self.tile_index(hex).map(move |idx| &mut self.tiles[idx])
+	}
+
+	/// Every tile of the map with its canonical hex, row by row from the north-west corner.
+	pub fn iter(&self) -> impl Iterator<Item = (Hex, &Tile)> {
+		self.tiles
+			.iter()
+			.enumerate()
+			.map(move |(idx, tile)| (self.tile_hex(idx), tile))
+	}
+
+	pub fn iter_mut(&mut self) -> impl Iterator<Item = (Hex, &mut Tile)> {
+		let width = self.width as usize;
+		self.tiles.iter_mut().enumerate().map(move |(idx, tile)| {
+			(
+				Hex::from_offset((idx % width) as i32, (idx / width) as i32),
+				tile,
+			)
+		})
+	}
+
+	/// Put a river along the edge of `hex` in `direction`, on both tiles sharing the edge.
+	pub fn set_river(&mut self, hex: Hex, direction: HexDirection) {
+		if let Some(tile) = self.get_mut(hex) {
+			tile.rivers |= 1 << direction as u8;
+		}
+		if let Some(tile) = self.get_mut(hex.neighbor(direction)) {
+			tile.rivers |= 1 << direction.opposite() as u8;
+		}
+	}
+
+	/// The neighbors of the hex that are on the map.
+	pub fn neighbors(&self, hex: Hex) -> impl Iterator<Item = Hex> + '_ {
+		HexDirection::ALL
+			.iter()
+			.filter_map(move |dir| self.normalize(hex.neighbor(*dir)))
+	}
+
+	/// The wrapped form of `to` that is closest to `from`.
+	fn nearest_wrap(&self, from: Hex, to: Hex) -> Hex {
+		let width = self.width as i32;
+		let to = self.normalize(to).unwrap_or(to);
+		[to, to + Hex::new(width, 0), to - Hex::new(width, 0)]
+			.iter()
+			.copied()
+			.min_by_key(|h| from.distance(*h))
+			.unwrap_or(to)
+	}
+
+	/// The distance between two hexes, going whichever way around the map is shorter.
+	pub fn distance(&self, a: Hex, b: Hex) -> u32 {
+		a.distance(self.nearest_wrap(a, b))
+	}
+
+	/// Normalize and de-duplicate hexes, dropping those off the map but otherwise keeping order.
+	fn on_map(&self, hexes: Vec<Hex>) -> Vec<Hex> {
+		let mut ret: Vec<Hex> = Vec::with_capacity(hexes.len());
+		for hex in hexes.into_iter().filter_map(|h| self.normalize(h)) {
+			if !ret.contains(&hex) {
+				ret.push(hex);
+			}
+		}
+		ret
+	}
+
+	/// All on-map hexes exactly `radius` steps from `center`.
+	pub fn ring(&self, center: Hex, radius: u32) -> Vec<Hex> {
+		self.on_map(center.ring(radius))
+	}
+
+	/// All on-map hexes within `radius` steps of `center`, ordered from the center outwards.
+	pub fn spiral(&self, center: Hex, radius: u32) -> Vec<Hex> {
+		self.on_map(center.spiral(radius))
+	}
+
+	/// The on-map hexes of a straight line between `a` and `b`, going whichever way around the map
+	/// is shorter.
+	pub fn line(&self, a: Hex, b: Hex) -> Vec<Hex> {
+		self.on_map(a.line_to(self.nearest_wrap(a, b)))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Hex, HexDirection, Terrain, WorldMap};
+
+	#[test]
+	fn wrapping() {
+		let map = WorldMap::new(10, 6);
+		let west_edge = Hex::from_offset(0, 2);
+		let east_edge = Hex::from_offset(9, 2);
+		assert_eq!(map.normalize(Hex::from_offset(10, 2)), Some(west_edge));
+		assert_eq!(map.normalize(Hex::from_offset(-1, 2)), Some(east_edge));
+		assert_eq!(map.normalize(Hex::from_offset(0, -1)), None);
+		assert_eq!(map.normalize(Hex::from_offset(0, 6)), None);
+		assert_eq!(map.distance(west_edge, east_edge), 1);
+		assert!(map.neighbors(west_edge).any(|h| h == east_edge));
+		assert_eq!(map.line(west_edge, east_edge), vec![west_edge, east_edge]);
+		// The top row has no northern neighbors
+		assert_eq!(map.neighbors(Hex::from_offset(3, 0)).count(), 4);
+		assert_eq!(map.spiral(Hex::from_offset(0, 0), 1).len(), 5);
+	}
+
+	#[test]
+	fn tiles() {
+		let mut map = WorldMap::new(4, 3);
+		assert_eq!(map.iter().count(), 12);
+		let hex = Hex::from_offset(3, 1);
+		map.get_mut(hex).unwrap().terrain = Terrain::Grassland;
+		assert_eq!(
+			map.get(hex + Hex::new(4, 0)).unwrap().terrain,
+			Terrain::Grassland
+		);
+		assert_eq!(
+			map.iter()
+				.filter(|(_, t)| t.terrain == Terrain::Grassland)
+				.map(|(h, _)| h)
+				.collect::<Vec<_>>(),
+			vec![hex]
+		);
+		assert!(map.get(Hex::from_offset(0, 3)).is_none());
+
+		// A river on the wrapping edge is seen from both sides
+		map.set_river(Hex::from_offset(0, 1), HexDirection::West);
+		assert!(map
+			.get(Hex::from_offset(0, 1))
+			.unwrap()
+			.has_river(HexDirection::West));
+		assert!(map
+			.get(Hex::from_offset(3, 1))
+			.unwrap()
+			.has_river(HexDirection::East));
+		assert!(!map
+			.get(Hex::from_offset(3, 1))
+			.unwrap()
+			.has_river(HexDirection::West));
+	}
+}
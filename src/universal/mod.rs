@@ -1,8 +1,17 @@
+pub mod borders;
 pub mod commands;
 pub mod conditional_map;
+pub mod connection;
 pub mod exit;
+pub mod hex;
 pub mod i18n;
+pub mod ids;
 pub mod local_server;
+pub mod map;
+pub mod recent_servers;
+pub mod replica;
+pub mod transport;
+pub mod yields;
 
 pub use i18n::I18n;
 
@@ -11,6 +20,9 @@ use bevy::prelude::*;
 use std::path::PathBuf;
 use unic_langid::LanguageIdentifier;
 
+/// Resource of the directory configuration files are stored in.
+pub struct ConfigDirectory(pub PathBuf);
+
 pub struct UniversalPluginGroup {
 	languages_root_path: PathBuf,
 	languages: Vec<LanguageIdentifier>,
@@ -59,6 +71,8 @@ impl PluginGroup for UniversalPluginGroup {
 				self.languages_root_path.clone(),
 				self.languages.clone(),
 			))
-			.add(local_server::LocalServerPlugin::default());
+			.add(local_server::LocalServerPlugin::default())
+			.add(connection::ConnectionPlugin::default())
+			.add(replica::ReplicaPlugin::default());
 	}
 }
@@ -0,0 +1,124 @@
+//! The servers a client recently joined, stored in the config directory so they can be picked again.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::SystemTime;
+
+pub const RECENT_SERVERS_FILE_NAME: &str = "recent_servers.ron";
+
+/// How many servers are remembered, the least recently joined are forgotten first.
+pub const MAX_RECENT_SERVERS: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecentServersError {
+	#[error("IO error while {1}")]
+	Io(#[source] std::io::Error, &'static str),
+	#[error("ron format error")]
+	Ron(#[from] ron::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentServer {
+	pub address: String,
+	/// The name the player joined with.
+	pub player_name: String,
+	pub last_joined: SystemTime,
+}
+
+/// Resource of the recently joined servers, most recent first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecentServers {
+	/// The name last joined with on any server, including the local one.
+	pub player_name: String,
+	pub servers: Vec<RecentServer>,
+}
+
+impl Default for RecentServers {
+	fn default() -> Self {
+		Self {
+			player_name: "Player".to_owned(),
+			servers: vec![],
+		}
+	}
+}
+
+impl RecentServers {
+	/// Read the recent servers from the config directory, a missing or broken file is logged and
+	/// treated as empty as it is not worth failing over.
+	pub fn load(config_dir: &Path) -> Self {
+		let path = config_dir.join(RECENT_SERVERS_FILE_NAME);
+		if !path.is_file() {
+			return Self::default();
+		}
+		match std::fs::read_to_string(&path)
+			.map_err(|e| RecentServersError::Io(e, "reading recent servers"))
+			.and_then(|data| Ok(ron::from_str(&data)?))
+		{
+			Ok(recent) => recent,
+			Err(e) => {
+				warn!("Ignoring recent servers at `{:?}`: {}", path, e);
+				Self::default()
+			}
+		}
+	}
+
+	pub fn save(&self, config_dir: &Path) -> Result<(), RecentServersError> {
+		let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+		std::fs::create_dir_all(config_dir)
+			.map_err(|e| RecentServersError::Io(e, "creating config directory"))?;
+		std::fs::write(config_dir.join(RECENT_SERVERS_FILE_NAME), data)
+			.map_err(|e| RecentServersError::Io(e, "writing recent servers"))
+	}
+
+	/// Remember joining `address` as `player_name` just now.
+	pub fn record(&mut self, address: &str, player_name: &str) {
+		self.player_name = player_name.to_owned();
+		self.servers.retain(|server| server.address != address);
+		self.servers.insert(
+			0,
+			RecentServer {
+				address: address.to_owned(),
+				player_name: player_name.to_owned(),
+				last_joined: SystemTime::now(),
+			},
+		);
+		self.servers.truncate(MAX_RECENT_SERVERS);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{RecentServers, MAX_RECENT_SERVERS};
+
+	#[test]
+	fn record_and_reload() {
+		let mut recent = RecentServers::default();
+		for idx in 0..MAX_RECENT_SERVERS + 2 {
+			recent.record(&format!("server-{}", idx), "Alice");
+		}
+		recent.record("server-5", "Bob");
+		assert_eq!(recent.servers.len(), MAX_RECENT_SERVERS);
+		assert_eq!(recent.servers[0].address, "server-5");
+		assert_eq!(recent.servers[0].player_name, "Bob");
+		assert_eq!(recent.servers[1].address, "server-11");
+		assert_eq!(recent.player_name, "Bob");
+		assert_eq!(
+			recent
+				.servers
+				.iter()
+				.filter(|s| s.address == "server-5")
+				.count(),
+			1
+		);
+
+		let dir =
+			std::env::temp_dir().join(format!("over_civ_recent_servers_{}", std::process::id()));
+		assert_eq!(RecentServers::load(&dir), RecentServers::default());
+		recent.save(&dir).unwrap();
+		let loaded = RecentServers::load(&dir);
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(loaded, recent);
+	}
+}
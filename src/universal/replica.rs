@@ -0,0 +1,65 @@
+//! The client's copy of the game state, kept up to date from the `State` and `Delta` messages of
+//! the server.  If a delta does not give the checksum the server had the copy drifted from the
+//! server's state, so the full state is asked for again.
+
+use crate::universal::commands::state::GameState;
+use crate::universal::commands::{ClientCommand, ServerCommand};
+use crate::universal::connection::{Connection, ConnectionEvent, ServerMessage};
+use bevy::prelude::*;
+
+/// Keeps the `Option<GameState>` resource, `None` until the server sent the full state.
+#[derive(Default)]
+pub struct ReplicaPlugin;
+
+impl Plugin for ReplicaPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<Option<GameState>>()
+			.add_system(update_replica.system());
+	}
+}
+
+fn update_replica(
+	mut messages: EventReader<ServerMessage>,
+	mut events: EventReader<ConnectionEvent>,
+	mut replica: ResMut<Option<GameState>>,
+	mut connection: ResMut<Option<Connection>>,
+	// Deltas are skipped while waiting for the full state after a mismatch
+	mut resyncing: Local<bool>,
+) {
+	if events
+		.iter()
+		.any(|event| matches!(event, ConnectionEvent::Disconnected(_)))
+	{
+		*replica = None;
+		*resyncing = false;
+	}
+	for ServerMessage(message) in messages.iter() {
+		match &message.command {
+			ServerCommand::State(state) => {
+				*replica = Some(state.clone());
+				*resyncing = false;
+			}
+			ServerCommand::Delta(delta) if !*resyncing => {
+				let state = match &mut *replica {
+					Some(state) => state,
+					None => {
+						trace!("Ignoring a delta before the full state");
+						continue;
+					}
+				};
+				if state.apply(delta) {
+					continue;
+				}
+				warn!("Game state checksum mismatch, requesting the full state");
+				*resyncing = true;
+				if let Some(conn) = &mut *connection {
+					if let Err(e) = conn.send(ClientCommand::RequestState) {
+						warn!("Failed requesting the game state: {}", e);
+					}
+				}
+			}
+			ServerCommand::GameEnded => *replica = None,
+			_ => (),
+		}
+	}
+}
@@ -0,0 +1,153 @@
+//! In-memory transport for a server running in the same process as its client.
+
+use super::{ClientTransport, Listener, ServerTransport, Transport, TransportError};
+use crate::universal::commands::{ClientCommand, Message, ServerCommand};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+
+pub struct ChannelTransport<Out, In> {
+	sender: Mutex<Sender<Message<Out>>>,
+	receiver: Mutex<Receiver<Message<In>>>,
+}
+
+/// Two connected ends, what one sends the other receives.
+pub fn pair<A, B>() -> (ChannelTransport<A, B>, ChannelTransport<B, A>) {
+	let (a_sender, b_receiver) = channel();
+	let (b_sender, a_receiver) = channel();
+	(
+		ChannelTransport {
+			sender: Mutex::new(a_sender),
+			receiver: Mutex::new(a_receiver),
+		},
+		ChannelTransport {
+			sender: Mutex::new(b_sender),
+			receiver: Mutex::new(b_receiver),
+		},
+	)
+}
+
+impl<Out: Send, In: Send> Transport<Out, In> for ChannelTransport<Out, In> {
+	fn send(&self, message: Message<Out>) -> Result<(), TransportError> {
+		self.sender
+			.lock()
+			.expect("poisoned ChannelTransport lock")
+			.send(message)
+			.map_err(|_| TransportError::Disconnected)
+	}
+
+	fn try_recv(&self) -> Result<Option<Message<In>>, TransportError> {
+		match self
+			.receiver
+			.lock()
+			.expect("poisoned ChannelTransport lock")
+			.try_recv()
+		{
+			Ok(message) => Ok(Some(message)),
+			Err(TryRecvError::Empty) => Ok(None),
+			Err(TryRecvError::Disconnected) => Err(TransportError::Disconnected),
+		}
+	}
+
+	fn peer(&self) -> String {
+		"local".to_owned()
+	}
+}
+
+/// Resource inserted by the local server that clients in the same process connect through.
+pub struct LocalConnector {
+	sender: Mutex<Sender<ServerTransport>>,
+}
+
+impl LocalConnector {
+	pub fn connect(&self) -> Result<ClientTransport, TransportError> {
+		let (client, server) = pair::<ClientCommand, ServerCommand>();
+		self.sender
+			.lock()
+			.expect("poisoned LocalConnector lock")
+			.send(Box::new(server))
+			.map_err(|_| TransportError::NoLocalServer)?;
+		Ok(Box::new(client))
+	}
+}
+
+/// The server side of a `LocalConnector`.
+pub struct ChannelListener {
+	receiver: Mutex<Receiver<ServerTransport>>,
+}
+
+impl Listener for ChannelListener {
+	fn try_accept(&self) -> Result<Option<ServerTransport>, TransportError> {
+		match self
+			.receiver
+			.lock()
+			.expect("poisoned ChannelListener lock")
+			.try_recv()
+		{
+			Ok(transport) => Ok(Some(transport)),
+			Err(TryRecvError::Empty) => Ok(None),
+			Err(TryRecvError::Disconnected) => Err(TransportError::Disconnected),
+		}
+	}
+}
+
+pub fn local_listener() -> (LocalConnector, ChannelListener) {
+	let (sender, receiver) = channel();
+	(
+		LocalConnector {
+			sender: Mutex::new(sender),
+		},
+		ChannelListener {
+			receiver: Mutex::new(receiver),
+		},
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::local_listener;
+	use crate::universal::commands::{ClientCommand, ResumeToken, Sequencer, ServerCommand};
+	use crate::universal::ids::PlayerId;
+	use crate::universal::transport::{Listener, TransportError};
+
+	#[test]
+	fn connect() {
+		let (connector, listener) = local_listener();
+		assert!(listener.try_accept().unwrap().is_none());
+		let client = connector.connect().unwrap();
+		let server = listener.try_accept().unwrap().unwrap();
+		let mut client_seq = Sequencer::default();
+		let mut server_seq = Sequencer::default();
+
+		assert!(server.try_recv().unwrap().is_none());
+		let join = client_seq.next(ClientCommand::Join {
+			name: "test".to_owned(),
+			player: None,
+		});
+		client.send(join.clone()).unwrap();
+		assert_eq!(server.try_recv().unwrap(), Some(join));
+		let joined = server_seq.next(ServerCommand::Joined {
+			player: PlayerId(1),
+			token: ResumeToken(7),
+		});
+		server.send(joined.clone()).unwrap();
+		assert_eq!(client.try_recv().unwrap(), Some(joined));
+
+		// Messages sent before a disconnect are still delivered
+		client.send(client_seq.next(ClientCommand::Leave)).unwrap();
+		drop(client);
+		assert!(server.try_recv().unwrap().is_some());
+		assert!(matches!(
+			server.try_recv(),
+			Err(TransportError::Disconnected)
+		));
+		assert!(server
+			.send(server_seq.next(ServerCommand::TurnStarted { turn: 1 }))
+			.is_err());
+
+		drop(listener);
+		assert!(matches!(
+			connector.connect(),
+			Err(TransportError::NoLocalServer)
+		));
+	}
+}
@@ -0,0 +1,248 @@
+//! LAN discovery, a server listening for remote clients broadcasts an announcement over UDP every
+//! few seconds and clients on the same network listen for them to list the games they can join.
+
+use super::TransportError;
+use crate::universal::commands::{GAME_VERSION, PROTOCOL_VERSION};
+use net2::UdpBuilder;
+use serde::{Deserialize, Serialize};
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Port announcements are broadcast to.
+pub const DISCOVERY_PORT: u16 = 24_866;
+
+/// How often a server announces itself.
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A server not heard from for this long is assumed gone.
+pub const DISCOVERY_EXPIRY: Duration = Duration::from_secs(7);
+
+/// Prefixed to every announcement so that other traffic on the port is ignored.
+const MAGIC: &[u8] = b"OVERCIV-LAN\n";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerAnnouncement {
+	pub name: String,
+	/// The TCP port the server accepts clients on, at the address the announcement came from.
+	pub port: u16,
+	/// Clients that joined the game.
+	pub players: u32,
+	/// Players in the game, 0 if no game is loaded.
+	pub max_players: u32,
+	pub protocol: u32,
+	pub game_version: String,
+	pub password: bool,
+}
+
+impl ServerAnnouncement {
+	/// If this binary can join the server.
+	pub fn compatible(&self) -> bool {
+		self.protocol == PROTOCOL_VERSION && self.game_version == GAME_VERSION
+	}
+
+	fn encode(&self) -> Result<Vec<u8>, TransportError> {
+		let mut data = MAGIC.to_vec();
+		data.extend_from_slice(&serde_json::to_vec(self)?);
+		Ok(data)
+	}
+
+	fn decode(data: &[u8]) -> Option<Self> {
+		if !data.starts_with(MAGIC) {
+			return None;
+		}
+		serde_json::from_slice(&data[MAGIC.len()..]).ok()
+	}
+}
+
+/// Sends announcements, by default to the broadcast address of the local network.
+pub struct Announcer {
+	socket: UdpSocket,
+	targets: Vec<SocketAddr>,
+}
+
+impl Announcer {
+	pub fn broadcast() -> Result<Self, TransportError> {
+		Self::to_targets(vec![(Ipv4Addr::BROADCAST, DISCOVERY_PORT).into()])
+	}
+
+	/// Send announcements only to these addresses.
+	pub fn to_targets(targets: Vec<SocketAddr>) -> Result<Self, TransportError> {
+		let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+		socket.set_broadcast(true)?;
+		Ok(Self { socket, targets })
+	}
+
+	pub fn announce(&self, announcement: &ServerAnnouncement) -> Result<(), TransportError> {
+		let data = announcement.encode()?;
+		for target in &self.targets {
+			self.socket.send_to(&data, target)?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+	/// The address to connect to, as `host:port`.
+	pub address: String,
+	pub announcement: ServerAnnouncement,
+	pub last_seen: Instant,
+}
+
+/// Receives announcements without blocking.
+pub struct DiscoveryListener {
+	socket: UdpSocket,
+}
+
+impl DiscoveryListener {
+	/// Listen on the `DISCOVERY_PORT` of every interface.
+	pub fn bind_default() -> Result<Self, TransportError> {
+		Self::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))
+	}
+
+	/// Listen on `address`, other listeners can listen on the same address so that every client on
+	/// a machine finds the games.
+	pub fn bind(address: impl ToSocketAddrs) -> Result<Self, TransportError> {
+		let address = address
+			.to_socket_addrs()?
+			.next()
+			.ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "no address to listen on"))?;
+		let builder = match address {
+			SocketAddr::V4(_) => UdpBuilder::new_v4()?,
+			SocketAddr::V6(_) => UdpBuilder::new_v6()?,
+		};
+		builder.reuse_address(true)?;
+		#[cfg(unix)]
+		net2::unix::UnixUdpBuilderExt::reuse_port(&builder, true)?;
+		let socket = builder.bind(address)?;
+		socket.set_nonblocking(true)?;
+		Ok(Self { socket })
+	}
+
+	pub fn local_addr(&self) -> Result<SocketAddr, TransportError> {
+		Ok(self.socket.local_addr()?)
+	}
+
+	/// The next announcement received, anything that is not an announcement is skipped.
+	pub fn try_recv(&self) -> Result<Option<DiscoveredServer>, TransportError> {
+		let mut buffer = [0u8; 2048];
+		loop {
+			match self.socket.recv_from(&mut buffer) {
+				Ok((len, from)) => {
+					if let Some(announcement) = ServerAnnouncement::decode(&buffer[..len]) {
+						return Ok(Some(DiscoveredServer {
+							address: SocketAddr::new(from.ip(), announcement.port).to_string(),
+							announcement,
+							last_seen: Instant::now(),
+						}));
+					}
+				}
+				Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+				Err(e) => return Err(e.into()),
+			}
+		}
+	}
+}
+
+/// Resource of the servers found on the LAN, sorted by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiscoveredServers(pub Vec<DiscoveredServer>);
+
+impl DiscoveredServers {
+	pub fn insert(&mut self, server: DiscoveredServer) {
+		match self.0.iter_mut().find(|s| s.address == server.address) {
+			Some(existing) => *existing = server,
+			None => self.0.push(server),
+		}
+		self.0.sort_by(|a, b| {
+			(&a.announcement.name, &a.address).cmp(&(&b.announcement.name, &b.address))
+		});
+	}
+
+	/// Forget the servers not heard from since `DISCOVERY_EXPIRY` before `now`, returns if any were.
+	pub fn expire(&mut self, now: Instant) -> bool {
+		let len = self.0.len();
+		self.0
+			.retain(|server| now.saturating_duration_since(server.last_seen) < DISCOVERY_EXPIRY);
+		len != self.0.len()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		Announcer, DiscoveredServers, DiscoveryListener, ServerAnnouncement, DISCOVERY_EXPIRY,
+	};
+	use crate::universal::commands::{GAME_VERSION, PROTOCOL_VERSION};
+	use std::net::UdpSocket;
+	use std::time::{Duration, Instant};
+
+	fn announcement(name: &str) -> ServerAnnouncement {
+		ServerAnnouncement {
+			name: name.to_owned(),
+			port: 4000,
+			players: 1,
+			max_players: 4,
+			protocol: PROTOCOL_VERSION,
+			game_version: GAME_VERSION.to_owned(),
+			password: true,
+		}
+	}
+
+	fn receive(listener: &DiscoveryListener) -> super::DiscoveredServer {
+		let start = Instant::now();
+		loop {
+			if let Some(server) = listener.try_recv().unwrap() {
+				return server;
+			}
+			assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+			std::thread::sleep(Duration::from_millis(5));
+		}
+	}
+
+	#[test]
+	fn loopback() {
+		let listener = DiscoveryListener::bind("127.0.0.1:0").unwrap();
+		let target = listener.local_addr().unwrap();
+		assert!(listener.try_recv().unwrap().is_none());
+
+		// Other traffic on the port is skipped
+		let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+		other.send_to(b"not an announcement", target).unwrap();
+
+		let announcer = Announcer::to_targets(vec![target]).unwrap();
+		announcer.announce(&announcement("Alpha")).unwrap();
+		let server = receive(&listener);
+		assert_eq!(server.address, "127.0.0.1:4000");
+		assert_eq!(server.announcement, announcement("Alpha"));
+		assert!(server.announcement.compatible());
+		assert!(listener.try_recv().unwrap().is_none());
+
+		// Other clients on the same machine listen on the same port
+		assert!(DiscoveryListener::bind(target).is_ok());
+	}
+
+	#[test]
+	fn discovered_servers() {
+		let now = Instant::now();
+		let server = |name: &str, address: &str, last_seen| super::DiscoveredServer {
+			address: address.to_owned(),
+			announcement: announcement(name),
+			last_seen,
+		};
+		let mut servers = DiscoveredServers::default();
+		servers.insert(server("Beta", "10.0.0.2:4000", now - DISCOVERY_EXPIRY));
+		servers.insert(server("Alpha", "10.0.0.1:4000", now));
+		servers.insert(server("Gamma", "10.0.0.2:4000", now));
+		let names: Vec<_> = servers
+			.0
+			.iter()
+			.map(|s| s.announcement.name.as_str())
+			.collect();
+		assert_eq!(names, vec!["Alpha", "Gamma"]);
+		assert!(!servers.expire(now));
+		assert!(servers.expire(now + DISCOVERY_EXPIRY));
+		assert!(servers.0.is_empty());
+	}
+}
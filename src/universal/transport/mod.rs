@@ -0,0 +1,50 @@
+//! Transports carry protocol messages between a client and the server, whichever way they are
+//! connected.  Neither end cares which transport is in use.
+
+pub mod channel;
+pub mod lan;
+pub mod tcp;
+
+use crate::universal::commands::{ClientCommand, Message, ServerCommand};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+	#[error("the other end disconnected")]
+	Disconnected,
+	#[error("no local server is running")]
+	NoLocalServer,
+	#[error("unknown host: {0}")]
+	UnknownHost(String),
+	#[error("the server refused the connection: {0}")]
+	Refused(String),
+	#[error("message of {0} bytes is too large")]
+	FrameTooLarge(usize),
+	#[error("IO error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("malformed message: {0}")]
+	Malformed(#[from] serde_json::Error),
+}
+
+/// One end of a connection, sending `Out` commands and receiving `In` commands.  Dropping it
+/// closes the connection.
+pub trait Transport<Out, In>: Send + Sync {
+	fn send(&self, message: Message<Out>) -> Result<(), TransportError>;
+
+	/// The next received message, without blocking if there is none yet.
+	fn try_recv(&self) -> Result<Option<Message<In>>, TransportError>;
+
+	/// A description of the other end for logging.
+	fn peer(&self) -> String;
+}
+
+/// The client's end of a connection to a server.
+pub type ClientTransport = Box<dyn Transport<ClientCommand, ServerCommand>>;
+
+/// The server's end of a connection to a client.
+pub type ServerTransport = Box<dyn Transport<ServerCommand, ClientCommand>>;
+
+/// Accepts new client connections for the server.
+pub trait Listener: Send + Sync {
+	/// A newly connected client, without blocking if there is none yet.
+	fn try_accept(&self) -> Result<Option<ServerTransport>, TransportError>;
+}
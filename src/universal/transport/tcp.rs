@@ -0,0 +1,525 @@
+//! TCP transport for servers in another process or on another machine.
+//!
+//! Every message is sent as a frame of a big endian `u32` length followed by that many bytes of
+//! JSON.  The first frame each way is a `Handshake`, the server refuses clients whose protocol or
+//! game version is not its own, or that did not give its password, and closes the connection.
+
+use super::{ClientTransport, Listener, ServerTransport, Transport, TransportError};
+use crate::universal::commands::{
+	ClientCommand, Message, ServerCommand, GAME_VERSION, PROTOCOL_VERSION,
+};
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Port used when an address does not give one.
+pub const DEFAULT_PORT: u16 = 24_865;
+
+/// Frames larger than this are refused, the full game state is by far the largest message.
+pub const MAX_FRAME_SIZE: u32 = 64 * 1024 * 1024;
+
+/// Handshake frames larger than this are refused, so that nobody can make the server set aside
+/// much memory before they were accepted.
+const MAX_HANDSHAKE_SIZE: u32 = 4 * 1024;
+
+/// How long connecting and the handshake may take before giving up.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long writing a frame may block the writer thread before the other end counts as gone.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Handshake {
+	Hello {
+		protocol: u32,
+		game: String,
+		#[serde(default)]
+		password: Option<String>,
+	},
+	Accepted,
+	Refused {
+		reason: String,
+	},
+}
+
+impl Handshake {
+	fn hello(password: Option<&str>) -> Self {
+		Handshake::Hello {
+			protocol: PROTOCOL_VERSION,
+			game: GAME_VERSION.to_owned(),
+			password: password.map(str::to_owned),
+		}
+	}
+}
+
+fn encode_frame<T: Serialize>(value: &T) -> Result<Vec<u8>, TransportError> {
+	let data = serde_json::to_vec(value)?;
+	if data.len() > MAX_FRAME_SIZE as usize {
+		return Err(TransportError::FrameTooLarge(data.len()));
+	}
+	let mut frame = Vec::with_capacity(4 + data.len());
+	frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	frame.extend_from_slice(&data);
+	Ok(frame)
+}
+
+fn write_frame<T: Serialize>(mut stream: &TcpStream, value: &T) -> Result<(), TransportError> {
+	stream.write_all(&encode_frame(value)?)?;
+	Ok(())
+}
+
+/// Read a frame of at most `max_size` bytes.
+fn read_frame<T: DeserializeOwned>(
+	mut stream: &TcpStream,
+	max_size: u32,
+) -> Result<T, TransportError> {
+	let mut len = [0u8; 4];
+	match stream.read_exact(&mut len) {
+		Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(TransportError::Disconnected),
+		result => result?,
+	}
+	let len = u32::from_be_bytes(len);
+	if len > max_size {
+		return Err(TransportError::FrameTooLarge(len as usize));
+	}
+	// Grows as the data arrives rather than all at once for whatever length the peer claims
+	let mut data = Vec::new();
+	stream.take(len.into()).read_to_end(&mut data)?;
+	if data.len() < len as usize {
+		return Err(TransportError::Disconnected);
+	}
+	Ok(serde_json::from_slice(&data)?)
+}
+
+/// An established connection, background threads write the outgoing frames and read the incoming
+/// ones so that sending never blocks on the network.
+pub struct TcpTransport<Out, In> {
+	writer: Mutex<Sender<Vec<u8>>>,
+	receiver: Mutex<Receiver<Result<Message<In>, TransportError>>>,
+	peer: SocketAddr,
+	_out: PhantomData<fn(Out)>,
+}
+
+impl<Out, In: DeserializeOwned + Send + 'static> TcpTransport<Out, In> {
+	fn start(stream: TcpStream) -> Result<Self, TransportError> {
+		let peer = stream.peer_addr()?;
+		stream.set_read_timeout(None)?;
+		stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+		stream.set_nodelay(true)?;
+		let reader = stream.try_clone()?;
+		let (sender, receiver) = channel();
+		std::thread::spawn(move || Self::read_messages(reader, sender));
+		let (writer, frames) = channel();
+		std::thread::spawn(move || write_frames(stream, frames));
+		Ok(Self {
+			writer: Mutex::new(writer),
+			receiver: Mutex::new(receiver),
+			peer,
+			_out: PhantomData,
+		})
+	}
+
+	fn read_messages(stream: TcpStream, sender: Sender<Result<Message<In>, TransportError>>) {
+		loop {
+			let result = read_frame(&stream, MAX_FRAME_SIZE);
+			let failed = result.is_err();
+			// Once the transport is dropped there is nobody left to read for.
+			if sender.send(result).is_err() || failed {
+				break;
+			}
+		}
+	}
+}
+
+/// Write the queued frames until the transport is dropped or a write fails.
+fn write_frames(mut stream: TcpStream, frames: Receiver<Vec<u8>>) {
+	for frame in frames {
+		if let Err(e) = stream.write_all(&frame) {
+			// Part of the frame may have been written, nothing can follow it.
+			debug!("Failed writing to {:?}: {}", stream.peer_addr(), e);
+			break;
+		}
+	}
+	// Shutting down also stops the reader thread, so the connection is seen as lost on the next
+	// receive.  It is fine if the other end already closed it.
+	let _ = stream.shutdown(Shutdown::Both);
+}
+
+impl<Out: Serialize, In: Send> Transport<Out, In> for TcpTransport<Out, In> {
+	fn send(&self, message: Message<Out>) -> Result<(), TransportError> {
+		let frame = encode_frame(&message)?;
+		self.writer
+			.lock()
+			.expect("poisoned TcpTransport lock")
+			.send(frame)
+			// The writer thread stops once a write failed
+			.map_err(|_| TransportError::Disconnected)
+	}
+
+	fn try_recv(&self) -> Result<Option<Message<In>>, TransportError> {
+		match self
+			.receiver
+			.lock()
+			.expect("poisoned TcpTransport lock")
+			.try_recv()
+		{
+			Ok(result) => result.map(Some),
+			Err(TryRecvError::Empty) => Ok(None),
+			Err(TryRecvError::Disconnected) => Err(TransportError::Disconnected),
+		}
+	}
+
+	fn peer(&self) -> String {
+		self.peer.to_string()
+	}
+}
+
+/// Connect to a server at `address`, a `host:port` or just a `host` to use the `DEFAULT_PORT`.
+/// This blocks until the handshake is done so it should not be called from a system.
+pub fn connect(address: &str, password: Option<&str>) -> Result<ClientTransport, TransportError> {
+	connect_with(address, &Handshake::hello(password))
+}
+
+fn connect_with(address: &str, hello: &Handshake) -> Result<ClientTransport, TransportError> {
+	let addrs: Vec<SocketAddr> = match address.to_socket_addrs() {
+		Ok(addrs) => addrs.collect(),
+		Err(_) => (address, DEFAULT_PORT).to_socket_addrs()?.collect(),
+	};
+	let mut last_error = TransportError::UnknownHost(address.to_owned());
+	for addr in addrs {
+		match TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT) {
+			Ok(stream) => {
+				stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+				write_frame(&stream, hello)?;
+				return match read_frame(&stream, MAX_HANDSHAKE_SIZE)? {
+					Handshake::Accepted => Ok(Box::new(TcpTransport::<
+						ClientCommand,
+						ServerCommand,
+					>::start(stream)?)),
+					Handshake::Refused { reason } => Err(TransportError::Refused(reason)),
+					Handshake::Hello { .. } => Err(TransportError::Refused(
+						"server did not follow the handshake".to_owned(),
+					)),
+				};
+			}
+			Err(e) => last_error = e.into(),
+		}
+	}
+	Err(last_error)
+}
+
+/// The reason to refuse a client's hello, if any.
+fn check_hello(hello: &Handshake, password: &Option<String>) -> Option<String> {
+	match hello {
+		Handshake::Hello { protocol, .. } if *protocol != PROTOCOL_VERSION => Some(format!(
+			"protocol version {} is not supported, the server has version {}",
+			protocol, PROTOCOL_VERSION
+		)),
+		Handshake::Hello { game, .. } if game != GAME_VERSION => Some(format!(
+			"game version {} is not supported, the server has version {}",
+			game, GAME_VERSION
+		)),
+		Handshake::Hello {
+			password: given, ..
+		} if password.is_some() && given != password => Some(match given {
+			Some(_) => "wrong password".to_owned(),
+			None => "a password is required".to_owned(),
+		}),
+		Handshake::Hello { .. } => None,
+		_ => Some("expected a hello".to_owned()),
+	}
+}
+
+fn accept_handshake(
+	stream: TcpStream,
+	password: &Option<String>,
+) -> Result<ServerTransport, TransportError> {
+	stream.set_nonblocking(false)?;
+	stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+	stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+	let hello = read_frame(&stream, MAX_HANDSHAKE_SIZE)?;
+	if let Some(reason) = check_hello(&hello, password) {
+		write_frame(
+			&stream,
+			&Handshake::Refused {
+				reason: reason.clone(),
+			},
+		)?;
+		return Err(TransportError::Refused(reason));
+	}
+	write_frame(&stream, &Handshake::Accepted)?;
+	Ok(Box::new(
+		TcpTransport::<ServerCommand, ClientCommand>::start(stream)?,
+	))
+}
+
+/// Accepts clients over TCP, each handshake happens on its own thread so a slow client does not
+/// hold up the server.
+pub struct TcpServerListener {
+	listener: TcpListener,
+	/// Clients must give this password in their hello if it is set.
+	password: Option<String>,
+	sender: Mutex<Sender<ServerTransport>>,
+	receiver: Mutex<Receiver<ServerTransport>>,
+}
+
+impl TcpServerListener {
+	pub fn bind(address: impl ToSocketAddrs) -> Result<Self, TransportError> {
+		let listener = TcpListener::bind(address)?;
+		listener.set_nonblocking(true)?;
+		let (sender, receiver) = channel();
+		Ok(Self {
+			listener,
+			password: None,
+			sender: Mutex::new(sender),
+			receiver: Mutex::new(receiver),
+		})
+	}
+
+	/// Only accept clients that give this password.
+	pub fn with_password(self, password: Option<String>) -> Self {
+		Self { password, ..self }
+	}
+
+	pub fn has_password(&self) -> bool {
+		self.password.is_some()
+	}
+
+	pub fn local_addr(&self) -> Result<SocketAddr, TransportError> {
+		Ok(self.listener.local_addr()?)
+	}
+}
+
+impl Listener for TcpServerListener {
+	fn try_accept(&self) -> Result<Option<ServerTransport>, TransportError> {
+		loop {
+			match self.listener.accept() {
+				Ok((stream, addr)) => {
+					let sender = self
+						.sender
+						.lock()
+						.expect("poisoned TcpServerListener lock")
+						.clone();
+					let password = self.password.clone();
+					std::thread::spawn(move || match accept_handshake(stream, &password) {
+						Ok(transport) => {
+							let _ = sender.send(transport);
+						}
+						Err(e) => info!("Refused client {}: {}", addr, e),
+					});
+				}
+				Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+				Err(e) => return Err(e.into()),
+			}
+		}
+		match self
+			.receiver
+			.lock()
+			.expect("poisoned TcpServerListener lock")
+			.try_recv()
+		{
+			Ok(transport) => Ok(Some(transport)),
+			Err(_) => Ok(None),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		connect, connect_with, read_frame, Handshake, TcpServerListener, TcpTransport,
+		MAX_FRAME_SIZE, MAX_HANDSHAKE_SIZE, WRITE_TIMEOUT,
+	};
+	use crate::universal::commands::{
+		ClientCommand, ResumeToken, Sequencer, ServerCommand, GAME_VERSION,
+	};
+	use crate::universal::ids::PlayerId;
+	use crate::universal::transport::{
+		ClientTransport, Listener, ServerTransport, Transport, TransportError,
+	};
+	use std::io::Write;
+	use std::sync::mpsc::Receiver;
+	use std::time::{Duration, Instant};
+
+	fn wait_for<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+		let start = Instant::now();
+		loop {
+			if let Some(value) = poll() {
+				return value;
+			}
+			assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+			std::thread::sleep(Duration::from_millis(5));
+		}
+	}
+
+	/// Connects a client while polling the listener, as the server would each update.
+	fn connect_and_accept(
+		listener: &TcpServerListener,
+		password: Option<&'static str>,
+	) -> (ClientTransport, ServerTransport) {
+		let addr = listener.local_addr().unwrap().to_string();
+		let client = std::thread::spawn(move || connect(&addr, password).unwrap());
+		let server = wait_for(|| listener.try_accept().unwrap());
+		(client.join().unwrap(), server)
+	}
+
+	#[test]
+	fn loopback() {
+		let listener = TcpServerListener::bind("127.0.0.1:0").unwrap();
+		assert!(listener.try_accept().unwrap().is_none());
+		let (client, server) = connect_and_accept(&listener, None);
+		let mut client_seq = Sequencer::default();
+		let mut server_seq = Sequencer::default();
+
+		let join = client_seq.next(ClientCommand::Join {
+			name: "test".to_owned(),
+			player: None,
+		});
+		client.send(join.clone()).unwrap();
+		assert_eq!(wait_for(|| server.try_recv().unwrap()), join);
+		let joined = server_seq.next(ServerCommand::Joined {
+			player: PlayerId(1),
+			token: ResumeToken(7),
+		});
+		server.send(joined.clone()).unwrap();
+		assert_eq!(wait_for(|| client.try_recv().unwrap()), joined);
+
+		// Messages sent before a disconnect are still delivered
+		client.send(client_seq.next(ClientCommand::Leave)).unwrap();
+		drop(client);
+		assert_eq!(
+			wait_for(|| server.try_recv().unwrap()).command,
+			ClientCommand::Leave
+		);
+		let error = wait_for(|| server.try_recv().err());
+		assert!(matches!(error, TransportError::Disconnected));
+	}
+
+	#[test]
+	fn version_mismatch() {
+		let listener = TcpServerListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap().to_string();
+		let (sender, receiver) = std::sync::mpsc::channel();
+		let thread_addr = addr.clone();
+		std::thread::spawn(move || {
+			let hello = Handshake::Hello {
+				protocol: 0,
+				game: GAME_VERSION.to_owned(),
+				password: None,
+			};
+			let _ = sender.send(connect_with(&thread_addr, &hello).err());
+			let hello = Handshake::Hello {
+				protocol: crate::universal::commands::PROTOCOL_VERSION,
+				game: "0.0.0-old".to_owned(),
+				password: None,
+			};
+			let _ = sender.send(connect_with(&thread_addr, &hello).err());
+		});
+		expect_refusals(
+			&listener,
+			&receiver,
+			&["protocol version 0", "game version 0.0.0-old"],
+		);
+	}
+
+	fn expect_refusals(
+		listener: &TcpServerListener,
+		receiver: &Receiver<Option<TransportError>>,
+		refusals: &[&str],
+	) {
+		for expected in refusals {
+			let error = wait_for(|| {
+				assert!(listener.try_accept().unwrap().is_none());
+				receiver.try_recv().ok()
+			});
+			match error {
+				Some(TransportError::Refused(reason)) => assert!(reason.starts_with(expected)),
+				e => panic!("expected a refusal, got {:?}", e),
+			}
+		}
+	}
+
+	#[test]
+	fn password() {
+		let listener = TcpServerListener::bind("127.0.0.1:0")
+			.unwrap()
+			.with_password(Some("secret".to_owned()));
+		assert!(listener.has_password());
+		let addr = listener.local_addr().unwrap().to_string();
+		let (sender, receiver) = std::sync::mpsc::channel();
+		std::thread::spawn(move || {
+			let _ = sender.send(connect(&addr, None).err());
+			let _ = sender.send(connect(&addr, Some("guess")).err());
+		});
+		expect_refusals(
+			&listener,
+			&receiver,
+			&["a password is required", "wrong password"],
+		);
+		connect_and_accept(&listener, Some("secret"));
+	}
+
+	#[test]
+	fn oversized_frame() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let mut sender = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+		let (receiver, _) = listener.accept().unwrap();
+		sender
+			.write_all(&(MAX_FRAME_SIZE + 1).to_be_bytes())
+			.unwrap();
+		assert!(matches!(
+			read_frame::<Handshake>(&receiver, MAX_FRAME_SIZE),
+			Err(TransportError::FrameTooLarge(_))
+		));
+
+		// Before the handshake only small frames are read
+		sender
+			.write_all(&(MAX_HANDSHAKE_SIZE + 1).to_be_bytes())
+			.unwrap();
+		assert!(matches!(
+			read_frame::<Handshake>(&receiver, MAX_HANDSHAKE_SIZE),
+			Err(TransportError::FrameTooLarge(_))
+		));
+
+		// A frame cut short by a disconnect is not read
+		sender.write_all(&100u32.to_be_bytes()).unwrap();
+		sender.write_all(b"{").unwrap();
+		drop(sender);
+		assert!(matches!(
+			read_frame::<Handshake>(&receiver, MAX_FRAME_SIZE),
+			Err(TransportError::Disconnected)
+		));
+	}
+
+	#[test]
+	fn peer_not_reading() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let peer = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+		let (stream, _) = listener.accept().unwrap();
+		let server = TcpTransport::<ServerCommand, ClientCommand>::start(stream).unwrap();
+		let mut seq = Sequencer::default();
+		let message = ServerCommand::Chat {
+			from: PlayerId(1),
+			text: "x".repeat(1024 * 1024),
+		};
+
+		// Far more than the socket buffers hold, none of it may wait on the peer
+		let start = Instant::now();
+		for _ in 0..32 {
+			server.send(seq.next(message.clone())).unwrap();
+		}
+		assert!(start.elapsed() < WRITE_TIMEOUT);
+
+		// The stalled writer gives up once the peer goes away
+		drop(peer);
+		let error = wait_for(|| server.send(seq.next(message.clone())).err());
+		assert!(matches!(error, TransportError::Disconnected));
+		wait_for(|| server.try_recv().err());
+	}
+}
@@ -0,0 +1,46 @@
+//! What tiles and cities produce every turn, shared by the server and its clients.
+
+use serde::{Deserialize, Serialize};
+use std::iter::Sum;
+use std::ops::Add;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Yields {
+	pub food: u32,
+	pub production: u32,
+	pub gold: u32,
+	pub science: u32,
+	pub culture: u32,
+}
+
+impl Yields {
+	/// Every yield times its weight in `weights`, added up.
+	pub fn weighted(&self, weights: &Yields) -> u32 {
+		self.food * weights.food
+			+ self.production * weights.production
+			+ self.gold * weights.gold
+			+ self.science * weights.science
+			+ self.culture * weights.culture
+	}
+}
+
+impl Add for Yields {
+	type Output = Yields;
+
+	fn add(self, other: Yields) -> Yields {
+		Yields {
+			food: self.food + other.food,
+			production: self.production + other.production,
+			gold: self.gold + other.gold,
+			science: self.science + other.science,
+			culture: self.culture + other.culture,
+		}
+	}
+}
+
+impl Sum for Yields {
+	fn sum<I: Iterator<Item = Yields>>(iter: I) -> Yields {
+		iter.fold(Yields::default(), Add::add)
+	}
+}